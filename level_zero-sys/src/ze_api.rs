@@ -509,6 +509,11 @@ impl _ze_structure_type_t {
     pub const ZE_STRUCTURE_TYPE_RAYTRACING_MEM_ALLOC_EXT_DESC: _ze_structure_type_t =
         _ze_structure_type_t(65537);
 }
+impl _ze_structure_type_t {
+    #[doc = "< ::ze_float_atomic_ext_properties_t"]
+    pub const ZE_STRUCTURE_TYPE_FLOAT_ATOMIC_EXT_PROPERTIES: _ze_structure_type_t =
+        _ze_structure_type_t(65541);
+}
 impl _ze_structure_type_t {
     pub const ZE_STRUCTURE_TYPE_FORCE_UINT32: _ze_structure_type_t =
         _ze_structure_type_t(2147483647);
@@ -2233,6 +2238,117 @@ fn bindgen_test_layout__ze_device_module_properties_t() {
         )
     );
 }
+impl _ze_device_fp_atomic_ext_flags_t {
+    #[doc = "< Supports atomic load, store, and exchange"]
+    pub const ZE_DEVICE_FP_ATOMIC_EXT_FLAG_GLOBAL_LOAD_STORE: _ze_device_fp_atomic_ext_flags_t =
+        _ze_device_fp_atomic_ext_flags_t(1);
+}
+impl _ze_device_fp_atomic_ext_flags_t {
+    #[doc = "< Supports atomic add and subtract"]
+    pub const ZE_DEVICE_FP_ATOMIC_EXT_FLAG_GLOBAL_ADD: _ze_device_fp_atomic_ext_flags_t =
+        _ze_device_fp_atomic_ext_flags_t(2);
+}
+impl _ze_device_fp_atomic_ext_flags_t {
+    #[doc = "< Supports atomic min and max"]
+    pub const ZE_DEVICE_FP_ATOMIC_EXT_FLAG_GLOBAL_MIN_MAX: _ze_device_fp_atomic_ext_flags_t =
+        _ze_device_fp_atomic_ext_flags_t(4);
+}
+impl _ze_device_fp_atomic_ext_flags_t {
+    #[doc = "< Supports atomic load, store, and exchange"]
+    pub const ZE_DEVICE_FP_ATOMIC_EXT_FLAG_LOCAL_LOAD_STORE: _ze_device_fp_atomic_ext_flags_t =
+        _ze_device_fp_atomic_ext_flags_t(65536);
+}
+impl _ze_device_fp_atomic_ext_flags_t {
+    #[doc = "< Supports atomic add and subtract"]
+    pub const ZE_DEVICE_FP_ATOMIC_EXT_FLAG_LOCAL_ADD: _ze_device_fp_atomic_ext_flags_t =
+        _ze_device_fp_atomic_ext_flags_t(131072);
+}
+impl _ze_device_fp_atomic_ext_flags_t {
+    #[doc = "< Supports atomic min and max"]
+    pub const ZE_DEVICE_FP_ATOMIC_EXT_FLAG_LOCAL_MIN_MAX: _ze_device_fp_atomic_ext_flags_t =
+        _ze_device_fp_atomic_ext_flags_t(262144);
+}
+impl _ze_device_fp_atomic_ext_flags_t {
+    pub const ZE_DEVICE_FP_ATOMIC_EXT_FLAG_FORCE_UINT32: _ze_device_fp_atomic_ext_flags_t =
+        _ze_device_fp_atomic_ext_flags_t(2147483647);
+}
+impl ::std::ops::BitOr<_ze_device_fp_atomic_ext_flags_t> for _ze_device_fp_atomic_ext_flags_t {
+    type Output = Self;
+    #[inline]
+    fn bitor(self, other: Self) -> Self {
+        _ze_device_fp_atomic_ext_flags_t(self.0 | other.0)
+    }
+}
+impl ::std::ops::BitOrAssign for _ze_device_fp_atomic_ext_flags_t {
+    #[inline]
+    fn bitor_assign(&mut self, rhs: _ze_device_fp_atomic_ext_flags_t) {
+        self.0 |= rhs.0;
+    }
+}
+impl ::std::ops::BitAnd<_ze_device_fp_atomic_ext_flags_t> for _ze_device_fp_atomic_ext_flags_t {
+    type Output = Self;
+    #[inline]
+    fn bitand(self, other: Self) -> Self {
+        _ze_device_fp_atomic_ext_flags_t(self.0 & other.0)
+    }
+}
+impl ::std::ops::BitAndAssign for _ze_device_fp_atomic_ext_flags_t {
+    #[inline]
+    fn bitand_assign(&mut self, rhs: _ze_device_fp_atomic_ext_flags_t) {
+        self.0 &= rhs.0;
+    }
+}
+#[repr(transparent)]
+#[doc = ""]
+#[doc = " @brief Supported floating-point atomic capability flags"]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct _ze_device_fp_atomic_ext_flags_t(pub u32);
+#[doc = ""]
+#[doc = " @brief Supported floating-point atomic capability flags"]
+pub use self::_ze_device_fp_atomic_ext_flags_t as ze_device_fp_atomic_ext_flags_t;
+#[doc = ""]
+#[doc = " @brief Device floating-point atomic properties queried using"]
+#[doc = "        ::zeDeviceGetModuleProperties"]
+#[doc = ""]
+#[doc = " @details"]
+#[doc = "     - This structure may be returned from ::zeDeviceGetModuleProperties,"]
+#[doc = "       via `pNext` member of ::ze_device_module_properties_t."]
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct _ze_float_atomic_ext_properties_t {
+    #[doc = "< [in] type of this structure"]
+    pub stype: ze_structure_type_t,
+    #[doc = "< [in,out][optional] pointer to extension-specific structure"]
+    pub pNext: *mut ::std::os::raw::c_void,
+    #[doc = "< [out] Capabilities for half-precision floating-point atomic operations"]
+    pub fp16Flags: ze_device_fp_atomic_ext_flags_t,
+    #[doc = "< [out] Capabilities for single-precision floating-point atomic"]
+    #[doc = "< operations"]
+    pub fp32Flags: ze_device_fp_atomic_ext_flags_t,
+    #[doc = "< [out] Capabilities for double-precision floating-point atomic"]
+    #[doc = "< operations"]
+    pub fp64Flags: ze_device_fp_atomic_ext_flags_t,
+}
+#[test]
+fn bindgen_test_layout__ze_float_atomic_ext_properties_t() {
+    assert_eq!(
+        ::std::mem::size_of::<_ze_float_atomic_ext_properties_t>(),
+        32usize,
+        concat!("Size of: ", stringify!(_ze_float_atomic_ext_properties_t))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<_ze_float_atomic_ext_properties_t>(),
+        8usize,
+        concat!(
+            "Alignment of ",
+            stringify!(_ze_float_atomic_ext_properties_t)
+        )
+    );
+}
+#[doc = ""]
+#[doc = " @brief Device floating-point atomic properties queried using"]
+#[doc = "        ::zeDeviceGetModuleProperties"]
+pub type ze_float_atomic_ext_properties_t = _ze_float_atomic_ext_properties_t;
 extern "C" {
     #[doc = ""]
     #[doc = " @brief Retrieves module properties of the device"]
@@ -124,6 +124,20 @@ impl Device {
         Ok(props)
     }
 
+    // Drivers without ZE_extension_float_atomics skip the extension struct,
+    // all flags are then reported as unsupported
+    pub fn get_float_atomic_properties(
+        &self,
+    ) -> Result<Box<sys::ze_float_atomic_ext_properties_t>> {
+        let mut props = Box::new(unsafe { mem::zeroed::<sys::ze_float_atomic_ext_properties_t>() });
+        props.stype = sys::ze_structure_type_t::ZE_STRUCTURE_TYPE_FLOAT_ATOMIC_EXT_PROPERTIES;
+        let mut module_props = unsafe { mem::zeroed::<sys::ze_device_module_properties_t>() };
+        module_props.stype = sys::ze_structure_type_t::ZE_STRUCTURE_TYPE_DEVICE_MODULE_PROPERTIES;
+        module_props.pNext = props.as_mut() as *mut _ as *mut _;
+        check! { sys::zeDeviceGetModuleProperties(self.0, &mut module_props) };
+        Ok(props)
+    }
+
     pub unsafe fn mem_alloc_device(
         &mut self,
         ctx: &mut Context,
@@ -228,14 +242,19 @@ impl CommandQueue {
         let result = FenceGuard::new(self, cmd.0)?;
         let mut raw_cmd = cmd.0;
         mem::forget(cmd);
-        check!(sys::zeCommandQueueExecuteCommandLists(
-            self.0,
-            1,
-            &mut raw_cmd,
-            result.0
-        ));
+        let err =
+            unsafe { sys::zeCommandQueueExecuteCommandLists(self.0, 1, &mut raw_cmd, result.0) };
+        if err != sys::ze_result_t::ZE_RESULT_SUCCESS {
+            result.destroy_unsubmitted();
+            return Err(err);
+        }
         Ok(result)
     }
+
+    pub fn synchronize(&self) -> Result<()> {
+        check!(sys::zeCommandQueueSynchronize(self.0, u64::max_value()));
+        Ok(())
+    }
 }
 
 impl Drop for CommandQueue {
@@ -422,6 +441,18 @@ impl Module {
             (Ok(Module(result)), log)
         }
     }
+
+    pub fn get_global_pointer(&self, name: &CStr) -> Result<(usize, *mut c_void)> {
+        let mut size = 0;
+        let mut result = ptr::null_mut();
+        check!(sys::zeModuleGetGlobalPointer(
+            self.0,
+            name.as_ptr(),
+            &mut size,
+            &mut result
+        ));
+        Ok((size, result))
+    }
 }
 
 impl Drop for Module {
@@ -431,6 +462,85 @@ impl Drop for Module {
     }
 }
 
+// Only read-only 1D images, the ones kernels sample textures from
+pub struct Image(sys::ze_image_handle_t);
+
+impl Image {
+    pub unsafe fn as_ffi(&self) -> sys::ze_image_handle_t {
+        self.0
+    }
+    pub unsafe fn from_ffi(x: sys::ze_image_handle_t) -> Self {
+        Self(x)
+    }
+
+    pub fn new_1d(
+        ctx: &mut Context,
+        dev: &Device,
+        format: sys::ze_image_format_t,
+        width: u64,
+    ) -> Result<Self> {
+        let desc = sys::ze_image_desc_t {
+            stype: sys::ze_structure_type_t::ZE_STRUCTURE_TYPE_IMAGE_DESC,
+            pNext: ptr::null(),
+            flags: sys::ze_image_flags_t(0),
+            type_: sys::ze_image_type_t::ZE_IMAGE_TYPE_1D,
+            format,
+            width,
+            height: 1,
+            depth: 1,
+            arraylevels: 0,
+            miplevels: 0,
+        };
+        let mut result = ptr::null_mut();
+        check!(sys::zeImageCreate(ctx.0, dev.0, &desc, &mut result));
+        Ok(Image(result))
+    }
+}
+
+impl Drop for Image {
+    #[allow(unused_must_use)]
+    fn drop(&mut self) {
+        check_panic! { sys::zeImageDestroy(self.0) };
+    }
+}
+
+pub struct Sampler(sys::ze_sampler_handle_t);
+
+impl Sampler {
+    pub unsafe fn as_ffi(&self) -> sys::ze_sampler_handle_t {
+        self.0
+    }
+    pub unsafe fn from_ffi(x: sys::ze_sampler_handle_t) -> Self {
+        Self(x)
+    }
+
+    pub fn new(
+        ctx: &mut Context,
+        dev: &Device,
+        address_mode: sys::ze_sampler_address_mode_t,
+        filter_mode: sys::ze_sampler_filter_mode_t,
+        normalized: bool,
+    ) -> Result<Self> {
+        let desc = sys::ze_sampler_desc_t {
+            stype: sys::ze_structure_type_t::ZE_STRUCTURE_TYPE_SAMPLER_DESC,
+            pNext: ptr::null(),
+            addressMode: address_mode,
+            filterMode: filter_mode,
+            isNormalized: normalized as sys::ze_bool_t,
+        };
+        let mut result = ptr::null_mut();
+        check!(sys::zeSamplerCreate(ctx.0, dev.0, &desc, &mut result));
+        Ok(Sampler(result))
+    }
+}
+
+impl Drop for Sampler {
+    #[allow(unused_must_use)]
+    fn drop(&mut self) {
+        check_panic! { sys::zeSamplerDestroy(self.0) };
+    }
+}
+
 pub struct BuildLog(sys::ze_module_build_log_handle_t);
 
 impl BuildLog {
@@ -607,6 +717,19 @@ impl<'a> CommandList<'a> {
         Ok(())
     }
 
+    // Fills the whole buffer with copies of `pattern`. Level Zero requires
+    // the pattern size to be a power of two
+    pub fn append_memory_fill_pattern<T, P: SafeRepr + Copy>(
+        &mut self,
+        dst: BufferPtrMut<'a, T>,
+        pattern: &P,
+        signal: Option<&mut Event<'a>>,
+        wait: &mut [Event<'a>],
+    ) -> Result<()> {
+        let byte_len = dst.len() * mem::size_of::<T>();
+        unsafe { self.append_memory_fill_unsafe(dst.get(), pattern, byte_len, signal, wait) }
+    }
+
     pub unsafe fn append_memory_fill_unsafe<T: Copy + Sized>(
         &mut self,
         dst: *mut c_void,
@@ -630,6 +753,46 @@ impl<'a> CommandList<'a> {
         Ok(())
     }
 
+    // Copies the whole image, src has to hold tightly packed texels
+    pub unsafe fn append_image_copy_from_memory(
+        &mut self,
+        dst: &Image,
+        src: *const c_void,
+        signal: Option<&mut Event<'a>>,
+        wait: &mut [Event<'a>],
+    ) -> Result<()> {
+        let signal_event = signal.map(|e| e.0).unwrap_or(ptr::null_mut());
+        let (wait_len, wait_ptr) = Event::raw_slice(wait);
+        check!(sys::zeCommandListAppendImageCopyFromMemory(
+            self.0,
+            dst.0,
+            src,
+            ptr::null(),
+            signal_event,
+            wait_len,
+            wait_ptr
+        ));
+        Ok(())
+    }
+
+    // Commands in a command list can run concurrently, everything appended
+    // after a barrier waits for everything appended before it
+    pub fn append_barrier(
+        &mut self,
+        signal: Option<&mut Event<'a>>,
+        wait: &mut [Event<'a>],
+    ) -> Result<()> {
+        let signal_event = signal.map(|e| e.0).unwrap_or(ptr::null_mut());
+        let (wait_len, wait_ptr) = unsafe { Event::raw_slice(wait) };
+        check!(sys::zeCommandListAppendBarrier(
+            self.0,
+            signal_event,
+            wait_len,
+            wait_ptr
+        ));
+        Ok(())
+    }
+
     pub fn append_launch_kernel(
         &mut self,
         kernel: &'a Kernel,
@@ -680,6 +843,25 @@ impl<'a> FenceGuard<'a> {
         check!(sys::zeFenceCreate(q.0, &desc, &mut result));
         Ok(FenceGuard(result, cmd_list, PhantomData))
     }
+
+    // Nothing will ever signal the fence of a command list that failed to
+    // submit, dropping the guard would wait for it forever
+    #[allow(unused_must_use)]
+    fn destroy_unsubmitted(self) {
+        unsafe {
+            sys::zeFenceDestroy(self.0);
+            sys::zeCommandListDestroy(self.1);
+        }
+        mem::forget(self);
+    }
+
+    pub fn is_signaled(&self) -> Result<bool> {
+        match unsafe { sys::zeFenceQueryStatus(self.0) } {
+            sys::ze_result_t::ZE_RESULT_SUCCESS => Ok(true),
+            sys::ze_result_t::ZE_RESULT_NOT_READY => Ok(false),
+            err => Err(err),
+        }
+    }
 }
 
 impl<'a> Drop for FenceGuard<'a> {
@@ -784,10 +966,34 @@ impl<'a> EventPool<'a> {
         Self(x, PhantomData)
     }
     pub fn new(ctx: &mut Context, count: u32, dev: Option<&[&'a Device]>) -> Result<Self> {
+        Self::new_with_flags(ctx, sys::ze_event_pool_flags_t(0), count, dev)
+    }
+
+    // Events from this pool can be queried for start and end of the kernel
+    // that signalled them
+    pub fn new_timestamp(
+        ctx: &mut Context,
+        count: u32,
+        dev: Option<&[&'a Device]>,
+    ) -> Result<Self> {
+        Self::new_with_flags(
+            ctx,
+            sys::ze_event_pool_flags_t::ZE_EVENT_POOL_FLAG_KERNEL_TIMESTAMP,
+            count,
+            dev,
+        )
+    }
+
+    fn new_with_flags(
+        ctx: &mut Context,
+        flags: sys::ze_event_pool_flags_t,
+        count: u32,
+        dev: Option<&[&'a Device]>,
+    ) -> Result<Self> {
         let desc = sys::ze_event_pool_desc_t {
             stype: sys::ze_structure_type_t::ZE_STRUCTURE_TYPE_EVENT_POOL_DESC,
             pNext: ptr::null(),
-            flags: sys::ze_event_pool_flags_t(0),
+            flags,
             count: count,
         };
         let mut dev = dev.map(|d| d.iter().map(|d| d.0).collect::<Vec<_>>());
@@ -836,6 +1042,13 @@ impl<'a> Event<'a> {
         Ok(Self(result, PhantomData))
     }
 
+    // Only meaningful for signalled events from a timestamp pool
+    pub fn query_kernel_timestamp(&self) -> Result<sys::ze_kernel_timestamp_result_t> {
+        let mut result = unsafe { mem::zeroed::<sys::ze_kernel_timestamp_result_t>() };
+        check!(sys::zeEventQueryKernelTimestamp(self.0, &mut result));
+        Ok(result)
+    }
+
     unsafe fn raw_slice(e: &mut [Event]) -> (u32, *mut sys::ze_event_handle_t) {
         let ptr = if e.len() == 0 {
             ptr::null_mut()
@@ -914,11 +1127,35 @@ impl<'a> Kernel<'a> {
         Ok(())
     }
 
+    pub fn set_arg_image(&self, index: u32, image: &Image) -> Result<()> {
+        self.set_arg_scalar(index, &image.0)
+    }
+
+    pub fn set_arg_sampler(&self, index: u32, sampler: &Sampler) -> Result<()> {
+        self.set_arg_scalar(index, &sampler.0)
+    }
+
     pub fn set_group_size(&self, x: u32, y: u32, z: u32) -> Result<()> {
         check!(sys::zeKernelSetGroupSize(self.0, x, y, z));
         Ok(())
     }
 
+    pub fn suggest_group_size(&self, x: u32, y: u32, z: u32) -> Result<(u32, u32, u32)> {
+        let mut group_x = 0;
+        let mut group_y = 0;
+        let mut group_z = 0;
+        check!(sys::zeKernelSuggestGroupSize(
+            self.0,
+            x,
+            y,
+            z,
+            &mut group_x,
+            &mut group_y,
+            &mut group_z
+        ));
+        Ok((group_x, group_y, group_z))
+    }
+
     pub fn get_properties(&self) -> Result<Box<sys::ze_kernel_properties_t>> {
         let mut props = Box::new(unsafe { mem::zeroed::<sys::ze_kernel_properties_t>() });
         check!(sys::zeKernelGetProperties(self.0, props.as_mut() as *mut _));
@@ -944,4 +1181,92 @@ mod tests {
             mem::size_of::<sys::ze_event_handle_t>()
         );
     }
+
+    #[test]
+    fn memory_fill_with_multibyte_pattern() -> Result<()> {
+        init()?;
+        let mut drivers = Driver::get()?;
+        let drv = drivers.drain(0..1).next().unwrap();
+        let mut ctx = Context::new(&drv)?;
+        let mut devices = drv.devices()?;
+        let dev = devices.drain(0..1).next().unwrap();
+        let queue = CommandQueue::new(&mut ctx, &dev)?;
+        let mut result = vec![0u32; 17];
+        {
+            let mut buffer = DeviceBuffer::<u32>::new(&mut ctx, &dev, result.len())?;
+            let buffer_ptr: BufferPtrMut<u32> = (&mut buffer).into();
+            let event_pool = EventPool::new(&mut ctx, 1, Some(&[&dev]))?;
+            let mut ev0 = Event::new(&event_pool, 0)?;
+            let mut cmd_list = CommandList::new(&mut ctx, &dev)?;
+            cmd_list.append_memory_fill_pattern(
+                buffer_ptr,
+                &0xDEADBEEFu32,
+                Some(&mut ev0),
+                &mut [],
+            )?;
+            cmd_list.append_memory_copy(result.as_mut_slice(), buffer_ptr, None, &mut [ev0])?;
+            queue.execute(cmd_list)?;
+        }
+        assert!(result.iter().all(|x| *x == 0xDEADBEEFu32));
+        Ok(())
+    }
+
+    // A command list from a copy-only group can't be submitted to the compute
+    // queue, the error must be returned instead of waiting for the fence.
+    // Skipped on devices without a copy-only group
+    #[test]
+    fn failed_submission_returns_error() -> Result<()> {
+        init()?;
+        let mut drivers = Driver::get()?;
+        let drv = drivers.drain(0..1).next().unwrap();
+        let mut ctx = Context::new(&drv)?;
+        let mut devices = drv.devices()?;
+        let dev = devices.drain(0..1).next().unwrap();
+        let mut count = 0;
+        check!(sys::zeDeviceGetCommandQueueGroupProperties(
+            dev.0,
+            &mut count,
+            ptr::null_mut()
+        ));
+        let mut groups = vec![
+            unsafe { mem::zeroed::<sys::ze_command_queue_group_properties_t>() };
+            count as usize
+        ];
+        for group in groups.iter_mut() {
+            group.stype =
+                sys::ze_structure_type_t::ZE_STRUCTURE_TYPE_COMMAND_QUEUE_GROUP_PROPERTIES;
+        }
+        check!(sys::zeDeviceGetCommandQueueGroupProperties(
+            dev.0,
+            &mut count,
+            groups.as_mut_ptr()
+        ));
+        let compute = sys::ze_command_queue_group_property_flags_t::ZE_COMMAND_QUEUE_GROUP_PROPERTY_FLAG_COMPUTE;
+        let copy_ordinal = match groups
+            .iter()
+            .position(|group| group.flags & compute != compute)
+        {
+            Some(ordinal) => ordinal as u32,
+            None => return Ok(()),
+        };
+        let queue = CommandQueue::new(&mut ctx, &dev)?;
+        let desc = sys::ze_command_list_desc_t {
+            stype: sys::_ze_structure_type_t::ZE_STRUCTURE_TYPE_COMMAND_LIST_DESC,
+            commandQueueGroupOrdinal: copy_ordinal,
+            pNext: ptr::null(),
+            flags: sys::ze_command_list_flags_t(0),
+        };
+        let mut raw_cmd_list = ptr::null_mut();
+        check!(sys::zeCommandListCreate(
+            ctx.0,
+            dev.0,
+            &desc,
+            &mut raw_cmd_list
+        ));
+        let cmd_list = unsafe { CommandList::from_ffi(raw_cmd_list) };
+        assert!(queue.execute(cmd_list).is_err());
+        // Queue is still usable
+        queue.execute(CommandList::new(&mut ctx, &dev)?)?;
+        Ok(())
+    }
 }
@@ -25,6 +25,32 @@
         return expected;                                                                                                                    \
     }
 
+// Native float atomics are used when the compiler exposes them, otherwise we fall back to a CAS loop
+#ifdef cl_ext_float_atomics
+    #define ATOMIC_F32 atomic_float
+    #define ATOMIC_F64 atomic_double
+    #define atomic_add(NAME, SUCCESS, FAILURE, SCOPE, SPACE, TYPE, ATOMIC_TYPE, INT_TYPE)                                                       \
+        TYPE FUNC(NAME)(SPACE TYPE* ptr, TYPE value) {                                                                                      \
+            return atomic_fetch_add_explicit((volatile SPACE ATOMIC_TYPE*)ptr, value, SUCCESS, SCOPE);                                      \
+        }
+#else
+    #define ATOMIC_F32 atomic_uint
+    #define ATOMIC_F64 atomic_ulong
+    #define atomic_add(NAME, SUCCESS, FAILURE, SCOPE, SPACE, TYPE, ATOMIC_TYPE, INT_TYPE)                                                       \
+        TYPE FUNC(NAME)(SPACE TYPE* ptr, TYPE value) {                                                                                      \
+            volatile SPACE ATOMIC_TYPE* atomic_ptr = (volatile SPACE ATOMIC_TYPE*)ptr;                                                      \
+            union {                                                                                                                         \
+                INT_TYPE int_view;                                                                                                          \
+                TYPE float_view;                                                                                                            \
+            } expected, desired;                                                                                                            \
+            expected.float_view = *ptr;                                                                                                     \
+            do {                                                                                                                            \
+                desired.float_view = expected.float_view + value;                                                                           \
+            } while (!atomic_compare_exchange_strong_explicit(atomic_ptr, &expected.int_view, desired.int_view, SUCCESS, FAILURE, SCOPE)); \
+            return expected.float_view;                                                                                                     \
+        }
+#endif
+
 // We are doing all this mess instead of accepting memory_order and memory_scope parameters
 // because ocloc emits broken (failing spirv-dis) SPIR-V when memory_order or memory_scope is a parameter
 
@@ -120,6 +146,98 @@ atomic_dec(atom_acquire_sys_shared_dec, memory_order_acquire, memory_order_acqui
 atomic_dec(atom_release_sys_shared_dec, memory_order_release, memory_order_acquire, memory_scope_device, __local);
 atomic_dec(atom_acq_rel_sys_shared_dec, memory_order_acq_rel, memory_order_acquire, memory_scope_device, __local);
 
+// atom.add.f32
+atomic_add(atom_relaxed_cta_generic_add_f32, memory_order_relaxed, memory_order_relaxed, memory_scope_work_group, , float, ATOMIC_F32, uint);
+atomic_add(atom_acquire_cta_generic_add_f32, memory_order_acquire, memory_order_acquire, memory_scope_work_group, , float, ATOMIC_F32, uint);
+atomic_add(atom_release_cta_generic_add_f32, memory_order_release, memory_order_acquire, memory_scope_work_group, , float, ATOMIC_F32, uint);
+atomic_add(atom_acq_rel_cta_generic_add_f32, memory_order_acq_rel, memory_order_acquire, memory_scope_work_group, , float, ATOMIC_F32, uint);
+
+atomic_add(atom_relaxed_gpu_generic_add_f32, memory_order_relaxed, memory_order_relaxed, memory_scope_device, , float, ATOMIC_F32, uint);
+atomic_add(atom_acquire_gpu_generic_add_f32, memory_order_acquire, memory_order_acquire, memory_scope_device, , float, ATOMIC_F32, uint);
+atomic_add(atom_release_gpu_generic_add_f32, memory_order_release, memory_order_acquire, memory_scope_device, , float, ATOMIC_F32, uint);
+atomic_add(atom_acq_rel_gpu_generic_add_f32, memory_order_acq_rel, memory_order_acquire, memory_scope_device, , float, ATOMIC_F32, uint);
+
+atomic_add(atom_relaxed_sys_generic_add_f32, memory_order_relaxed, memory_order_relaxed, memory_scope_device, , float, ATOMIC_F32, uint);
+atomic_add(atom_acquire_sys_generic_add_f32, memory_order_acquire, memory_order_acquire, memory_scope_device, , float, ATOMIC_F32, uint);
+atomic_add(atom_release_sys_generic_add_f32, memory_order_release, memory_order_acquire, memory_scope_device, , float, ATOMIC_F32, uint);
+atomic_add(atom_acq_rel_sys_generic_add_f32, memory_order_acq_rel, memory_order_acquire, memory_scope_device, , float, ATOMIC_F32, uint);
+
+atomic_add(atom_relaxed_cta_global_add_f32, memory_order_relaxed, memory_order_relaxed, memory_scope_work_group, __global, float, ATOMIC_F32, uint);
+atomic_add(atom_acquire_cta_global_add_f32, memory_order_acquire, memory_order_acquire, memory_scope_work_group, __global, float, ATOMIC_F32, uint);
+atomic_add(atom_release_cta_global_add_f32, memory_order_release, memory_order_acquire, memory_scope_work_group, __global, float, ATOMIC_F32, uint);
+atomic_add(atom_acq_rel_cta_global_add_f32, memory_order_acq_rel, memory_order_acquire, memory_scope_work_group, __global, float, ATOMIC_F32, uint);
+
+atomic_add(atom_relaxed_gpu_global_add_f32, memory_order_relaxed, memory_order_relaxed, memory_scope_device, __global, float, ATOMIC_F32, uint);
+atomic_add(atom_acquire_gpu_global_add_f32, memory_order_acquire, memory_order_acquire, memory_scope_device, __global, float, ATOMIC_F32, uint);
+atomic_add(atom_release_gpu_global_add_f32, memory_order_release, memory_order_acquire, memory_scope_device, __global, float, ATOMIC_F32, uint);
+atomic_add(atom_acq_rel_gpu_global_add_f32, memory_order_acq_rel, memory_order_acquire, memory_scope_device, __global, float, ATOMIC_F32, uint);
+
+atomic_add(atom_relaxed_sys_global_add_f32, memory_order_relaxed, memory_order_relaxed, memory_scope_device, __global, float, ATOMIC_F32, uint);
+atomic_add(atom_acquire_sys_global_add_f32, memory_order_acquire, memory_order_acquire, memory_scope_device, __global, float, ATOMIC_F32, uint);
+atomic_add(atom_release_sys_global_add_f32, memory_order_release, memory_order_acquire, memory_scope_device, __global, float, ATOMIC_F32, uint);
+atomic_add(atom_acq_rel_sys_global_add_f32, memory_order_acq_rel, memory_order_acquire, memory_scope_device, __global, float, ATOMIC_F32, uint);
+
+atomic_add(atom_relaxed_cta_shared_add_f32, memory_order_relaxed, memory_order_relaxed, memory_scope_work_group, __local, float, ATOMIC_F32, uint);
+atomic_add(atom_acquire_cta_shared_add_f32, memory_order_acquire, memory_order_acquire, memory_scope_work_group, __local, float, ATOMIC_F32, uint);
+atomic_add(atom_release_cta_shared_add_f32, memory_order_release, memory_order_acquire, memory_scope_work_group, __local, float, ATOMIC_F32, uint);
+atomic_add(atom_acq_rel_cta_shared_add_f32, memory_order_acq_rel, memory_order_acquire, memory_scope_work_group, __local, float, ATOMIC_F32, uint);
+
+atomic_add(atom_relaxed_gpu_shared_add_f32, memory_order_relaxed, memory_order_relaxed, memory_scope_device, __local, float, ATOMIC_F32, uint);
+atomic_add(atom_acquire_gpu_shared_add_f32, memory_order_acquire, memory_order_acquire, memory_scope_device, __local, float, ATOMIC_F32, uint);
+atomic_add(atom_release_gpu_shared_add_f32, memory_order_release, memory_order_acquire, memory_scope_device, __local, float, ATOMIC_F32, uint);
+atomic_add(atom_acq_rel_gpu_shared_add_f32, memory_order_acq_rel, memory_order_acquire, memory_scope_device, __local, float, ATOMIC_F32, uint);
+
+atomic_add(atom_relaxed_sys_shared_add_f32, memory_order_relaxed, memory_order_relaxed, memory_scope_device, __local, float, ATOMIC_F32, uint);
+atomic_add(atom_acquire_sys_shared_add_f32, memory_order_acquire, memory_order_acquire, memory_scope_device, __local, float, ATOMIC_F32, uint);
+atomic_add(atom_release_sys_shared_add_f32, memory_order_release, memory_order_acquire, memory_scope_device, __local, float, ATOMIC_F32, uint);
+atomic_add(atom_acq_rel_sys_shared_add_f32, memory_order_acq_rel, memory_order_acquire, memory_scope_device, __local, float, ATOMIC_F32, uint);
+
+// atom.add.f64
+atomic_add(atom_relaxed_cta_generic_add_f64, memory_order_relaxed, memory_order_relaxed, memory_scope_work_group, , double, ATOMIC_F64, ulong);
+atomic_add(atom_acquire_cta_generic_add_f64, memory_order_acquire, memory_order_acquire, memory_scope_work_group, , double, ATOMIC_F64, ulong);
+atomic_add(atom_release_cta_generic_add_f64, memory_order_release, memory_order_acquire, memory_scope_work_group, , double, ATOMIC_F64, ulong);
+atomic_add(atom_acq_rel_cta_generic_add_f64, memory_order_acq_rel, memory_order_acquire, memory_scope_work_group, , double, ATOMIC_F64, ulong);
+
+atomic_add(atom_relaxed_gpu_generic_add_f64, memory_order_relaxed, memory_order_relaxed, memory_scope_device, , double, ATOMIC_F64, ulong);
+atomic_add(atom_acquire_gpu_generic_add_f64, memory_order_acquire, memory_order_acquire, memory_scope_device, , double, ATOMIC_F64, ulong);
+atomic_add(atom_release_gpu_generic_add_f64, memory_order_release, memory_order_acquire, memory_scope_device, , double, ATOMIC_F64, ulong);
+atomic_add(atom_acq_rel_gpu_generic_add_f64, memory_order_acq_rel, memory_order_acquire, memory_scope_device, , double, ATOMIC_F64, ulong);
+
+atomic_add(atom_relaxed_sys_generic_add_f64, memory_order_relaxed, memory_order_relaxed, memory_scope_device, , double, ATOMIC_F64, ulong);
+atomic_add(atom_acquire_sys_generic_add_f64, memory_order_acquire, memory_order_acquire, memory_scope_device, , double, ATOMIC_F64, ulong);
+atomic_add(atom_release_sys_generic_add_f64, memory_order_release, memory_order_acquire, memory_scope_device, , double, ATOMIC_F64, ulong);
+atomic_add(atom_acq_rel_sys_generic_add_f64, memory_order_acq_rel, memory_order_acquire, memory_scope_device, , double, ATOMIC_F64, ulong);
+
+atomic_add(atom_relaxed_cta_global_add_f64, memory_order_relaxed, memory_order_relaxed, memory_scope_work_group, __global, double, ATOMIC_F64, ulong);
+atomic_add(atom_acquire_cta_global_add_f64, memory_order_acquire, memory_order_acquire, memory_scope_work_group, __global, double, ATOMIC_F64, ulong);
+atomic_add(atom_release_cta_global_add_f64, memory_order_release, memory_order_acquire, memory_scope_work_group, __global, double, ATOMIC_F64, ulong);
+atomic_add(atom_acq_rel_cta_global_add_f64, memory_order_acq_rel, memory_order_acquire, memory_scope_work_group, __global, double, ATOMIC_F64, ulong);
+
+atomic_add(atom_relaxed_gpu_global_add_f64, memory_order_relaxed, memory_order_relaxed, memory_scope_device, __global, double, ATOMIC_F64, ulong);
+atomic_add(atom_acquire_gpu_global_add_f64, memory_order_acquire, memory_order_acquire, memory_scope_device, __global, double, ATOMIC_F64, ulong);
+atomic_add(atom_release_gpu_global_add_f64, memory_order_release, memory_order_acquire, memory_scope_device, __global, double, ATOMIC_F64, ulong);
+atomic_add(atom_acq_rel_gpu_global_add_f64, memory_order_acq_rel, memory_order_acquire, memory_scope_device, __global, double, ATOMIC_F64, ulong);
+
+atomic_add(atom_relaxed_sys_global_add_f64, memory_order_relaxed, memory_order_relaxed, memory_scope_device, __global, double, ATOMIC_F64, ulong);
+atomic_add(atom_acquire_sys_global_add_f64, memory_order_acquire, memory_order_acquire, memory_scope_device, __global, double, ATOMIC_F64, ulong);
+atomic_add(atom_release_sys_global_add_f64, memory_order_release, memory_order_acquire, memory_scope_device, __global, double, ATOMIC_F64, ulong);
+atomic_add(atom_acq_rel_sys_global_add_f64, memory_order_acq_rel, memory_order_acquire, memory_scope_device, __global, double, ATOMIC_F64, ulong);
+
+atomic_add(atom_relaxed_cta_shared_add_f64, memory_order_relaxed, memory_order_relaxed, memory_scope_work_group, __local, double, ATOMIC_F64, ulong);
+atomic_add(atom_acquire_cta_shared_add_f64, memory_order_acquire, memory_order_acquire, memory_scope_work_group, __local, double, ATOMIC_F64, ulong);
+atomic_add(atom_release_cta_shared_add_f64, memory_order_release, memory_order_acquire, memory_scope_work_group, __local, double, ATOMIC_F64, ulong);
+atomic_add(atom_acq_rel_cta_shared_add_f64, memory_order_acq_rel, memory_order_acquire, memory_scope_work_group, __local, double, ATOMIC_F64, ulong);
+
+atomic_add(atom_relaxed_gpu_shared_add_f64, memory_order_relaxed, memory_order_relaxed, memory_scope_device, __local, double, ATOMIC_F64, ulong);
+atomic_add(atom_acquire_gpu_shared_add_f64, memory_order_acquire, memory_order_acquire, memory_scope_device, __local, double, ATOMIC_F64, ulong);
+atomic_add(atom_release_gpu_shared_add_f64, memory_order_release, memory_order_acquire, memory_scope_device, __local, double, ATOMIC_F64, ulong);
+atomic_add(atom_acq_rel_gpu_shared_add_f64, memory_order_acq_rel, memory_order_acquire, memory_scope_device, __local, double, ATOMIC_F64, ulong);
+
+atomic_add(atom_relaxed_sys_shared_add_f64, memory_order_relaxed, memory_order_relaxed, memory_scope_device, __local, double, ATOMIC_F64, ulong);
+atomic_add(atom_acquire_sys_shared_add_f64, memory_order_acquire, memory_order_acquire, memory_scope_device, __local, double, ATOMIC_F64, ulong);
+atomic_add(atom_release_sys_shared_add_f64, memory_order_release, memory_order_acquire, memory_scope_device, __local, double, ATOMIC_F64, ulong);
+atomic_add(atom_acq_rel_sys_shared_add_f64, memory_order_acq_rel, memory_order_acquire, memory_scope_device, __local, double, ATOMIC_F64, ulong);
+
 uint FUNC(bfe_u32)(uint base, uint pos, uint len) {
     return intel_ubfe(base, pos, len);
 }
@@ -136,6 +254,85 @@ long FUNC(bfe_s64)(long base, uint pos, uint len) {
     return intel_sbfe(base, pos, len);
 }
 
+// wmma fragments hold pairs of .f16 elements in .b32 registers. PTX leaves
+// the fragment layout unspecified, we use the following one (l is the lane id):
+//  * .a: lane l holds row l % 16, register i holds columns 2i and 2i+1
+//  * .b: lane l holds column l % 16, register i holds rows 2i and 2i+1
+//  * .c and .d: lane l holds row l / 2, register i holds columns 8 * (l % 2) + 2i and 8 * (l % 2) + 2i+1
+// Lanes 16-31 of .a and .b fragments duplicate lanes 0-15
+// Positions are returned as (row0, col0, row1, col1)
+static uint wmma_lane() {
+    return get_local_linear_id() % 32;
+}
+
+static uint4 wmma_position_a(uint lane, uint index) {
+    uint row = lane % 16;
+    return (uint4)(row, 2 * index, row, 2 * index + 1);
+}
+
+static uint4 wmma_position_b(uint lane, uint index) {
+    uint col = lane % 16;
+    return (uint4)(2 * index, col, 2 * index + 1, col);
+}
+
+static uint4 wmma_position_c(uint lane, uint index) {
+    uint row = lane / 2;
+    uint col = 8 * (lane % 2) + 2 * index;
+    return (uint4)(row, col, row, col + 1);
+}
+
+static size_t wmma_offset_row(uint row, uint col, uint stride) {
+    return (size_t)row * stride + col;
+}
+
+static size_t wmma_offset_col(uint row, uint col, uint stride) {
+    return (size_t)col * stride + row;
+}
+
+#define wmma_load(NAME, MATRIX, LAYOUT, SPACE)                                                                                              \
+    uint FUNC(NAME)(SPACE ushort* ptr, uint stride, uint index) {                                                                           \
+        uint4 pos = wmma_position_ ## MATRIX(wmma_lane(), index);                                                                           \
+        ushort lo = ptr[wmma_offset_ ## LAYOUT(pos.x, pos.y, stride)];                                                                      \
+        ushort hi = ptr[wmma_offset_ ## LAYOUT(pos.z, pos.w, stride)];                                                                      \
+        return as_uint((ushort2)(lo, hi));                                                                                                  \
+    }
+
+#define wmma_store_d(NAME, LAYOUT, SPACE)                                                                                                   \
+    void FUNC(NAME)(SPACE ushort* ptr, uint value, uint stride, uint index) {                                                               \
+        uint4 pos = wmma_position_c(wmma_lane(), index);                                                                                    \
+        ushort2 halves = as_ushort2(value);                                                                                                 \
+        ptr[wmma_offset_ ## LAYOUT(pos.x, pos.y, stride)] = halves.x;                                                                       \
+        ptr[wmma_offset_ ## LAYOUT(pos.z, pos.w, stride)] = halves.y;                                                                       \
+    }
+
+wmma_load(wmma_load_a_row_m16n16k16_generic_f16, a, row, );
+wmma_load(wmma_load_a_row_m16n16k16_global_f16, a, row, __global);
+wmma_load(wmma_load_a_row_m16n16k16_shared_f16, a, row, __local);
+wmma_load(wmma_load_a_col_m16n16k16_generic_f16, a, col, );
+wmma_load(wmma_load_a_col_m16n16k16_global_f16, a, col, __global);
+wmma_load(wmma_load_a_col_m16n16k16_shared_f16, a, col, __local);
+
+wmma_load(wmma_load_b_row_m16n16k16_generic_f16, b, row, );
+wmma_load(wmma_load_b_row_m16n16k16_global_f16, b, row, __global);
+wmma_load(wmma_load_b_row_m16n16k16_shared_f16, b, row, __local);
+wmma_load(wmma_load_b_col_m16n16k16_generic_f16, b, col, );
+wmma_load(wmma_load_b_col_m16n16k16_global_f16, b, col, __global);
+wmma_load(wmma_load_b_col_m16n16k16_shared_f16, b, col, __local);
+
+wmma_load(wmma_load_c_row_m16n16k16_generic_f16, c, row, );
+wmma_load(wmma_load_c_row_m16n16k16_global_f16, c, row, __global);
+wmma_load(wmma_load_c_row_m16n16k16_shared_f16, c, row, __local);
+wmma_load(wmma_load_c_col_m16n16k16_generic_f16, c, col, );
+wmma_load(wmma_load_c_col_m16n16k16_global_f16, c, col, __global);
+wmma_load(wmma_load_c_col_m16n16k16_shared_f16, c, col, __local);
+
+wmma_store_d(wmma_store_d_row_m16n16k16_generic_f16, row, );
+wmma_store_d(wmma_store_d_row_m16n16k16_global_f16, row, __global);
+wmma_store_d(wmma_store_d_row_m16n16k16_shared_f16, row, __local);
+wmma_store_d(wmma_store_d_col_m16n16k16_generic_f16, col, );
+wmma_store_d(wmma_store_d_col_m16n16k16_global_f16, col, __global);
+wmma_store_d(wmma_store_d_col_m16n16k16_shared_f16, col, __local);
+
 void FUNC(__assertfail)(
     __private ulong* message,
     __private ulong* file,
@@ -144,3 +341,99 @@ void FUNC(__assertfail)(
     __private ulong* charSize
 ) {
 }
+
+// Device-side printf. The host points printf_buffer at a buffer that starts
+// with a header (bytes used, capacity) and prints its records once the kernel
+// is done. Every record is 8-byte aligned and laid out as:
+//  * uint record size, uint format size
+//  * format string with its terminating NUL, padded to 8 bytes
+//  * argument bytes, copied from the buffer built by the caller
+// Records that do not fit are dropped
+struct printf_buffer {
+    uint used;
+    uint capacity;
+    uchar data[];
+};
+
+__global struct printf_buffer* FUNC(printf_buffer) = 0;
+
+static uint printf_align(uint value, uint alignment) {
+    return (value + alignment - 1) & ~(alignment - 1);
+}
+
+// Arguments are laid out one after another, every argument aligned to its size.
+// Floating point values are promoted to double, pointers, strings and
+// arguments with l, ll, L, z, j or t length modifiers take 8 bytes, all other
+// arguments (including * width and precision) take 4 bytes
+static uint printf_arguments_size(__generic const char* format, __private uint* count) {
+    uint size = 0;
+    *count = 0;
+    while (*format != 0) {
+        if (*format++ != '%')
+            continue;
+        if (*format == '%') {
+            format++;
+            continue;
+        }
+        while (*format == '-' || *format == '+' || *format == ' ' || *format == '#' || *format == '0')
+            format++;
+        while ((*format >= '0' && *format <= '9') || *format == '.' || *format == '*') {
+            if (*format == '*') {
+                size = printf_align(size, 4) + 4;
+                (*count)++;
+            }
+            format++;
+        }
+        bool wide = false;
+        while (*format == 'h' || *format == 'l' || *format == 'L' || *format == 'z' || *format == 'j' || *format == 't') {
+            wide |= *format != 'h';
+            format++;
+        }
+        char conversion = *format;
+        if (conversion == 0)
+            break;
+        format++;
+        switch (conversion) {
+            case 'f': case 'F': case 'e': case 'E': case 'g': case 'G': case 'a': case 'A': case 'p': case 's':
+                wide = true;
+                break;
+        }
+        uint arg_size = wide ? 8 : 4;
+        size = printf_align(size, arg_size) + arg_size;
+        (*count)++;
+    }
+    return size;
+}
+
+void FUNC(vprintf)(
+    __private uint* result,
+    __private ulong* format,
+    __private ulong* arguments
+) {
+    __global struct printf_buffer* buffer = FUNC(printf_buffer);
+    __generic const char* format_ptr = (__generic const char*)*format;
+    __generic const uchar* arguments_ptr = (__generic const uchar*)*arguments;
+    if (buffer == 0 || format_ptr == 0) {
+        *result = (uint)-1;
+        return;
+    }
+    uint format_size = 0;
+    while (format_ptr[format_size++] != 0);
+    uint count;
+    uint arguments_size = printf_arguments_size(format_ptr, &count);
+    uint arguments_offset = printf_align(8 + format_size, 8);
+    uint record_size = arguments_offset + printf_align(arguments_size, 8);
+    uint offset = atomic_fetch_add_explicit((volatile __global atomic_uint*)&buffer->used, record_size, memory_order_relaxed, memory_scope_device);
+    if (offset + record_size > buffer->capacity || offset + record_size < offset) {
+        *result = (uint)-1;
+        return;
+    }
+    __global uchar* record = buffer->data + offset;
+    ((__global uint*)record)[0] = record_size;
+    ((__global uint*)record)[1] = format_size;
+    for (uint i = 0; i < format_size; i++)
+        record[8 + i] = format_ptr[i];
+    for (uint i = 0; i < arguments_size; i++)
+        record[arguments_offset + i] = arguments_ptr[i];
+    *result = count;
+}
@@ -25,6 +25,8 @@ quick_error! {
         ZeroDimensionArray {}
         ArrayInitalizer {}
         NonExternPointer {}
+        EmptyMemberMask {}
+        PartialBarrier {}
     }
 }
 
@@ -221,6 +223,129 @@ sub_enum!(SelpType {
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum BarDetails {
     SyncAligned,
+    Arrive,
+}
+
+#[derive(Copy, Clone)]
+pub struct VoteDetails {
+    pub mode: VoteMode,
+    pub negate_pred: bool,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum VoteMode {
+    Ballot,
+    All,
+    Any,
+}
+
+#[derive(Copy, Clone)]
+pub struct MembarDetails {
+    pub semantics: FenceSemantics,
+    pub scope: MemScope,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum FenceSemantics {
+    SequentiallyConsistent,
+    AcquireRelease,
+    Acquire,
+    Release,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum PrmtMode {
+    F4e,
+    B4e,
+    Rc8,
+    Ecl,
+    Ecr,
+    Rc16,
+}
+
+#[derive(Copy, Clone)]
+pub struct DpDetails {
+    pub a_signed: bool,
+    pub b_signed: bool,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum Dp2aMode {
+    Lo,
+    Hi,
+}
+
+// Only tex.1d.v4, on texture objects (unified texture mode) or on module-scope
+// texrefs
+#[derive(Copy, Clone)]
+pub struct TexDetails {
+    pub data_type: SizedScalarType,
+    pub coordinate_type: SizedScalarType,
+    // Image and sampler parameters of the kernel backing the texture, set
+    // during translation
+    pub texture: Option<(u32, u32)>,
+}
+
+// Only the full 32-bit forms of scalar video instructions, without byte or
+// half-word operand selection
+#[derive(Copy, Clone)]
+pub struct VideoDetails {
+    pub op: VideoOp,
+    pub d_signed: bool,
+    pub a_signed: bool,
+    pub b_signed: bool,
+    pub saturate: bool,
+    pub secondary: Option<VideoSecondaryOp>,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum VideoOp {
+    Add,
+    Sub,
+    AbsDiff,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum VideoSecondaryOp {
+    Add,
+    Min,
+    Max,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum TestpOp {
+    Finite,
+    Infinite,
+    Number,
+    NotANumber,
+    Normal,
+    Subnormal,
+}
+
+#[derive(Copy, Clone)]
+pub struct ShfDetails {
+    pub direction: ShfDirection,
+    pub mode: ShfMode,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum ShfDirection {
+    Left,
+    Right,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum ShfMode {
+    Clamp,
+    Wrap,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum ShflMode {
+    Up,
+    Down,
+    Bfly,
+    Idx,
 }
 
 pub trait UnwrapWithVec<E, To> {
@@ -262,6 +387,8 @@ pub struct Module<'a> {
 pub enum Directive<'a, P: ArgParams> {
     Variable(Variable<VariableType, P::Id>),
     Method(Function<'a, &'a str, Statement<P>>),
+    // Module-scope .texref, bound by the host with cuTexRefSetAddress
+    Texref(P::Id),
 }
 
 pub enum MethodDecl<'a, ID> {
@@ -276,6 +403,7 @@ pub type FnArgument<ID> = Variable<FnArgumentType, ID>;
 pub type KernelArgument<ID> = Variable<KernelArgumentType, ID>;
 
 pub struct Function<'a, ID, S> {
+    pub linking: LinkingDirective,
     pub func_directive: MethodDecl<'a, ID>,
     pub body: Option<Vec<S>>,
 }
@@ -568,6 +696,7 @@ pub enum Instruction<P: ArgParams> {
     Mul(MulDetails, Arg3<P>),
     Add(ArithDetails, Arg3<P>),
     Setp(SetpData, Arg4Setp<P>),
+    Set(SetData, Arg3<P>),
     SetpBool(SetpBoolData, Arg5Setp<P>),
     Not(BooleanType, Arg2<P>),
     Bra(BraData, Arg1<P>),
@@ -575,11 +704,14 @@ pub enum Instruction<P: ArgParams> {
     Cvta(CvtaDetails, Arg2<P>),
     Shl(ShlType, Arg3<P>),
     Shr(ShrType, Arg3<P>),
+    Shf(ShfDetails, Arg4<P>),
     St(StData, Arg2St<P>),
     Ret(RetData),
     Call(CallInst<P>),
     Abs(AbsDetails, Arg2<P>),
     Mad(MulDetails, Arg4<P>),
+    Mul24(Mul24Details, Arg3<P>),
+    Mad24(Mul24Details, Arg4<P>),
     Or(BooleanType, Arg3<P>),
     Sub(ArithDetails, Arg3<P>),
     Min(MinMaxDetails, Arg3<P>),
@@ -590,6 +722,7 @@ pub enum Instruction<P: ArgParams> {
     Bar(BarDetails, Arg1Bar<P>),
     Atom(AtomDetails, Arg3<P>),
     AtomCas(AtomCasDetails, Arg4<P>),
+    Red(AtomDetails, Arg2St<P>),
     Div(DivDetails, Arg3<P>),
     Sqrt(SqrtDetails, Arg2<P>),
     Rsqrt(RsqrtDetails, Arg2<P>),
@@ -598,12 +731,119 @@ pub enum Instruction<P: ArgParams> {
     Cos { flush_to_zero: bool, arg: Arg2<P> },
     Lg2 { flush_to_zero: bool, arg: Arg2<P> },
     Ex2 { flush_to_zero: bool, arg: Arg2<P> },
+    Tanh { arg: Arg2<P> },
     Clz { typ: BitType, arg: Arg2<P> },
     Brev { typ: BitType, arg: Arg2<P> },
     Popc { typ: BitType, arg: Arg2<P> },
+    Prmt { mode: Option<PrmtMode>, arg: Arg4<P> },
     Xor { typ: BooleanType, arg: Arg3<P> },
     Bfe { typ: IntType, arg: Arg4<P> },
+    Bfi { typ: BitType, arg: Arg5<P> },
+    Bfind { typ: IntType, shift_amount: bool, arg: Arg2<P> },
+    Szext { mode: ShfMode, typ: IntType, arg: Arg3<P> },
+    Bmsk { mode: ShfMode, arg: Arg3<P> },
+    Dp4a(DpDetails, Arg4<P>),
+    Dp2a(Dp2aMode, DpDetails, Arg4<P>),
+    Video(VideoDetails, ArgVideo<P>),
     Rem { typ: IntType, arg: Arg3<P> },
+    Sad { typ: IntType, arg: Arg4<P> },
+    Copysign { typ: FloatType, arg: Arg3<P> },
+    Testp { op: TestpOp, typ: FloatType, arg: Arg2<P> },
+    Shfl(ShflMode, ArgShfl<P>),
+    Vote(VoteDetails, Arg3<P>),
+    Membar(MembarDetails),
+    Nanosleep(Arg1Bar<P>),
+    Isspacep { space: IsspacepSpace, arg: Arg2<P> },
+    Prefetch(PrefetchDetails, Arg1Bar<P>),
+    AddC { typ: IntType, arg: ArgCarry<P> },
+    SubC { typ: IntType, arg: ArgCarry<P> },
+    WmmaLoad(WmmaLoadDetails, ArgWmmaLoad<P>),
+    WmmaStore(WmmaStoreDetails, ArgWmmaStore<P>),
+    // src1 is the texture object, src2 the coordinate
+    Tex(TexDetails, Arg3<P>),
+}
+
+impl<P: ArgParams> Instruction<P> {
+    // PTX mnemonic without modifiers. fence is parsed as membar, add.cc and
+    // sub.cc are reported as add and sub
+    pub fn opcode(&self) -> &'static str {
+        match self {
+            Instruction::Ld(..) => "ld",
+            Instruction::Mov(..) => "mov",
+            Instruction::Mul(..) => "mul",
+            Instruction::Add(..) => "add",
+            Instruction::Setp(..) | Instruction::SetpBool(..) => "setp",
+            Instruction::Set(..) => "set",
+            Instruction::Not(..) => "not",
+            Instruction::Bra(..) => "bra",
+            Instruction::Cvt(..) => "cvt",
+            Instruction::Cvta(..) => "cvta",
+            Instruction::Shl(..) => "shl",
+            Instruction::Shr(..) => "shr",
+            Instruction::Shf(..) => "shf",
+            Instruction::St(..) => "st",
+            Instruction::Ret(..) => "ret",
+            Instruction::Call(..) => "call",
+            Instruction::Abs(..) => "abs",
+            Instruction::Mad(..) => "mad",
+            Instruction::Mul24(..) => "mul24",
+            Instruction::Mad24(..) => "mad24",
+            Instruction::Or(..) => "or",
+            Instruction::Sub(..) => "sub",
+            Instruction::Min(..) => "min",
+            Instruction::Max(..) => "max",
+            Instruction::Rcp(..) => "rcp",
+            Instruction::And(..) => "and",
+            Instruction::Selp(..) => "selp",
+            Instruction::Bar(..) => "bar",
+            Instruction::Atom(..) | Instruction::AtomCas(..) => "atom",
+            Instruction::Red(..) => "red",
+            Instruction::Div(..) => "div",
+            Instruction::Sqrt(..) => "sqrt",
+            Instruction::Rsqrt(..) => "rsqrt",
+            Instruction::Neg(..) => "neg",
+            Instruction::Sin { .. } => "sin",
+            Instruction::Cos { .. } => "cos",
+            Instruction::Lg2 { .. } => "lg2",
+            Instruction::Ex2 { .. } => "ex2",
+            Instruction::Tanh { .. } => "tanh",
+            Instruction::Clz { .. } => "clz",
+            Instruction::Brev { .. } => "brev",
+            Instruction::Popc { .. } => "popc",
+            Instruction::Prmt { .. } => "prmt",
+            Instruction::Xor { .. } => "xor",
+            Instruction::Bfe { .. } => "bfe",
+            Instruction::Bfi { .. } => "bfi",
+            Instruction::Bfind { .. } => "bfind",
+            Instruction::Szext { .. } => "szext",
+            Instruction::Bmsk { .. } => "bmsk",
+            Instruction::Dp4a(..) => "dp4a",
+            Instruction::Dp2a(..) => "dp2a",
+            Instruction::Video(details, _) => match details.op {
+                VideoOp::Add => "vadd",
+                VideoOp::Sub => "vsub",
+                VideoOp::AbsDiff => "vabsdiff",
+            },
+            Instruction::Rem { .. } => "rem",
+            Instruction::Sad { .. } => "sad",
+            Instruction::Copysign { .. } => "copysign",
+            Instruction::Testp { .. } => "testp",
+            Instruction::Shfl(..) => "shfl",
+            Instruction::Vote(..) => "vote",
+            Instruction::Membar(..) => "membar",
+            Instruction::Nanosleep(..) => "nanosleep",
+            Instruction::Isspacep { .. } => "isspacep",
+            Instruction::Prefetch(details, _) if details.uniform => "prefetchu",
+            Instruction::Prefetch(..) => "prefetch",
+            Instruction::AddC { arg, .. } if arg.carry_in.is_some() => "addc",
+            Instruction::AddC { .. } => "add",
+            Instruction::SubC { arg, .. } if arg.carry_in.is_some() => "subc",
+            Instruction::SubC { .. } => "sub",
+            Instruction::WmmaLoad(..) => "wmma.load",
+            Instruction::WmmaStore(..) => "wmma.store",
+            Instruction::Tex(..) => "tex",
+        }
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -682,6 +922,58 @@ pub struct Arg4<P: ArgParams> {
     pub src3: P::Operand,
 }
 
+pub struct Arg5<P: ArgParams> {
+    pub dst: P::Operand,
+    pub src1: P::Operand,
+    pub src2: P::Operand,
+    pub src3: P::Operand,
+    pub src4: P::Operand,
+}
+
+pub struct ArgWmmaLoad<P: ArgParams> {
+    pub dst: Vec<P::Id>,
+    pub src1: P::Operand,
+    pub src2: P::Operand,
+}
+
+pub struct ArgWmmaStore<P: ArgParams> {
+    pub src1: P::Operand,
+    pub src2: Vec<P::Id>,
+    pub src3: P::Operand,
+}
+
+// src3 is the operand of the secondary operation, if there is one
+pub struct ArgVideo<P: ArgParams> {
+    pub dst: P::Operand,
+    pub src1: P::Operand,
+    pub src2: P::Operand,
+    pub src3: Option<P::Operand>,
+}
+
+// Carry flag is not a real register, so it's passed around as an implicitly
+// declared predicate register named CARRY_FLAG
+pub struct ArgCarry<P: ArgParams> {
+    pub dst: P::Operand,
+    pub carry_out: Option<P::Id>,
+    pub src1: P::Operand,
+    pub src2: P::Operand,
+    pub carry_in: Option<P::Id>,
+}
+
+pub const CARRY_FLAG: &str = "CC.CF";
+
+impl<'a> ArgCarry<ParsedArgParams<'a>> {
+    pub fn new(arg: Arg3<ParsedArgParams<'a>>, carry_in: bool, carry_out: bool) -> Self {
+        ArgCarry {
+            dst: arg.dst,
+            carry_out: if carry_out { Some(CARRY_FLAG) } else { None },
+            src1: arg.src1,
+            src2: arg.src2,
+            carry_in: if carry_in { Some(CARRY_FLAG) } else { None },
+        }
+    }
+}
+
 pub struct Arg4Setp<P: ArgParams> {
     pub dst1: P::Id,
     pub dst2: Option<P::Id>,
@@ -689,6 +981,20 @@ pub struct Arg4Setp<P: ArgParams> {
     pub src2: P::Operand,
 }
 
+// src1: value, src2: lane/offset, src3: packed clamp and segment mask,
+// src4: membermask. lane_id and warp_size are not written in PTX source,
+// parser fills them with %laneid and WARP_SZ
+pub struct ArgShfl<P: ArgParams> {
+    pub dst1: P::Id,
+    pub dst2: Option<P::Id>,
+    pub src1: P::Operand,
+    pub src2: P::Operand,
+    pub src3: P::Operand,
+    pub src4: P::Operand,
+    pub lane_id: P::Operand,
+    pub warp_size: P::Operand,
+}
+
 pub struct Arg5Setp<P: ArgParams> {
     pub dst1: P::Id,
     pub dst2: Option<P::Id>,
@@ -819,6 +1125,18 @@ pub enum MulIntControl {
     Wide,
 }
 
+#[derive(Copy, Clone)]
+pub struct Mul24Details {
+    pub typ: IntType,
+    pub control: Mul24Control,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Mul24Control {
+    Low,
+    High,
+}
+
 #[derive(PartialEq, Eq, Copy, Clone)]
 pub enum RoundingMode {
     NearestEven,
@@ -862,6 +1180,15 @@ pub enum SetpBoolPostOp {
     Xor,
 }
 
+// Only packed half comparisons are supported, every lane of dst is set to
+// 1.0 (for .f16x2) or 0xFFFF (for .u32/.s32) when the comparison is true
+pub struct SetData {
+    pub dst_type: ScalarType,
+    pub src_type: ScalarType,
+    pub flush_to_zero: Option<bool>,
+    pub cmp_op: SetpCompareOp,
+}
+
 pub struct SetpBoolData {
     pub typ: ScalarType,
     pub flush_to_zero: Option<bool>,
@@ -878,6 +1205,7 @@ pub enum CvtDetails {
     FloatFromFloat(CvtDesc<FloatType, FloatType>),
     IntFromFloat(CvtDesc<IntType, FloatType>),
     FloatFromInt(CvtDesc<FloatType, IntType>),
+    TruncatedFromFloat(CvtTruncatedDesc),
 }
 
 pub struct CvtIntToIntDesc {
@@ -894,6 +1222,35 @@ pub struct CvtDesc<Dst, Src> {
     pub src: Src,
 }
 
+// There are no native .bf16 and .tf32 types, results of the conversion are
+// bit patterns stored in .b16 and .b32 registers respectively
+pub struct CvtTruncatedDesc {
+    pub rounding: TruncatedRoundingMode,
+    pub dst: TruncatedFloatType,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub enum TruncatedRoundingMode {
+    NearestEven,
+    NearestAway,
+    Zero,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub enum TruncatedFloatType {
+    BF16,
+    TF32,
+}
+
+impl TruncatedFloatType {
+    pub fn bit_type(self) -> ScalarType {
+        match self {
+            TruncatedFloatType::BF16 => ScalarType::B16,
+            TruncatedFloatType::TF32 => ScalarType::B32,
+        }
+    }
+}
+
 impl CvtDetails {
     pub fn new_int_from_int_checked(
         saturate: bool,
@@ -976,6 +1333,19 @@ pub enum CvtaSize {
     U64,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum IsspacepSpace {
+    Global,
+    Local,
+    Shared,
+}
+
+#[derive(Copy, Clone)]
+pub struct PrefetchDetails {
+    pub state_space: LdStateSpace,
+    pub uniform: bool,
+}
+
 #[derive(PartialEq, Eq, Copy, Clone)]
 pub enum ShlType {
     B16,
@@ -1035,6 +1405,8 @@ pub enum MulDetails {
     Unsigned(MulUInt),
     Signed(MulSInt),
     Float(ArithFloat),
+    // Operands are .bf16 bit patterns stored in .b16 registers
+    BF16,
 }
 
 #[derive(Copy, Clone)]
@@ -1054,6 +1426,8 @@ pub enum ArithDetails {
     Unsigned(UIntType),
     Signed(ArithSInt),
     Float(ArithFloat),
+    // Operands are .bf16 bit patterns stored in .b16 registers
+    BF16,
 }
 
 #[derive(Copy, Clone)]
@@ -1142,6 +1516,8 @@ pub enum AtomSIntOp {
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum AtomFloatOp {
     Add,
+    Min,
+    Max,
 }
 
 #[derive(Copy, Clone)]
@@ -1173,6 +1549,43 @@ pub enum DivFloatKind {
     Rounding(RoundingMode),
 }
 
+// Only .m16n16k16 with .f16 elements is supported for now
+#[derive(Copy, Clone)]
+pub struct WmmaLoadDetails {
+    pub matrix: WmmaMatrix,
+    pub layout: WmmaLayout,
+    pub space: LdStateSpace,
+}
+
+#[derive(Copy, Clone)]
+pub struct WmmaStoreDetails {
+    pub layout: WmmaLayout,
+    pub space: LdStateSpace,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum WmmaMatrix {
+    A,
+    B,
+    C,
+}
+
+impl WmmaMatrix {
+    // Number of .b32 registers in a .m16n16k16 .f16 fragment
+    pub fn fragment_len(self) -> usize {
+        match self {
+            WmmaMatrix::A | WmmaMatrix::B => 8,
+            WmmaMatrix::C => 4,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum WmmaLayout {
+    Row,
+    Col,
+}
+
 pub enum NumsOrArrays<'a> {
     Nums(Vec<(&'a str, u32)>),
     Arrays(Vec<NumsOrArrays<'a>>),
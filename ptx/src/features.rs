@@ -0,0 +1,81 @@
+use crate::ast;
+use std::collections::HashSet;
+
+// Everything a module uses that decides whether it can be translated, it's
+// collected from the AST alone, without translating anything
+#[derive(Default)]
+pub struct FeatureSet {
+    // PTX mnemonics, see ast::Instruction::opcode(...)
+    pub instructions: HashSet<&'static str>,
+    // Scalar types of all declared variables and arguments
+    pub types: HashSet<ast::ScalarType>,
+}
+
+pub fn module_features(module: &ast::Module) -> FeatureSet {
+    let mut features = FeatureSet::default();
+    for directive in module.directives.iter() {
+        match directive {
+            ast::Directive::Variable(var) => {
+                features.add_type(&ast::Type::from(var.v_type.clone()));
+            }
+            ast::Directive::Method(method) => {
+                features.add_method_decl(&method.func_directive);
+                if let Some(body) = &method.body {
+                    features.add_statements(body);
+                }
+            }
+            ast::Directive::Texref(_) => {}
+        }
+    }
+    features
+}
+
+impl FeatureSet {
+    fn add_method_decl(&mut self, decl: &ast::MethodDecl<'_, &str>) {
+        match decl {
+            ast::MethodDecl::Kernel { in_args, .. } => {
+                for arg in in_args.iter() {
+                    if let ast::KernelArgumentType::Normal(_) = arg.v_type {
+                        self.add_type(&ast::Type::from(arg.v_type.clone()));
+                    }
+                }
+            }
+            ast::MethodDecl::Func(ret_vals, _, params) => {
+                for arg in ret_vals.iter().chain(params.iter()) {
+                    if let ast::FnArgumentType::Shared = arg.v_type {
+                        continue;
+                    }
+                    self.add_type(&arg.v_type.to_func_type());
+                }
+            }
+        }
+    }
+
+    fn add_statements(&mut self, statements: &[ast::Statement<ast::ParsedArgParams<'_>>]) {
+        for statement in statements {
+            match statement {
+                ast::Statement::Variable(var) => {
+                    self.add_type(&ast::Type::from(var.var.v_type.clone()));
+                }
+                ast::Statement::Instruction(_, inst) => {
+                    self.instructions.insert(inst.opcode());
+                }
+                ast::Statement::Block(block) => self.add_statements(block),
+                ast::Statement::Label(_) => {}
+            }
+        }
+    }
+
+    fn add_type(&mut self, typ: &ast::Type) {
+        let scalar = match typ {
+            ast::Type::Scalar(t) | ast::Type::Vector(t, _) | ast::Type::Array(t, _) => *t,
+            ast::Type::Pointer(pointer, _) => match pointer {
+                ast::PointerType::Scalar(t)
+                | ast::PointerType::Vector(t, _)
+                | ast::PointerType::Array(t, _)
+                | ast::PointerType::Pointer(t, _) => *t,
+            },
+        };
+        self.types.insert(scalar);
+    }
+}
@@ -26,17 +26,29 @@ lalrpop_mod!(
 );
 
 pub mod ast;
+mod features;
 #[cfg(test)]
 mod test;
 mod translate;
 
 pub use crate::ptx::ModuleParser;
+pub use features::module_features;
+pub use features::FeatureSet;
 pub use lalrpop_util::lexer::Token;
 pub use lalrpop_util::ParseError;
 pub use rspirv::dr::Error as SpirvError;
+pub use translate::parse_and_translate;
+pub use translate::parse_and_translate_with_options;
+pub use translate::parse_module_checked;
 pub use translate::to_spirv_module;
+pub use translate::to_spirv_module_with_options;
 pub use translate::KernelInfo;
+pub use translate::KernelTexture;
+pub use translate::ParseTranslateError;
 pub use translate::TranslateError;
+pub use translate::TranslateOptions;
+pub use translate::NSMID_GLOBAL;
+pub use translate::PRINTF_BUFFER_GLOBAL;
 
 pub(crate) fn without_none<T>(x: Vec<Option<T>>) -> Vec<T> {
     x.into_iter().filter_map(|x| x).collect()
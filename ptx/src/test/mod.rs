@@ -1,5 +1,10 @@
+use super::ast;
 use super::ptx;
+use super::KernelTexture;
 use super::TranslateError;
+use super::TranslateOptions;
+use rspirv::dr::Operand;
+use std::collections::HashSet;
 
 mod spirv_run;
 
@@ -47,3 +52,427 @@ fn vectorAdd_11_ptx() -> Result<(), TranslateError> {
     let vector_add = include_str!("vectorAdd_11.ptx");
     compile_and_assert(vector_add)
 }
+
+// Every prefix of a valid module must be either translated or rejected
+// with an error, without a panic escaping parse_and_translate. Prefixes
+// that end inside a block are never complete modules
+#[test]
+fn truncated_modules_do_not_panic() {
+    let modules = [
+        include_str!("vectorAdd_kernel64.ptx"),
+        include_str!("spirv_run/add.ptx"),
+        include_str!("spirv_run/call.ptx"),
+    ];
+    for module in modules.iter() {
+        assert!(crate::parse_and_translate(module).is_ok());
+        for (end, _) in module.char_indices() {
+            let prefix = &module[..end];
+            let result = crate::parse_and_translate(prefix);
+            if prefix.matches('{').count() > prefix.matches('}').count() {
+                assert!(result.is_err(), "{}", prefix);
+            }
+        }
+    }
+}
+
+#[test]
+fn vote_rejects_empty_membermask() {
+    let vote = include_str!("spirv_run/vote.ptx").replace("0x0000ffff", "0");
+    let mut errors = Vec::new();
+    assert!(ptx::ModuleParser::new().parse(&mut errors, &vote).is_err());
+}
+
+#[test]
+fn bfi_rejects_narrow_types() {
+    let bfi = include_str!("spirv_run/bfi.ptx");
+    let mut errors = Vec::new();
+    assert!(ptx::ModuleParser::new().parse(&mut errors, bfi).is_ok());
+    for narrow in [".b8", ".b16"].iter() {
+        let bfi = bfi.replace("bfi.b32", &format!("bfi{}", narrow));
+        let mut errors = Vec::new();
+        assert!(ptx::ModuleParser::new().parse(&mut errors, &bfi).is_err());
+    }
+}
+
+#[test]
+fn bar_sync_with_partial_count_is_reported() {
+    let bar_sync = include_str!("spirv_run/bar_sync.ptx").replace("0, %ntid.x", "0, 32");
+    let mut errors = Vec::new();
+    ptx::ModuleParser::new()
+        .parse(&mut errors, &bar_sync)
+        .unwrap();
+    assert!(matches!(errors[..], [ast::PtxError::PartialBarrier]));
+}
+
+#[test]
+fn irreducible_control_flow_is_rejected() {
+    let irreducible = include_str!("irreducible.ptx");
+    assert!(matches!(
+        compile_and_assert(irreducible),
+        Err(TranslateError::IrreducibleControlFlow)
+    ));
+}
+
+#[test]
+fn module_features_lists_instructions_and_types() {
+    let vector_add = include_str!("vectorAdd_kernel64.ptx");
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new()
+        .parse(&mut errors, vector_add)
+        .unwrap();
+    let features = crate::module_features(&ast);
+    let mut instructions = features.instructions.into_iter().collect::<Vec<_>>();
+    instructions.sort();
+    assert_eq!(
+        instructions,
+        ["add", "bra", "cvt", "ld", "mov", "mul", "not", "ret", "setp", "shl", "st"]
+    );
+    for typ in [
+        ast::ScalarType::Pred,
+        ast::ScalarType::F32,
+        ast::ScalarType::B32,
+        ast::ScalarType::B64,
+        ast::ScalarType::U32,
+        ast::ScalarType::U64,
+    ]
+    .iter()
+    {
+        assert!(features.types.contains(typ));
+    }
+    assert!(!features.types.contains(&ast::ScalarType::F64));
+}
+
+#[test]
+fn noreturn_function_is_accepted() -> Result<(), TranslateError> {
+    let noreturn = include_str!("noreturn.ptx");
+    compile_and_assert(noreturn)
+}
+
+// Every block of the kernel branches to the next one and every function calls
+// the next one, none of it should be walked recursively
+#[test]
+fn long_chains_do_not_overflow_the_stack() -> Result<(), TranslateError> {
+    const BLOCKS: usize = 10000;
+    const FUNCTIONS: usize = 2000;
+    let mut ptx = String::from(".version 6.5\n.target sm_30\n.address_size 64\n");
+    ptx.push_str(&format!(".func chain_{}()\n{{\nret;\n}}\n", FUNCTIONS - 1));
+    for i in (0..FUNCTIONS - 1).rev() {
+        ptx.push_str(&format!(
+            ".func chain_{}()\n{{\ncall chain_{};\nret;\n}}\n",
+            i,
+            i + 1
+        ));
+    }
+    ptx.push_str(".visible .entry long_chain(.param .u64 output)\n{\n");
+    ptx.push_str(".reg .u64 out_addr;\n.reg .u64 temp;\n.reg .pred p;\n");
+    ptx.push_str("ld.param.u64 out_addr, [output];\nmov.u64 temp, 0;\ncall chain_0;\n");
+    for i in 0..BLOCKS {
+        ptx.push_str(&format!(
+            "setp.eq.u64 p, temp, {0};\n@p bra BLOCK_{0};\nadd.u64 temp, temp, 1;\nBLOCK_{0}:\n",
+            i
+        ));
+    }
+    ptx.push_str("st.u64 [out_addr], temp;\nret;\n}\n");
+    compile_and_assert(&ptx)
+}
+
+#[test]
+fn forced_subgroup_size_emits_execution_mode() -> Result<(), TranslateError> {
+    let vector_add = include_str!("vectorAdd_kernel64.ptx");
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new()
+        .parse(&mut errors, vector_add)
+        .unwrap();
+    let options = TranslateOptions {
+        subgroup_size: Some(32),
+        ..TranslateOptions::default()
+    };
+    let module = crate::to_spirv_module_with_options(ast, options)?;
+    assert_eq!(module.spirv.execution_modes.len(), 1);
+    let operands = &module.spirv.execution_modes[0].operands;
+    assert_eq!(
+        operands[1],
+        Operand::ExecutionMode(spirv::ExecutionMode::SubgroupSize)
+    );
+    assert_eq!(operands[2], Operand::LiteralInt32(32));
+    Ok(())
+}
+
+#[test]
+fn native_float_atomic_add_skips_ptx_impl() -> Result<(), TranslateError> {
+    let atom_add = include_str!("spirv_run/atom_add_f32.ptx");
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new()
+        .parse(&mut errors, atom_add)
+        .unwrap();
+    let options = TranslateOptions {
+        atomic_float32_add: true,
+        ..TranslateOptions::default()
+    };
+    let module = crate::to_spirv_module_with_options(ast, options)?;
+    assert!(module.should_link_ptx_impl.is_none());
+    let capability = Operand::Capability(spirv::Capability::AtomicFloat32AddEXT);
+    assert!(module
+        .spirv
+        .capabilities
+        .iter()
+        .any(|cap| cap.operands[0] == capability));
+    Ok(())
+}
+
+#[test]
+fn texref_is_not_emitted() -> Result<(), TranslateError> {
+    let ptx = "
+        .version 6.5
+        .target sm_30
+        .address_size 64
+
+        .global .texref tex;
+
+        .visible .entry noop()
+        {
+            ret;
+        }";
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx).unwrap();
+    let module = crate::to_spirv_module(ast)?;
+    assert!(module.texrefs.contains("tex"));
+    assert!(module.spirv.annotations.iter().all(
+        |inst| inst.operands[1] != Operand::Decoration(spirv::Decoration::LinkageAttributes)
+    ));
+    assert!(module.kernel_info["noop"].textures.is_empty());
+    Ok(())
+}
+
+#[test]
+fn tex_fetches_through_texref() -> Result<(), TranslateError> {
+    let ptx = "
+        .version 6.5
+        .target sm_30
+        .address_size 64
+
+        .global .texref tex;
+
+        .visible .entry fetch(
+            .param .u64 output
+        )
+        {
+            .reg .u64       out_addr;
+            .reg .s32       index;
+            .reg .f32       r<4>;
+
+            ld.param.u64    out_addr, [output];
+            mov.s32         index, 2;
+            tex.1d.v4.f32.s32 {r0, r1, r2, r3}, [tex, {index}];
+            st.global.f32   [out_addr], r0;
+            ret;
+        }";
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx).unwrap();
+    let module = crate::to_spirv_module(ast)?;
+    assert_eq!(
+        module.kernel_info["fetch"].textures,
+        vec![KernelTexture::Texref("tex".to_string())]
+    );
+    Ok(())
+}
+
+#[test]
+fn tex_samples_texture_object_param() -> Result<(), TranslateError> {
+    let ptx = "
+        .version 6.5
+        .target sm_30
+        .address_size 64
+
+        .visible .entry fetch(
+            .param .u64 output,
+            .param .u64 texture
+        )
+        {
+            .reg .u64       out_addr;
+            .reg .u64       tex_obj;
+            .reg .f32       coord;
+            .reg .f32       r<4>;
+
+            ld.param.u64    out_addr, [output];
+            ld.param.u64    tex_obj, [texture];
+            mov.f32         coord, 0f3FC00000;
+            tex.1d.v4.f32.f32 {r0, r1, r2, r3}, [tex_obj, {coord}];
+            tex.1d.v4.f32.f32 {r0, r1, r2, r3}, [tex_obj, {coord}];
+            st.global.f32   [out_addr], r0;
+            ret;
+        }";
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx).unwrap();
+    let module = crate::to_spirv_module(ast)?;
+    assert_eq!(
+        module.kernel_info["fetch"].textures,
+        vec![KernelTexture::Object(1)]
+    );
+    let capability = Operand::Capability(spirv::Capability::Sampled1D);
+    assert!(module
+        .spirv
+        .capabilities
+        .iter()
+        .any(|cap| cap.operands[0] == capability));
+    Ok(())
+}
+
+#[test]
+fn function_linkage_is_preserved() -> Result<(), TranslateError> {
+    let ptx = "
+        .version 6.5
+        .target sm_30
+        .address_size 64
+
+        .extern .func imported();
+        .extern .func imported();
+
+        .weak .func exported()
+        {
+            ret;
+        }
+
+        .func internal()
+        {
+            ret;
+        }
+
+        .visible .entry noop()
+        {
+            call imported;
+            call exported;
+            call internal;
+            ret;
+        }";
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx).unwrap();
+    let module = crate::to_spirv_module(ast)?;
+    let linkage = module
+        .spirv
+        .annotations
+        .iter()
+        .filter(|inst| {
+            inst.operands[1] == Operand::Decoration(spirv::Decoration::LinkageAttributes)
+        })
+        .map(|inst| (inst.operands[2].clone(), inst.operands[3].clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(linkage.len(), 2);
+    assert!(linkage.contains(&(
+        Operand::LiteralString("imported".to_string()),
+        Operand::LinkageType(spirv::LinkageType::Import)
+    )));
+    assert!(linkage.contains(&(
+        Operand::LiteralString("exported".to_string()),
+        Operand::LinkageType(spirv::LinkageType::Export)
+    )));
+    assert_eq!(
+        module
+            .spirv
+            .functions
+            .iter()
+            .filter(|f| f.blocks.is_empty())
+            .count(),
+        1
+    );
+    Ok(())
+}
+
+#[test]
+fn clock_without_shader_clock_is_rejected() {
+    let ptx = "
+        .version 6.5
+        .target sm_30
+        .address_size 64
+
+        .visible .entry clock(
+            .param .u64 output
+        )
+        {
+            .reg .u64 	    out_addr;
+            .reg .u64 	    clock;
+
+            ld.param.u64    out_addr, [output];
+            mov.u64         clock, %clock64;
+            st.u64          [out_addr], clock;
+            ret;
+        }";
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx).unwrap();
+    let options = TranslateOptions {
+        no_shader_clock: true,
+        ..TranslateOptions::default()
+    };
+    assert!(matches!(
+        crate::to_spirv_module_with_options(ast, options),
+        Err(TranslateError::MissingShaderClock)
+    ));
+}
+
+#[test]
+fn recursion_without_stack_calls_is_rejected() {
+    let ptx = include_str!("spirv_run/recursive_call.ptx");
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx).unwrap();
+    let options = TranslateOptions {
+        no_recursion: true,
+        ..TranslateOptions::default()
+    };
+    assert!(matches!(
+        crate::to_spirv_module_with_options(ast, options),
+        Err(TranslateError::MissingStackCalls)
+    ));
+}
+
+// zluda_ptx_impl.spv is built by hand from zluda_ptx_impl.cl (see the header
+// of the .cl file), this catches a .cl change checked in without the rebuild.
+// Names are taken from FUNC(...) outside of macro definitions and from the
+// first argument of the macros that define functions
+#[test]
+fn ptx_impl_spv_exports_every_cl_function() {
+    let cl = include_str!("../../lib/zluda_ptx_impl.cl");
+    let spv = include_bytes!("../../lib/zluda_ptx_impl.spv");
+    let macro_def = regex::Regex::new(r"^\s*#define (\w+)\(NAME\b").unwrap();
+    let macro_use = regex::Regex::new(r"^\s*(\w+)\((\w+),").unwrap();
+    let func = regex::Regex::new(r"FUNC\((\w+)\)").unwrap();
+    let mut macros = HashSet::new();
+    let mut expected = HashSet::new();
+    for line in cl.lines() {
+        if let Some(captures) = macro_def.captures(line) {
+            macros.insert(captures[1].to_string());
+            continue;
+        }
+        if line.trim_end().ends_with('\\') {
+            continue;
+        }
+        expected.extend(func.captures_iter(line).map(|c| c[1].to_string()));
+        if let Some(captures) = macro_use.captures(line) {
+            if macros.contains(&captures[1]) {
+                expected.insert(captures[2].to_string());
+            }
+        }
+    }
+    let module = rspirv::dr::load_bytes(&spv[..]).unwrap();
+    let exported = module
+        .annotations
+        .iter()
+        .filter(|inst| {
+            inst.operands.get(1) == Some(&Operand::Decoration(spirv::Decoration::LinkageAttributes))
+        })
+        .filter_map(|inst| match inst.operands.get(2) {
+            Some(Operand::LiteralString(name)) => Some(name.clone()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let mut missing = expected
+        .iter()
+        .map(|name| format!("__zluda_ptx_impl__{}", name))
+        .filter(|name| !exported.contains(name))
+        .collect::<Vec<_>>();
+    missing.sort();
+    assert!(
+        missing.is_empty(),
+        "zluda_ptx_impl.spv is out of date, missing: {:?}",
+        missing
+    );
+}
@@ -2,7 +2,7 @@ use crate::ptx;
 use crate::translate;
 use rspirv::{
     binary::{Assemble, Disassemble},
-    dr::{Block, Function, Instruction, Loader, Operand},
+    dr::{Block, Builder, Function, Instruction, Loader, Module, Operand},
 };
 use spirv_headers::Word;
 use spirv_tools_sys::{
@@ -15,6 +15,7 @@ use std::fmt::{Debug, Display, Formatter};
 use std::hash::Hash;
 use std::mem;
 use std::slice;
+use std::time::Duration;
 use std::{borrow::Cow, collections::HashMap, env, fs, path::PathBuf, ptr, str};
 use std::{cmp, collections::hash_map::Entry};
 
@@ -30,6 +31,13 @@ macro_rules! test_ptx {
             }
         }
 
+        test_spvtxt!($fn_name);
+    };
+}
+
+// Only the .spvtxt half of test_ptx, for tests that run the kernel themselves
+macro_rules! test_spvtxt {
+    ($fn_name:ident) => {
         paste::item! {
             #[test]
             fn [<$fn_name _spvtxt>]() -> Result<(), Box<dyn std::error::Error>> {
@@ -42,12 +50,46 @@ macro_rules! test_ptx {
     };
 }
 
+// Like test_ptx, but launches `$global_size` threads in groups of
+// `$group_size` and does not compare against a .spvtxt
+macro_rules! test_ptx_dims {
+    ($fn_name:ident, $input:expr, $output:expr, $global_size:expr, $group_size:expr) => {
+        paste::item! {
+            #[test]
+            fn [<$fn_name _ptx>]() -> Result<(), Box<dyn std::error::Error>> {
+                let ptx = include_str!(concat!(stringify!($fn_name), ".ptx"));
+                let input = $input;
+                let mut output = $output;
+                test_ptx_assert_dims(
+                    stringify!($fn_name),
+                    ptx,
+                    &input,
+                    &mut output,
+                    $global_size,
+                    Some($group_size),
+                )
+            }
+        }
+    };
+}
+
 test_ptx!(ld_st, [1u64], [1u64]);
 test_ptx!(ld_st_implicit, [0.5f32], [0.5f32]);
+test_ptx!(ld_st_volatile, [1u64], [1u64]);
+test_ptx!(ld_st_cs, [1u64], [1u64]);
+// Input and output buffers have different element types and lengths
+test_ptx!(
+    ld_st_reinterpret,
+    [0x01u8, 0x02u8, 0x03u8, 0x04u8],
+    [0x04030201u32]
+);
+test_ptx!(st_vec_member, [1f32, 2f32, 3f32, 4f32], [3f32]);
+test_ptx!(ld_vec_member, [1u32, 2u32], [2u32, 1u32]);
 test_ptx!(mov, [1u64], [1u64]);
 test_ptx!(mul_lo, [1u64], [2u64]);
 test_ptx!(mul_hi, [u64::max_value()], [1u64]);
 test_ptx!(add, [1u64], [2u64]);
+test_ptx!(add_bf16, [0x3FC0u16, 0x4010u16], [0x4070u16]);
 test_ptx!(setp, [10u64, 11u64], [1u64, 0u64]);
 test_ptx!(setp_gt, [f32::NAN, 1f32], [1f32]);
 test_ptx!(setp_leu, [1f32, f32::NAN], [1f32]);
@@ -107,6 +149,12 @@ test_ptx!(shared_ptr_32, [513u64], [513u64]);
 test_ptx!(atom_cas, [91u32, 91u32], [91u32, 100u32]);
 test_ptx!(atom_inc, [100u32], [100u32, 101u32, 0u32]);
 test_ptx!(atom_add, [2u32, 4u32], [2u32, 6u32]);
+test_ptx!(atom_exch, [100u32, 200u32], [200u32, 100u32]);
+test_ptx!(atom_add_f32, [1.25f32, 0.5f32], [1.25f32, 1.75f32]);
+test_ptx!(atom_min_f32, [1.25f32, 0.5f32], [1.25f32, 0.5f32]);
+test_ptx!(atom_min_s32, [5i32, -3i32], [5i32, -3i32]);
+// 0xFFFFFFFF is the largest unsigned value, but it would be -1 if compared as signed
+test_ptx!(atom_max_u32, [1u32, 0xFFFFFFFFu32], [1u32, 0xFFFFFFFFu32]);
 test_ptx!(div_approx, [1f32, 2f32], [0.5f32]);
 test_ptx!(sqrt, [0.25f32], [0.5f32]);
 test_ptx!(rsqrt, [0.25f64], [2f64]);
@@ -115,9 +163,33 @@ test_ptx!(sin, [std::f32::consts::PI / 2f32], [1f32]);
 test_ptx!(cos, [std::f32::consts::PI], [-1f32]);
 test_ptx!(lg2, [512f32], [9f32]);
 test_ptx!(ex2, [10f32], [1024f32]);
+// Bit patterns: negative denormal, negative NaN with a payload, -5
+test_ptx!(
+    abs,
+    [0x80000001u32, 0xFFC00001u32, 0xFFFFFFFBu32],
+    [0x00000001u32, 0x7FC00001u32, 5u32]
+);
+// Bit patterns: sign of -0.0 onto a NaN with a payload, sign of -NaN onto 2.0
+test_ptx!(
+    copysign,
+    [0x80000000u32, 0x7FC00001u32, 0xFFC00000u32, 0x40000000u32],
+    [0xFFC00001u32, 0xC0000000u32]
+);
+// Denormal input is flushed before and denormal result after the exp2
+test_ptx!(ex2_ftz, [f32::from_bits(1), -130f32], [1f32, 0f32]);
 test_ptx!(cvt_rni, [9.5f32, 10.5f32], [10f32, 10f32]);
 test_ptx!(cvt_rzi, [-13.8f32, 12.9f32], [-13f32, 12f32]);
 test_ptx!(cvt_s32_f32, [-13.8f32, 12.9f32], [-13i32, 13i32]);
+test_ptx!(
+    cvt_bf16,
+    [0x3F818000u32, 0x3F81FFFFu32],
+    [0x3F82u16, 0x3F81u16]
+);
+test_ptx!(
+    cvt_tf32,
+    [0x3F801000u32, 0x3F801000u32],
+    [0x3F802000u32, 0x3F800000u32]
+);
 test_ptx!(clz, [0b00000101_00101101_00010011_10101011u32], [5u32]);
 test_ptx!(popc, [0b10111100_10010010_01001001_10001010u32], [14u32]);
 test_ptx!(
@@ -147,6 +219,102 @@ test_ptx!(shared_ptr_take_address, [97815231u64], [97815231u64]);
 // For now, we just make sure that it builds and links
 test_ptx!(assertfail, [716523871u64], [716523872u64]);
 test_ptx!(cvt_s64_s32, [-1i32], [-1i64]);
+test_ptx!(membar, [123u64], [123u64]);
+// Splits a double into 32-bit halves and joins them back in swapped order
+test_ptx!(
+    mov_b64_split,
+    [0x0123456789ABCDEFu64],
+    [0x89ABCDEF01234567u64, 0x0123456789ABCDEFu64]
+);
+// Unpacks halves (2.0, 1.0) into separate registers and packs them back swapped
+test_ptx!(mov_f16x2_split, [0x3C004000u32], [0x40003C00u32, 0x3C004000u32]);
+// Lanes of a are (1.0, 3.0) and lanes of b are (2.0, 0.5), so every lane has
+// a different result. Outputs are add, mul, fma, both setp.lt predicates,
+// set.lt.u32 and set.gt.f16x2
+test_ptx!(
+    f16x2,
+    [0x42003C00u32, 0x38004000u32],
+    [
+        0x43004200u32,
+        0x3E004000,
+        0x44804200,
+        1,
+        0,
+        0x0000FFFF,
+        0x3C000000,
+    ]
+);
+// Input halves are the smallest subnormal and 1/3, followed by f32 infinity,
+// NaN, a value exactly halfway between two halves and 1/3. Every half is
+// converted up and back down, the tie shows the difference between .rn and .rz
+test_ptx!(
+    cvt_f16,
+    [
+        0x35550001u32,
+        0x7F800000,
+        0x7FC00000,
+        0x3F8FF000,
+        0x3EAAAAAB,
+    ],
+    [
+        0x33800000u32,
+        0x35550001,
+        0x35557C00,
+        0x7F800000,
+        0x7FC00000,
+        0x3C7F3C80,
+    ]
+);
+// Every input is classified by all six testp variants, output bits are in
+// the order finite, infinite, number, notanumber, normal and subnormal.
+// Inputs are NaN, -inf, the smallest subnormal, 1.0, zero and the largest
+// negative subnormal
+test_ptx!(
+    testp,
+    [
+        0x7FC00000u32,
+        0xFF800000,
+        0x00000001,
+        0x3F800000,
+        0x00000000,
+        0x807FFFFF,
+    ],
+    [8u32, 6, 37, 21, 5, 37]
+);
+// The first call takes the full path through the function, the second one
+// takes the guarded early return
+test_ptx!(pred_ret, [5u64, 20u64], [6u64, 20u64]);
+// Both the carry out of the low half and the borrow out of the low half must
+// propagate into the high half
+test_ptx!(add_cc, [u64::MAX, 5u64, 1u64, 2u64], [0u64, 8u64, 2u64, u64::MAX - 3]);
+// Stores both a plain and a stateful (cvta) address, the latter has to be
+// converted back to an integer before it's written out
+test_ptx!(global_ptr_array, [21u64], [42u64]);
+// -3 < 4 when signed, but 0xFFFFFFFD > 4 when unsigned and the sum wraps around
+test_ptx!(sad, [-3i32 as u32, 4u32, 10u32], [17u32, 3u32]);
+test_ptx!(nanosleep, [5u32], [1005u32]);
+test_ptx!(call_param_ret, [17u64, 5u64], [3u64, 2u64]);
+// Outputs are vabsdiff.u32.u32.u32, vabsdiff.u32.s32.s32 and the first one
+// with .add. -2 is a large number when read as unsigned
+test_ptx!(vabsdiff, [3u32, -2i32 as u32, 10], [0xFFFFFFFBu32, 5, 17]);
+// Without .sat the unsigned sum wraps around, with .sat it clamps to the range
+// of the destination type, even if the sources are signed. The secondary .max
+// is applied after saturation
+test_ptx!(
+    vadd_sat,
+    [0xFFFFFFF0u32, 0x20, -5i32 as u32, i32::MIN as u32],
+    [0x10u32, u32::MAX, i32::MIN as u32, 0, -5i32 as u32]
+);
+// Widths are 8, 8, 0, 40 (clamped to 32) and 32 (wrapped to 0)
+test_ptx!(szext, [0x800000F5u32], [0xFFFFFFF5u32, 0xF5, 0, 0x800000F5, 0]);
+// (start, width) pairs are (4, 8), (4, 0), (0, 32), (4, 40) where the width
+// is clamped to 32 and the mask cut off at the top of the word, and (40, 8)
+// where the start wraps around to 8
+test_ptx!(bmsk, [4u32, 8], [0xFF0u32, 0, 0xFFFFFFFF, 0xFFFFFFF0, 0xFF00]);
+// The first two elements are the data and the flag written by thread 0, the
+// rest are the data as seen by each thread
+test_ptx_dims!(fence_ordering, [0x1234u32], [0x1234u32; 2 + 256], 256, 64);
+test_spvtxt!(fence_ordering);
 
 struct DisplayError<T: Debug> {
     err: T,
@@ -168,42 +336,1004 @@ impl<T: Debug> error::Error for DisplayError<T> {}
 
 fn test_ptx_assert<
     'a,
-    Input: From<u8> + ze::SafeRepr + Debug + Copy + PartialEq,
+    Input: ze::SafeRepr + Debug + Copy + PartialEq,
     Output: From<u8> + ze::SafeRepr + Debug + Copy + PartialEq,
 >(
     name: &str,
     ptx_text: &'a str,
     input: &[Input],
     output: &mut [Output],
+) -> Result<(), Box<dyn error::Error + 'a>> {
+    test_ptx_assert_dims(name, ptx_text, input, output, 1, None)
+}
+
+fn test_ptx_assert_dims<
+    'a,
+    Input: ze::SafeRepr + Debug + Copy + PartialEq,
+    Output: From<u8> + ze::SafeRepr + Debug + Copy + PartialEq,
+>(
+    name: &str,
+    ptx_text: &'a str,
+    input: &[Input],
+    output: &mut [Output],
+    global_size: u32,
+    group_size: Option<u32>,
 ) -> Result<(), Box<dyn error::Error + 'a>> {
     let mut errors = Vec::new();
     let ast = ptx::ModuleParser::new().parse(&mut errors, ptx_text)?;
     assert!(errors.len() == 0);
     let zluda_module = translate::to_spirv_module(ast)?;
     let name = CString::new(name)?;
-    let result = run_spirv(name.as_c_str(), zluda_module, input, output)
-        .map_err(|err| DisplayError { err })?;
+    let result = run_spirv(
+        name.as_c_str(),
+        zluda_module,
+        input,
+        output,
+        global_size,
+        group_size,
+    )
+    .map_err(|err| DisplayError { err })?;
     assert_eq!(result.as_slice(), output);
     Ok(())
 }
 
-fn run_spirv<
-    Input: From<u8> + ze::SafeRepr + Copy + Debug,
+fn count_instructions(module: &Module, opcode: spirv_headers::Op) -> usize {
+    module
+        .functions
+        .iter()
+        .flat_map(|f| f.blocks.iter())
+        .flat_map(|b| b.instructions.iter())
+        .filter(|inst| inst.class.opcode == opcode)
+        .count()
+}
+
+#[test]
+fn suggested_group_size() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("suggested_group_size.ptx");
+    let input = (0..1024u32).collect::<Vec<_>>();
+    let mut output = vec![0u32; input.len()];
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    let name = CString::new("suggested_group_size")?;
+    let result = run_spirv(
+        name.as_c_str(),
+        zluda_module,
+        &input,
+        &mut output,
+        input.len() as u32,
+        None,
+    )
+    .map_err(|err| DisplayError { err })?;
+    let expected = (1..1025u32).collect::<Vec<_>>();
+    assert_eq!(result, expected);
+    Ok(())
+}
+
+test_spvtxt!(suggested_group_size);
+
+// Kernel creation fails after the context, queue and module are created, they
+// must be torn down cleanly and leave the device usable
+#[test]
+fn run_spirv_error_tears_down() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("add.ptx");
+    let input = [1u64];
+    let mut output = [0u64];
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    let name = CString::new("not_a_kernel")?;
+    let result = run_spirv(name.as_c_str(), zluda_module, &input, &mut output, 1, None);
+    assert!(result.is_err());
+    test_ptx_assert("add", ptx, &input, &mut [2u64])
+}
+
+// Fragments are distributed across the lanes of a warp, so this kernel must
+// run with exactly 32 threads in a group
+#[test]
+fn wmma_load_store() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("wmma_load_store.ptx");
+    let input = (0..256u16).collect::<Vec<_>>();
+    let mut output = vec![0u16; input.len()];
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    let name = CString::new("wmma_load_store")?;
+    let result = run_spirv(
+        name.as_c_str(),
+        zluda_module,
+        &input,
+        &mut output,
+        32,
+        Some(32),
+    )
+    .map_err(|err| DisplayError { err })?;
+    // The matrix is loaded column-major and stored row-major
+    let expected = (0..256u16)
+        .map(|i| (i % 16) * 16 + i / 16)
+        .collect::<Vec<_>>();
+    assert_eq!(result, expected);
+    Ok(())
+}
+
+test_spvtxt!(wmma_load_store);
+
+// WARP_SZ is the subgroup size chosen by the compiler, pin it to each size
+// the device supports and check that exact size is reported back
+#[test]
+fn warp_sz() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("warp_sz.ptx");
+    let sub_group_sizes = device_subgroup_sizes().map_err(|err| DisplayError { err })?;
+    assert!(sub_group_sizes.len() > 0);
+    for size in sub_group_sizes {
+        let input = [0u32];
+        let mut output = [0u32];
+        let mut errors = Vec::new();
+        let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+        assert!(errors.len() == 0);
+        let zluda_module = translate::to_spirv_module_with_options(
+            ast,
+            translate::TranslateOptions {
+                subgroup_size: Some(size),
+                ..translate::TranslateOptions::default()
+            },
+        )?;
+        let name = CString::new("warp_sz")?;
+        let result = run_spirv(name.as_c_str(), zluda_module, &input, &mut output, 1, None)
+            .map_err(|err| DisplayError { err })?;
+        assert_eq!(result[0], size);
+    }
+    Ok(())
+}
+
+test_spvtxt!(warp_sz);
+
+fn device_subgroup_sizes() -> ze::Result<Vec<u32>> {
+    ze::init()?;
+    let mut drivers = ze::Driver::get()?;
+    let drv = drivers.drain(0..1).next().unwrap();
+    let mut devices = drv.devices()?;
+    let dev = devices.drain(0..1).next().unwrap();
+    let props = dev.get_compute_properties()?;
+    Ok(props.subGroupSizes[..props.numSubGroupSizes as usize].to_vec())
+}
+
+// Large arguments go through range reduction, which is only accurate within
+// a tolerance
+#[test]
+fn sin_cos_large_angle() -> Result<(), Box<dyn error::Error>> {
+    let angle = 10000f32;
+    let cases = [
+        ("sin", include_str!("sin.ptx"), (angle as f64).sin()),
+        ("cos", include_str!("cos.ptx"), (angle as f64).cos()),
+    ];
+    for (name, ptx, expected) in cases.iter() {
+        let mut output = [0f32];
+        let mut errors = Vec::new();
+        let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+        assert!(errors.len() == 0);
+        let zluda_module = translate::to_spirv_module(ast)?;
+        let kernel_name = CString::new(*name)?;
+        let result = run_spirv(
+            kernel_name.as_c_str(),
+            zluda_module,
+            &[angle],
+            &mut output,
+            1,
+            None,
+        )
+        .map_err(|err| DisplayError { err })?;
+        assert!(
+            (result[0] as f64 - expected).abs() < 1e-5,
+            "{}({}) = {}, expected {}",
+            name,
+            angle,
+            result[0],
+            expected
+        );
+    }
+    Ok(())
+}
+
+// tanh.approx is only accurate within a tolerance, but zero must stay exact
+// and large inputs must saturate to exactly 1 or -1
+#[test]
+fn tanh_approx() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("tanh_approx.ptx");
+    let input = [0f32, 0.5, -1.0, 100.0, -100.0];
+    let mut output = [0f32; 5];
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    let name = CString::new("tanh_approx")?;
+    let result = run_spirv(name.as_c_str(), zluda_module, &input, &mut output, 1, None)
+        .map_err(|err| DisplayError { err })?;
+    for (x, actual) in input.iter().zip(result.iter()) {
+        let expected = (*x as f64).tanh();
+        assert!(
+            (*actual as f64 - expected).abs() < 1e-6,
+            "tanh({}) = {}, expected {}",
+            x,
+            actual,
+            expected
+        );
+    }
+    assert_eq!(result[0].to_bits(), 0);
+    assert_eq!(result[3], 1.0);
+    assert_eq!(result[4], -1.0);
+    Ok(())
+}
+
+test_spvtxt!(tanh_approx);
+
+// rcp.approx is only accurate within a tolerance. A result that would be
+// denormal is flushed to zero and so is a denormal input, keeping its sign
+#[test]
+fn rcp_approx_f64() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("rcp_approx_f64.ptx");
+    let input = [3f64, 1e308f64, -f64::from_bits(1)];
+    let mut output = [0f64; 3];
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    let name = CString::new("rcp_approx_f64")?;
+    let result = run_spirv(name.as_c_str(), zluda_module, &input, &mut output, 1, None)
+        .map_err(|err| DisplayError { err })?;
+    assert!((result[0] - 1f64 / 3f64).abs() < 1e-12, "{}", result[0]);
+    assert_eq!(result[1], 0f64);
+    assert_eq!(result[2], std::f64::NEG_INFINITY);
+    Ok(())
+}
+
+test_spvtxt!(rcp_approx_f64);
+
+// Every thread sets its own bit in the same word and gets back the bits set by
+// the threads that came before it, so all returned values are different
+#[test]
+fn atom_or() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("atom_or.ptx");
+    let input = [0u32];
+    let mut output = [0u32; 9];
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    let name = CString::new("atom_or")?;
+    let result = run_spirv(
+        name.as_c_str(),
+        zluda_module,
+        &input,
+        &mut output,
+        8,
+        Some(8),
+    )
+    .map_err(|err| DisplayError { err })?;
+    assert_eq!(result[0], 0xFF);
+    for (tid, old) in result[1..].iter().enumerate() {
+        assert_eq!(old & !0xFF, 0, "{}", tid);
+        assert_eq!(old & (1 << tid), 0, "{}", tid);
+    }
+    let mut old_values = result[1..].to_vec();
+    old_values.sort();
+    old_values.dedup();
+    assert_eq!(old_values.len(), 8);
+    Ok(())
+}
+
+test_spvtxt!(atom_or);
+
+// Every thread reads the value its neighbour stored in shared memory, which
+// is only visible after the barrier
+#[test]
+fn bar_sync() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("bar_sync.ptx");
+    let input = (0..8u32).collect::<Vec<_>>();
+    let mut output = vec![0u32; input.len()];
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    let name = CString::new("bar_sync")?;
+    let result = run_spirv(
+        name.as_c_str(),
+        zluda_module,
+        &input,
+        &mut output,
+        8,
+        Some(8),
+    )
+    .map_err(|err| DisplayError { err })?;
+    assert_eq!(result, [1u32, 0, 3, 2, 5, 4, 7, 6]);
+    Ok(())
+}
+
+test_spvtxt!(bar_sync);
+
+// div.rn must be correctly rounded, so it matches host division bit for bit,
+// also when the result or one of the operands is denormal
+#[test]
+fn div_rn() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("div_rn.ptx");
+    let input = [
+        1f32,
+        3f32,
+        7f32,
+        10f32,
+        16777213f32,
+        0.7f32,
+        f32::MIN_POSITIVE,
+        3f32,
+        f32::from_bits(3),
+        0.75f32,
+    ];
+    let mut output = [0f32; 5];
+    for (expected, operands) in output.iter_mut().zip(input.chunks(2)) {
+        *expected = operands[0] / operands[1];
+    }
+    assert!(!output[3].is_normal() && output[3] != 0f32);
+    assert_eq!(output[4], f32::from_bits(4));
+    test_ptx_assert("div_rn", ptx, &input, &mut output)
+}
+
+test_spvtxt!(div_rn);
+
+// div.full is allowed up to 2 ulp of error
+#[test]
+fn div_full() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("div_full.ptx");
+    let input = [
+        1f32,
+        3f32,
+        7f32,
+        10f32,
+        16777213f32,
+        0.7f32,
+        -5f32,
+        9f32,
+        1e30f32,
+        1e-7f32,
+    ];
+    let mut output = [0f32; 5];
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    let name = CString::new("div_full")?;
+    let result = run_spirv(name.as_c_str(), zluda_module, &input, &mut output, 1, None)
+        .map_err(|err| DisplayError { err })?;
+    for (result, operands) in result.iter().zip(input.chunks(2)) {
+        let expected = operands[0] / operands[1];
+        let ulp_error = (result.to_bits() as i64 - expected.to_bits() as i64).abs();
+        assert!(
+            ulp_error <= 2,
+            "{} / {} = {}",
+            operands[0],
+            operands[1],
+            result
+        );
+    }
+    Ok(())
+}
+
+test_spvtxt!(div_full);
+
+// Clock values are unpredictable, beyond building and running the only thing
+// to check is that the 64-bit clock does not go backwards
+#[test]
+fn clock() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("clock.ptx");
+    let input = [0u64];
+    let mut output = [0u64; 3];
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    assert_eq!(
+        count_instructions(&zluda_module.spirv, spirv_headers::Op::ReadClockKHR),
+        3
+    );
+    let name = CString::new("clock")?;
+    let result = run_spirv(name.as_c_str(), zluda_module, &input, &mut output, 1, None)
+        .map_err(|err| DisplayError { err })?;
+    assert!(result[2] >= result[1]);
+    Ok(())
+}
+
+test_spvtxt!(clock);
+
+// Lanes and warps follow the subgroup size picked by the device, which is not
+// necessarily 32
+#[test]
+fn laneid() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("laneid.ptx");
+    let input = [0u32];
+    let mut output = [0u32; 256];
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    let name = CString::new("laneid")?;
+    let result = run_spirv(
+        name.as_c_str(),
+        zluda_module,
+        &input,
+        &mut output,
+        64,
+        Some(64),
+    )
+    .map_err(|err| DisplayError { err })?;
+    let warp_size = result[192] as usize;
+    assert!(result[192..].iter().all(|size| *size as usize == warp_size));
+    for tid in 0..64 {
+        assert_eq!(result[tid] as usize, tid % warp_size, "{}", tid);
+        assert_eq!(result[64 + tid] as usize, tid / warp_size, "{}", tid);
+        assert_eq!(result[128 + tid] as usize, 64 / warp_size, "{}", tid);
+    }
+    Ok(())
+}
+
+test_spvtxt!(laneid);
+
+// Nothing sets the %nsmid global here, so it keeps its initial value
+#[test]
+fn smid() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("smid.ptx");
+    let input = [0u32];
+    let mut output = [u32::MAX; 2];
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    assert!(zluda_module.uses_nsmid);
+    let name = CString::new("smid")?;
+    let result = run_spirv(name.as_c_str(), zluda_module, &input, &mut output, 1, None)
+        .map_err(|err| DisplayError { err })?;
+    assert!(result[0] < result[1], "{:?}", result);
+    Ok(())
+}
+
+test_spvtxt!(smid);
+
+#[test]
+fn bench_add() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("add.ptx");
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    let name = CString::new("add")?;
+    let timings = bench_spirv(name.as_c_str(), zluda_module, &[1u64], 10)
+        .map_err(|err| DisplayError { err })?;
+    assert_eq!(timings.len(), 10);
+    // A single-thread add can't take zero time or anywhere near a second
+    for timing in timings.iter() {
+        assert!(
+            *timing > Duration::from_nanos(0) && *timing < Duration::from_secs(1),
+            "{:?}",
+            timings
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn extern_func() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("extern_func.ptx");
+    let lib_ptx = include_str!("extern_func_lib.ptx");
+    let input = [11u64];
+    let mut output = [0u64];
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    let lib_ast = ptx::ModuleParser::new().parse(&mut errors, lib_ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    let lib_module = translate::to_spirv_module(lib_ast)?;
+    let name = CString::new("extern_func")?;
+    let result = run_spirv_linked(
+        name.as_c_str(),
+        zluda_module,
+        &[lib_module],
+        &input,
+        &mut output,
+        1,
+        None,
+    )
+    .map_err(|err| DisplayError { err })?;
+    assert_eq!(result, [12u64]);
+    Ok(())
+}
+
+test_spvtxt!(extern_func);
+test_spvtxt!(extern_func_lib);
+
+// Rows are rmi, rpi and rni; the halfway value -2.5 rounds to the even -2
+#[test]
+fn cvt_rmi_rpi() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("cvt_rmi_rpi.ptx");
+    let input = [-2.5f32, -1.2f32, 2.5f32];
+    let mut output = [-3i32, -2, 2, -2, -1, 3, -2, -1, 2];
+    test_ptx_assert("cvt_rmi_rpi", ptx, &input, &mut output)
+}
+test_spvtxt!(cvt_rmi_rpi);
+
+#[test]
+fn cvt_sat_u8_s32() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("cvt_sat_u8_s32.ptx");
+    let input = [-300i32, 200, 300];
+    let mut output = [0u32, 200, 255];
+    test_ptx_assert("cvt_sat_u8_s32", ptx, &input, &mut output)
+}
+test_spvtxt!(cvt_sat_u8_s32);
+
+#[test]
+fn cvt_sat_s16_s32() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("cvt_sat_s16_s32.ptx");
+    let input = [-40000i32, 40000, -1234];
+    let mut output = [-32768i32, 32767, -1234];
+    test_ptx_assert("cvt_sat_s16_s32", ptx, &input, &mut output)
+}
+test_spvtxt!(cvt_sat_s16_s32);
+
+// Shared pointer converted with cvta, then the global input pointer
+#[test]
+fn isspacep() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("isspacep.ptx");
+    let input = [0u32];
+    let mut output = [1u32, 0, 0, 1];
+    test_ptx_assert("isspacep", ptx, &input, &mut output)
+}
+test_spvtxt!(isspacep);
+
+#[test]
+fn prefetch() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("prefetch.ptx");
+    let input = [123u64];
+    let mut output = [123u64];
+    test_ptx_assert("prefetch", ptx, &input, &mut output)
+}
+test_spvtxt!(prefetch);
+
+// Every thread adds its own element to the same word. Float addition is not
+// associative and the order of threads is not fixed, hence the tolerance.
+// Covers the ptx_impl fallback and, if the device has it, the native atomic
+#[test]
+fn atom_add_f32_lanes() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("atom_add_f32_lanes.ptx");
+    let input = (1..=32).map(|x| x as f32 * 0.1).collect::<Vec<_>>();
+    let expected = input.iter().sum::<f32>();
+    let mut variants = vec![translate::TranslateOptions::default()];
+    if device_supports_atomic_float32_add().map_err(|err| DisplayError { err })? {
+        variants.push(translate::TranslateOptions {
+            atomic_float32_add: true,
+            ..translate::TranslateOptions::default()
+        });
+    }
+    for options in variants {
+        let mut errors = Vec::new();
+        let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+        assert!(errors.len() == 0);
+        let zluda_module = translate::to_spirv_module_with_options(ast, options)?;
+        assert_eq!(
+            count_instructions(&zluda_module.spirv, spirv_headers::Op::AtomicFAddEXT) > 0,
+            options.atomic_float32_add
+        );
+        let name = CString::new("atom_add_f32_lanes")?;
+        let mut output = [0f32];
+        let result = run_spirv(
+            name.as_c_str(),
+            zluda_module,
+            &input,
+            &mut output,
+            32,
+            Some(32),
+        )
+        .map_err(|err| DisplayError { err })?;
+        assert!(
+            (result[0] - expected).abs() < 1e-3,
+            "{} != {}",
+            result[0],
+            expected
+        );
+    }
+    Ok(())
+}
+test_spvtxt!(atom_add_f32_lanes);
+
+fn device_supports_atomic_float32_add() -> ze::Result<bool> {
+    ze::init()?;
+    let mut drivers = ze::Driver::get()?;
+    let drv = drivers.drain(0..1).next().unwrap();
+    let mut devices = drv.devices()?;
+    let dev = devices.drain(0..1).next().unwrap();
+    let flags = dev.get_float_atomic_properties()?.fp32Flags;
+    let global_add =
+        ze::sys::ze_device_fp_atomic_ext_flags_t::ZE_DEVICE_FP_ATOMIC_EXT_FLAG_GLOBAL_ADD;
+    Ok(flags & global_add == global_add)
+}
+
+// Sums 1..=10 in a loop closed by a conditional back-edge
+#[test]
+fn loop_sum() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("loop_sum.ptx");
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    assert_eq!(
+        count_instructions(&zluda_module.spirv, spirv_headers::Op::LoopMerge),
+        1
+    );
+    test_ptx_assert("loop_sum", ptx, &[10u64], &mut [55u64])
+}
+
+test_spvtxt!(loop_sum);
+
+// Both sides of the diamond must rejoin at the final store
+#[test]
+fn if_else() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("if_else.ptx");
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    assert_eq!(
+        count_instructions(&zluda_module.spirv, spirv_headers::Op::SelectionMerge),
+        1
+    );
+    test_ptx_assert("if_else", ptx, &[3u64], &mut [1u64])?;
+    test_ptx_assert("if_else", ptx, &[7u64], &mut [2u64])
+}
+
+test_spvtxt!(if_else);
+
+// Outputs are shf.l.clamp, shf.l.wrap, shf.r.clamp and shf.r.wrap. Shift by 32
+// is clamped to 32 but wraps to 0, shift by 40 is clamped to 32 but wraps to 8
+#[test]
+fn shf() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("shf.ptx");
+    let (low, high) = (0x89ABCDEFu32, 0x01234567u32);
+    test_ptx_assert("shf", ptx, &[low, high, 0], &mut [high, high, low, low])?;
+    test_ptx_assert("shf", ptx, &[low, high, 32], &mut [low, high, high, low])?;
+    test_ptx_assert(
+        "shf",
+        ptx,
+        &[low, high, 40],
+        &mut [low, 0x23456789, high, 0x6789ABCD],
+    )
+}
+
+test_spvtxt!(shf);
+
+// Inputs are insert, base, position and length. The last case inserts a field
+// that does not fit and has to be cut at the top bit
+#[test]
+fn bfi() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("bfi.ptx");
+    let base = 0x12345678u32;
+    test_ptx_assert("bfi", ptx, &[0xAB, base, 8, 8], &mut [0x1234AB78u32])?;
+    test_ptx_assert("bfi", ptx, &[0xAB, base, 8, 0], &mut [base])?;
+    test_ptx_assert("bfi", ptx, &[0xAB, base, 28, 8], &mut [0xB2345678u32])
+}
+
+test_spvtxt!(bfi);
+
+// Plain min/max return the number, .NaN variants return the canonical NaN.
+// Compared as bits, because NaN != NaN. There is no .spvtxt: SPIRV-Tools can't
+// assemble a NaN float literal and a NaN OpConstant never compares equal
+#[test]
+fn min_max_nan() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("min_max_nan.ptx");
+    let number = 2f32.to_bits();
+    let input = [0x7FC00000u32, number];
+    let mut output = [number, number, 0x7FFFFFFF, 0x7FFFFFFF];
+    test_ptx_assert("min_max_nan", ptx, &input, &mut output)
+}
+
+// Running is skipped on devices that can't build recursive functions
+#[test]
+fn recursive_call() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("recursive_call.ptx");
+    let spirv_txt = include_bytes!("recursive_call.spvtxt");
+    test_spvtxt_assert(ptx, spirv_txt, "recursive_call.spvtxt")?;
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    assert!(zluda_module.uses_recursion);
+    if !builds_on_device(&zluda_module).map_err(|err| DisplayError { err })? {
+        return Ok(());
+    }
+    let name = CString::new("recursive_call")?;
+    let result = run_spirv(name.as_c_str(), zluda_module, &[5u64], &mut [0u64], 1, None)
+        .map_err(|err| DisplayError { err })?;
+    assert_eq!(result, [120u64]);
+    Ok(())
+}
+
+// The second return value is used directly as a predicate
+#[test]
+fn call_multi_ret() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("call_multi_ret.ptx");
+    test_ptx_assert("call_multi_ret", ptx, &[7u64, 8u64], &mut [103u64, 4u64])
+}
+test_spvtxt!(call_multi_ret);
+
+// Outputs are dp4a.s32.s32, dp4a.u32.u32, dp2a.lo.s32.s32 and dp2a.hi.u32.u32.
+// Bytes of a are 1, -2, 2, -1 (1, 254, 2, 255 unsigned) and its halves are
+// -511, -254. Bytes of b are 3, 2, -3, 4 (3, 2, 253, 4 unsigned)
+#[test]
+fn dp4a() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("dp4a.ptx");
+    let (a, b) = (0xFF02FE01u32, 0x04FD0203u32);
+    test_ptx_assert(
+        "dp4a",
+        ptx,
+        &[a, b, 10],
+        &mut [-1i32 as u32, 2047, -2031i32 as u32, 16712463],
+    )?;
+    test_ptx_assert(
+        "dp4a",
+        ptx,
+        &[a, b, 0],
+        &mut [-11i32 as u32, 2037, -2041i32 as u32, 16712453],
+    )
+}
+
+test_spvtxt!(dp4a);
+
+// Outputs are mul24.lo.u32, mul24.hi.u32, mad24.lo.s32 and mul24.hi.s32. Bit 24
+// of the first input is discarded, so as a signed 24-bit value it is -1
+#[test]
+fn mul24() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("mul24.ptx");
+    test_ptx_assert(
+        "mul24",
+        ptx,
+        &[0x01FFFFFFu32, 2, 5],
+        &mut [0x01FFFFFEu32, 0x1FF, 3, 0xFFFFFFFF],
+    )?;
+    test_ptx_assert(
+        "mul24",
+        ptx,
+        &[0x00800000u32, 0x200, 5],
+        &mut [0u32, 0x10000, 5, 0xFFFF0000],
+    )
+}
+
+test_spvtxt!(mul24);
+
+// Registers are lowered to function variables, so every register operand in
+// the chain is a single OpLoad that the driver promotes back to an SSA value.
+// setp loads x, selp loads result and p, the rest are address loads
+#[test]
+fn selp_chain() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("selp_chain.ptx");
+    let chain_length = 64;
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    let loads = count_instructions(&zluda_module.spirv, spirv_headers::Op::Load);
+    assert!(loads <= 3 * chain_length + 6);
+    test_ptx_assert("selp_chain", ptx, &[0u32], &mut [0u32])?;
+    test_ptx_assert("selp_chain", ptx, &[17u32], &mut [51u32])?;
+    test_ptx_assert("selp_chain", ptx, &[63u32], &mut [189u32])?;
+    test_ptx_assert("selp_chain", ptx, &[64u32], &mut [1000u32])
+}
+
+test_spvtxt!(selp_chain);
+
+// Outputs are prmt.b32 and prmt.b32.rc16. 0x5410 and 0x7632 are the usual way
+// to pack the low and high halves of two registers. In the last case the top
+// nibble bits replicate the sign of byte 7 (0x88) and of byte 0 (0x11)
+#[test]
+fn prmt() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("prmt.ptx");
+    let (a, b) = (0x44332211u32, 0x88776655u32);
+    test_ptx_assert("prmt", ptx, &[a, b, 0x3210], &mut [a, 0x22112211u32])?;
+    test_ptx_assert("prmt", ptx, &[a, b, 0x7654], &mut [b, 0x22112211u32])?;
+    test_ptx_assert(
+        "prmt",
+        ptx,
+        &[a, b, 0x5410],
+        &mut [0x66552211u32, 0x22112211u32],
+    )?;
+    test_ptx_assert(
+        "prmt",
+        ptx,
+        &[a, b, 0x7632],
+        &mut [0x88774433u32, 0x22112211u32],
+    )?;
+    test_ptx_assert(
+        "prmt",
+        ptx,
+        &[a, b, 0x8F01],
+        &mut [0x00FF1122u32, 0x44334433u32],
+    )
+}
+
+test_spvtxt!(prmt);
+
+// Only the two lowest bits of the control operand are used, so 5 selects byte 1
+#[test]
+fn prmt_special() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("prmt_special.ptx");
+    let (a, b) = (0x44332211u32, 0x88776655u32);
+    test_ptx_assert(
+        "prmt_special",
+        ptx,
+        &[a, b, 5],
+        &mut [0x22222222u32, 0x44332222u32],
+    )?;
+    test_ptx_assert(
+        "prmt_special",
+        ptx,
+        &[a, b, 2],
+        &mut [0x33333333u32, 0x44333333u32],
+    )
+}
+
+test_spvtxt!(prmt_special);
+
+// Outputs are bfind.u32, bfind.s32 and bfind.shiftamt.u32. For the negative
+// input bfind.s32 skips the sign bits and finds the highest clear bit instead
+#[test]
+fn bfind() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("bfind.ptx");
+    let none = u32::MAX;
+    test_ptx_assert("bfind", ptx, &[0u32], &mut [none, none, none])?;
+    test_ptx_assert("bfind", ptx, &[0x10000u32], &mut [16, 16, 15])?;
+    test_ptx_assert("bfind", ptx, &[0xFFFF0000u32], &mut [31, 15, 0])
+}
+
+test_spvtxt!(bfind);
+
+// Every thread adds tid+1 to the same shared counter without reading it back
+#[test]
+fn red_add() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("red_add.ptx");
+    let input = [0u32];
+    let mut output = [0u32];
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    let name = CString::new("red_add")?;
+    let result = run_spirv(
+        name.as_c_str(),
+        zluda_module,
+        &input,
+        &mut output,
+        8,
+        Some(8),
+    )
+    .map_err(|err| DisplayError { err })?;
+    assert_eq!(result, [36u32]);
+    Ok(())
+}
+
+test_spvtxt!(red_add);
+
+// PTX assumes 32 lanes in a warp, but a shuffle can only reach lanes of the
+// same subgroup. When the subgroup is narrower than 32 lanes, source lanes
+// past the end of the subgroup are out of bounds, same as lanes past the clamp
+#[test]
+fn shfl() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("shfl.ptx");
+    let input = (0..32u32).map(|x| x * 10 + 1).collect::<Vec<_>>();
+    let mut output = [0u32; 128];
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    let name = CString::new("shfl")?;
+    let result = run_spirv(
+        name.as_c_str(),
+        zluda_module,
+        &input,
+        &mut output,
+        32,
+        Some(32),
+    )
+    .map_err(|err| DisplayError { err })?;
+    let warp_size = result[96] as usize;
+    assert!(result[96..].iter().all(|size| *size as usize == warp_size));
+    for tid in 0..32 {
+        let lane = tid % warp_size;
+        let (down, in_bounds) = if lane + 1 < warp_size {
+            (input[tid + 1], 1)
+        } else {
+            (input[tid], 0)
+        };
+        assert_eq!(result[tid], down, "{}", tid);
+        assert_eq!(result[32 + tid], in_bounds, "{}", tid);
+        assert_eq!(result[64 + tid], input[tid - lane], "{}", tid);
+    }
+    Ok(())
+}
+
+test_spvtxt!(shfl);
+
+// Threads are grouped by subgroup, not by a 32 lane warp. Inputs are chosen so
+// that .all and .any give different results for subgroups of 8, 16 or 32 lanes
+#[test]
+fn vote() -> Result<(), Box<dyn error::Error>> {
+    let ptx = include_str!("vote.ptx");
+    let input = (0..32u32)
+        .map(|tid| match tid {
+            0..=15 => 1,
+            16..=23 => tid % 2,
+            _ => 0,
+        })
+        .collect::<Vec<_>>();
+    let mut output = [0u32; 160];
+    let mut errors = Vec::new();
+    let ast = ptx::ModuleParser::new().parse(&mut errors, ptx)?;
+    assert!(errors.len() == 0);
+    let zluda_module = translate::to_spirv_module(ast)?;
+    let name = CString::new("vote")?;
+    let result = run_spirv(
+        name.as_c_str(),
+        zluda_module,
+        &input,
+        &mut output,
+        32,
+        Some(32),
+    )
+    .map_err(|err| DisplayError { err })?;
+    let warp_size = result[128] as usize;
+    assert!(result[128..].iter().all(|size| *size as usize == warp_size));
+    let lanes_mask = ((1u64 << warp_size) - 1) as u32;
+    for tid in 0..32 {
+        let first_lane = tid - (tid % warp_size);
+        let preds = &input[first_lane..first_lane + warp_size];
+        let ballot = preds
+            .iter()
+            .enumerate()
+            .fold(0u32, |acc, (lane, pred)| acc | (*pred << lane));
+        let all = preds.iter().all(|pred| *pred != 0) as u32;
+        let any = preds.iter().any(|pred| *pred != 0) as u32;
+        assert_eq!(result[tid], ballot, "{}", tid);
+        assert_eq!(result[32 + tid], !ballot & lanes_mask & 0xffff, "{}", tid);
+        assert_eq!(result[64 + tid], all, "{}", tid);
+        assert_eq!(result[96 + tid], any, "{}", tid);
+    }
+    Ok(())
+}
+
+test_spvtxt!(vote);
+
+fn run_spirv<Input: ze::SafeRepr + Copy + Debug, Output: From<u8> + ze::SafeRepr + Copy + Debug>(
+    name: &CStr,
+    module: translate::Module,
+    input: &[Input],
+    output: &mut [Output],
+    global_size: u32,
+    group_size: Option<u32>,
+) -> ze::Result<Vec<Output>> {
+    run_spirv_linked(name, module, &[], input, output, global_size, group_size)
+}
+
+// Like run_spirv, but the module is linked with `libraries` first, which is
+// where its .extern functions are resolved
+fn run_spirv_linked<
+    Input: ze::SafeRepr + Copy + Debug,
     Output: From<u8> + ze::SafeRepr + Copy + Debug,
 >(
     name: &CStr,
     module: translate::Module,
+    libraries: &[translate::Module],
     input: &[Input],
     output: &mut [Output],
+    global_size: u32,
+    group_size: Option<u32>,
 ) -> ze::Result<Vec<Output>> {
     ze::init()?;
-    let spirv = module.spirv.assemble();
-    let byte_il = unsafe {
-        slice::from_raw_parts::<u8>(
-            spirv.as_ptr() as *const _,
-            spirv.len() * mem::size_of::<u32>(),
-        )
-    };
     let use_shared_mem = module
         .kernel_info
         .get(name.to_str().unwrap())
@@ -211,40 +1341,16 @@ fn run_spirv<
         .unwrap_or(false);
     let mut result = vec![0u8.into(); output.len()];
     {
+        // Resources are dropped in reverse order of creation, also on an early
+        // return. DeviceBuffer doesn't borrow the context it was allocated in,
+        // so it must be created after ctx to be freed before it
         let mut drivers = ze::Driver::get()?;
         let drv = drivers.drain(0..1).next().unwrap();
         let mut ctx = ze::Context::new(&drv)?;
         let mut devices = drv.devices()?;
         let dev = devices.drain(0..1).next().unwrap();
         let queue = ze::CommandQueue::new(&mut ctx, &dev)?;
-        let (module, maybe_log) = match module.should_link_ptx_impl {
-            Some(ptx_impl) => ze::Module::build_link_spirv(
-                &mut ctx,
-                &dev,
-                &[ptx_impl, byte_il],
-                Some(module.build_options.as_c_str()),
-            ),
-            None => {
-                let (module, log) = ze::Module::build_spirv_logged(
-                    &mut ctx,
-                    &dev,
-                    byte_il,
-                    Some(module.build_options.as_c_str()),
-                );
-                (module, Some(log))
-            }
-        };
-        let module = match module {
-            Ok(m) => m,
-            Err(err) => {
-                let raw_err_string = maybe_log
-                    .map(|log| log.get_cstring())
-                    .transpose()?
-                    .unwrap_or(CString::default());
-                let err_string = raw_err_string.to_string_lossy();
-                panic!("{:?}\n{}", err, err_string);
-            }
-        };
+        let module = build_module(&mut ctx, &dev, &module, libraries)?;
         let mut kernel = ze::Kernel::new_resident(&module, name)?;
         kernel.set_indirect_access(
             ze::sys::ze_kernel_indirect_access_flags_t::ZE_KERNEL_INDIRECT_ACCESS_FLAG_DEVICE,
@@ -261,19 +1367,172 @@ fn run_spirv<
         let mut init_evs = [ev0, ev1];
         cmd_list.append_memory_copy(inp_b_ptr_mut, input, Some(&mut init_evs[0]), &mut [])?;
         cmd_list.append_memory_fill(out_b_ptr_mut, 0, Some(&mut init_evs[1]), &mut [])?;
-        kernel.set_group_size(1, 1, 1)?;
+        let group_size = match group_size {
+            Some(group_size) => group_size,
+            None => kernel.suggest_group_size(global_size, 1, 1)?.0,
+        };
+        kernel.set_group_size(group_size, 1, 1)?;
         kernel.set_arg_buffer(0, inp_b_ptr_mut)?;
         kernel.set_arg_buffer(1, out_b_ptr_mut)?;
         if use_shared_mem {
             unsafe { kernel.set_arg_raw(2, 128, ptr::null())? };
         }
-        cmd_list.append_launch_kernel(&kernel, &[1, 1, 1], Some(&mut ev2), &mut init_evs)?;
+        cmd_list.append_launch_kernel(
+            &kernel,
+            &[global_size / group_size, 1, 1],
+            Some(&mut ev2),
+            &mut init_evs,
+        )?;
         cmd_list.append_memory_copy(result.as_mut_slice(), out_b_ptr_mut, None, &mut [ev2])?;
         queue.execute(cmd_list)?;
     }
     Ok(result)
 }
 
+// Build failures panic with the build log, it's more useful than the error code
+fn build_module(
+    ctx: &mut ze::Context,
+    dev: &ze::Device,
+    module: &translate::Module,
+    libraries: &[translate::Module],
+) -> ze::Result<ze::Module> {
+    let spirv = module.spirv.assemble();
+    let libraries_spirv = libraries
+        .iter()
+        .map(|library| library.spirv.assemble())
+        .collect::<Vec<_>>();
+    let mut binaries = Vec::new();
+    if let Some(ptx_impl) = module.should_link_ptx_impl {
+        binaries.push(ptx_impl);
+    }
+    binaries.extend(libraries_spirv.iter().map(|spirv| spirv_bytes(spirv)));
+    binaries.push(spirv_bytes(&spirv));
+    let (result, maybe_log) = if binaries.len() > 1 {
+        ze::Module::build_link_spirv(ctx, dev, &binaries, Some(module.build_options.as_c_str()))
+    } else {
+        let (result, log) = ze::Module::build_spirv_logged(
+            ctx,
+            dev,
+            binaries[0],
+            Some(module.build_options.as_c_str()),
+        );
+        (result, Some(log))
+    };
+    match result {
+        Ok(m) => Ok(m),
+        Err(err) => {
+            let raw_err_string = maybe_log
+                .map(|log| log.get_cstring())
+                .transpose()?
+                .unwrap_or(CString::default());
+            let err_string = raw_err_string.to_string_lossy();
+            panic!("{:?}\n{}", err, err_string);
+        }
+    }
+}
+
+fn builds_on_device(module: &translate::Module) -> ze::Result<bool> {
+    ze::init()?;
+    let mut drivers = ze::Driver::get()?;
+    let drv = drivers.drain(0..1).next().unwrap();
+    let mut ctx = ze::Context::new(&drv)?;
+    let mut devices = drv.devices()?;
+    let dev = devices.drain(0..1).next().unwrap();
+    let spirv = module.spirv.assemble();
+    let (result, _) = ze::Module::build_spirv_logged(
+        &mut ctx,
+        &dev,
+        spirv_bytes(&spirv),
+        Some(module.build_options.as_c_str()),
+    );
+    Ok(result.is_ok())
+}
+
+fn spirv_bytes(spirv: &[u32]) -> &[u8] {
+    unsafe {
+        slice::from_raw_parts::<u8>(
+            spirv.as_ptr() as *const _,
+            spirv.len() * mem::size_of::<u32>(),
+        )
+    }
+}
+
+// Launches the kernel (on a single thread) `iters` times with the same
+// buffers and returns how long each launch ran on the device. Output buffer
+// has the same type and length as the input
+fn bench_spirv<Input: ze::SafeRepr + Copy + Debug>(
+    name: &CStr,
+    module: translate::Module,
+    input: &[Input],
+    iters: u32,
+) -> ze::Result<Vec<Duration>> {
+    ze::init()?;
+    let use_shared_mem = module
+        .kernel_info
+        .get(name.to_str().unwrap())
+        .map(|info| info.uses_shared_mem)
+        .unwrap_or(false);
+    let mut drivers = ze::Driver::get()?;
+    let drv = drivers.drain(0..1).next().unwrap();
+    let mut ctx = ze::Context::new(&drv)?;
+    let mut devices = drv.devices()?;
+    let dev = devices.drain(0..1).next().unwrap();
+    let props = dev.get_properties()?;
+    let timestamp_mask = match props.kernelTimestampValidBits {
+        bits if bits >= 64 => u64::MAX,
+        bits => (1u64 << bits) - 1,
+    };
+    let queue = ze::CommandQueue::new(&mut ctx, &dev)?;
+    let module = build_module(&mut ctx, &dev, &module, &[])?;
+    let mut kernel = ze::Kernel::new_resident(&module, name)?;
+    kernel.set_indirect_access(
+        ze::sys::ze_kernel_indirect_access_flags_t::ZE_KERNEL_INDIRECT_ACCESS_FLAG_DEVICE,
+    )?;
+    let mut inp_b = ze::DeviceBuffer::<Input>::new(&mut ctx, &dev, cmp::max(input.len(), 1))?;
+    let mut out_b = ze::DeviceBuffer::<Input>::new(&mut ctx, &dev, cmp::max(input.len(), 1))?;
+    let inp_b_ptr_mut: ze::BufferPtrMut<Input> = (&mut inp_b).into();
+    let out_b_ptr_mut: ze::BufferPtrMut<Input> = (&mut out_b).into();
+    let init_pool = ze::EventPool::new(&mut ctx, 1, Some(&[&dev]))?;
+    let timestamp_pool = ze::EventPool::new_timestamp(&mut ctx, iters, Some(&[&dev]))?;
+    let mut init_ev = [ze::Event::new(&init_pool, 0)?];
+    let mut launch_evs = (0..iters)
+        .map(|i| ze::Event::new(&timestamp_pool, i))
+        .collect::<ze::Result<Vec<_>>>()?;
+    let group_size = kernel.suggest_group_size(1, 1, 1)?.0;
+    kernel.set_group_size(group_size, 1, 1)?;
+    kernel.set_arg_buffer(0, inp_b_ptr_mut)?;
+    kernel.set_arg_buffer(1, out_b_ptr_mut)?;
+    if use_shared_mem {
+        unsafe { kernel.set_arg_raw(2, 128, ptr::null())? };
+    }
+    let mut cmd_list = ze::CommandList::new(&mut ctx, &dev)?;
+    cmd_list.append_memory_copy(inp_b_ptr_mut, input, Some(&mut init_ev[0]), &mut [])?;
+    // Every launch waits for the previous one, so they don't overlap
+    for i in 0..launch_evs.len() {
+        let (previous, current) = launch_evs.split_at_mut(i);
+        let wait = match previous.last_mut() {
+            Some(previous) => slice::from_mut(previous),
+            None => &mut init_ev[..],
+        };
+        cmd_list.append_launch_kernel(&kernel, &[1, 1, 1], Some(&mut current[0]), wait)?;
+    }
+    queue.execute(cmd_list)?;
+    launch_evs
+        .iter()
+        .map(|ev| {
+            let timestamp = ev.query_kernel_timestamp()?;
+            let ticks = timestamp
+                .global
+                .kernelEnd
+                .wrapping_sub(timestamp.global.kernelStart)
+                & timestamp_mask;
+            Ok(Duration::from_nanos(
+                ticks.saturating_mul(props.timerResolution),
+            ))
+        })
+        .collect()
+}
+
 fn test_spvtxt_assert<'a>(
     ptx_txt: &'a str,
     spirv_txt: &'a [u8],
@@ -318,7 +1577,7 @@ fn test_spvtxt_assert<'a>(
     rspirv::binary::parse_words(&parsed_spirv, &mut loader)?;
     let spvtxt_mod = loader.module();
     unsafe { spirv_tools::spvBinaryDestroy(spv_binary) };
-    if !is_spirv_fns_equal(&spirv_module.spirv.functions, &spvtxt_mod.functions) {
+    if !is_spirv_module_equal(&spirv_module.spirv, &spvtxt_mod) {
         // We could simply use ptx_mod.disassemble, but SPIRV-Tools text formattinmg is so much nicer
         let spv_from_ptx_binary = spirv_module.spirv.assemble();
         let mut spv_text: spirv_tools::spv_text = ptr::null_mut();
@@ -359,6 +1618,7 @@ fn test_spvtxt_assert<'a>(
     Ok(())
 }
 
+#[derive(Clone)]
 struct EqMap<T>
 where
     T: Eq + Copy + Hash,
@@ -402,19 +1662,70 @@ fn is_spirv_fns_equal(fns1: &[Function], fns2: &[Function]) -> bool {
     true
 }
 
-fn is_spirv_fn_equal(fn1: &Function, fn2: &Function) -> bool {
+// Functions are compared in order, instruction by instruction. Decorations,
+// types and global values may be legally emitted in a different order, so they
+// are compared as unordered sets
+fn is_spirv_module_equal(m1: &Module, m2: &Module) -> bool {
+    if m1.functions.len() != m2.functions.len() {
+        return false;
+    }
     let mut map = EqMap::new();
-    if !is_option_equal(&fn1.def, &fn2.def, &mut map, is_instr_equal) {
+    for (fn1, fn2) in m1.functions.iter().zip(m2.functions.iter()) {
+        if !is_spirv_fn_equal_with(fn1, fn2, &mut map) {
+            return false;
+        }
+    }
+    is_unordered_instrs_equal(&m1.types_global_values, &m2.types_global_values, &mut map)
+        && is_unordered_instrs_equal(&m1.annotations, &m2.annotations, &mut map)
+}
+
+// Every instruction is paired with the first equal and not yet paired
+// instruction from the other set. Ids are bound only when the pair matches
+fn is_unordered_instrs_equal(
+    instrs1: &[Instruction],
+    instrs2: &[Instruction],
+    map: &mut EqMap<Word>,
+) -> bool {
+    if instrs1.len() != instrs2.len() {
+        return false;
+    }
+    let mut unpaired = instrs2.iter().collect::<Vec<_>>();
+    for inst1 in instrs1 {
+        let pair = unpaired.iter().position(|inst2| {
+            let mut candidate_map = map.clone();
+            if is_instr_equal(inst1, inst2, &mut candidate_map) {
+                *map = candidate_map;
+                true
+            } else {
+                false
+            }
+        });
+        match pair {
+            Some(index) => {
+                unpaired.swap_remove(index);
+            }
+            None => return false,
+        }
+    }
+    true
+}
+
+fn is_spirv_fn_equal(fn1: &Function, fn2: &Function) -> bool {
+    is_spirv_fn_equal_with(fn1, fn2, &mut EqMap::new())
+}
+
+fn is_spirv_fn_equal_with(fn1: &Function, fn2: &Function, map: &mut EqMap<Word>) -> bool {
+    if !is_option_equal(&fn1.def, &fn2.def, map, is_instr_equal) {
         return false;
     }
-    if !is_option_equal(&fn1.end, &fn2.end, &mut map, is_instr_equal) {
+    if !is_option_equal(&fn1.end, &fn2.end, map, is_instr_equal) {
         return false;
     }
     if fn1.parameters.len() != fn2.parameters.len() {
         return false;
     }
     for (inst1, inst2) in fn1.parameters.iter().zip(fn2.parameters.iter()) {
-        if !is_instr_equal(inst1, inst2, &mut map) {
+        if !is_instr_equal(inst1, inst2, map) {
             return false;
         }
     }
@@ -422,7 +1733,7 @@ fn is_spirv_fn_equal(fn1: &Function, fn2: &Function) -> bool {
         return false;
     }
     for (b1, b2) in fn1.blocks.iter().zip(fn2.blocks.iter()) {
-        if !is_block_equal(b1, b2, &mut map) {
+        if !is_block_equal(b1, b2, map) {
             return false;
         }
     }
@@ -497,10 +1808,66 @@ fn is_option_equal<T, F: FnOnce(&T, &T, &mut EqMap<Word>) -> bool>(
     match (o1, o2) {
         (Some(t1), Some(t2)) => f(t1, t2, map),
         (None, None) => true,
-        _ => panic!(),
+        _ => false,
     }
 }
 
+fn module_with_alignments(alignments: &[u32]) -> Module {
+    module_with_alignments_from(Builder::new(), alignments)
+}
+
+fn module_with_alignments_from(mut builder: Builder, alignments: &[u32]) -> Module {
+    let uint = builder.type_int(32, 0);
+    let uint_ptr = builder.type_pointer(None, spirv_headers::StorageClass::CrossWorkgroup, uint);
+    for alignment in alignments {
+        let var = builder.variable(
+            uint_ptr,
+            None,
+            spirv_headers::StorageClass::CrossWorkgroup,
+            None,
+        );
+        builder.decorate(
+            var,
+            spirv_headers::Decoration::Alignment,
+            [Operand::LiteralInt32(*alignment)],
+        );
+    }
+    builder.module()
+}
+
+#[test]
+fn reordered_decorations_are_equal() {
+    let module = module_with_alignments(&[4, 8]);
+    let mut reordered = module_with_alignments(&[4, 8]);
+    reordered.annotations.reverse();
+    assert!(is_spirv_module_equal(&module, &reordered));
+    let different = module_with_alignments(&[4, 16]);
+    assert!(!is_spirv_module_equal(&module, &different));
+}
+
+#[test]
+fn renumbered_and_reordered_modules_are_equal() {
+    let module = module_with_alignments(&[4, 8]);
+    let mut builder = Builder::new();
+    builder.id();
+    let mut renumbered = module_with_alignments_from(builder, &[4, 8]);
+    renumbered.annotations.reverse();
+    assert!(is_spirv_module_equal(&module, &renumbered));
+}
+
+#[test]
+fn fn_with_def_differs_from_fn_without_def() {
+    let mut with_def = Function::new();
+    with_def.def = Some(Instruction::new(
+        spirv_headers::Op::Function,
+        Some(1),
+        Some(2),
+        Vec::new(),
+    ));
+    let without_def = Function::new();
+    assert!(!is_spirv_fns_equal(&[with_def], &[without_def]));
+}
+
 unsafe extern "C" fn parse_header_cb(
     user_data: *mut c_void,
     endian: spv_endianness_t,
@@ -1,7 +1,7 @@
 use crate::ast;
 use half::f16;
 use rspirv::dr;
-use std::{borrow::Cow, collections::BTreeSet, ffi::CString, hash::Hash, iter, mem};
+use std::{borrow::Cow, collections::BTreeSet, ffi::CString, hash::Hash, iter, mem, panic};
 use std::{
     collections::{hash_map, HashMap, HashSet},
     convert::TryInto,
@@ -10,6 +10,13 @@ use std::{
 use rspirv::binary::Assemble;
 
 static ZLUDA_PTX_IMPL: &'static [u8] = include_bytes!("../lib/zluda_ptx_impl.spv");
+const VPRINTF_IMPORT: &str = "__zluda_ptx_impl__vprintf";
+// Program-scope variable of zluda_ptx_impl holding the address of the buffer
+// device-side vprintf appends its records to
+pub const PRINTF_BUFFER_GLOBAL: &str = "__zluda_ptx_impl__printf_buffer";
+// Exported global %nsmid is read from, the host sets it to the multiprocessor
+// count of the device the module is compiled for
+pub const NSMID_GLOBAL: &str = "__zluda_nsmid";
 
 quick_error! {
     #[derive(Debug)]
@@ -24,6 +31,38 @@ quick_error! {
         }
         Unreachable {}
         Todo {}
+        IrreducibleControlFlow {
+            display("Loops with more than one entry point are not supported")
+        }
+        MissingShaderClock {
+            display("%clock and %clock64 require SPV_KHR_shader_clock")
+        }
+        MissingStackCalls {
+            display("Recursive functions require stack calls")
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum ParseTranslateError {
+        Parse(err: String) {
+            display("{}", err)
+        }
+        // First of the errors the parser recovered from
+        Ptx(err: ast::PtxError) {
+            from()
+            display("{}", err)
+            cause(err)
+        }
+        Translate(err: TranslateError) {
+            from()
+            display("{}", err)
+            cause(err)
+        }
+        Panic(msg: String) {
+            display("{}", msg)
+        }
     }
 }
 
@@ -45,6 +84,10 @@ enum SpirvType {
     Pointer(Box<SpirvType>, spirv::StorageClass),
     Func(Option<Box<SpirvType>>, Vec<SpirvType>),
     Struct(Vec<SpirvScalarKey>),
+    // Only read-only 1D images are ever emitted, they back tex.1d
+    Image,
+    Sampler,
+    SampledImage,
 }
 
 impl SpirvType {
@@ -179,6 +222,10 @@ impl TypeWordMap {
     }
 
     fn get_or_add_spirv_scalar(&mut self, b: &mut dr::Builder, key: SpirvScalarKey) -> spirv::Word {
+        // f16x2 is a two-element vector of halves
+        if key == SpirvScalarKey::F16x2 {
+            return self.get_or_add(b, SpirvType::Vector(SpirvScalarKey::F16, 2));
+        }
         *self
             .complex
             .entry(SpirvType::Base(key))
@@ -191,7 +238,7 @@ impl TypeWordMap {
                 SpirvScalarKey::F32 => b.type_float(32),
                 SpirvScalarKey::F64 => b.type_float(64),
                 SpirvScalarKey::Pred => b.type_bool(),
-                SpirvScalarKey::F16x2 => todo!(),
+                SpirvScalarKey::F16x2 => unreachable!(),
             })
     }
 
@@ -256,6 +303,29 @@ impl TypeWordMap {
                     .entry(t)
                     .or_insert_with(|| b.type_struct(underlying_ids))
             }
+            SpirvType::Image => {
+                let void = self.void();
+                *self.complex.entry(t).or_insert_with(|| {
+                    b.type_image(
+                        void,
+                        spirv::Dim::Dim1D,
+                        0,
+                        0,
+                        0,
+                        0,
+                        spirv::ImageFormat::Unknown,
+                        Some(spirv::AccessQualifier::ReadOnly),
+                    )
+                })
+            }
+            SpirvType::Sampler => *self.complex.entry(t).or_insert_with(|| b.type_sampler()),
+            SpirvType::SampledImage => {
+                let image = self.get_or_add(b, SpirvType::Image);
+                *self
+                    .complex
+                    .entry(t)
+                    .or_insert_with(|| b.type_sampled_image(image))
+            }
         }
     }
 
@@ -264,7 +334,7 @@ impl TypeWordMap {
         b: &mut dr::Builder,
         in_params: impl ExactSizeIterator<Item = SpirvType>,
         mut out_params: impl ExactSizeIterator<Item = SpirvType>,
-    ) -> (spirv::Word, spirv::Word) {
+    ) -> Result<(spirv::Word, spirv::Word), TranslateError> {
         let (out_args, out_spirv_type) = if out_params.len() == 0 {
             (None, self.void())
         } else if out_params.len() == 1 {
@@ -274,12 +344,16 @@ impl TypeWordMap {
                 self.get_or_add(b, arg_as_key),
             )
         } else {
-            todo!()
+            let struct_type = multiple_return_type(out_params)?;
+            (
+                Some(Box::new(struct_type.clone())),
+                self.get_or_add(b, struct_type),
+            )
         };
-        (
+        Ok((
             out_spirv_type,
             self.get_or_add(b, SpirvType::Func(out_args, in_params.collect::<Vec<_>>())),
-        )
+        ))
     }
 
     fn get_or_add_constant(
@@ -457,6 +531,17 @@ pub struct Module {
     pub kernel_info: HashMap<String, KernelInfo>,
     pub should_link_ptx_impl: Option<&'static [u8]>,
     pub build_options: CString,
+    pub subgroup_size: Option<u32>,
+    // Module calls vprintf, the host has to set up PRINTF_BUFFER_GLOBAL
+    pub uses_printf: bool,
+    // Names of module-scope .texref declarations
+    pub texrefs: HashSet<String>,
+    // Module reads %nsmid, the host has to set up NSMID_GLOBAL
+    pub uses_nsmid: bool,
+    // Module has recursive functions, building it requires stack calls
+    pub uses_recursion: bool,
+    // Module reads %clock or %clock64, building it requires SPV_KHR_shader_clock
+    pub uses_shader_clock: bool,
 }
 impl Module {
     pub fn assemble(&self) -> Vec<u32> {
@@ -467,19 +552,112 @@ impl Module {
 pub struct KernelInfo {
     pub arguments_sizes: Vec<usize>,
     pub uses_shared_mem: bool,
+    // Every texture sampled by the kernel adds an image and a sampler
+    // parameter, in this order, after all the other parameters
+    pub textures: Vec<KernelTexture>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum KernelTexture {
+    // Texture object passed in the kernel parameter with this index
+    Object(usize),
+    // Module-scope .texref with this name
+    Texref(String),
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct TranslateOptions {
+    // If set, every kernel requests this subgroup size through the
+    // SubgroupSize execution mode. Warp-level PTX (shuffles, votes) assumes 32
+    pub subgroup_size: Option<u32>,
+    // Set if the target device can't read its clock (SPV_KHR_shader_clock),
+    // modules reading %clock or %clock64 are then rejected
+    pub no_shader_clock: bool,
+    // Set if the target device can't build recursive functions (no stack
+    // calls), modules with recursion are then rejected
+    pub no_recursion: bool,
+    // Set if the target device supports SPV_EXT_shader_atomic_float_add for
+    // the given width. atom.add.f32/f64 are then emitted as OpAtomicFAddEXT,
+    // otherwise they are compare-and-swap loops in ptx_impl
+    pub atomic_float32_add: bool,
+    pub atomic_float64_add: bool,
 }
 
 pub fn to_spirv_module<'a>(ast: ast::Module<'a>) -> Result<Module, TranslateError> {
+    to_spirv_module_with_options(ast, TranslateOptions::default())
+}
+
+// Parser and translator still have unimplemented corners that panic, this
+// turns those panics (and all other failures) into errors. Meant for PTX
+// coming straight from applications
+pub fn parse_module_checked<'a>(ptx_text: &'a str) -> Result<ast::Module<'a>, ParseTranslateError> {
+    catch_panic(|| {
+        let mut errors = Vec::new();
+        let ast = crate::ptx::ModuleParser::new()
+            .parse(&mut errors, ptx_text)
+            .map_err(|err| ParseTranslateError::Parse(format!("{:?}", err)))?;
+        if let Some(err) = errors.into_iter().next() {
+            return Err(ParseTranslateError::Ptx(err));
+        }
+        Ok(ast)
+    })
+}
+
+pub fn parse_and_translate(ptx_text: &str) -> Result<Module, ParseTranslateError> {
+    parse_and_translate_with_options(ptx_text, TranslateOptions::default())
+}
+
+pub fn parse_and_translate_with_options(
+    ptx_text: &str,
+    options: TranslateOptions,
+) -> Result<Module, ParseTranslateError> {
+    let ast = parse_module_checked(ptx_text)?;
+    catch_panic(move || Ok(to_spirv_module_with_options(ast, options)?))
+}
+
+fn catch_panic<T>(
+    f: impl FnOnce() -> Result<T, ParseTranslateError>,
+) -> Result<T, ParseTranslateError> {
+    match panic::catch_unwind(panic::AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let msg = if let Some(msg) = payload.downcast_ref::<&'static str>() {
+                msg.to_string()
+            } else if let Some(msg) = payload.downcast_ref::<String>() {
+                msg.clone()
+            } else {
+                String::new()
+            };
+            Err(ParseTranslateError::Panic(msg))
+        }
+    }
+}
+
+pub fn to_spirv_module_with_options<'a>(
+    ast: ast::Module<'a>,
+    options: TranslateOptions,
+) -> Result<Module, TranslateError> {
     let mut id_defs = GlobalStringIdResolver::new(1);
     let mut ptx_impl_imports = HashMap::new();
     let directives = ast
         .directives
         .into_iter()
         .filter_map(|directive| {
-            translate_directive(&mut id_defs, &mut ptx_impl_imports, directive).transpose()
+            translate_directive(&mut id_defs, &mut ptx_impl_imports, &options, directive)
+                .transpose()
         })
         .collect::<Result<Vec<_>, _>>()?;
     let must_link_ptx_impl = ptx_impl_imports.len() > 0;
+    let uses_printf = ptx_impl_imports.contains_key(VPRINTF_IMPORT);
+    let texrefs = id_defs
+        .texrefs
+        .values()
+        .map(|name| name.to_string())
+        .collect::<HashSet<_>>();
+    let uses_textures = directives.iter().any(|directive| match directive {
+        Directive::Method(f) => !f.spirv_decl.textures.is_empty(),
+        Directive::Variable(_) => false,
+    });
     let directives = ptx_impl_imports
         .into_iter()
         .map(|(_, v)| v)
@@ -488,19 +666,48 @@ pub fn to_spirv_module<'a>(ast: ast::Module<'a>) -> Result<Module, TranslateErro
     let mut builder = dr::Builder::new();
     builder.reserve_ids(id_defs.current_id());
     let call_map = get_call_map(&directives);
+    let uses_recursion = uses_recursion(&directives);
+    if uses_recursion && options.no_recursion {
+        return Err(TranslateError::MissingStackCalls);
+    }
     let mut directives = convert_dynamic_shared_memory_usage(directives, &mut || builder.id());
     normalize_variable_decls(&mut directives);
     let denorm_information = compute_denorm_information(&directives);
     // https://www.khronos.org/registry/spir-v/specs/unified1/SPIRV.html#_a_id_logicallayout_a_logical_layout_of_a_module
     builder.set_version(1, 3);
     emit_capabilities(&mut builder);
+    if options.subgroup_size.is_some() {
+        builder.capability(spirv::Capability::SubgroupDispatch);
+    }
+    if options.atomic_float32_add {
+        builder.capability(spirv::Capability::AtomicFloat32AddEXT);
+    }
+    if options.atomic_float64_add {
+        builder.capability(spirv::Capability::AtomicFloat64AddEXT);
+    }
+    if uses_textures {
+        builder.capability(spirv::Capability::ImageBasic);
+        builder.capability(spirv::Capability::Sampled1D);
+    }
     emit_extensions(&mut builder);
+    if options.atomic_float32_add || options.atomic_float64_add {
+        builder.extension("SPV_EXT_shader_atomic_float_add");
+    }
+    let uses_shader_clock = id_defs.special_registers.uses_clock();
+    if uses_shader_clock {
+        if options.no_shader_clock {
+            return Err(TranslateError::MissingShaderClock);
+        }
+        builder.capability(spirv::Capability::ShaderClockKHR);
+        builder.extension("SPV_KHR_shader_clock");
+    }
     let opencl_id = emit_opencl_import(&mut builder);
     emit_memory_model(&mut builder);
     let mut map = TypeWordMap::new(&mut builder);
     emit_builtins(&mut builder, &mut map, &id_defs);
+    let uses_nsmid = emit_nsmid_global(&mut builder, &mut map, &id_defs)?;
     let mut kernel_info = HashMap::new();
-    let build_options = emit_denorm_build_string(&call_map, &denorm_information);
+    let build_options = emit_build_options(&call_map, &denorm_information, &directives);
     emit_directives(
         &mut builder,
         &mut map,
@@ -510,6 +717,7 @@ pub fn to_spirv_module<'a>(ast: ast::Module<'a>) -> Result<Module, TranslateErro
         &call_map,
         directives,
         &mut kernel_info,
+        options.subgroup_size,
     )?;
     let spirv = builder.module();
     Ok(Module {
@@ -521,14 +729,37 @@ pub fn to_spirv_module<'a>(ast: ast::Module<'a>) -> Result<Module, TranslateErro
             None
         },
         build_options,
+        subgroup_size: options.subgroup_size,
+        uses_printf,
+        texrefs,
+        uses_nsmid,
+        uses_recursion,
+        uses_shader_clock,
     })
 }
 
 // TODO: remove this once we have perf-function support for denorms
-fn emit_denorm_build_string(
+fn emit_build_options(
     call_map: &HashMap<&str, HashSet<u32>>,
     denorm_information: &HashMap<MethodName, HashMap<u8, (spirv::FPDenormMode, isize)>>,
+    directives: &[Directive],
 ) -> CString {
+    let mut options = Vec::new();
+    if prefers_flushing_denorms(call_map, denorm_information) {
+        options.push("-cl-denorms-are-zero");
+    }
+    // OpenCL allows 2.5 ulp of error in single precision division and 3 ulp in
+    // square root, PTX .rn variants have to be correctly rounded
+    if uses_rounded_f32_div_sqrt(directives) {
+        options.push("-cl-fp32-correctly-rounded-divide-sqrt");
+    }
+    CString::new(options.join(" ")).unwrap()
+}
+
+fn prefers_flushing_denorms(
+    call_map: &HashMap<&str, HashSet<u32>>,
+    denorm_information: &HashMap<MethodName, HashMap<u8, (spirv::FPDenormMode, isize)>>,
+) -> bool {
     let denorm_counts = denorm_information
         .iter()
         .map(|(method, meth_denorm)| {
@@ -552,11 +783,35 @@ fn emit_denorm_build_string(
                 .unwrap_or(&0);
         }
     }
-    if flush_over_preserve > 0 {
-        CString::new("-cl-denorms-are-zero").unwrap()
-    } else {
-        CString::default()
-    }
+    flush_over_preserve > 0
+}
+
+fn uses_rounded_f32_div_sqrt(directives: &[Directive]) -> bool {
+    directives.iter().any(|directive| match directive {
+        Directive::Method(Function {
+            body: Some(statements),
+            ..
+        }) => statements.iter().any(|statement| match statement {
+            Statement::Instruction(ast::Instruction::Div(
+                ast::DivDetails::Float(ast::DivFloatDetails {
+                    typ: ast::FloatType::F32,
+                    kind: ast::DivFloatKind::Rounding(_),
+                    ..
+                }),
+                _,
+            )) => true,
+            Statement::Instruction(ast::Instruction::Sqrt(
+                ast::SqrtDetails {
+                    typ: ast::FloatType::F32,
+                    kind: ast::SqrtKind::Rounding(_),
+                    ..
+                },
+                _,
+            )) => true,
+            _ => false,
+        }),
+        _ => false,
+    })
 }
 
 fn emit_directives<'input>(
@@ -568,8 +823,18 @@ fn emit_directives<'input>(
     call_map: &HashMap<&'input str, HashSet<spirv::Word>>,
     directives: Vec<Directive>,
     kernel_info: &mut HashMap<String, KernelInfo>,
+    subgroup_size: Option<u32>,
 ) -> Result<(), TranslateError> {
     let empty_body = Vec::new();
+    // .extern declarations can be repeated or followed by a definition, only
+    // one OpFunction can be emitted per function
+    let mut declared_methods = directives
+        .iter()
+        .filter_map(|d| match d {
+            Directive::Method(f) if f.body.is_some() => Some(MethodName::new(&f.func_decl)),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
     for d in directives.iter() {
         match d {
             Directive::Variable(var) => {
@@ -579,7 +844,10 @@ fn emit_directives<'input>(
                 let f_body = match &f.body {
                     Some(f) => f,
                     None => {
-                        if f.import_as.is_some() {
+                        if f.import_as.is_some()
+                            || (f.linkage.is_some()
+                                && declared_methods.insert(MethodName::new(&f.func_decl)))
+                        {
                             &empty_body
                         } else {
                             continue;
@@ -599,18 +867,30 @@ fn emit_directives<'input>(
                     call_map,
                     &directives,
                     kernel_info,
+                    subgroup_size,
+                )?;
+                emit_function_body_ops(
+                    builder,
+                    map,
+                    opencl_id,
+                    &id_defs.special_registers,
+                    &f_body,
                 )?;
-                emit_function_body_ops(builder, map, opencl_id, &f_body)?;
                 builder.end_function()?;
-                if let (ast::MethodDecl::Func(_, fn_id, _), Some(name)) =
-                    (&f.func_decl, &f.import_as)
+                let linkage = match (&f.import_as, &f.linkage) {
+                    (Some(name), _) => Some((name, spirv::LinkageType::Import)),
+                    (None, Some((name, linkage))) => Some((name, *linkage)),
+                    (None, None) => None,
+                };
+                if let (ast::MethodDecl::Func(_, fn_id, _), Some((name, linkage))) =
+                    (&f.func_decl, linkage)
                 {
                     builder.decorate(
                         *fn_id,
                         spirv::Decoration::LinkageAttributes,
                         &[
                             dr::Operand::LiteralString(name.clone()),
-                            dr::Operand::LinkageType(spirv::LinkageType::Import),
+                            dr::Operand::LinkageType(linkage),
                         ],
                     );
                 }
@@ -663,19 +943,67 @@ fn get_call_map<'input>(
     result
 }
 
+// Call chains can be arbitrarily deep, so this and other call graph walks
+// below keep an explicit worklist instead of recursing
 fn add_call_map_single<'input>(
     directly_called_by: &MultiHashMap<MethodName<'input>, spirv::Word>,
     visited: &mut HashSet<spirv::Word>,
     current: spirv::Word,
 ) {
-    if !visited.insert(current) {
-        return;
+    let mut worklist = vec![current];
+    while let Some(current) = worklist.pop() {
+        if !visited.insert(current) {
+            continue;
+        }
+        if let Some(children) = directly_called_by.get(&MethodName::Func(current)) {
+            worklist.extend(children.iter().copied());
+        }
+    }
+}
+
+// Calls are never inlined, a .func that calls itself (directly or not) is
+// emitted as a cycle of OpFunctionCall
+fn uses_recursion(module: &[Directive]) -> bool {
+    let mut directly_calls = MultiHashMap::new();
+    for directive in module {
+        match directive {
+            Directive::Method(Function {
+                func_decl: ast::MethodDecl::Func(_, name, _),
+                body: Some(statements),
+                ..
+            }) => {
+                for statement in statements {
+                    if let Statement::Call(call) = statement {
+                        multi_hash_map_append(&mut directly_calls, *name, call.func);
+                    }
+                }
+            }
+            _ => {}
+        }
     }
-    if let Some(children) = directly_called_by.get(&MethodName::Func(current)) {
-        for child in children {
-            add_call_map_single(directly_called_by, visited, *child);
+    directly_calls
+        .keys()
+        .any(|func| calls_transitively(&directly_calls, *func))
+}
+
+fn calls_transitively(
+    directly_calls: &MultiHashMap<spirv::Word, spirv::Word>,
+    func: spirv::Word,
+) -> bool {
+    let mut visited = HashSet::new();
+    let mut worklist = directly_calls[&func].clone();
+    while let Some(current) = worklist.pop() {
+        if current == func {
+            return true;
+        }
+        if !visited.insert(current) {
+            continue;
+        }
+        if let Some(children) = directly_calls.get(&current) {
+            worklist.extend(children.iter().copied());
         }
     }
+    false
 }
 
 type MultiHashMap<K, V> = HashMap<K, Vec<V>>;
@@ -726,6 +1054,7 @@ fn convert_dynamic_shared_memory_usage<'input>(
                 globals,
                 body: Some(statements),
                 import_as,
+                linkage,
                 spirv_decl,
             }) => {
                 let call_key = MethodName::new(&func_decl);
@@ -749,6 +1078,7 @@ fn convert_dynamic_shared_memory_usage<'input>(
                     globals,
                     body: Some(statements),
                     import_as,
+                    linkage,
                     spirv_decl,
                 })
             }
@@ -767,6 +1097,7 @@ fn convert_dynamic_shared_memory_usage<'input>(
                 globals,
                 body: Some(statements),
                 import_as,
+                linkage,
                 mut spirv_decl,
             }) => {
                 if !methods_using_extern_shared.contains(&spirv_decl.name) {
@@ -775,6 +1106,7 @@ fn convert_dynamic_shared_memory_usage<'input>(
                         globals,
                         body: Some(statements),
                         import_as,
+                        linkage,
                         spirv_decl,
                     });
                 }
@@ -824,6 +1156,7 @@ fn convert_dynamic_shared_memory_usage<'input>(
                     globals,
                     body: Some(new_statements),
                     import_as,
+                    linkage,
                     spirv_decl,
                 })
             }
@@ -910,15 +1243,14 @@ fn get_callers_of_extern_shared_single<'a>(
     directly_called_by: &MultiHashMap<spirv::Word, MethodName<'a>>,
     fn_id: spirv::Word,
 ) {
-    if let Some(callers) = directly_called_by.get(&fn_id) {
-        for caller in callers {
-            if methods_using_extern_shared.insert(*caller) {
-                if let MethodName::Func(caller_fn) = caller {
-                    get_callers_of_extern_shared_single(
-                        methods_using_extern_shared,
-                        directly_called_by,
-                        *caller_fn,
-                    );
+    let mut worklist = vec![fn_id];
+    while let Some(fn_id) = worklist.pop() {
+        if let Some(callers) = directly_called_by.get(&fn_id) {
+            for caller in callers {
+                if methods_using_extern_shared.insert(*caller) {
+                    if let MethodName::Func(caller_fn) = caller {
+                        worklist.push(*caller_fn);
+                    }
                 }
             }
         }
@@ -1030,7 +1362,7 @@ fn emit_builtins(
     map: &mut TypeWordMap,
     id_defs: &GlobalStringIdResolver,
 ) {
-    for (reg, id) in id_defs.special_registers.builtins() {
+    for (reg, builtin, id) in id_defs.special_registers.builtins() {
         let result_type = map.get_or_add(
             builder,
             SpirvType::Pointer(
@@ -1042,11 +1374,47 @@ fn emit_builtins(
         builder.decorate(
             id,
             spirv::Decoration::BuiltIn,
-            &[dr::Operand::BuiltIn(reg.get_builtin())],
+            &[dr::Operand::BuiltIn(builtin)],
         );
     }
 }
 
+// Until the host overwrites it, %nsmid is 1, so that %smid is always in range
+fn emit_nsmid_global(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    id_defs: &GlobalStringIdResolver,
+) -> Result<bool, TranslateError> {
+    let id = match id_defs.special_registers.get_id(PtxSpecialRegister::NSmId) {
+        Some(id) => id,
+        None => return Ok(false),
+    };
+    let u32_type = ast::Type::Scalar(ast::ScalarType::U32);
+    let result_type = map.get_or_add(
+        builder,
+        SpirvType::Pointer(
+            Box::new(SpirvType::from(u32_type.clone())),
+            spirv::StorageClass::CrossWorkgroup,
+        ),
+    );
+    let initializer = map.get_or_add_constant(builder, &u32_type, &vec_repr(1u32))?;
+    builder.variable(
+        result_type,
+        Some(id),
+        spirv::StorageClass::CrossWorkgroup,
+        Some(initializer),
+    );
+    builder.decorate(
+        id,
+        spirv::Decoration::LinkageAttributes,
+        &[
+            dr::Operand::LiteralString(NSMID_GLOBAL.to_string()),
+            dr::Operand::LinkageType(spirv::LinkageType::Export),
+        ],
+    );
+    Ok(true)
+}
+
 fn emit_function_header<'a>(
     builder: &mut dr::Builder,
     map: &mut TypeWordMap,
@@ -1057,6 +1425,7 @@ fn emit_function_header<'a>(
     call_map: &HashMap<&'a str, HashSet<spirv::Word>>,
     direcitves: &[Directive],
     kernel_info: &mut HashMap<String, KernelInfo>,
+    subgroup_size: Option<u32>,
 ) -> Result<(), TranslateError> {
     if let MethodName::Kernel(name) = func_decl.name {
         let input_args = if !func_decl.uses_shared_mem {
@@ -1073,11 +1442,21 @@ fn emit_function_header<'a>(
             KernelInfo {
                 arguments_sizes: args_lens,
                 uses_shared_mem: func_decl.uses_shared_mem,
+                textures: func_decl
+                    .textures
+                    .iter()
+                    .map(|(texture, _, _)| texture.clone())
+                    .collect(),
             },
         );
     }
-    let (ret_type, func_type) =
-        get_function_type(builder, map, &func_decl.input, &func_decl.output);
+    let (ret_type, func_type) = get_function_type(
+        builder,
+        map,
+        &func_decl.input,
+        &func_decl.output,
+        func_decl.textures.len(),
+    )?;
     let fn_id = match func_decl.name {
         MethodName::Kernel(name) => {
             let fn_id = defined_globals.get_id(name)?;
@@ -1110,6 +1489,13 @@ fn emit_function_header<'a>(
             }
             global_variables.append(&mut interface);
             builder.entry_point(spirv::ExecutionModel::Kernel, fn_id, name, global_variables);
+            if let Some(subgroup_size) = subgroup_size {
+                builder.execution_mode(
+                    fn_id,
+                    spirv::ExecutionMode::SubgroupSize,
+                    vec![subgroup_size],
+                );
+            }
             fn_id
         }
         MethodName::Func(name) => name,
@@ -1145,6 +1531,18 @@ fn emit_function_header<'a>(
         );
         builder.function.as_mut().unwrap().parameters.push(inst);
     }
+    for (_, image, sampler) in func_decl.textures.iter() {
+        for (id, typ) in [(*image, SpirvType::Image), (*sampler, SpirvType::Sampler)].iter() {
+            let result_type = map.get_or_add(builder, typ.clone());
+            let inst = dr::Instruction::new(
+                spirv::Op::FunctionParameter,
+                Some(result_type),
+                Some(*id),
+                Vec::new(),
+            );
+            builder.function.as_mut().unwrap().parameters.push(inst);
+        }
+    }
     Ok(())
 }
 
@@ -1158,6 +1556,10 @@ fn emit_capabilities(builder: &mut dr::Builder) {
     builder.capability(spirv::Capability::Int64);
     builder.capability(spirv::Capability::Float16);
     builder.capability(spirv::Capability::Float64);
+    builder.capability(spirv::Capability::GroupNonUniform);
+    builder.capability(spirv::Capability::GroupNonUniformShuffle);
+    builder.capability(spirv::Capability::GroupNonUniformVote);
+    builder.capability(spirv::Capability::GroupNonUniformBallot);
     // TODO: re-enable when Intel float control extension works
     //builder.capability(spirv::Capability::FunctionFloatControlINTEL);
 }
@@ -1182,12 +1584,19 @@ fn emit_memory_model(builder: &mut dr::Builder) {
 fn translate_directive<'input>(
     id_defs: &mut GlobalStringIdResolver<'input>,
     ptx_impl_imports: &mut HashMap<String, Directive<'input>>,
+    options: &TranslateOptions,
     d: ast::Directive<'input, ast::ParsedArgParams<'input>>,
 ) -> Result<Option<Directive<'input>>, TranslateError> {
     Ok(match d {
         ast::Directive::Variable(v) => Some(Directive::Variable(translate_variable(id_defs, v)?)),
         ast::Directive::Method(f) => {
-            translate_function(id_defs, ptx_impl_imports, f)?.map(Directive::Method)
+            translate_function(id_defs, ptx_impl_imports, options, f)?.map(Directive::Method)
+        }
+        // There is nothing to emit, kernels sampling a texref get its image
+        // and sampler as parameters (see resolve_textures)
+        ast::Directive::Texref(name) => {
+            id_defs.add_texref(name);
+            None
         }
     })
 }
@@ -1228,17 +1637,32 @@ fn translate_variable<'a>(
 fn translate_function<'a>(
     id_defs: &mut GlobalStringIdResolver<'a>,
     ptx_impl_imports: &mut HashMap<String, Directive<'a>>,
+    options: &TranslateOptions,
     f: ast::ParsedFunction<'a>,
 ) -> Result<Option<Function<'a>>, TranslateError> {
     let import_as = match &f.func_directive {
         ast::MethodDecl::Func(_, "__assertfail", _) => {
             Some("__zluda_ptx_impl____assertfail".to_owned())
         }
+        ast::MethodDecl::Func(_, "vprintf", _) => Some(VPRINTF_IMPORT.to_owned()),
         _ => None,
     };
+    let linkage = match &f.func_directive {
+        ast::MethodDecl::Func(_, name, _) => get_function_linkage(f.linking, f.body.is_some())
+            .map(|linkage| (name.to_string(), linkage)),
+        ast::MethodDecl::Kernel { .. } => None,
+    };
     let (str_resolver, fn_resolver, fn_decl) = id_defs.start_fn(&f.func_directive)?;
-    let mut func = to_ssa(ptx_impl_imports, str_resolver, fn_resolver, fn_decl, f.body)?;
+    let mut func = to_ssa(
+        ptx_impl_imports,
+        options,
+        str_resolver,
+        fn_resolver,
+        fn_decl,
+        f.body,
+    )?;
     func.import_as = import_as;
+    func.linkage = linkage;
     if func.import_as.is_some() {
         ptx_impl_imports.insert(
             func.import_as.as_ref().unwrap().clone(),
@@ -1250,6 +1674,26 @@ fn translate_function<'a>(
     }
 }
 
+// SPIR-V has no weak linkage without SPV_KHR_linkonce_odr, so .weak
+// definitions are exported like .visible ones. A module linked with another
+// definition of the same function fails to link instead of picking one
+fn get_function_linkage(
+    linking: ast::LinkingDirective,
+    has_body: bool,
+) -> Option<spirv::LinkageType> {
+    if !has_body {
+        if linking.contains(ast::LinkingDirective::EXTERN) {
+            Some(spirv::LinkageType::Import)
+        } else {
+            None
+        }
+    } else if linking.intersects(ast::LinkingDirective::VISIBLE | ast::LinkingDirective::WEAK) {
+        Some(spirv::LinkageType::Export)
+    } else {
+        None
+    }
+}
+
 fn expand_kernel_params<'a, 'b>(
     fn_resolver: &mut FnStringIdResolver<'a, 'b>,
     args: impl Iterator<Item = &'b ast::KernelArgument<&'a str>>,
@@ -1291,6 +1735,7 @@ fn expand_fn_params<'a, 'b>(
 
 fn to_ssa<'input, 'b>(
     ptx_impl_imports: &mut HashMap<String, Directive>,
+    options: &TranslateOptions,
     mut id_defs: FnStringIdResolver<'input, 'b>,
     fn_defs: GlobalFnDeclResolver<'input, 'b>,
     f_args: ast::MethodDecl<'input, spirv::Word>,
@@ -1305,6 +1750,7 @@ fn to_ssa<'input, 'b>(
                 body: None,
                 globals: Vec::new(),
                 import_as: None,
+                linkage: None,
                 spirv_decl,
             })
         }
@@ -1314,6 +1760,13 @@ fn to_ssa<'input, 'b>(
     let unadorned_statements = normalize_predicates(normalized_ids, &mut numeric_id_defs)?;
     let typed_statements =
         convert_to_typed_statements(unadorned_statements, &fn_defs, &mut numeric_id_defs)?;
+    let typed_statements = resolve_textures(
+        &mut spirv_decl,
+        &f_args,
+        typed_statements,
+        &fn_defs,
+        &mut numeric_id_defs,
+    )?;
     let typed_statements =
         convert_to_stateful_memory_access(&mut spirv_decl, typed_statements, &mut numeric_id_defs)?;
     let ssa_statements = insert_mem_ssa_statements(
@@ -1329,13 +1782,18 @@ fn to_ssa<'input, 'b>(
         insert_implicit_conversions(expanded_statements, &mut numeric_id_defs)?;
     let mut numeric_id_defs = numeric_id_defs.unmut();
     let labeled_statements = normalize_labels(expanded_statements, &mut numeric_id_defs);
-    let (f_body, globals) =
-        extract_globals(labeled_statements, ptx_impl_imports, &mut numeric_id_defs);
+    let (f_body, globals) = extract_globals(
+        labeled_statements,
+        ptx_impl_imports,
+        options,
+        &mut numeric_id_defs,
+    )?;
     Ok(Function {
         func_decl: f_args,
         globals: globals,
         body: Some(f_body),
         import_as: None,
+        linkage: None,
         spirv_decl,
     })
 }
@@ -1352,11 +1810,18 @@ fn fix_builtins(
                 details
                 @
                 LoadVarDetails {
-                    member_index: Some((_, Some(_))),
+                    member_index: Some(_),
                     ..
                 },
             ) => {
                 let index = details.member_index.unwrap().0;
+                let sreg = match numeric_id_defs.special_registers.get(details.arg.src) {
+                    Some(reg) => reg,
+                    None => {
+                        result.push(Statement::LoadVar(details));
+                        continue;
+                    }
+                };
                 if index == 3 {
                     result.push(Statement::Constant(ConstantDefinition {
                         dst: details.arg.dst,
@@ -1364,11 +1829,7 @@ fn fix_builtins(
                         value: ast::ImmediateValue::U64(0),
                     }));
                 } else {
-                    let sreg_and_type = match numeric_id_defs.special_registers.get(details.arg.src)
-                    {
-                        Some(reg) => get_sreg_id_scalar_type(numeric_id_defs, reg),
-                        None => None,
-                    };
+                    let sreg_and_type = get_sreg_id_scalar_type(numeric_id_defs, sreg);
                     let (sreg_src, scalar_typ, vector_width) = match sreg_and_type {
                         Some(sreg_and_type) => sreg_and_type,
                         None => {
@@ -1385,7 +1846,7 @@ fn fix_builtins(
                             dst: temp_id,
                         },
                         typ: ast::Type::Scalar(scalar_typ),
-                        member_index: Some((index, Some(vector_width))),
+                        member_index: Some((index, vector_width)),
                     }));
                     result.push(Statement::Conversion(ImplicitConversion {
                         src: temp_id,
@@ -1423,11 +1884,15 @@ fn get_sreg_id_scalar_type(
 fn extract_globals<'input, 'b>(
     sorted_statements: Vec<ExpandedStatement>,
     ptx_impl_imports: &mut HashMap<String, Directive>,
+    options: &TranslateOptions,
     id_def: &mut NumericIdResolver,
-) -> (
-    Vec<ExpandedStatement>,
-    Vec<ast::Variable<ast::VariableType, spirv::Word>>,
-) {
+) -> Result<
+    (
+        Vec<ExpandedStatement>,
+        Vec<ast::Variable<ast::VariableType, spirv::Word>>,
+    ),
+    TranslateError,
+> {
     let mut local = Vec::with_capacity(sorted_statements.len());
     let mut global = Vec::new();
     for statement in sorted_statements {
@@ -1470,6 +1935,7 @@ fn extract_globals<'input, 'b>(
                     d,
                     a,
                     "inc",
+                    ast::SizedScalarType::U32,
                 ));
             }
             Statement::Instruction(ast::Instruction::Atom(
@@ -1491,12 +1957,85 @@ fn extract_globals<'input, 'b>(
                     d,
                     a,
                     "dec",
+                    ast::SizedScalarType::U32,
+                ));
+            }
+            Statement::Instruction(ast::Instruction::Atom(
+                d
+                @
+                ast::AtomDetails {
+                    inner:
+                        ast::AtomInnerDetails::Float {
+                            op: ast::AtomFloatOp::Add,
+                            typ: ast::FloatType::F32,
+                        },
+                    ..
+                },
+                a,
+            )) if !options.atomic_float32_add => {
+                local.push(to_ptx_impl_atomic_call(
+                    id_def,
+                    ptx_impl_imports,
+                    d,
+                    a,
+                    "add_f32",
+                    ast::SizedScalarType::F32,
+                ));
+            }
+            Statement::Instruction(ast::Instruction::Atom(
+                d
+                @
+                ast::AtomDetails {
+                    inner:
+                        ast::AtomInnerDetails::Float {
+                            op: ast::AtomFloatOp::Add,
+                            typ: ast::FloatType::F64,
+                        },
+                    ..
+                },
+                a,
+            )) if !options.atomic_float64_add => {
+                local.push(to_ptx_impl_atomic_call(
+                    id_def,
+                    ptx_impl_imports,
+                    d,
+                    a,
+                    "add_f64",
+                    ast::SizedScalarType::F64,
                 ));
             }
+            // red has no destination, but ptx_impl atomics always return the old value
+            Statement::Instruction(ast::Instruction::Red(d, a)) => {
+                match d.inner.ptx_impl_op(options) {
+                    Some((op, typ)) => {
+                        let dst = id_def.new_non_variable(Some(ast::Type::Scalar(typ.into())));
+                        let arg = ast::Arg3 {
+                            dst,
+                            src1: a.src1,
+                            src2: a.src2,
+                        };
+                        local.push(to_ptx_impl_atomic_call(
+                            id_def,
+                            ptx_impl_imports,
+                            d,
+                            arg,
+                            op,
+                            typ,
+                        ));
+                    }
+                    None => local.push(Statement::Instruction(ast::Instruction::Red(d, a))),
+                }
+            }
+            Statement::Instruction(ast::Instruction::WmmaLoad(d, a)) => {
+                local.extend(to_ptx_impl_wmma_load_calls(id_def, ptx_impl_imports, d, a));
+            }
+            Statement::Instruction(ast::Instruction::WmmaStore(d, a)) => {
+                local.extend(to_ptx_impl_wmma_store_calls(id_def, ptx_impl_imports, d, a));
+            }
             s => local.push(s),
         }
     }
-    (local, global)
+    Ok((local, global))
 }
 
 fn normalize_variable_decls(directives: &mut Vec<Directive>) {
@@ -1607,9 +2146,19 @@ impl<'a, 'b> VectorRepackVisitor<'a, 'b> {
         // mov.u32 foobar, {a,b};
         let scalar_t = match typ {
             ast::Type::Vector(scalar_t, _) => *scalar_t,
+            // mov.b64 {lo,hi}, foobar; the packed value is bitcast to/from the
+            // vector by implicit conversions
+            ast::Type::Scalar(scalar_t)
+                if scalar_t.kind() == ScalarKind::Bit
+                    && scalar_t.size_of() as usize % idx.len() == 0 =>
+            {
+                ast::ScalarType::from_parts(scalar_t.size_of() / idx.len() as u8, ScalarKind::Bit)
+            }
             _ => return Err(TranslateError::MismatchedType),
         };
-        let temp_vec = self.id_def.new_non_variable(Some(typ.clone()));
+        let temp_vec = self
+            .id_def
+            .new_non_variable(Some(ast::Type::Vector(scalar_t, idx.len() as u8)));
         let statement = Statement::RepackVector(RepackVectorDetails {
             is_extract: is_dst,
             typ: scalar_t,
@@ -1654,13 +2203,13 @@ impl<'a, 'b> ArgumentMapVisitor<NormalizedArgParams, TypedArgParams>
     }
 }
 
-//TODO: share common code between this and to_ptx_impl_bfe_call
 fn to_ptx_impl_atomic_call(
     id_defs: &mut NumericIdResolver,
     ptx_impl_imports: &mut HashMap<String, Directive>,
     details: ast::AtomDetails,
     arg: ast::Arg3<ExpandedArgParams>,
     op: &'static str,
+    typ: ast::SizedScalarType,
 ) -> ExpandedStatement {
     let semantics = ptx_semantics_name(details.semantics);
     let scope = ptx_scope_name(details.scope);
@@ -1675,79 +2224,20 @@ fn to_ptx_impl_atomic_call(
         ast::AtomSpace::Global => ast::PointerStateSpace::Global,
         ast::AtomSpace::Shared => ast::PointerStateSpace::Shared,
     };
-    let fn_id = match ptx_impl_imports.entry(fn_name) {
-        hash_map::Entry::Vacant(entry) => {
-            let fn_id = id_defs.new_non_variable(None);
-            let func_decl = ast::MethodDecl::Func::<spirv::Word>(
-                vec![ast::FnArgument {
-                    align: None,
-                    v_type: ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(
-                        ast::ScalarType::U32,
-                    )),
-                    name: id_defs.new_non_variable(None),
-                    array_init: Vec::new(),
-                }],
-                fn_id,
-                vec![
-                    ast::FnArgument {
-                        align: None,
-                        v_type: ast::FnArgumentType::Reg(ast::VariableRegType::Pointer(
-                            ast::SizedScalarType::U32,
-                            ptr_space,
-                        )),
-                        name: id_defs.new_non_variable(None),
-                        array_init: Vec::new(),
-                    },
-                    ast::FnArgument {
-                        align: None,
-                        v_type: ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(
-                            ast::ScalarType::U32,
-                        )),
-                        name: id_defs.new_non_variable(None),
-                        array_init: Vec::new(),
-                    },
-                ],
-            );
-            let spirv_decl = SpirvMethodDecl::new(&func_decl);
-            let func = Function {
-                func_decl,
-                globals: Vec::new(),
-                body: None,
-                import_as: Some(entry.key().clone()),
-                spirv_decl,
-            };
-            entry.insert(Directive::Method(func));
-            fn_id
-        }
-        hash_map::Entry::Occupied(entry) => match entry.get() {
-            Directive::Method(Function {
-                func_decl: ast::MethodDecl::Func(_, name, _),
-                ..
-            }) => *name,
-            _ => unreachable!(),
-        },
-    };
-    Statement::Call(ResolvedCall {
-        uniform: false,
-        func: fn_id,
-        ret_params: vec![(
-            arg.dst,
-            ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(ast::ScalarType::U32)),
-        )],
-        param_list: vec![
+    let scalar_type = ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(typ.into()));
+    to_ptx_impl_call(
+        id_defs,
+        ptx_impl_imports,
+        fn_name,
+        vec![(arg.dst, scalar_type.clone())],
+        vec![
             (
                 arg.src1,
-                ast::FnArgumentType::Reg(ast::VariableRegType::Pointer(
-                    ast::SizedScalarType::U32,
-                    ptr_space,
-                )),
-            ),
-            (
-                arg.src2,
-                ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(ast::ScalarType::U32)),
+                ast::FnArgumentType::Reg(ast::VariableRegType::Pointer(typ, ptr_space)),
             ),
+            (arg.src2, scalar_type),
         ],
-    })
+    )
 }
 
 fn to_ptx_impl_bfe_call(
@@ -1765,41 +2255,144 @@ fn to_ptx_impl_bfe_call(
         _ => unreachable!(),
     };
     let fn_name = format!("{}{}", prefix, suffix);
-    let fn_id = match ptx_impl_imports.entry(fn_name) {
-        hash_map::Entry::Vacant(entry) => {
-            let fn_id = id_defs.new_non_variable(None);
-            let func_decl = ast::MethodDecl::Func::<spirv::Word>(
-                vec![ast::FnArgument {
-                    align: None,
-                    v_type: ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(typ.into())),
-                    name: id_defs.new_non_variable(None),
-                    array_init: Vec::new(),
-                }],
-                fn_id,
-                vec![
-                    ast::FnArgument {
-                        align: None,
-                        v_type: ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(typ.into())),
-                        name: id_defs.new_non_variable(None),
-                        array_init: Vec::new(),
-                    },
-                    ast::FnArgument {
-                        align: None,
-                        v_type: ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(
-                            ast::ScalarType::U32,
-                        )),
-                        name: id_defs.new_non_variable(None),
-                        array_init: Vec::new(),
-                    },
-                    ast::FnArgument {
+    let value_type = ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(typ.into()));
+    let u32_type = ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(ast::ScalarType::U32));
+    to_ptx_impl_call(
+        id_defs,
+        ptx_impl_imports,
+        fn_name,
+        vec![(arg.dst, value_type.clone())],
+        vec![
+            (arg.src1, value_type),
+            (arg.src2, u32_type.clone()),
+            (arg.src3, u32_type),
+        ],
+    )
+}
+
+// Fragments are loaded and stored one .b32 register at a time, the register
+// index is passed to the implementation which maps it (together with the lane
+// id) to a pair of matrix elements
+fn to_ptx_impl_wmma_load_calls(
+    id_defs: &mut NumericIdResolver,
+    ptx_impl_imports: &mut HashMap<String, Directive>,
+    details: ast::WmmaLoadDetails,
+    arg: ast::ArgWmmaLoad<ExpandedArgParams>,
+) -> Vec<ExpandedStatement> {
+    let matrix = match details.matrix {
+        ast::WmmaMatrix::A => "a",
+        ast::WmmaMatrix::B => "b",
+        ast::WmmaMatrix::C => "c",
+    };
+    let fn_name = format!(
+        "__zluda_ptx_impl__wmma_load_{}_{}_m16n16k16_{}_f16",
+        matrix,
+        ptx_wmma_layout_name(details.layout),
+        ptx_ld_space_name(details.space)
+    );
+    let ptr_type = wmma_pointer_type(details.space);
+    let b32_type = ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(ast::ScalarType::B32));
+    let u32_type = ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(ast::ScalarType::U32));
+    let mut result = Vec::with_capacity(arg.dst.len() * 2);
+    for (index, dst) in arg.dst.into_iter().enumerate() {
+        let index_id = id_defs.new_non_variable(Some(ast::Type::Scalar(ast::ScalarType::U32)));
+        result.push(Statement::Constant(ConstantDefinition {
+            dst: index_id,
+            typ: ast::ScalarType::U32,
+            value: ast::ImmediateValue::U64(index as u64),
+        }));
+        result.push(to_ptx_impl_call(
+            id_defs,
+            ptx_impl_imports,
+            fn_name.clone(),
+            vec![(dst, b32_type.clone())],
+            vec![
+                (arg.src1, ptr_type.clone()),
+                (arg.src2, u32_type.clone()),
+                (index_id, u32_type.clone()),
+            ],
+        ));
+    }
+    result
+}
+
+fn to_ptx_impl_wmma_store_calls(
+    id_defs: &mut NumericIdResolver,
+    ptx_impl_imports: &mut HashMap<String, Directive>,
+    details: ast::WmmaStoreDetails,
+    arg: ast::ArgWmmaStore<ExpandedArgParams>,
+) -> Vec<ExpandedStatement> {
+    let fn_name = format!(
+        "__zluda_ptx_impl__wmma_store_d_{}_m16n16k16_{}_f16",
+        ptx_wmma_layout_name(details.layout),
+        ptx_ld_space_name(details.space)
+    );
+    let ptr_type = wmma_pointer_type(details.space);
+    let b32_type = ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(ast::ScalarType::B32));
+    let u32_type = ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(ast::ScalarType::U32));
+    let mut result = Vec::with_capacity(arg.src2.len() * 2);
+    for (index, src) in arg.src2.into_iter().enumerate() {
+        let index_id = id_defs.new_non_variable(Some(ast::Type::Scalar(ast::ScalarType::U32)));
+        result.push(Statement::Constant(ConstantDefinition {
+            dst: index_id,
+            typ: ast::ScalarType::U32,
+            value: ast::ImmediateValue::U64(index as u64),
+        }));
+        result.push(to_ptx_impl_call(
+            id_defs,
+            ptx_impl_imports,
+            fn_name.clone(),
+            Vec::new(),
+            vec![
+                (arg.src1, ptr_type.clone()),
+                (src, b32_type.clone()),
+                (arg.src3, u32_type.clone()),
+                (index_id, u32_type.clone()),
+            ],
+        ));
+    }
+    result
+}
+
+fn wmma_pointer_type(space: ast::LdStateSpace) -> ast::FnArgumentType {
+    let ptr_space = match space {
+        ast::LdStateSpace::Generic => ast::PointerStateSpace::Generic,
+        ast::LdStateSpace::Global => ast::PointerStateSpace::Global,
+        ast::LdStateSpace::Shared => ast::PointerStateSpace::Shared,
+        _ => unreachable!(),
+    };
+    ast::FnArgumentType::Reg(ast::VariableRegType::Pointer(
+        ast::SizedScalarType::U16,
+        ptr_space,
+    ))
+}
+
+// Calls a function from zluda_ptx_impl, it is declared as an import with the
+// types of the arguments on first use
+fn to_ptx_impl_call(
+    id_defs: &mut NumericIdResolver,
+    ptx_impl_imports: &mut HashMap<String, Directive>,
+    fn_name: String,
+    ret_params: Vec<(spirv::Word, ast::FnArgumentType)>,
+    param_list: Vec<(spirv::Word, ast::FnArgumentType)>,
+) -> ExpandedStatement {
+    let fn_id = match ptx_impl_imports.entry(fn_name) {
+        hash_map::Entry::Vacant(entry) => {
+            let fn_id = id_defs.new_non_variable(None);
+            let mut to_fn_args = |args: &[(spirv::Word, ast::FnArgumentType)]| {
+                args.iter()
+                    .map(|(_, v_type)| ast::FnArgument {
                         align: None,
-                        v_type: ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(
-                            ast::ScalarType::U32,
-                        )),
+                        v_type: v_type.clone(),
                         name: id_defs.new_non_variable(None),
                         array_init: Vec::new(),
-                    },
-                ],
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let func_decl = ast::MethodDecl::Func::<spirv::Word>(
+                to_fn_args(&ret_params),
+                fn_id,
+                to_fn_args(&param_list),
             );
             let spirv_decl = SpirvMethodDecl::new(&func_decl);
             let func = Function {
@@ -1807,6 +2400,7 @@ fn to_ptx_impl_bfe_call(
                 globals: Vec::new(),
                 body: None,
                 import_as: Some(entry.key().clone()),
+                linkage: None,
                 spirv_decl,
             };
             entry.insert(Directive::Method(func));
@@ -1823,24 +2417,8 @@ fn to_ptx_impl_bfe_call(
     Statement::Call(ResolvedCall {
         uniform: false,
         func: fn_id,
-        ret_params: vec![(
-            arg.dst,
-            ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(typ.into())),
-        )],
-        param_list: vec![
-            (
-                arg.src1,
-                ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(typ.into())),
-            ),
-            (
-                arg.src2,
-                ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(ast::ScalarType::U32)),
-            ),
-            (
-                arg.src3,
-                ast::FnArgumentType::Reg(ast::VariableRegType::Scalar(ast::ScalarType::U32)),
-            ),
-        ],
+        ret_params,
+        param_list,
     })
 }
 
@@ -1988,21 +2566,24 @@ fn insert_mem_ssa_statements<'a, 'b>(
             }
             Statement::Instruction(inst) => match inst {
                 ast::Instruction::Ret(d) => {
-                    // TODO: handle multiple output args
-                    if let &[out_param] = &fn_decl.output.as_slice() {
-                        let (typ, _) = id_def.get_typed(out_param.name)?;
-                        let new_id = id_def.new_non_variable(Some(typ.clone()));
-                        result.push(Statement::LoadVar(LoadVarDetails {
-                            arg: ast::Arg2 {
-                                dst: new_id,
-                                src: out_param.name,
-                            },
-                            typ: typ.clone(),
-                            member_index: None,
-                        }));
-                        result.push(Statement::RetValue(d, new_id));
-                    } else {
+                    if fn_decl.output.is_empty() {
                         result.push(Statement::Instruction(ast::Instruction::Ret(d)))
+                    } else {
+                        let mut values = Vec::with_capacity(fn_decl.output.len());
+                        for out_param in fn_decl.output.iter() {
+                            let (typ, _) = id_def.get_typed(out_param.name)?;
+                            let new_id = id_def.new_non_variable(Some(typ.clone()));
+                            result.push(Statement::LoadVar(LoadVarDetails {
+                                arg: ast::Arg2 {
+                                    dst: new_id,
+                                    src: out_param.name,
+                                },
+                                typ: typ.clone(),
+                                member_index: None,
+                            }));
+                            values.push((new_id, typ));
+                        }
+                        result.push(Statement::RetValue(d, values));
                     }
                 }
                 inst => insert_mem_ssa_statement_default(id_def, &mut result, inst)?,
@@ -2068,6 +2649,11 @@ fn type_to_variable_type(
             )))
         }
         ast::Type::Pointer(_, ast::LdStateSpace::Shared) => None,
+        // Aggregate .param arguments and return values, e.g. a .b8 array
+        // holding a packed struct, are passed by pointer just like scalars
+        ast::Type::Pointer(ast::PointerType::Array(..), ast::LdStateSpace::Param) if is_func => {
+            None
+        }
         _ => return Err(error_unreachable()),
     })
 }
@@ -2133,14 +2719,7 @@ impl<'a, 'input> InsertMemSSAVisitor<'a, 'input> {
                     }
                     _ => return Err(TranslateError::MismatchedType),
                 };
-                Some((
-                    idx,
-                    if self.id_def.special_registers.get(symbol).is_some() {
-                        Some(vector_width)
-                    } else {
-                        None
-                    },
-                ))
+                Some((idx, vector_width))
             }
             None => None,
         };
@@ -2162,7 +2741,7 @@ impl<'a, 'input> InsertMemSSAVisitor<'a, 'input> {
                         src2: generated_id,
                     },
                     typ: var_type,
-                    member_index: member_index.map(|(idx, _)| idx),
+                    member_index,
                 }));
         }
         Ok(generated_id)
@@ -2399,7 +2978,7 @@ impl<'a, 'b> FlattenArguments<'a, 'b> {
         let scalar_t = if let ast::Type::Scalar(scalar) = typ {
             *scalar
         } else {
-            todo!()
+            return Err(TranslateError::Todo);
         };
         let id = self.id_def.new_non_variable(ast::Type::Scalar(scalar_t));
         self.func.push(Statement::Constant(ConstantDefinition {
@@ -2480,6 +3059,18 @@ fn insert_implicit_conversions(
                 if let ast::Instruction::AtomCas(d, _) = &inst {
                     state_space = Some(d.space.to_ld_ss());
                 }
+                if let ast::Instruction::Red(d, _) = &inst {
+                    state_space = Some(d.space.to_ld_ss());
+                }
+                if let ast::Instruction::WmmaLoad(d, _) = &inst {
+                    state_space = Some(d.space);
+                }
+                if let ast::Instruction::WmmaStore(d, _) = &inst {
+                    state_space = Some(d.space);
+                }
+                if let ast::Instruction::Prefetch(d, _) = &inst {
+                    state_space = Some(d.state_space);
+                }
                 if let ast::Instruction::Mov(..) = &inst {
                     default_conversion_fn = should_bitcast_packed;
                 }
@@ -2623,24 +3214,350 @@ fn get_function_type(
     map: &mut TypeWordMap,
     spirv_input: &[ast::Variable<ast::Type, spirv::Word>],
     spirv_output: &[ast::Variable<ast::Type, spirv::Word>],
-) -> (spirv::Word, spirv::Word) {
+    textures: usize,
+) -> Result<(spirv::Word, spirv::Word), TranslateError> {
+    let texture_params = (0..textures).flat_map(|_| vec![SpirvType::Image, SpirvType::Sampler]);
     map.get_or_add_fn(
         builder,
         spirv_input
             .iter()
-            .map(|var| SpirvType::from(var.v_type.clone())),
+            .map(|var| SpirvType::from(var.v_type.clone()))
+            .chain(texture_params)
+            .collect::<Vec<_>>()
+            .into_iter(),
         spirv_output
             .iter()
             .map(|var| SpirvType::from(var.v_type.clone())),
     )
 }
 
+// A function with more than one non-.param return value returns all of them
+// packed in a struct. Only scalar return values can be packed
+fn multiple_return_type(
+    types: impl Iterator<Item = SpirvType>,
+) -> Result<SpirvType, TranslateError> {
+    let members = types
+        .map(|typ| match typ {
+            SpirvType::Base(key) => Ok(key),
+            _ => Err(TranslateError::Todo),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(SpirvType::Struct(members))
+}
+
+// Control flow graph of a function body at the granularity of labels. Block 0
+// is the block of the first label, which is always the function entry.
+// Statements between a terminator and the next label are unreachable and are
+// not part of any block
+struct ControlFlowGraph {
+    labels: Vec<spirv::Word>,
+    successors: Vec<Vec<usize>>,
+    predecessors: Vec<Vec<usize>>,
+    idom: Vec<Option<usize>>,
+}
+
+struct StructuredLoop {
+    merge: spirv::Word,
+    continue_target: spirv::Word,
+}
+
+impl ControlFlowGraph {
+    fn new(func: &[ExpandedStatement]) -> Self {
+        let mut labels = Vec::new();
+        let mut label_to_block = HashMap::new();
+        for s in func {
+            if let Statement::Label(id) = s {
+                label_to_block.insert(*id, labels.len());
+                labels.push(*id);
+            }
+        }
+        let mut successors = vec![Vec::new(); labels.len()];
+        let mut current_block = None;
+        let mut add_edge = |from: Option<usize>, to: spirv::Word| {
+            if let (Some(from), Some(to)) = (from, label_to_block.get(&to)) {
+                let edges: &mut Vec<usize> = &mut successors[from];
+                if !edges.contains(to) {
+                    edges.push(*to);
+                }
+            }
+        };
+        for s in func {
+            match s {
+                Statement::Label(id) => {
+                    add_edge(current_block, *id);
+                    current_block = label_to_block.get(id).copied();
+                }
+                Statement::Conditional(bra) => {
+                    add_edge(current_block, bra.if_true);
+                    add_edge(current_block, bra.if_false);
+                    current_block = None;
+                }
+                Statement::Instruction(ast::Instruction::Bra(_, arg)) => {
+                    add_edge(current_block, arg.src);
+                    current_block = None;
+                }
+                Statement::Instruction(ast::Instruction::Ret(_)) | Statement::RetValue(..) => {
+                    current_block = None;
+                }
+                _ => {}
+            }
+        }
+        let mut predecessors = vec![Vec::new(); labels.len()];
+        for (from, edges) in successors.iter().enumerate() {
+            for to in edges.iter() {
+                predecessors[*to].push(from);
+            }
+        }
+        let idom = if labels.is_empty() {
+            Vec::new()
+        } else {
+            immediate_dominators(0, &successors, &predecessors)
+        };
+        ControlFlowGraph {
+            labels,
+            successors,
+            predecessors,
+            idom,
+        }
+    }
+
+    // A graph is reducible if every edge that closes a cycle during a depth
+    // first traversal jumps to a block dominating its source. Otherwise there
+    // is a loop that can be entered through more than one block
+    fn is_reducible(&self) -> bool {
+        if self.labels.is_empty() {
+            return true;
+        }
+        let mut on_stack = vec![false; self.labels.len()];
+        let mut visited = vec![false; self.labels.len()];
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        on_stack[0] = true;
+        while let Some((block, next_edge)) = stack.pop() {
+            if let Some(&succ) = self.successors[block].get(next_edge) {
+                stack.push((block, next_edge + 1));
+                if on_stack[succ] {
+                    if !dominates(&self.idom, succ, block) {
+                        return false;
+                    }
+                } else if !visited[succ] {
+                    visited[succ] = true;
+                    on_stack[succ] = true;
+                    stack.push((succ, 0));
+                }
+            } else {
+                on_stack[block] = false;
+            }
+        }
+        true
+    }
+
+    // Finds loops that have a single back-edge and a single exit block, keyed
+    // by the label of the loop header. Other loops are left unannotated, which
+    // is legal in kernels, but gives the driver compiler less to work with
+    fn structured_loops(&self) -> HashMap<spirv::Word, StructuredLoop> {
+        let mut latches = vec![Vec::new(); self.labels.len()];
+        for (block, edges) in self.successors.iter().enumerate() {
+            if self.idom[block].is_none() {
+                continue;
+            }
+            for succ in edges.iter() {
+                if dominates(&self.idom, *succ, block) {
+                    latches[*succ].push(block);
+                }
+            }
+        }
+        let mut used_merges = HashSet::new();
+        let mut result = HashMap::new();
+        for (header, latches) in latches.iter().enumerate() {
+            let latch = match latches[..] {
+                [latch] => latch,
+                _ => continue,
+            };
+            let body = self.natural_loop(header, latch);
+            let mut exits = body
+                .iter()
+                .flat_map(|block| self.successors[*block].iter())
+                .filter(|succ| !body.contains(*succ))
+                .collect::<HashSet<_>>()
+                .into_iter();
+            let merge = match (exits.next(), exits.next()) {
+                (Some(merge), None) => *merge,
+                _ => continue,
+            };
+            if !dominates(&self.idom, header, merge) || !used_merges.insert(merge) {
+                continue;
+            }
+            result.insert(
+                self.labels[header],
+                StructuredLoop {
+                    merge: self.labels[merge],
+                    continue_target: self.labels[latch],
+                },
+            );
+        }
+        result
+    }
+
+    // Finds the merge block of every two-way conditional branch that is not a
+    // loop back-edge, keyed by the label of the block ending with the branch.
+    // The merge block is the immediate post-dominator of the branch block,
+    // unless it's already claimed by a loop or another selection
+    fn selection_merges(
+        &self,
+        loops: &HashMap<spirv::Word, StructuredLoop>,
+    ) -> HashMap<spirv::Word, spirv::Word> {
+        let ipdom = self.immediate_post_dominators();
+        let exit = self.labels.len();
+        let mut used_merges = loops
+            .values()
+            .flat_map(|l| iter::once(l.merge).chain(iter::once(l.continue_target)))
+            .collect::<HashSet<_>>();
+        let mut result = HashMap::new();
+        for (block, edges) in self.successors.iter().enumerate() {
+            if edges.len() != 2 || self.idom[block].is_none() {
+                continue;
+            }
+            if edges.iter().any(|succ| dominates(&self.idom, *succ, block)) {
+                continue;
+            }
+            let merge = match ipdom[block] {
+                Some(merge) if merge != exit => merge,
+                _ => continue,
+            };
+            if !dominates(&self.idom, block, merge) || !used_merges.insert(self.labels[merge]) {
+                continue;
+            }
+            result.insert(self.labels[block], self.labels[merge]);
+        }
+        result
+    }
+
+    // Post-dominators are dominators of the reversed graph, all the blocks
+    // without successors are joined by a virtual exit block
+    fn immediate_post_dominators(&self) -> Vec<Option<usize>> {
+        let exit = self.labels.len();
+        let mut successors = self.predecessors.clone();
+        successors.push(Vec::new());
+        let mut predecessors = self.successors.clone();
+        predecessors.push(Vec::new());
+        for (block, edges) in self.successors.iter().enumerate() {
+            if edges.is_empty() && self.idom[block].is_some() {
+                successors[exit].push(block);
+                predecessors[block].push(exit);
+            }
+        }
+        immediate_dominators(exit, &successors, &predecessors)
+    }
+
+    fn natural_loop(&self, header: usize, latch: usize) -> HashSet<usize> {
+        let mut body = HashSet::new();
+        body.insert(header);
+        let mut stack = vec![latch];
+        while let Some(block) = stack.pop() {
+            if self.idom[block].is_some() && body.insert(block) {
+                stack.extend(self.predecessors[block].iter().copied());
+            }
+        }
+        body
+    }
+}
+
+fn reverse_postorder(entry: usize, successors: &[Vec<usize>]) -> Vec<usize> {
+    let mut visited = vec![false; successors.len()];
+    let mut postorder = Vec::with_capacity(successors.len());
+    let mut stack = vec![(entry, 0)];
+    visited[entry] = true;
+    while let Some((node, next_edge)) = stack.pop() {
+        if let Some(&succ) = successors[node].get(next_edge) {
+            stack.push((node, next_edge + 1));
+            if !visited[succ] {
+                visited[succ] = true;
+                stack.push((succ, 0));
+            }
+        } else {
+            postorder.push(node);
+        }
+    }
+    postorder.reverse();
+    postorder
+}
+
+// "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy. Nodes
+// unreachable from the entry have no immediate dominator, the entry is its
+// own immediate dominator
+fn immediate_dominators(
+    entry: usize,
+    successors: &[Vec<usize>],
+    predecessors: &[Vec<usize>],
+) -> Vec<Option<usize>> {
+    let order = reverse_postorder(entry, successors);
+    let mut order_index = vec![usize::MAX; successors.len()];
+    for (i, node) in order.iter().enumerate() {
+        order_index[*node] = i;
+    }
+    let mut idom = vec![None; successors.len()];
+    idom[entry] = Some(entry);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for node in order.iter().skip(1) {
+            let mut new_idom = None;
+            for pred in predecessors[*node].iter() {
+                if idom[*pred].is_none() {
+                    continue;
+                }
+                new_idom = Some(match new_idom {
+                    None => *pred,
+                    Some(mut other) => {
+                        let mut pred = *pred;
+                        while pred != other {
+                            while order_index[pred] > order_index[other] {
+                                pred = idom[pred].unwrap();
+                            }
+                            while order_index[other] > order_index[pred] {
+                                other = idom[other].unwrap();
+                            }
+                        }
+                        pred
+                    }
+                });
+            }
+            if new_idom.is_some() && idom[*node] != new_idom {
+                idom[*node] = new_idom;
+                changed = true;
+            }
+        }
+    }
+    idom
+}
+
+fn dominates(idom: &[Option<usize>], dominator: usize, mut node: usize) -> bool {
+    loop {
+        if node == dominator {
+            return true;
+        }
+        match idom[node] {
+            Some(parent) if parent != node => node = parent,
+            _ => return false,
+        }
+    }
+}
+
 fn emit_function_body_ops(
     builder: &mut dr::Builder,
     map: &mut TypeWordMap,
     opencl: spirv::Word,
+    special_registers: &SpecialRegistersMap,
     func: &[ExpandedStatement],
 ) -> Result<(), TranslateError> {
+    let cfg = ControlFlowGraph::new(func);
+    if !cfg.is_reducible() {
+        return Err(TranslateError::IrreducibleControlFlow);
+    }
+    let loops = cfg.structured_loops();
+    let selections = cfg.selection_merges(&loops);
+    let mut current_block = None;
     for s in func {
         match s {
             Statement::Label(id) => {
@@ -2648,6 +3565,25 @@ fn emit_function_body_ops(
                     builder.branch(*id)?;
                 }
                 builder.begin_block(Some(*id))?;
+                current_block = Some(*id);
+                // Loop header is emitted as a separate block, this way
+                // OpLoopMerge is always followed by a valid terminator
+                if let Some(structured_loop) = loops.get(id) {
+                    let body = builder.id();
+                    let continue_target = if structured_loop.continue_target == *id {
+                        body
+                    } else {
+                        structured_loop.continue_target
+                    };
+                    builder.loop_merge(
+                        structured_loop.merge,
+                        continue_target,
+                        spirv::LoopControl::NONE,
+                        [],
+                    )?;
+                    builder.branch(body)?;
+                    builder.begin_block(Some(body))?;
+                }
             }
             _ => {
                 if builder.block.is_none() && builder.function.is_some() {
@@ -2658,20 +3594,41 @@ fn emit_function_body_ops(
         match s {
             Statement::Label(_) => (),
             Statement::Call(call) => {
-                let (result_type, result_id) = match &*call.ret_params {
-                    [(id, typ)] => (
-                        map.get_or_add(builder, SpirvType::from(typ.to_func_type())),
-                        Some(*id),
-                    ),
-                    [] => (map.void(), None),
-                    _ => todo!(),
-                };
                 let arg_list = call
                     .param_list
                     .iter()
                     .map(|(id, _)| *id)
                     .collect::<Vec<_>>();
-                builder.function_call(result_type, result_id, call.func, arg_list)?;
+                match &*call.ret_params {
+                    [(id, typ)] => {
+                        let result_type =
+                            map.get_or_add(builder, SpirvType::from(typ.to_func_type()));
+                        builder.function_call(result_type, Some(*id), call.func, arg_list)?;
+                    }
+                    [] => {
+                        builder.function_call(map.void(), None, call.func, arg_list)?;
+                    }
+                    ret_params => {
+                        let struct_type = multiple_return_type(
+                            ret_params
+                                .iter()
+                                .map(|(_, typ)| SpirvType::from(typ.to_func_type())),
+                        )?;
+                        let struct_type = map.get_or_add(builder, struct_type);
+                        let result =
+                            builder.function_call(struct_type, None, call.func, arg_list)?;
+                        for (index, (id, typ)) in ret_params.iter().enumerate() {
+                            let result_type =
+                                map.get_or_add(builder, SpirvType::from(typ.to_func_type()));
+                            builder.composite_extract(
+                                result_type,
+                                Some(*id),
+                                result,
+                                [index as u32],
+                            )?;
+                        }
+                    }
+                }
             }
             Statement::Variable(var) => {
                 emit_variable(builder, map, var)?;
@@ -2774,6 +3731,9 @@ fn emit_function_body_ops(
             }
             Statement::Conversion(cv) => emit_implicit_conversion(builder, map, cv)?,
             Statement::Conditional(bra) => {
+                if let Some(merge) = current_block.take().and_then(|b| selections.get(&b)) {
+                    builder.selection_merge(*merge, spirv::SelectionControl::NONE)?;
+                }
                 builder.branch_conditional(bra.predicate, bra.if_true, bra.if_false, [])?;
             }
             Statement::Instruction(inst) => match inst {
@@ -2784,29 +3744,43 @@ fn emit_function_body_ops(
                     builder.branch(arg.src)?;
                 }
                 ast::Instruction::Ld(data, arg) => {
-                    if data.qualifier != ast::LdStQualifier::Weak {
-                        todo!()
-                    }
+                    let memory_access = ld_st_memory_access(
+                        data.qualifier,
+                        match data.caching {
+                            // Fetch again on every load, same as volatile
+                            ast::LdCacheOperator::Uncached => spirv::MemoryAccess::VOLATILE,
+                            ast::LdCacheOperator::Cached
+                            | ast::LdCacheOperator::L2Only
+                            | ast::LdCacheOperator::Streaming
+                            | ast::LdCacheOperator::LastUse => spirv::MemoryAccess::NONE,
+                        },
+                    )?;
                     let result_type =
                         map.get_or_add(builder, SpirvType::from(ast::Type::from(data.typ.clone())));
                     builder.load(
                         result_type,
                         Some(arg.dst),
                         arg.src,
-                        Some(spirv::MemoryAccess::ALIGNED),
+                        Some(memory_access),
                         [dr::Operand::LiteralInt32(
                             ast::Type::from(data.typ.clone()).size_of() as u32,
                         )],
                     )?;
                 }
                 ast::Instruction::St(data, arg) => {
-                    if data.qualifier != ast::LdStQualifier::Weak {
-                        todo!()
-                    }
+                    let memory_access = ld_st_memory_access(
+                        data.qualifier,
+                        match data.caching {
+                            ast::StCacheOperator::Writeback
+                            | ast::StCacheOperator::L2Only
+                            | ast::StCacheOperator::Streaming
+                            | ast::StCacheOperator::Writethrough => spirv::MemoryAccess::NONE,
+                        },
+                    )?;
                     builder.store(
                         arg.src1,
                         arg.src2,
-                        Some(spirv::MemoryAccess::ALIGNED),
+                        Some(memory_access),
                         [dr::Operand::LiteralInt32(
                             ast::Type::from(data.typ.clone()).size_of() as u32,
                         )],
@@ -2827,6 +3801,9 @@ fn emit_function_body_ops(
                         emit_mul_uint(builder, map, opencl, ctr, arg)?
                     }
                     ast::MulDetails::Float(ref ctr) => emit_mul_float(builder, map, ctr, arg)?,
+                    ast::MulDetails::BF16 => {
+                        emit_bf16_binary_op(builder, map, arg, dr::Builder::f_mul)?
+                    }
                 },
                 ast::Instruction::Add(add, arg) => match add {
                     ast::ArithDetails::Signed(ref desc) => {
@@ -2836,13 +3813,19 @@ fn emit_function_body_ops(
                         emit_add_int(builder, map, (*desc).into(), false, arg)?
                     }
                     ast::ArithDetails::Float(desc) => emit_add_float(builder, map, desc, arg)?,
+                    ast::ArithDetails::BF16 => {
+                        emit_bf16_binary_op(builder, map, arg, dr::Builder::f_add)?
+                    }
                 },
                 ast::Instruction::Setp(setp, arg) => {
-                    if arg.dst2.is_some() {
-                        todo!()
+                    if arg.dst2.is_some() && setp.typ != ast::ScalarType::F16x2 {
+                        return Err(TranslateError::Todo);
                     }
                     emit_setp(builder, map, setp, arg)?;
                 }
+                ast::Instruction::Set(set, arg) => {
+                    emit_set(builder, map, set, arg)?;
+                }
                 ast::Instruction::Not(t, a) => {
                     let result_type = map.get_or_add(builder, SpirvType::from(t.to_type()));
                     let result_id = Some(a.dst);
@@ -2870,6 +3853,9 @@ fn emit_function_body_ops(
                     let offset_src = insert_shift_hack(builder, map, a.src2, size_of)?;
                     builder.shift_left_logical(result_type, Some(a.dst), a.src1, offset_src)?;
                 }
+                ast::Instruction::Shf(details, a) => {
+                    emit_shf(builder, map, opencl, *details, a)?;
+                }
                 ast::Instruction::Shr(t, a) => {
                     let full_type = ast::ScalarType::from(*t);
                     let size_of = full_type.size_of();
@@ -2894,14 +3880,18 @@ fn emit_function_body_ops(
                 ast::Instruction::Cvt(dets, arg) => {
                     emit_cvt(builder, map, opencl, dets, arg)?;
                 }
-                ast::Instruction::Cvta(_, arg) => {
-                    // This would be only meaningful if const/slm/global pointers
-                    // had a different format than generic pointers, but they don't pretty much by ptx definition
-                    // Honestly, I have no idea why this instruction exists and is emitted by the compiler
-                    let result_type = map.get_or_add_scalar(builder, ast::ScalarType::B64);
-                    builder.copy_object(result_type, Some(arg.dst), arg.src)?;
+                ast::Instruction::Cvta(details, arg) => {
+                    emit_cvta(builder, map, details, arg)?;
+                }
+                ast::Instruction::SetpBool(_, _) => return Err(TranslateError::Todo),
+                ast::Instruction::Mul24(details, arg) => {
+                    emit_mul24(builder, map, *details, Some(arg.dst), arg.src1, arg.src2)?;
+                }
+                ast::Instruction::Mad24(details, arg) => {
+                    let u32_type = map.get_or_add_scalar(builder, ast::ScalarType::U32);
+                    let product = emit_mul24(builder, map, *details, None, arg.src1, arg.src2)?;
+                    builder.i_add(u32_type, Some(arg.dst), product, arg.src3)?;
                 }
-                ast::Instruction::SetpBool(_, _) => todo!(),
                 ast::Instruction::Mad(mad, arg) => match mad {
                     ast::MulDetails::Signed(ref desc) => {
                         emit_mad_sint(builder, map, opencl, desc, arg)?
@@ -2912,6 +3902,7 @@ fn emit_function_body_ops(
                     ast::MulDetails::Float(desc) => {
                         emit_mad_float(builder, map, opencl, desc, arg)?
                     }
+                    ast::MulDetails::BF16 => emit_bf16_fma(builder, map, opencl, arg)?,
                 },
                 ast::Instruction::Or(t, a) => {
                     let result_type = map.get_or_add_scalar(builder, ast::ScalarType::from(*t));
@@ -2931,6 +3922,9 @@ fn emit_function_body_ops(
                     ast::ArithDetails::Float(desc) => {
                         emit_sub_float(builder, map, desc, arg)?;
                     }
+                    ast::ArithDetails::BF16 => {
+                        emit_bf16_binary_op(builder, map, arg, dr::Builder::f_sub)?;
+                    }
                 },
                 ast::Instruction::Min(d, a) => {
                     emit_min(builder, map, opencl, d, a)?;
@@ -2939,7 +3933,7 @@ fn emit_function_body_ops(
                     emit_max(builder, map, opencl, d, a)?;
                 }
                 ast::Instruction::Rcp(d, a) => {
-                    emit_rcp(builder, map, d, a)?;
+                    emit_rcp(builder, map, opencl, d, a)?;
                 }
                 ast::Instruction::And(t, a) => {
                     let result_type = map.get_or_add_scalar(builder, ast::ScalarType::from(*t));
@@ -2970,11 +3964,32 @@ fn emit_function_body_ops(
                                     | spirv::MemorySemantics::SEQUENTIALLY_CONSISTENT,
                             ),
                         )?,
+                        // There is no split barrier in SPIR-V, arrive waits like sync does
+                        ast::BarDetails::Arrive => map.get_or_add_constant(
+                            builder,
+                            &ast::Type::Scalar(ast::ScalarType::U32),
+                            &vec_repr(
+                                spirv::MemorySemantics::CROSS_WORKGROUP_MEMORY
+                                    | spirv::MemorySemantics::WORKGROUP_MEMORY
+                                    | spirv::MemorySemantics::RELEASE,
+                            ),
+                        )?,
                     };
                     builder.control_barrier(workgroup_scope, workgroup_scope, barrier_semantics)?;
                 }
                 ast::Instruction::Atom(details, arg) => {
-                    emit_atom(builder, map, details, arg)?;
+                    emit_atom(
+                        builder,
+                        map,
+                        opencl,
+                        details,
+                        Some(arg.dst),
+                        arg.src1,
+                        arg.src2,
+                    )?;
+                }
+                ast::Instruction::Red(details, arg) => {
+                    emit_atom(builder, map, opencl, details, None, arg.src1, arg.src2)?;
                 }
                 ast::Instruction::AtomCas(details, arg) => {
                     let result_type = map.get_or_add_scalar(builder, details.typ.into());
@@ -3038,44 +4053,50 @@ fn emit_function_body_ops(
                 }
                 ast::Instruction::Sin { arg, .. } => {
                     let result_type = map.get_or_add_scalar(builder, ast::ScalarType::F32);
+                    let src = emit_sin_cos_range_reduction(builder, map, opencl, arg.src)?;
                     builder.ext_inst(
                         result_type,
                         Some(arg.dst),
                         opencl,
                         spirv::CLOp::sin as u32,
-                        [arg.src],
+                        [src],
                     )?;
                 }
                 ast::Instruction::Cos { arg, .. } => {
                     let result_type = map.get_or_add_scalar(builder, ast::ScalarType::F32);
+                    let src = emit_sin_cos_range_reduction(builder, map, opencl, arg.src)?;
                     builder.ext_inst(
                         result_type,
                         Some(arg.dst),
                         opencl,
                         spirv::CLOp::cos as u32,
-                        [arg.src],
+                        [src],
                     )?;
                 }
-                ast::Instruction::Lg2 { arg, .. } => {
-                    let result_type = map.get_or_add_scalar(builder, ast::ScalarType::F32);
-                    builder.ext_inst(
-                        result_type,
-                        Some(arg.dst),
+                ast::Instruction::Lg2 { flush_to_zero, arg } => {
+                    emit_f32_ext_inst_ftz(
+                        builder,
+                        map,
                         opencl,
-                        spirv::CLOp::log2 as u32,
-                        [arg.src],
+                        spirv::CLOp::log2,
+                        *flush_to_zero,
+                        arg,
                     )?;
                 }
-                ast::Instruction::Ex2 { arg, .. } => {
-                    let result_type = map.get_or_add_scalar(builder, ast::ScalarType::F32);
-                    builder.ext_inst(
-                        result_type,
-                        Some(arg.dst),
+                ast::Instruction::Ex2 { flush_to_zero, arg } => {
+                    emit_f32_ext_inst_ftz(
+                        builder,
+                        map,
                         opencl,
-                        spirv::CLOp::exp2 as u32,
-                        [arg.src],
+                        spirv::CLOp::exp2,
+                        *flush_to_zero,
+                        arg,
                     )?;
                 }
+                // tanh.approx has no .ftz, subnormal inputs are supported
+                ast::Instruction::Tanh { arg } => {
+                    emit_f32_ext_inst_ftz(builder, map, opencl, spirv::CLOp::tanh, false, arg)?;
+                }
                 ast::Instruction::Clz { typ, arg } => {
                     let result_type = map.get_or_add_scalar(builder, (*typ).into());
                     builder.ext_inst(
@@ -3094,6 +4115,9 @@ fn emit_function_body_ops(
                     let result_type = map.get_or_add_scalar(builder, (*typ).into());
                     builder.bit_count(result_type, Some(arg.dst), arg.src)?;
                 }
+                ast::Instruction::Prmt { mode, arg } => {
+                    emit_prmt(builder, map, opencl, *mode, arg)?;
+                }
                 ast::Instruction::Xor { typ, arg } => {
                     let builder_fn = match typ {
                         ast::BooleanType::Pred => emit_logical_xor_spirv,
@@ -3118,6 +4142,48 @@ fn emit_function_body_ops(
                         arg.src3,
                     )?;
                 }
+                ast::Instruction::Bfi { typ, arg } => {
+                    emit_bfi(builder, map, opencl, *typ, arg)?;
+                }
+                ast::Instruction::Bfind {
+                    typ,
+                    shift_amount,
+                    arg,
+                } => {
+                    emit_bfind(builder, map, opencl, *typ, *shift_amount, arg)?;
+                }
+                ast::Instruction::Szext { mode, typ, arg } => {
+                    // Both zero and 32 are valid counts for a bit field extract
+                    let builder_fn = if typ.is_signed() {
+                        dr::Builder::bit_field_s_extract
+                    } else {
+                        dr::Builder::bit_field_u_extract
+                    };
+                    let width = emit_clamp_or_wrap(builder, map, opencl, *mode, arg.src2)?;
+                    let offset = map.get_or_add_constant(
+                        builder,
+                        &ast::Type::Scalar(ast::ScalarType::U32),
+                        &vec_repr(0u32),
+                    )?;
+                    let result_type = map.get_or_add_scalar(builder, (*typ).into());
+                    builder_fn(builder, result_type, Some(arg.dst), arg.src1, offset, width)?;
+                }
+                ast::Instruction::Bmsk { mode, arg } => {
+                    emit_bmsk(builder, map, opencl, *mode, arg)?;
+                }
+                ast::Instruction::Dp4a(details, arg) => {
+                    emit_dot_product(builder, map, *details, &[0, 1, 2, 3], 8, arg)?;
+                }
+                ast::Instruction::Dp2a(mode, details, arg) => {
+                    let b_bytes: &[u32] = match mode {
+                        ast::Dp2aMode::Lo => &[0, 1],
+                        ast::Dp2aMode::Hi => &[2, 3],
+                    };
+                    emit_dot_product(builder, map, *details, b_bytes, 16, arg)?;
+                }
+                ast::Instruction::Video(details, arg) => {
+                    emit_video(builder, map, opencl, *details, arg)?;
+                }
                 ast::Instruction::Rem { typ, arg } => {
                     let builder_fn = if typ.is_signed() {
                         dr::Builder::s_mod
@@ -3127,44 +4193,132 @@ fn emit_function_body_ops(
                     let result_type = map.get_or_add_scalar(builder, (*typ).into());
                     builder_fn(builder, result_type, Some(arg.dst), arg.src1, arg.src2)?;
                 }
-            },
-            Statement::LoadVar(details) => {
-                emit_load_var(builder, map, details)?;
-            }
-            Statement::StoreVar(details) => {
-                let dst_ptr = match details.member_index {
-                    Some(index) => {
-                        let result_ptr_type = map.get_or_add(
-                            builder,
-                            SpirvType::new_pointer(
-                                details.typ.clone(),
-                                spirv::StorageClass::Function,
-                            ),
-                        );
-                        let index_spirv = map.get_or_add_constant(
+                ast::Instruction::Sad { typ, arg } => {
+                    // OpenCL abs_diff returns an unsigned result without overflow, its
+                    // bits are the same as PTX's modular ((a < b) ? b - a : a - b)
+                    let opencl_fn = if typ.is_signed() {
+                        spirv::CLOp::s_abs_diff
+                    } else {
+                        spirv::CLOp::u_abs_diff
+                    };
+                    let result_type = map.get_or_add_scalar(builder, (*typ).into());
+                    let abs_diff = builder.ext_inst(
+                        result_type,
+                        None,
+                        opencl,
+                        opencl_fn as spirv::Word,
+                        [arg.src1, arg.src2],
+                    )?;
+                    builder.i_add(result_type, Some(arg.dst), abs_diff, arg.src3)?;
+                }
+                ast::Instruction::Copysign { typ, arg } => {
+                    // PTX takes the sign from the first operand, OpenCL from the second
+                    let result_type = map.get_or_add_scalar(builder, (*typ).into());
+                    builder.ext_inst(
+                        result_type,
+                        Some(arg.dst),
+                        opencl,
+                        spirv::CLOp::copysign as spirv::Word,
+                        [arg.src2, arg.src1],
+                    )?;
+                }
+                ast::Instruction::Testp { op, typ, arg } => {
+                    emit_testp(builder, map, *op, *typ, arg)?;
+                }
+                ast::Instruction::Shfl(mode, arg) => {
+                    emit_shfl(builder, map, opencl, *mode, arg)?;
+                }
+                ast::Instruction::Vote(details, arg) => {
+                    emit_vote(builder, map, details, arg)?;
+                }
+                // There is no way to sleep in SPIR-V. PTX only promises a
+                // duration between 0 and twice the operand, so returning
+                // immediately is valid; spin-wait loops around it just poll
+                // more often
+                ast::Instruction::Nanosleep(_) => {}
+                ast::Instruction::Isspacep { space, arg } => {
+                    emit_isspacep(builder, map, *space, arg)?;
+                }
+                // Prefetches are only hints. OpenCL prefetch accepts only
+                // global pointers, prefetches of other spaces are dropped
+                ast::Instruction::Prefetch(details, arg) => {
+                    if details.state_space == ast::LdStateSpace::Global {
+                        let count = map.get_or_add_constant(
                             builder,
-                            &ast::Type::Scalar(ast::ScalarType::U32),
-                            &vec_repr(index as u32),
+                            &ast::Type::Scalar(ast::ScalarType::U64),
+                            &vec_repr(1u64),
                         )?;
-                        builder.in_bounds_access_chain(
-                            result_ptr_type,
+                        builder.ext_inst(
+                            map.void(),
                             None,
-                            details.arg.src1,
-                            &[index_spirv],
-                        )?
+                            opencl,
+                            spirv::CLOp::prefetch as spirv::Word,
+                            [arg.src, count],
+                        )?;
                     }
-                    None => details.arg.src1,
-                };
-                builder.store(dst_ptr, details.arg.src2, None, [])?;
-            }
-            Statement::RetValue(_, id) => {
-                builder.ret_value(*id)?;
-            }
-            Statement::PtrAccess(PtrAccess {
-                underlying_type,
-                state_space,
-                dst,
-                ptr_src,
+                }
+                ast::Instruction::Membar(details) => {
+                    let scope = map.get_or_add_constant(
+                        builder,
+                        &ast::Type::Scalar(ast::ScalarType::U32),
+                        &vec_repr(details.scope.to_spirv() as u32),
+                    )?;
+                    let semantics = map.get_or_add_constant(
+                        builder,
+                        &ast::Type::Scalar(ast::ScalarType::U32),
+                        &vec_repr(
+                            (details.semantics.to_spirv()
+                                | spirv::MemorySemantics::CROSS_WORKGROUP_MEMORY
+                                | spirv::MemorySemantics::WORKGROUP_MEMORY)
+                                .bits(),
+                        ),
+                    )?;
+                    builder.memory_barrier(scope, semantics)?;
+                }
+                ast::Instruction::AddC { typ, arg } => {
+                    emit_carry_arith(builder, map, *typ, arg, dr::Builder::i_add_carry)?;
+                }
+                ast::Instruction::SubC { typ, arg } => {
+                    emit_carry_arith(builder, map, *typ, arg, dr::Builder::i_sub_borrow)?;
+                }
+                ast::Instruction::Tex(details, arg) => {
+                    emit_tex(builder, map, details, arg)?;
+                }
+                // Replaced with calls to ptx_impl functions in extract_globals
+                ast::Instruction::WmmaLoad(..) | ast::Instruction::WmmaStore(..) => {
+                    return Err(error_unreachable())
+                }
+            },
+            Statement::LoadVar(details) => match special_registers.get(details.arg.src) {
+                Some(sreg) if sreg.is_clock() => emit_read_clock(builder, map, details)?,
+                Some(PtxSpecialRegister::SmId) => emit_read_smid(builder, map, details)?,
+                _ => emit_load_var(builder, map, details)?,
+            },
+            Statement::StoreVar(details) => {
+                emit_store_var(builder, map, details)?;
+            }
+            Statement::RetValue(_, values) => match &**values {
+                [(id, _)] => {
+                    builder.ret_value(*id)?;
+                }
+                values => {
+                    let struct_type = multiple_return_type(
+                        values.iter().map(|(_, typ)| SpirvType::from(typ.clone())),
+                    )?;
+                    let struct_type = map.get_or_add(builder, struct_type);
+                    let result = builder.composite_construct(
+                        struct_type,
+                        None,
+                        values.iter().map(|(id, _)| *id),
+                    )?;
+                    builder.ret_value(result)?;
+                }
+            },
+            Statement::PtrAccess(PtrAccess {
+                underlying_type,
+                state_space,
+                dst,
+                ptr_src,
                 offset_src,
             }) => {
                 let u8_pointer = map.get_or_add(
@@ -3225,6 +4379,511 @@ fn emit_function_body_ops(
     Ok(())
 }
 
+// Every byte of the result is picked from the eight bytes of b:a by a 3-bit
+// selector. In the default mode every byte has its own nibble in the control
+// operand and the top bit of the nibble replicates the sign of the picked byte.
+// Special modes derive all four selectors from the two lowest bits of the
+// control operand
+fn emit_prmt(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    opencl: spirv::Word,
+    mode: Option<ast::PrmtMode>,
+    arg: &ast::Arg4<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
+    let u32_type = map.get_or_add_scalar(builder, ast::ScalarType::U32);
+    let u64_type = map.get_or_add_scalar(builder, ast::ScalarType::U64);
+    let bool_type = map.get_or_add_scalar(builder, ast::ScalarType::Pred);
+    let word_size = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(ast::ScalarType::U64),
+        &vec_repr(32u64),
+    )?;
+    let mut u32_constant = |builder: &mut dr::Builder, value: u32| {
+        map.get_or_add_constant(
+            builder,
+            &ast::Type::Scalar(ast::ScalarType::U32),
+            &vec_repr(value),
+        )
+    };
+    let low = builder.u_convert(u64_type, None, arg.src1)?;
+    let high = builder.u_convert(u64_type, None, arg.src2)?;
+    let high = builder.shift_left_logical(u64_type, None, high, word_size)?;
+    let bytes = builder.bitwise_or(u64_type, None, high, low)?;
+    let three = u32_constant(builder, 3)?;
+    let seven = u32_constant(builder, 7)?;
+    let control = match mode {
+        Some(_) => builder.bitwise_and(u32_type, None, arg.src3, three)?,
+        None => arg.src3,
+    };
+    let byte_mask = u32_constant(builder, 0xff)?;
+    let mut result = None;
+    for i in 0..4u32 {
+        let mut sign_nibble = None;
+        let selector = match mode {
+            None => {
+                let nibble_shift = u32_constant(builder, i * 4)?;
+                let nibble_mask = u32_constant(builder, 0xf)?;
+                let nibble = builder.shift_right_logical(u32_type, None, control, nibble_shift)?;
+                let nibble = builder.bitwise_and(u32_type, None, nibble, nibble_mask)?;
+                sign_nibble = Some(nibble);
+                builder.bitwise_and(u32_type, None, nibble, seven)?
+            }
+            Some(ast::PrmtMode::F4e) | Some(ast::PrmtMode::B4e) => {
+                let step = if mode == Some(ast::PrmtMode::F4e) {
+                    i
+                } else {
+                    (8 - i) & 7
+                };
+                let step = u32_constant(builder, step)?;
+                let selector = builder.i_add(u32_type, None, control, step)?;
+                builder.bitwise_and(u32_type, None, selector, seven)?
+            }
+            Some(ast::PrmtMode::Rc8) => control,
+            Some(ast::PrmtMode::Ecl) | Some(ast::PrmtMode::Ecr) => {
+                let clamp_fn = if mode == Some(ast::PrmtMode::Ecl) {
+                    spirv::CLOp::u_max
+                } else {
+                    spirv::CLOp::u_min
+                };
+                let index = u32_constant(builder, i)?;
+                builder.ext_inst(
+                    u32_type,
+                    None,
+                    opencl,
+                    clamp_fn as spirv::Word,
+                    [control, index],
+                )?
+            }
+            Some(ast::PrmtMode::Rc16) => {
+                let one = u32_constant(builder, 1)?;
+                let half = builder.bitwise_and(u32_type, None, control, one)?;
+                let half = builder.shift_left_logical(u32_type, None, half, one)?;
+                let byte_in_half = u32_constant(builder, i & 1)?;
+                builder.bitwise_or(u32_type, None, half, byte_in_half)?
+            }
+        };
+        let shift = builder.shift_left_logical(u32_type, None, selector, three)?;
+        let shift = builder.u_convert(u64_type, None, shift)?;
+        let byte = builder.shift_right_logical(u64_type, None, bytes, shift)?;
+        let byte = builder.u_convert(u32_type, None, byte)?;
+        let byte = builder.bitwise_and(u32_type, None, byte, byte_mask)?;
+        let byte = match sign_nibble {
+            Some(nibble) => {
+                let sign_shift = u32_constant(builder, 7)?;
+                let replicate_bit = u32_constant(builder, 8)?;
+                let zero = u32_constant(builder, 0)?;
+                let sign = builder.shift_right_logical(u32_type, None, byte, sign_shift)?;
+                let sign = builder.i_mul(u32_type, None, sign, byte_mask)?;
+                let replicate = builder.bitwise_and(u32_type, None, nibble, replicate_bit)?;
+                let replicate = builder.i_not_equal(bool_type, None, replicate, zero)?;
+                builder.select(u32_type, None, replicate, sign, byte)?
+            }
+            None => byte,
+        };
+        let position = u32_constant(builder, i * 8)?;
+        let byte = builder.shift_left_logical(u32_type, None, byte, position)?;
+        result = Some(match result {
+            None => byte,
+            Some(result) => builder.bitwise_or(u32_type, None, result, byte)?,
+        });
+    }
+    builder.copy_object(u32_type, Some(arg.dst), result.unwrap())?;
+    Ok(())
+}
+
+// PTX only looks at the lowest 8 bits of position and length and clamps the
+// inserted field to the width of the type. OpBitFieldInsert is undefined if
+// the field does not fit, so both are clamped here
+fn emit_bfi(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    opencl: spirv::Word,
+    typ: ast::BitType,
+    arg: &ast::Arg5<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
+    let scalar_type = ast::ScalarType::from(typ);
+    let result_type = map.get_or_add_scalar(builder, scalar_type);
+    let u32_type = map.get_or_add_scalar(builder, ast::ScalarType::U32);
+    let byte_mask = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(ast::ScalarType::U32),
+        &vec_repr(0xffu32),
+    )?;
+    let width = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(ast::ScalarType::U32),
+        &vec_repr(scalar_type.size_of() as u32 * 8),
+    )?;
+    let u_min = spirv::CLOp::u_min as spirv::Word;
+    let position = builder.bitwise_and(u32_type, None, arg.src3, byte_mask)?;
+    let position = builder.ext_inst(u32_type, None, opencl, u_min, [position, width])?;
+    let max_length = builder.i_sub(u32_type, None, width, position)?;
+    let length = builder.bitwise_and(u32_type, None, arg.src4, byte_mask)?;
+    let length = builder.ext_inst(u32_type, None, opencl, u_min, [length, max_length])?;
+    builder.bit_field_insert(
+        result_type,
+        Some(arg.dst),
+        arg.src2,
+        arg.src1,
+        position,
+        length,
+    )?;
+    Ok(())
+}
+
+// For negative signed inputs bfind looks for the most significant clear bit,
+// which is the same as looking for the most significant set bit of !src
+fn emit_bfind(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    opencl: spirv::Word,
+    typ: ast::IntType,
+    shift_amount: bool,
+    arg: &ast::Arg2<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
+    let scalar_type = ast::ScalarType::from(typ);
+    let size = scalar_type.size_of() as usize;
+    let src_type = map.get_or_add_scalar(builder, scalar_type);
+    let u32_type = map.get_or_add_scalar(builder, ast::ScalarType::U32);
+    let bool_type = map.get_or_add_scalar(builder, ast::ScalarType::Pred);
+    let width = size as u32 * 8;
+    let src = if typ.is_signed() {
+        let sign_shift = map.get_or_add_constant(
+            builder,
+            &ast::Type::Scalar(scalar_type),
+            &vec_repr(width as u64 - 1)[..size],
+        )?;
+        let sign = builder.shift_right_arithmetic(src_type, None, arg.src, sign_shift)?;
+        builder.bitwise_xor(src_type, None, arg.src, sign)?
+    } else {
+        arg.src
+    };
+    let leading_zeros = builder.ext_inst(
+        src_type,
+        None,
+        opencl,
+        spirv::CLOp::clz as spirv::Word,
+        [src],
+    )?;
+    let leading_zeros = if width == 64 {
+        builder.u_convert(u32_type, None, leading_zeros)?
+    } else {
+        leading_zeros
+    };
+    let result = if shift_amount {
+        leading_zeros
+    } else {
+        let max_bit = map.get_or_add_constant(
+            builder,
+            &ast::Type::Scalar(ast::ScalarType::U32),
+            &vec_repr(width - 1),
+        )?;
+        builder.i_sub(u32_type, None, max_bit, leading_zeros)?
+    };
+    let zero = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(scalar_type),
+        &vec_repr(0u64)[..size],
+    )?;
+    let not_found = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(ast::ScalarType::U32),
+        &vec_repr(u32::MAX),
+    )?;
+    let is_zero = builder.i_equal(bool_type, None, src, zero)?;
+    builder.select(u32_type, Some(arg.dst), is_zero, not_found, result)?;
+    Ok(())
+}
+
+// Every a element is multiplied by the b byte with the same index in b_bytes
+// and the products are added to src3. a elements are a_width bits wide, so
+// dp2a works on 16-bit halves of a and dp4a on bytes. Each operand is sign or
+// zero extended on its own
+fn emit_dot_product(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    details: ast::DpDetails,
+    b_bytes: &[u32],
+    a_width: u32,
+    arg: &ast::Arg4<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
+    let u32_type = map.get_or_add_scalar(builder, ast::ScalarType::U32);
+    let mut u32_constant = |builder: &mut dr::Builder, value: u32| {
+        map.get_or_add_constant(
+            builder,
+            &ast::Type::Scalar(ast::ScalarType::U32),
+            &vec_repr(value),
+        )
+    };
+    let extract = |signed: bool| {
+        if signed {
+            dr::Builder::bit_field_s_extract
+        } else {
+            dr::Builder::bit_field_u_extract
+        }
+    };
+    let extract_a = extract(details.a_signed);
+    let extract_b = extract(details.b_signed);
+    let a_count = u32_constant(builder, a_width)?;
+    let b_count = u32_constant(builder, 8)?;
+    let mut result = arg.src3;
+    for (i, b_byte) in b_bytes.iter().copied().enumerate() {
+        let a_offset = u32_constant(builder, i as u32 * a_width)?;
+        let b_offset = u32_constant(builder, b_byte * 8)?;
+        let a = extract_a(builder, u32_type, None, arg.src1, a_offset, a_count)?;
+        let b = extract_b(builder, u32_type, None, arg.src2, b_offset, b_count)?;
+        let product = builder.i_mul(u32_type, None, a, b)?;
+        result = builder.i_add(u32_type, None, result, product)?;
+    }
+    builder.copy_object(u32_type, Some(arg.dst), result)?;
+    Ok(())
+}
+
+// Operands are sign- or zero-extended to 64 bits, so the intermediate result is
+// exact and .sat can clamp it to the range of the destination type. The
+// secondary operation is applied after saturation and wraps around
+fn emit_video(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    opencl: spirv::Word,
+    details: ast::VideoDetails,
+    arg: &ast::ArgVideo<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
+    let u32_type = map.get_or_add_scalar(builder, ast::ScalarType::U32);
+    let u64_type = map.get_or_add_scalar(builder, ast::ScalarType::U64);
+    let extend = |builder: &mut dr::Builder, signed: bool, src: spirv::Word| {
+        if signed {
+            builder.s_convert(u64_type, None, src)
+        } else {
+            builder.u_convert(u64_type, None, src)
+        }
+    };
+    let a = extend(builder, details.a_signed, arg.src1)?;
+    let b = extend(builder, details.b_signed, arg.src2)?;
+    let mut result = match details.op {
+        ast::VideoOp::Add => builder.i_add(u64_type, None, a, b)?,
+        ast::VideoOp::Sub => builder.i_sub(u64_type, None, a, b)?,
+        ast::VideoOp::AbsDiff => builder.ext_inst(
+            u64_type,
+            None,
+            opencl,
+            spirv::CLOp::s_abs_diff as spirv::Word,
+            [a, b],
+        )?,
+    };
+    if details.saturate {
+        let (min, max) = if details.d_signed {
+            (i32::MIN as i64, i32::MAX as i64)
+        } else {
+            (0, u32::MAX as i64)
+        };
+        let s64_type = ast::Type::Scalar(ast::ScalarType::S64);
+        let min = map.get_or_add_constant(builder, &s64_type, &vec_repr(min))?;
+        let max = map.get_or_add_constant(builder, &s64_type, &vec_repr(max))?;
+        result = builder.ext_inst(
+            u64_type,
+            None,
+            opencl,
+            spirv::CLOp::s_clamp as spirv::Word,
+            [result, min, max],
+        )?;
+    }
+    let (secondary, src3) = match (details.secondary, arg.src3) {
+        (Some(secondary), Some(src3)) => (secondary, src3),
+        (None, None) => {
+            builder.u_convert(u32_type, Some(arg.dst), result)?;
+            return Ok(());
+        }
+        _ => return Err(error_unreachable()),
+    };
+    let result = builder.u_convert(u32_type, None, result)?;
+    let opencl_fn = match (secondary, details.d_signed) {
+        (ast::VideoSecondaryOp::Add, _) => {
+            builder.i_add(u32_type, Some(arg.dst), result, src3)?;
+            return Ok(());
+        }
+        (ast::VideoSecondaryOp::Min, true) => spirv::CLOp::s_min,
+        (ast::VideoSecondaryOp::Min, false) => spirv::CLOp::u_min,
+        (ast::VideoSecondaryOp::Max, true) => spirv::CLOp::s_max,
+        (ast::VideoSecondaryOp::Max, false) => spirv::CLOp::u_max,
+    };
+    builder.ext_inst(
+        u32_type,
+        Some(arg.dst),
+        opencl,
+        opencl_fn as spirv::Word,
+        [result, src3],
+    )?;
+    Ok(())
+}
+
+// SPIR-V has no direct test for subnormals, so .normal and .subnormal compare
+// the magnitude bits (everything but the sign) with the smallest normal and
+// with infinity. Zero is neither normal nor subnormal
+fn emit_testp(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    op: ast::TestpOp,
+    typ: ast::FloatType,
+    arg: &ast::Arg2<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
+    let pred_type = map.get_or_add_scalar(builder, ast::ScalarType::Pred);
+    match op {
+        ast::TestpOp::NotANumber => {
+            builder.is_nan(pred_type, Some(arg.dst), arg.src)?;
+        }
+        ast::TestpOp::Number => {
+            let is_nan = builder.is_nan(pred_type, None, arg.src)?;
+            builder.logical_not(pred_type, Some(arg.dst), is_nan)?;
+        }
+        ast::TestpOp::Infinite => {
+            builder.is_inf(pred_type, Some(arg.dst), arg.src)?;
+        }
+        ast::TestpOp::Finite => {
+            let is_nan = builder.is_nan(pred_type, None, arg.src)?;
+            let is_inf = builder.is_inf(pred_type, None, arg.src)?;
+            let not_finite = builder.logical_or(pred_type, None, is_nan, is_inf)?;
+            builder.logical_not(pred_type, Some(arg.dst), not_finite)?;
+        }
+        ast::TestpOp::Normal | ast::TestpOp::Subnormal => {
+            let (bits_type, magnitude_mask, min_normal, infinity) = match typ {
+                ast::FloatType::F32 => (
+                    ast::ScalarType::U32,
+                    vec_repr(0x7FFF_FFFFu32),
+                    vec_repr(0x0080_0000u32),
+                    vec_repr(0x7F80_0000u32),
+                ),
+                ast::FloatType::F64 => (
+                    ast::ScalarType::U64,
+                    vec_repr(0x7FFF_FFFF_FFFF_FFFFu64),
+                    vec_repr(0x0010_0000_0000_0000u64),
+                    vec_repr(0x7FF0_0000_0000_0000u64),
+                ),
+                _ => return Err(error_unreachable()),
+            };
+            let bits_spirv_type = map.get_or_add_scalar(builder, bits_type);
+            let mut bits_constant = |builder: &mut dr::Builder, value: &[u8]| {
+                map.get_or_add_constant(builder, &ast::Type::Scalar(bits_type), value)
+            };
+            let magnitude_mask = bits_constant(builder, &magnitude_mask)?;
+            let min_normal = bits_constant(builder, &min_normal)?;
+            let bits = builder.bitcast(bits_spirv_type, None, arg.src)?;
+            let magnitude = builder.bitwise_and(bits_spirv_type, None, bits, magnitude_mask)?;
+            let below_normal = builder.u_less_than(pred_type, None, magnitude, min_normal)?;
+            if op == ast::TestpOp::Normal {
+                let infinity = bits_constant(builder, &infinity)?;
+                let at_least_normal = builder.logical_not(pred_type, None, below_normal)?;
+                let below_infinity = builder.u_less_than(pred_type, None, magnitude, infinity)?;
+                builder.logical_and(pred_type, Some(arg.dst), at_least_normal, below_infinity)?;
+            } else {
+                let zero = bits_constant(builder, &vec![0; bits_type.size_of() as usize])?;
+                let non_zero = builder.i_not_equal(pred_type, None, magnitude, zero)?;
+                builder.logical_and(pred_type, Some(arg.dst), below_normal, non_zero)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Both words are concatenated into a 64-bit value, so a shift by 32 is still
+// well-defined
+fn emit_shf(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    opencl: spirv::Word,
+    details: ast::ShfDetails,
+    arg: &ast::Arg4<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
+    let b32_type = map.get_or_add_scalar(builder, ast::ScalarType::B32);
+    let b64_type = map.get_or_add_scalar(builder, ast::ScalarType::B64);
+    let shift = emit_clamp_or_wrap(builder, map, opencl, details.mode, arg.src3)?;
+    let shift = builder.u_convert(b64_type, None, shift)?;
+    let word_size = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(ast::ScalarType::B64),
+        &vec_repr(32u64),
+    )?;
+    let low = builder.u_convert(b64_type, None, arg.src1)?;
+    let high = builder.u_convert(b64_type, None, arg.src2)?;
+    let high = builder.shift_left_logical(b64_type, None, high, word_size)?;
+    let value = builder.bitwise_or(b64_type, None, high, low)?;
+    let result = match details.direction {
+        ast::ShfDirection::Left => {
+            let shifted = builder.shift_left_logical(b64_type, None, value, shift)?;
+            builder.shift_right_logical(b64_type, None, shifted, word_size)?
+        }
+        ast::ShfDirection::Right => builder.shift_right_logical(b64_type, None, value, shift)?,
+    };
+    builder.u_convert(b32_type, Some(arg.dst), result)?;
+    Ok(())
+}
+
+// Bit positions and widths of shf, szext and bmsk are either clamped to 32 or
+// taken modulo 32
+fn emit_clamp_or_wrap(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    opencl: spirv::Word,
+    mode: ast::ShfMode,
+    src: spirv::Word,
+) -> Result<spirv::Word, TranslateError> {
+    let u32_type = map.get_or_add_scalar(builder, ast::ScalarType::U32);
+    Ok(match mode {
+        ast::ShfMode::Clamp => {
+            let max = map.get_or_add_constant(
+                builder,
+                &ast::Type::Scalar(ast::ScalarType::U32),
+                &vec_repr(32u32),
+            )?;
+            builder.ext_inst(
+                u32_type,
+                None,
+                opencl,
+                spirv::CLOp::u_min as spirv::Word,
+                [src, max],
+            )?
+        }
+        ast::ShfMode::Wrap => {
+            let mask = map.get_or_add_constant(
+                builder,
+                &ast::Type::Scalar(ast::ScalarType::U32),
+                &vec_repr(31u32),
+            )?;
+            builder.bitwise_and(u32_type, None, src, mask)?
+        }
+    })
+}
+
+// The mask is inserted into zero as a bit field. Offset plus count of a bit
+// field must not exceed 32, so the part of the mask above the word is dropped
+// before the insert
+fn emit_bmsk(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    opencl: spirv::Word,
+    mode: ast::ShfMode,
+    arg: &ast::Arg3<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
+    let u32_type = map.get_or_add_scalar(builder, ast::ScalarType::U32);
+    let start = emit_clamp_or_wrap(builder, map, opencl, mode, arg.src1)?;
+    let width = emit_clamp_or_wrap(builder, map, opencl, mode, arg.src2)?;
+    let u32_scalar = ast::Type::Scalar(ast::ScalarType::U32);
+    let word_size = map.get_or_add_constant(builder, &u32_scalar, &vec_repr(32u32))?;
+    let zero = map.get_or_add_constant(builder, &u32_scalar, &vec_repr(0u32))?;
+    let ones = map.get_or_add_constant(builder, &u32_scalar, &vec_repr(u32::MAX))?;
+    let remaining = builder.i_sub(u32_type, None, word_size, start)?;
+    let width = builder.ext_inst(
+        u32_type,
+        None,
+        opencl,
+        spirv::CLOp::u_min as spirv::Word,
+        [width, remaining],
+    )?;
+    builder.bit_field_insert(u32_type, Some(arg.dst), zero, ones, start, width)?;
+    Ok(())
+}
+
 // HACK ALERT
 // For some reason IGC fails linking if the value and shift size are of different type
 fn insert_shift_hack(
@@ -3300,8 +4959,11 @@ fn emit_float_div_decoration(builder: &mut dr::Builder, dst: spirv::Word, kind:
 fn emit_atom(
     builder: &mut dr::Builder,
     map: &mut TypeWordMap,
+    opencl: spirv::Word,
     details: &ast::AtomDetails,
-    arg: &ast::Arg3<ExpandedArgParams>,
+    dst: Option<spirv::Word>,
+    ptr: spirv::Word,
+    value: spirv::Word,
 ) -> Result<(), TranslateError> {
     let (spirv_op, typ) = match details.inner {
         ast::AtomInnerDetails::Bit { op, typ } => {
@@ -3332,8 +4994,48 @@ fn emit_atom(
             };
             (spirv_op, typ.into())
         }
-        // TODO: Hardware is capable of this, implement it through builtin
-        ast::AtomInnerDetails::Float { .. } => todo!(),
+        // Only reached if the device supports float atomic add, otherwise
+        // atom.add.f32 and atom.add.f64 are replaced with ptx_impl calls in
+        // extract_globals
+        ast::AtomInnerDetails::Float {
+            op: ast::AtomFloatOp::Add,
+            typ,
+        } if typ == ast::FloatType::F32 || typ == ast::FloatType::F64 => {
+            (dr::Builder::atomic_f_add_ext, typ.into())
+        }
+        ast::AtomInnerDetails::Float {
+            op: op @ ast::AtomFloatOp::Min,
+            typ,
+        }
+        | ast::AtomInnerDetails::Float {
+            op: op @ ast::AtomFloatOp::Max,
+            typ,
+        } if typ == ast::FloatType::F32 || typ == ast::FloatType::F64 => {
+            let cl_op = if op == ast::AtomFloatOp::Min {
+                spirv::CLOp::fmin
+            } else {
+                spirv::CLOp::fmax
+            };
+            return emit_atomic_cas_loop(
+                builder,
+                map,
+                details,
+                typ.into(),
+                ptr,
+                dst,
+                |builder, map, old_value| {
+                    let result_type = map.get_or_add_scalar(builder, typ.into());
+                    Ok(builder.ext_inst(
+                        result_type,
+                        None,
+                        opencl,
+                        cl_op as spirv::Word,
+                        [old_value, value],
+                    )?)
+                },
+            );
+        }
+        ast::AtomInnerDetails::Float { .. } => return Err(TranslateError::Todo),
     };
     let result_type = map.get_or_add_scalar(builder, typ);
     let memory_const = map.get_or_add_constant(
@@ -3349,12 +5051,123 @@ fn emit_atom(
     spirv_op(
         builder,
         result_type,
-        Some(arg.dst),
-        arg.src1,
+        dst,
+        ptr,
         memory_const,
         semantics_const,
-        arg.src2,
+        value,
+    )?;
+    Ok(())
+}
+
+// Atomically replaces the value pointed to by `ptr` with `combine(old_value)`
+// by retrying OpAtomicCompareExchange until no other thread has modified the
+// value in the meantime. `dst`, if present, receives the value observed before
+// the swap.
+// Used for atomic operations which have no native SPIR-V equivalent. The loop
+// is structured (header with OpLoopMerge, body, continue block) and a failed
+// exchange retries with the value it observed instead of loading it again
+fn emit_atomic_cas_loop(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    details: &ast::AtomDetails,
+    typ: ast::ScalarType,
+    ptr: spirv::Word,
+    dst: Option<spirv::Word>,
+    combine: impl FnOnce(
+        &mut dr::Builder,
+        &mut TypeWordMap,
+        spirv::Word,
+    ) -> Result<spirv::Word, TranslateError>,
+) -> Result<(), TranslateError> {
+    // OpAtomicCompareExchange works only on integers, float values are compared bitwise
+    let int_type = ast::ScalarType::from_parts(typ.size_of(), ScalarKind::Bit);
+    let is_bitcast = int_type != typ;
+    let int_type_id = map.get_or_add_scalar(builder, int_type);
+    let value_type_id = map.get_or_add_scalar(builder, typ);
+    let int_ptr = if is_bitcast {
+        let int_ptr_type = map.get_or_add(
+            builder,
+            SpirvType::new_pointer(
+                ast::Type::Scalar(int_type),
+                details.space.to_ld_ss().to_spirv(),
+            ),
+        );
+        builder.bitcast(int_ptr_type, None, ptr)?
+    } else {
+        ptr
+    };
+    let scope = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(ast::ScalarType::U32),
+        &vec_repr(details.scope.to_spirv() as u32),
+    )?;
+    let semantics = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(ast::ScalarType::U32),
+        &vec_repr(details.semantics.to_spirv().bits()),
+    )?;
+    let relaxed = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(ast::ScalarType::U32),
+        &vec_repr(spirv::MemorySemantics::NONE.bits()),
+    )?;
+    let entry_label = builder
+        .block
+        .as_ref()
+        .and_then(|block| block.label.as_ref())
+        .and_then(|label| label.result_id)
+        .ok_or_else(error_unreachable)?;
+    let initial_int = builder.atomic_load(int_type_id, None, int_ptr, scope, relaxed)?;
+    let header_label = builder.id();
+    let body_label = builder.id();
+    let continue_label = builder.id();
+    let done_label = builder.id();
+    let exchanged = builder.id();
+    builder.branch(header_label)?;
+    builder.begin_block(Some(header_label))?;
+    let old_int = builder.phi(
+        int_type_id,
+        None,
+        [(initial_int, entry_label), (exchanged, continue_label)],
     )?;
+    builder.loop_merge(done_label, continue_label, spirv::LoopControl::NONE, [])?;
+    builder.branch(body_label)?;
+    builder.begin_block(Some(body_label))?;
+    let old_value = if is_bitcast {
+        builder.bitcast(value_type_id, None, old_int)?
+    } else {
+        old_int
+    };
+    let new_value = combine(builder, map, old_value)?;
+    let new_int = if is_bitcast {
+        builder.bitcast(int_type_id, None, new_value)?
+    } else {
+        new_value
+    };
+    builder.atomic_compare_exchange(
+        int_type_id,
+        Some(exchanged),
+        int_ptr,
+        scope,
+        semantics,
+        relaxed,
+        new_int,
+        old_int,
+    )?;
+    let bool_type = map.get_or_add_scalar(builder, ast::ScalarType::Pred);
+    let success = builder.i_equal(bool_type, None, exchanged, old_int)?;
+    builder.branch_conditional(success, done_label, continue_label, [])?;
+    builder.begin_block(Some(continue_label))?;
+    builder.branch(header_label)?;
+    builder.begin_block(Some(done_label))?;
+    if let Some(dst) = dst {
+        if is_bitcast {
+            builder.bitcast(value_type_id, Some(dst), exchanged)?;
+        } else {
+            builder.copy_object(value_type_id, Some(dst), exchanged)?;
+        }
+    }
     Ok(())
 }
 
@@ -3390,14 +5203,32 @@ fn ptx_space_name(space: ast::AtomSpace) -> &'static str {
     }
 }
 
+fn ptx_ld_space_name(space: ast::LdStateSpace) -> &'static str {
+    match space {
+        ast::LdStateSpace::Generic => "generic",
+        ast::LdStateSpace::Const => "const",
+        ast::LdStateSpace::Global => "global",
+        ast::LdStateSpace::Local => "local",
+        ast::LdStateSpace::Param => "param",
+        ast::LdStateSpace::Shared => "shared",
+    }
+}
+
+fn ptx_wmma_layout_name(layout: ast::WmmaLayout) -> &'static str {
+    match layout {
+        ast::WmmaLayout::Row => "row",
+        ast::WmmaLayout::Col => "col",
+    }
+}
+
 fn emit_mul_float(
     builder: &mut dr::Builder,
     map: &mut TypeWordMap,
     ctr: &ast::ArithFloat,
     arg: &ast::Arg3<ExpandedArgParams>,
-) -> Result<(), dr::Error> {
+) -> Result<(), TranslateError> {
     if ctr.saturate {
-        todo!()
+        return Err(TranslateError::Todo);
     }
     let result_type = map.get_or_add_scalar(builder, ctr.typ.into());
     builder.f_mul(result_type, Some(arg.dst), arg.src1, arg.src2)?;
@@ -3408,6 +5239,7 @@ fn emit_mul_float(
 fn emit_rcp(
     builder: &mut dr::Builder,
     map: &mut TypeWordMap,
+    opencl: spirv::Word,
     desc: &ast::RcpDetails,
     a: &ast::Arg2<ExpandedArgParams>,
 ) -> Result<(), TranslateError> {
@@ -3418,6 +5250,21 @@ fn emit_rcp(
     };
     let one = map.get_or_add_constant(builder, &ast::Type::Scalar(instr_type), &constant)?;
     let result_type = map.get_or_add_scalar(builder, instr_type);
+    // Kernel denormal mode is only set for f16 and f32, so f64 has to be
+    // flushed explicitly
+    if desc.is_f64 && desc.flush_to_zero == Some(true) {
+        let src = emit_flush_denormal(builder, map, opencl, instr_type, a.src, None)?;
+        let result = builder.f_div(result_type, None, one, src)?;
+        builder.decorate(
+            result,
+            spirv::Decoration::FPFastMathMode,
+            &[dr::Operand::FPFastMathMode(
+                spirv::FPFastMathMode::ALLOW_RECIP,
+            )],
+        );
+        emit_flush_denormal(builder, map, opencl, instr_type, result, Some(a.dst))?;
+        return Ok(());
+    }
     builder.f_div(result_type, Some(a.dst), one, a.src)?;
     emit_rounding_decoration(builder, a.dst, desc.rounding);
     builder.decorate(
@@ -3484,7 +5331,7 @@ fn emit_mad_uint(
     opencl: spirv::Word,
     desc: &ast::MulUInt,
     arg: &ast::Arg4<ExpandedArgParams>,
-) -> Result<(), dr::Error> {
+) -> Result<(), TranslateError> {
     let inst_type = map.get_or_add(builder, SpirvType::from(ast::ScalarType::from(desc.typ)));
     match desc.control {
         ast::MulIntControl::Low => {
@@ -3500,7 +5347,7 @@ fn emit_mad_uint(
                 [arg.src1, arg.src2, arg.src3],
             )?;
         }
-        ast::MulIntControl::Wide => todo!(),
+        ast::MulIntControl::Wide => return Err(TranslateError::Todo),
     };
     Ok(())
 }
@@ -3511,7 +5358,7 @@ fn emit_mad_sint(
     opencl: spirv::Word,
     desc: &ast::MulSInt,
     arg: &ast::Arg4<ExpandedArgParams>,
-) -> Result<(), dr::Error> {
+) -> Result<(), TranslateError> {
     let inst_type = map.get_or_add(builder, SpirvType::from(ast::ScalarType::from(desc.typ)));
     match desc.control {
         ast::MulIntControl::Low => {
@@ -3527,7 +5374,7 @@ fn emit_mad_sint(
                 [arg.src1, arg.src2, arg.src3],
             )?;
         }
-        ast::MulIntControl::Wide => todo!(),
+        ast::MulIntControl::Wide => return Err(TranslateError::Todo),
     };
     Ok(())
 }
@@ -3580,21 +5427,13 @@ fn emit_min(
     opencl: spirv::Word,
     desc: &ast::MinMaxDetails,
     arg: &ast::Arg3<ExpandedArgParams>,
-) -> Result<(), dr::Error> {
+) -> Result<(), TranslateError> {
     let cl_op = match desc {
         ast::MinMaxDetails::Signed(_) => spirv::CLOp::s_min,
         ast::MinMaxDetails::Unsigned(_) => spirv::CLOp::u_min,
         ast::MinMaxDetails::Float(_) => spirv::CLOp::fmin,
     };
-    let inst_type = map.get_or_add(builder, SpirvType::from(desc.get_type()));
-    builder.ext_inst(
-        inst_type,
-        Some(arg.dst),
-        opencl,
-        cl_op as spirv::Word,
-        [arg.src1, arg.src2],
-    )?;
-    Ok(())
+    emit_min_max(builder, map, opencl, desc, cl_op, arg)
 }
 
 fn emit_max(
@@ -3603,20 +5442,82 @@ fn emit_max(
     opencl: spirv::Word,
     desc: &ast::MinMaxDetails,
     arg: &ast::Arg3<ExpandedArgParams>,
-) -> Result<(), dr::Error> {
+) -> Result<(), TranslateError> {
     let cl_op = match desc {
         ast::MinMaxDetails::Signed(_) => spirv::CLOp::s_max,
         ast::MinMaxDetails::Unsigned(_) => spirv::CLOp::u_max,
         ast::MinMaxDetails::Float(_) => spirv::CLOp::fmax,
     };
+    emit_min_max(builder, map, opencl, desc, cl_op, arg)
+}
+
+fn emit_min_max(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    opencl: spirv::Word,
+    desc: &ast::MinMaxDetails,
+    cl_op: spirv::CLOp,
+    arg: &ast::Arg3<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
     let inst_type = map.get_or_add(builder, SpirvType::from(desc.get_type()));
-    builder.ext_inst(
-        inst_type,
-        Some(arg.dst),
-        opencl,
-        cl_op as spirv::Word,
-        [arg.src1, arg.src2],
-    )?;
+    match desc {
+        ast::MinMaxDetails::Float(ast::MinMaxFloat { nan: true, typ, .. }) => {
+            let result = builder.ext_inst(
+                inst_type,
+                None,
+                opencl,
+                cl_op as spirv::Word,
+                [arg.src1, arg.src2],
+            )?;
+            emit_propagate_nan(builder, map, *typ, inst_type, arg, result)?;
+        }
+        _ => {
+            builder.ext_inst(
+                inst_type,
+                Some(arg.dst),
+                opencl,
+                cl_op as spirv::Word,
+                [arg.src1, arg.src2],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// OpenCL fmin/fmax return the other operand if one of them is NaN, .NaN
+// variants return the canonical NaN instead
+fn emit_propagate_nan(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    typ: ast::FloatType,
+    inst_type: spirv::Word,
+    arg: &ast::Arg3<ExpandedArgParams>,
+    result: spirv::Word,
+) -> Result<(), TranslateError> {
+    let (pred_type, nan) = match typ {
+        ast::FloatType::F16x2 => {
+            let pred_type = map.get_or_add(builder, SpirvType::Vector(SpirvScalarKey::Pred, 2));
+            let lane = ast::Type::Scalar(ast::ScalarType::F16);
+            let lane_nan = map.get_or_add_constant(builder, &lane, &vec_repr(0x7FFFu16))?;
+            let nan = builder.constant_composite(inst_type, None, &[lane_nan, lane_nan]);
+            (pred_type, nan)
+        }
+        _ => {
+            let pred_type = map.get_or_add_scalar(builder, ast::ScalarType::Pred);
+            let canonical_nan = match typ {
+                ast::FloatType::F16 => vec_repr(0x7FFFu16),
+                ast::FloatType::F32 => vec_repr(0x7FFFFFFFu32),
+                _ => vec_repr(0x7FFFFFFFFFFFFFFFu64),
+            };
+            let typ = ast::Type::Scalar(typ.into());
+            let nan = map.get_or_add_constant(builder, &typ, &canonical_nan)?;
+            (pred_type, nan)
+        }
+    };
+    let src1_nan = builder.is_nan(pred_type, None, arg.src1)?;
+    let src2_nan = builder.is_nan(pred_type, None, arg.src2)?;
+    let any_nan = builder.logical_or(pred_type, None, src1_nan, src2_nan)?;
+    builder.select(inst_type, Some(arg.dst), any_nan, nan, result)?;
     Ok(())
 }
 
@@ -3630,7 +5531,7 @@ fn emit_cvt(
     match dets {
         ast::CvtDetails::FloatFromFloat(desc) => {
             if desc.saturate {
-                todo!()
+                return Err(TranslateError::Todo);
             }
             let dest_t: ast::ScalarType = desc.dst.into();
             let result_type = map.get_or_add(builder, SpirvType::from(dest_t));
@@ -3683,7 +5584,7 @@ fn emit_cvt(
         }
         ast::CvtDetails::FloatFromInt(desc) => {
             if desc.saturate {
-                todo!()
+                return Err(TranslateError::Todo);
             }
             let dest_t: ast::ScalarType = desc.dst.into();
             let result_type = map.get_or_add(builder, SpirvType::from(dest_t));
@@ -3697,17 +5598,39 @@ fn emit_cvt(
         ast::CvtDetails::IntFromFloat(desc) => {
             let dest_t: ast::ScalarType = desc.dst.into();
             let result_type = map.get_or_add(builder, SpirvType::from(dest_t));
+            // FPRoundingMode decoration is ignored by drivers on float-to-int
+            // conversions, which always truncate, so we round explicitly first
+            let src = match desc.rounding {
+                Some(rounding) => {
+                    let src_t: ast::ScalarType = desc.src.into();
+                    let src_type = map.get_or_add(builder, SpirvType::from(src_t));
+                    let round_op = match rounding {
+                        ast::RoundingMode::NearestEven => spirv::CLOp::rint,
+                        ast::RoundingMode::Zero => spirv::CLOp::trunc,
+                        ast::RoundingMode::NegativeInf => spirv::CLOp::floor,
+                        ast::RoundingMode::PositiveInf => spirv::CLOp::ceil,
+                    };
+                    builder.ext_inst(src_type, None, opencl, round_op as u32, [arg.src])?
+                }
+                None => arg.src,
+            };
             if desc.dst.is_signed() {
-                builder.convert_f_to_s(result_type, Some(arg.dst), arg.src)?;
+                builder.convert_f_to_s(result_type, Some(arg.dst), src)?;
             } else {
-                builder.convert_f_to_u(result_type, Some(arg.dst), arg.src)?;
+                builder.convert_f_to_u(result_type, Some(arg.dst), src)?;
             }
-            emit_rounding_decoration(builder, arg.dst, desc.rounding);
             emit_saturating_decoration(builder, arg.dst, desc.saturate);
         }
         ast::CvtDetails::IntFromInt(desc) => {
             let dest_t: ast::ScalarType = desc.dst.into();
             let src_t: ast::ScalarType = desc.src.into();
+            // SatConvert* instructions only saturate on a change of signedness,
+            // so when the width changes we clamp to the destination range first
+            let src = if desc.saturate && desc.dst.width() != desc.src.width() {
+                emit_saturating_clamp(builder, map, opencl, desc.dst, desc.src, arg.src)?
+            } else {
+                arg.src
+            };
             // first do shortening/widening
             let src = if desc.dst.width() != desc.src.width() {
                 let new_dst = if dest_t.kind() == src_t.kind() {
@@ -3716,7 +5639,7 @@ fn emit_cvt(
                     builder.id()
                 };
                 let cv = ImplicitConversion {
-                    src: arg.src,
+                    src,
                     dst: new_dst,
                     from: ast::Type::Scalar(src_t),
                     to: ast::Type::Scalar(ast::ScalarType::from_parts(
@@ -3730,14 +5653,14 @@ fn emit_cvt(
                 emit_implicit_conversion(builder, map, &cv)?;
                 new_dst
             } else {
-                arg.src
+                src
             };
             if dest_t.kind() == src_t.kind() {
                 return Ok(());
             }
             // now do actual conversion
             let result_type = map.get_or_add(builder, SpirvType::from(dest_t));
-            if desc.saturate {
+            if desc.saturate && desc.dst.width() == desc.src.width() {
                 if desc.dst.is_signed() {
                     builder.sat_convert_u_to_s(result_type, Some(arg.dst), src)?;
                 } else {
@@ -3747,10 +5670,425 @@ fn emit_cvt(
                 builder.bitcast(result_type, Some(arg.dst), src)?;
             }
         }
+        ast::CvtDetails::TruncatedFromFloat(desc) => {
+            emit_cvt_truncated(builder, map, desc.rounding, desc.dst, arg.src, arg.dst)?;
+        }
+    }
+    Ok(())
+}
+
+// Clamps an integer of type `src_t` to the values representable in `dst_t`,
+// for cvt.sat between integer types. The value is returned as-is if every
+// `src_t` value fits
+fn emit_saturating_clamp(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    opencl: spirv::Word,
+    dst_t: ast::IntType,
+    src_t: ast::IntType,
+    src: spirv::Word,
+) -> Result<spirv::Word, TranslateError> {
+    let (dst_min, dst_max) = int_range(dst_t);
+    let (src_min, src_max) = int_range(src_t);
+    let (min, max) = (i128::max(dst_min, src_min), i128::min(dst_max, src_max));
+    if (min, max) == (src_min, src_max) {
+        return Ok(src);
+    }
+    let scalar_t: ast::ScalarType = src_t.into();
+    let typ = ast::Type::Scalar(scalar_t);
+    let width = src_t.width() as usize;
+    let result_type = map.get_or_add(builder, SpirvType::from(scalar_t));
+    let max = map.get_or_add_constant(builder, &typ, &(max as u64).to_le_bytes()[..width])?;
+    Ok(if src_t.is_signed() {
+        let min = map.get_or_add_constant(builder, &typ, &(min as u64).to_le_bytes()[..width])?;
+        builder.ext_inst(
+            result_type,
+            None,
+            opencl,
+            spirv::CLOp::s_clamp as spirv::Word,
+            [src, min, max],
+        )?
+    } else {
+        builder.ext_inst(
+            result_type,
+            None,
+            opencl,
+            spirv::CLOp::u_min as spirv::Word,
+            [src, max],
+        )?
+    })
+}
+
+fn int_range(t: ast::IntType) -> (i128, i128) {
+    let bits = t.width() as u32 * 8;
+    if t.is_signed() {
+        (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+    } else {
+        (0, (1i128 << bits) - 1)
+    }
+}
+
+// Rounding is done on the bit pattern: adding a bias just below the cut-off
+// point carries into the kept mantissa bits (and into the exponent on
+// overflow). NaNs are special-cased, because both the bias and the truncation
+// can turn them into infinities
+fn emit_cvt_truncated(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    rounding: ast::TruncatedRoundingMode,
+    dst_t: ast::TruncatedFloatType,
+    src: spirv::Word,
+    dst: spirv::Word,
+) -> Result<(), TranslateError> {
+    let (dropped_bits, canonical_nan) = match dst_t {
+        ast::TruncatedFloatType::BF16 => (16u32, vec_repr(0x7fffu16)),
+        ast::TruncatedFloatType::TF32 => (13u32, vec_repr(0x7fffffffu32)),
+    };
+    let u32_type = map.get_or_add_scalar(builder, ast::ScalarType::U32);
+    let u32_const = |builder: &mut dr::Builder, map: &mut TypeWordMap, value: u32| {
+        map.get_or_add_constant(
+            builder,
+            &ast::Type::Scalar(ast::ScalarType::U32),
+            &vec_repr(value),
+        )
+    };
+    let src_bits = builder.bitcast(u32_type, None, src)?;
+    let rounded = match rounding {
+        ast::TruncatedRoundingMode::Zero => src_bits,
+        ast::TruncatedRoundingMode::NearestAway => {
+            let bias = u32_const(builder, map, 1 << (dropped_bits - 1))?;
+            builder.i_add(u32_type, None, src_bits, bias)?
+        }
+        ast::TruncatedRoundingMode::NearestEven => {
+            // Ties round up only if the lowest kept bit is set
+            let dropped_bits_const = u32_const(builder, map, dropped_bits)?;
+            let kept_bits =
+                builder.shift_right_logical(u32_type, None, src_bits, dropped_bits_const)?;
+            let one = u32_const(builder, map, 1)?;
+            let kept_lsb = builder.bitwise_and(u32_type, None, kept_bits, one)?;
+            let half = u32_const(builder, map, (1 << (dropped_bits - 1)) - 1)?;
+            let bias = builder.i_add(u32_type, None, half, kept_lsb)?;
+            builder.i_add(u32_type, None, src_bits, bias)?
+        }
+    };
+    let dst_type = map.get_or_add_scalar(builder, dst_t.bit_type());
+    let truncated = match dst_t {
+        ast::TruncatedFloatType::BF16 => {
+            let dropped_bits_const = u32_const(builder, map, dropped_bits)?;
+            let high_bits =
+                builder.shift_right_logical(u32_type, None, rounded, dropped_bits_const)?;
+            builder.u_convert(dst_type, None, high_bits)?
+        }
+        ast::TruncatedFloatType::TF32 => {
+            let mask = u32_const(builder, map, !((1 << dropped_bits) - 1))?;
+            builder.bitwise_and(dst_type, None, rounded, mask)?
+        }
+    };
+    let pred_type = map.get_or_add_scalar(builder, ast::ScalarType::Pred);
+    let is_nan = builder.is_nan(pred_type, None, src)?;
+    let nan = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(dst_t.bit_type()),
+        &canonical_nan,
+    )?;
+    builder.select(dst_type, Some(dst), is_nan, nan, truncated)?;
+    Ok(())
+}
+
+// SPIR-V has no notion of cache levels, so cache operators are only hints and
+// are dropped. Nontemporal would be the closest match for streaming accesses,
+// but it needs SPIR-V 1.4 and we emit 1.3. Only Aligned takes an extra
+// operand, callers pass the alignment
+fn ld_st_memory_access(
+    qualifier: ast::LdStQualifier,
+    caching: spirv::MemoryAccess,
+) -> Result<spirv::MemoryAccess, TranslateError> {
+    let qualifier = match qualifier {
+        ast::LdStQualifier::Weak => spirv::MemoryAccess::NONE,
+        ast::LdStQualifier::Volatile => spirv::MemoryAccess::VOLATILE,
+        ast::LdStQualifier::Relaxed(_) | ast::LdStQualifier::Acquire(_) => {
+            return Err(TranslateError::Todo)
+        }
+    };
+    Ok(spirv::MemoryAccess::ALIGNED | qualifier | caching)
+}
+
+// Const and global pointers have the same format as generic pointers, so
+// converting them is a no-op. Shared and local pointers are offsets into their
+// own memory and have to be cast explicitly, otherwise generic loads and
+// isspacep would not recognize them
+fn emit_cvta(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    details: &ast::CvtaDetails,
+    arg: &ast::Arg2<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
+    let result_type = map.get_or_add_scalar(builder, ast::ScalarType::B64);
+    let (space, to_generic) = if details.to == ast::CvtaStateSpace::Generic {
+        (details.from, true)
+    } else {
+        (details.to, false)
+    };
+    let storage_class = match (space, &details.size) {
+        (ast::CvtaStateSpace::Shared, ast::CvtaSize::U64) => spirv::StorageClass::Workgroup,
+        (ast::CvtaStateSpace::Local, ast::CvtaSize::U64) => spirv::StorageClass::Function,
+        _ => {
+            builder.copy_object(result_type, Some(arg.dst), arg.src)?;
+            return Ok(());
+        }
+    };
+    let (generic_ptr_type, space_ptr_type) = byte_pointer_types(builder, map, storage_class);
+    let ptr = if to_generic {
+        let space_ptr = builder.convert_u_to_ptr(space_ptr_type, None, arg.src)?;
+        builder.ptr_cast_to_generic(generic_ptr_type, None, space_ptr)?
+    } else {
+        let generic_ptr = builder.convert_u_to_ptr(generic_ptr_type, None, arg.src)?;
+        builder.generic_cast_to_ptr(space_ptr_type, None, generic_ptr)?
+    };
+    builder.convert_ptr_to_u(result_type, Some(arg.dst), ptr)?;
+    Ok(())
+}
+
+// A generic address is in a state space if the cast to that storage class does
+// not return null. Shared and local addresses are only recognized if they were
+// converted with cvta, the address of a variable taken with mov is not generic
+fn emit_isspacep(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    space: ast::IsspacepSpace,
+    arg: &ast::Arg2<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
+    let storage_class = match space {
+        ast::IsspacepSpace::Global => spirv::StorageClass::CrossWorkgroup,
+        ast::IsspacepSpace::Local => spirv::StorageClass::Function,
+        ast::IsspacepSpace::Shared => spirv::StorageClass::Workgroup,
+    };
+    let (generic_ptr_type, space_ptr_type) = byte_pointer_types(builder, map, storage_class);
+    let generic_ptr = builder.convert_u_to_ptr(generic_ptr_type, None, arg.src)?;
+    let space_ptr =
+        builder.generic_cast_to_ptr_explicit(space_ptr_type, None, generic_ptr, storage_class)?;
+    let b64_type = ast::Type::Scalar(ast::ScalarType::B64);
+    let u64_type = map.get_or_add(builder, SpirvType::from(b64_type.clone()));
+    let address = builder.convert_ptr_to_u(u64_type, None, space_ptr)?;
+    let null = map.get_or_add_constant(builder, &b64_type, &vec_repr(0u64))?;
+    let pred_type = map.get_or_add_scalar(builder, ast::ScalarType::Pred);
+    builder.i_not_equal(pred_type, Some(arg.dst), address, null)?;
+    Ok(())
+}
+
+fn byte_pointer_types(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    storage_class: spirv::StorageClass,
+) -> (spirv::Word, spirv::Word) {
+    let byte_type = ast::Type::Scalar(ast::ScalarType::B8);
+    let generic_ptr_type = map.get_or_add(
+        builder,
+        SpirvType::new_pointer(byte_type.clone(), spirv::StorageClass::Generic),
+    );
+    let space_ptr_type = map.get_or_add(builder, SpirvType::new_pointer(byte_type, storage_class));
+    (generic_ptr_type, space_ptr_type)
+}
+
+// There is no bf16 arithmetic in SPIR-V. Widening bf16 to f32 is exact, so
+// we compute in f32 and round the result back to bf16
+fn emit_bf16_to_f32(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    src: spirv::Word,
+) -> Result<spirv::Word, TranslateError> {
+    let u32_type = map.get_or_add_scalar(builder, ast::ScalarType::U32);
+    let f32_type = map.get_or_add_scalar(builder, ast::ScalarType::F32);
+    let wide_src = builder.u_convert(u32_type, None, src)?;
+    let sixteen = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(ast::ScalarType::U32),
+        &vec_repr(16u32),
+    )?;
+    let f32_bits = builder.shift_left_logical(u32_type, None, wide_src, sixteen)?;
+    Ok(builder.bitcast(f32_type, None, f32_bits)?)
+}
+
+fn emit_bf16_binary_op(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    arg: &ast::Arg3<ExpandedArgParams>,
+    f32_op: fn(
+        &mut dr::Builder,
+        spirv::Word,
+        Option<spirv::Word>,
+        spirv::Word,
+        spirv::Word,
+    ) -> Result<spirv::Word, dr::Error>,
+) -> Result<(), TranslateError> {
+    let f32_type = map.get_or_add_scalar(builder, ast::ScalarType::F32);
+    let src1 = emit_bf16_to_f32(builder, map, arg.src1)?;
+    let src2 = emit_bf16_to_f32(builder, map, arg.src2)?;
+    let result = f32_op(builder, f32_type, None, src1, src2)?;
+    emit_cvt_truncated(
+        builder,
+        map,
+        ast::TruncatedRoundingMode::NearestEven,
+        ast::TruncatedFloatType::BF16,
+        result,
+        arg.dst,
+    )
+}
+
+fn emit_bf16_fma(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    opencl: spirv::Word,
+    arg: &ast::Arg4<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
+    let f32_type = map.get_or_add_scalar(builder, ast::ScalarType::F32);
+    let src1 = emit_bf16_to_f32(builder, map, arg.src1)?;
+    let src2 = emit_bf16_to_f32(builder, map, arg.src2)?;
+    let src3 = emit_bf16_to_f32(builder, map, arg.src3)?;
+    let result = builder.ext_inst(
+        f32_type,
+        None,
+        opencl,
+        spirv::CLOp::fma as spirv::Word,
+        [src1, src2, src3],
+    )?;
+    emit_cvt_truncated(
+        builder,
+        map,
+        ast::TruncatedRoundingMode::NearestEven,
+        ast::TruncatedFloatType::BF16,
+        result,
+        arg.dst,
+    )
+}
+
+// sin and cos lose accuracy on large arguments, so arguments outside of
+// [-π, π] are first reduced by a multiple of 2π. 2π is split into two f32
+// parts (Cody-Waite), fma keeps k * 2π from being rounded too early.
+// Small arguments are passed through unchanged
+fn emit_sin_cos_range_reduction(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    opencl: spirv::Word,
+    src: spirv::Word,
+) -> Result<spirv::Word, TranslateError> {
+    let f32_type = map.get_or_add_scalar(builder, ast::ScalarType::F32);
+    let f32_const = |builder: &mut dr::Builder, map: &mut TypeWordMap, value: f32| {
+        map.get_or_add_constant(
+            builder,
+            &ast::Type::Scalar(ast::ScalarType::F32),
+            &vec_repr(value),
+        )
+    };
+    let abs_src = builder.ext_inst(
+        f32_type,
+        None,
+        opencl,
+        spirv::CLOp::fabs as spirv::Word,
+        [src],
+    )?;
+    let pred_type = map.get_or_add_scalar(builder, ast::ScalarType::Pred);
+    let pi = f32_const(builder, map, std::f32::consts::PI)?;
+    let is_large = builder.f_ord_greater_than(pred_type, None, abs_src, pi)?;
+    let inv_two_pi = f32_const(builder, map, std::f32::consts::FRAC_1_PI / 2f32)?;
+    let turns = builder.f_mul(f32_type, None, src, inv_two_pi)?;
+    let k = builder.ext_inst(
+        f32_type,
+        None,
+        opencl,
+        spirv::CLOp::rint as spirv::Word,
+        [turns],
+    )?;
+    let neg_k = builder.f_negate(f32_type, None, k)?;
+    let two_pi_hi = f32_const(builder, map, 2f32 * std::f32::consts::PI)?;
+    let reduced_hi = builder.ext_inst(
+        f32_type,
+        None,
+        opencl,
+        spirv::CLOp::fma as spirv::Word,
+        [neg_k, two_pi_hi, src],
+    )?;
+    let two_pi_lo = f32_const(builder, map, -1.7484555e-7f32)?;
+    let reduced = builder.ext_inst(
+        f32_type,
+        None,
+        opencl,
+        spirv::CLOp::fma as spirv::Word,
+        [neg_k, two_pi_lo, reduced_hi],
+    )?;
+    Ok(builder.select(f32_type, None, is_large, reduced, src)?)
+}
+
+// Denormal mode is chosen per kernel, so a .ftz instruction in a kernel that
+// otherwise preserves denormals has to flush its input and output explicitly
+fn emit_f32_ext_inst_ftz(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    opencl: spirv::Word,
+    op: spirv::CLOp,
+    flush_to_zero: bool,
+    arg: &Arg2,
+) -> Result<(), TranslateError> {
+    let result_type = map.get_or_add_scalar(builder, ast::ScalarType::F32);
+    if !flush_to_zero {
+        builder.ext_inst(
+            result_type,
+            Some(arg.dst),
+            opencl,
+            op as spirv::Word,
+            [arg.src],
+        )?;
+        return Ok(());
     }
+    let src = emit_flush_denormal(builder, map, opencl, ast::ScalarType::F32, arg.src, None)?;
+    let result = builder.ext_inst(result_type, None, opencl, op as spirv::Word, [src])?;
+    emit_flush_denormal(
+        builder,
+        map,
+        opencl,
+        ast::ScalarType::F32,
+        result,
+        Some(arg.dst),
+    )?;
     Ok(())
 }
 
+// Denormals are flushed to a zero of the same sign
+fn emit_flush_denormal(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    opencl: spirv::Word,
+    typ: ast::ScalarType,
+    src: spirv::Word,
+    dst: Option<spirv::Word>,
+) -> Result<spirv::Word, TranslateError> {
+    let (min_normal, zero) = match typ {
+        ast::ScalarType::F32 => (vec_repr(std::f32::MIN_POSITIVE), vec_repr(0f32)),
+        ast::ScalarType::F64 => (vec_repr(std::f64::MIN_POSITIVE), vec_repr(0f64)),
+        _ => return Err(error_unreachable()),
+    };
+    let result_type = map.get_or_add_scalar(builder, typ);
+    let abs_src = builder.ext_inst(
+        result_type,
+        None,
+        opencl,
+        spirv::CLOp::fabs as spirv::Word,
+        [src],
+    )?;
+    let pred_type = map.get_or_add_scalar(builder, ast::ScalarType::Pred);
+    let min_normal = map.get_or_add_constant(builder, &ast::Type::Scalar(typ), &min_normal)?;
+    let is_denormal = builder.f_ord_less_than(pred_type, None, abs_src, min_normal)?;
+    let zero = map.get_or_add_constant(builder, &ast::Type::Scalar(typ), &zero)?;
+    let signed_zero = builder.ext_inst(
+        result_type,
+        None,
+        opencl,
+        spirv::CLOp::copysign as spirv::Word,
+        [zero, src],
+    )?;
+    Ok(builder.select(result_type, dst, is_denormal, signed_zero, src)?)
+}
+
 fn emit_saturating_decoration(builder: &mut dr::Builder, dst: u32, saturate: bool) {
     if saturate {
         builder.decorate(dst, spirv::Decoration::SaturatedConversion, []);
@@ -3783,17 +6121,78 @@ impl ast::RoundingMode {
     }
 }
 
+// f16x2 is compared lane by lane into a vector of two predicates, lane 0 goes
+// to the first destination and lane 1 to the second one
 fn emit_setp(
     builder: &mut dr::Builder,
     map: &mut TypeWordMap,
     setp: &ast::SetpData,
     arg: &ast::Arg4Setp<ExpandedArgParams>,
-) -> Result<(), dr::Error> {
-    let result_type = map.get_or_add(builder, SpirvType::Base(SpirvScalarKey::Pred));
-    let result_id = Some(arg.dst1);
-    let operand_1 = arg.src1;
-    let operand_2 = arg.src2;
-    match (setp.cmp_op, setp.typ.kind()) {
+) -> Result<(), TranslateError> {
+    let pred_type = map.get_or_add(builder, SpirvType::Base(SpirvScalarKey::Pred));
+    let (cmp_op, src1, src2) = (setp.cmp_op, arg.src1, arg.src2);
+    if setp.typ != ast::ScalarType::F16x2 {
+        let kind = setp.typ.kind();
+        emit_compare(builder, pred_type, Some(arg.dst1), cmp_op, kind, src1, src2)?;
+        return Ok(());
+    }
+    let mask_type = map.get_or_add(builder, SpirvType::Vector(SpirvScalarKey::Pred, 2));
+    let kind = ScalarKind::Float;
+    let mask = emit_compare(builder, mask_type, None, cmp_op, kind, src1, src2)?;
+    builder.composite_extract(pred_type, Some(arg.dst1), mask, [0])?;
+    if let Some(dst2) = arg.dst2 {
+        builder.composite_extract(pred_type, Some(dst2), mask, [1])?;
+    }
+    Ok(())
+}
+
+fn emit_set(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    set: &ast::SetData,
+    arg: &ast::Arg3<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
+    if set.src_type != ast::ScalarType::F16x2 {
+        return Err(TranslateError::Todo);
+    }
+    let mask_type = map.get_or_add(builder, SpirvType::Vector(SpirvScalarKey::Pred, 2));
+    let (cmp_op, src1, src2) = (set.cmp_op, arg.src1, arg.src2);
+    let kind = ScalarKind::Float;
+    let mask = emit_compare(builder, mask_type, None, cmp_op, kind, src1, src2)?;
+    let (lane_type, true_lane, false_lane) = match set.dst_type {
+        ast::ScalarType::F16x2 => (
+            ast::ScalarType::F16,
+            vec_repr(f16::from_f32(1.0)),
+            vec_repr(f16::from_f32(0.0)),
+        ),
+        _ => (ast::ScalarType::U16, vec_repr(0xFFFFu16), vec_repr(0u16)),
+    };
+    let lane = ast::Type::Scalar(lane_type);
+    let true_lane = map.get_or_add_constant(builder, &lane, &true_lane)?;
+    let false_lane = map.get_or_add_constant(builder, &lane, &false_lane)?;
+    let lanes_type = map.get_or_add(builder, SpirvType::Vector(lane_type.into(), 2));
+    let true_lanes = builder.constant_composite(lanes_type, None, &[true_lane, true_lane]);
+    let false_lanes = builder.constant_composite(lanes_type, None, &[false_lane, false_lane]);
+    if set.dst_type == ast::ScalarType::F16x2 {
+        builder.select(lanes_type, Some(arg.dst), mask, true_lanes, false_lanes)?;
+    } else {
+        let lanes = builder.select(lanes_type, None, mask, true_lanes, false_lanes)?;
+        let dst_type = map.get_or_add_scalar(builder, set.dst_type);
+        builder.bitcast(dst_type, Some(arg.dst), lanes)?;
+    }
+    Ok(())
+}
+
+fn emit_compare(
+    builder: &mut dr::Builder,
+    result_type: spirv::Word,
+    result_id: Option<spirv::Word>,
+    cmp_op: ast::SetpCompareOp,
+    kind: ScalarKind,
+    operand_1: spirv::Word,
+    operand_2: spirv::Word,
+) -> Result<spirv::Word, TranslateError> {
+    Ok(match (cmp_op, kind) {
         (ast::SetpCompareOp::Eq, ScalarKind::Signed)
         | (ast::SetpCompareOp::Eq, ScalarKind::Unsigned)
         | (ast::SetpCompareOp::Eq, ScalarKind::Bit) => {
@@ -3868,9 +6267,8 @@ fn emit_setp(
         (ast::SetpCompareOp::NanGreaterOrEq, _) => {
             builder.f_unord_greater_than_equal(result_type, result_id, operand_1, operand_2)
         }
-        _ => todo!(),
-    }?;
-    Ok(())
+        _ => return Err(TranslateError::Todo),
+    }?)
 }
 
 fn emit_mul_sint(
@@ -3920,6 +6318,58 @@ fn emit_mul_sint(
     Ok(())
 }
 
+// Only the low 24 bits of both operands take part in the multiplication.
+// .hi returns bits 47:16 of the 48-bit product, so it is computed in 64 bits
+fn emit_mul24(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    details: ast::Mul24Details,
+    dst: Option<spirv::Word>,
+    src1: spirv::Word,
+    src2: spirv::Word,
+) -> Result<spirv::Word, TranslateError> {
+    let u32_type = map.get_or_add_scalar(builder, ast::ScalarType::U32);
+    let zero = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(ast::ScalarType::U32),
+        &vec_repr(0u32),
+    )?;
+    let width = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(ast::ScalarType::U32),
+        &vec_repr(24u32),
+    )?;
+    let signed = details.typ.is_signed();
+    let extract = if signed {
+        dr::Builder::bit_field_s_extract
+    } else {
+        dr::Builder::bit_field_u_extract
+    };
+    let src1 = extract(builder, u32_type, None, src1, zero, width)?;
+    let src2 = extract(builder, u32_type, None, src2, zero, width)?;
+    Ok(match details.control {
+        ast::Mul24Control::Low => builder.i_mul(u32_type, dst, src1, src2)?,
+        ast::Mul24Control::High => {
+            let u64_type = map.get_or_add_scalar(builder, ast::ScalarType::U64);
+            let convert = if signed {
+                dr::Builder::s_convert
+            } else {
+                dr::Builder::u_convert
+            };
+            let src1 = convert(builder, u64_type, None, src1)?;
+            let src2 = convert(builder, u64_type, None, src2)?;
+            let product = builder.i_mul(u64_type, None, src1, src2)?;
+            let shift = map.get_or_add_constant(
+                builder,
+                &ast::Type::Scalar(ast::ScalarType::U64),
+                &vec_repr(16u64),
+            )?;
+            let product = builder.shift_right_logical(u64_type, None, product, shift)?;
+            builder.u_convert(u32_type, dst, product)?
+        }
+    })
+}
+
 fn emit_mul_uint(
     builder: &mut dr::Builder,
     map: &mut TypeWordMap,
@@ -4009,15 +6459,219 @@ fn emit_abs(
     Ok(())
 }
 
-fn emit_add_int(
+// Follows the pseudocode from
+// https://docs.nvidia.com/cuda/parallel-thread-execution/index.html#data-movement-and-conversion-instructions-shfl-sync
+// PTX assumes a warp of 32 lanes, but we run with whatever subgroup size the
+// driver picked (WARP_SZ, reported by get_max_simd on the host side).
+// Source lanes past the end of the subgroup are treated like lanes outside of
+// the clamp: the predicate is false and the thread gets its own value back.
+// This means that on a 16-wide subgroup, a full-warp (c = 0x1f) shuffle
+// reduction only reduces across 16 lanes
+// membermask is ignored, non-uniform group operations already work only with
+// the invocations that are currently active
+fn emit_shfl(
     builder: &mut dr::Builder,
     map: &mut TypeWordMap,
-    typ: ast::ScalarType,
-    saturate: bool,
-    arg: &ast::Arg3<ExpandedArgParams>,
-) -> Result<(), dr::Error> {
+    opencl: spirv::Word,
+    mode: ast::ShflMode,
+    arg: &ast::ArgShfl<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
+    let u32_type = map.get_or_add_scalar(builder, ast::ScalarType::U32);
+    let pred_type = map.get_or_add_scalar(builder, ast::ScalarType::Pred);
+    let u32_constant = |builder: &mut dr::Builder, map: &mut TypeWordMap, value: u32| {
+        map.get_or_add_constant(
+            builder,
+            &ast::Type::Scalar(ast::ScalarType::U32),
+            &vec_repr(value),
+        )
+    };
+    let lane_mask = u32_constant(builder, map, 0x1f)?;
+    let segmask_shift = u32_constant(builder, map, 8)?;
+    let one = u32_constant(builder, map, 1)?;
+    let subgroup_scope = u32_constant(builder, map, spirv::Scope::Subgroup as u32)?;
+    let b = builder.bitwise_and(u32_type, None, arg.src2, lane_mask)?;
+    let clamp = builder.bitwise_and(u32_type, None, arg.src3, lane_mask)?;
+    let segmask = builder.shift_right_logical(u32_type, None, arg.src3, segmask_shift)?;
+    let segmask = builder.bitwise_and(u32_type, None, segmask, lane_mask)?;
+    let not_segmask = builder.not(u32_type, None, segmask)?;
+    let min_lane = builder.bitwise_and(u32_type, None, arg.lane_id, segmask)?;
+    let clamp_in_segment = builder.bitwise_and(u32_type, None, clamp, not_segmask)?;
+    let max_lane = builder.bitwise_or(u32_type, None, min_lane, clamp_in_segment)?;
+    // Source lane can be negative in .up mode, hence signed comparisons
+    let (in_bounds, value) = match mode {
+        ast::ShflMode::Up => {
+            // For .up max_lane is a lower bound and the source lane never
+            // moves past the current lane, so it always stays in the subgroup
+            let j = builder.i_sub(u32_type, None, arg.lane_id, b)?;
+            let in_bounds = builder.s_greater_than_equal(pred_type, arg.dst2, j, max_lane)?;
+            let value =
+                builder.group_non_uniform_shuffle(u32_type, None, subgroup_scope, arg.src1, j)?;
+            (in_bounds, value)
+        }
+        ast::ShflMode::Down | ast::ShflMode::Bfly | ast::ShflMode::Idx => {
+            let j = match mode {
+                ast::ShflMode::Down => builder.i_add(u32_type, None, arg.lane_id, b)?,
+                ast::ShflMode::Bfly => builder.bitwise_xor(u32_type, None, arg.lane_id, b)?,
+                ast::ShflMode::Idx => {
+                    let b_in_segment = builder.bitwise_and(u32_type, None, b, not_segmask)?;
+                    builder.bitwise_or(u32_type, None, min_lane, b_in_segment)?
+                }
+                ast::ShflMode::Up => return Err(error_unreachable()),
+            };
+            let last_lane = builder.i_sub(u32_type, None, arg.warp_size, one)?;
+            let max_lane = builder.ext_inst(
+                u32_type,
+                None,
+                opencl,
+                spirv::CLOp::u_min as spirv::Word,
+                [max_lane, last_lane],
+            )?;
+            let in_bounds = builder.s_less_than_equal(pred_type, arg.dst2, j, max_lane)?;
+            let value = if mode == ast::ShflMode::Bfly {
+                builder.group_non_uniform_shuffle_xor(
+                    u32_type,
+                    None,
+                    subgroup_scope,
+                    arg.src1,
+                    b,
+                )?
+            } else {
+                builder.group_non_uniform_shuffle(u32_type, None, subgroup_scope, arg.src1, j)?
+            };
+            (in_bounds, value)
+        }
+    };
+    builder.select(u32_type, Some(arg.dst1), in_bounds, value, arg.src1)?;
+    Ok(())
+}
+
+// Ballot bits are indexed by the subgroup lane, bits of threads outside of
+// membermask are cleared. .all and .any take every active thread of the
+// subgroup into account, membermask is only validated by the parser
+fn emit_vote(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    details: &ast::VoteDetails,
+    arg: &ast::Arg3<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
+    let pred_type = map.get_or_add_scalar(builder, ast::ScalarType::Pred);
+    let subgroup_scope = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(ast::ScalarType::U32),
+        &vec_repr(spirv::Scope::Subgroup as u32),
+    )?;
+    let pred = if details.negate_pred {
+        builder.logical_not(pred_type, None, arg.src1)?
+    } else {
+        arg.src1
+    };
+    match details.mode {
+        ast::VoteMode::Ballot => {
+            let u32_type = map.get_or_add_scalar(builder, ast::ScalarType::U32);
+            let ballot_type = map.get_or_add(builder, SpirvType::Vector(SpirvScalarKey::B32, 4));
+            let ballot =
+                builder.group_non_uniform_ballot(ballot_type, None, subgroup_scope, pred)?;
+            let low_bits = builder.composite_extract(u32_type, None, ballot, [0])?;
+            builder.bitwise_and(u32_type, Some(arg.dst), low_bits, arg.src2)?;
+        }
+        ast::VoteMode::All => {
+            builder.group_non_uniform_all(pred_type, Some(arg.dst), subgroup_scope, pred)?;
+        }
+        ast::VoteMode::Any => {
+            builder.group_non_uniform_any(pred_type, Some(arg.dst), subgroup_scope, pred)?;
+        }
+    }
+    Ok(())
+}
+
+// OpIAddCarry and OpISubBorrow only take two operands, so an incoming carry is
+// applied in a second step and the outgoing carry is the union of both steps
+fn emit_carry_arith(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    typ: ast::IntType,
+    arg: &ast::ArgCarry<ExpandedArgParams>,
+    builder_fn: fn(
+        &mut dr::Builder,
+        spirv::Word,
+        Option<spirv::Word>,
+        spirv::Word,
+        spirv::Word,
+    ) -> Result<spirv::Word, dr::Error>,
+) -> Result<(), TranslateError> {
+    let scalar_type = ast::ScalarType::from(typ);
+    let int_type = map.get_or_add_scalar(builder, scalar_type);
+    let pred_type = map.get_or_add_scalar(builder, ast::ScalarType::Pred);
+    let result_type = map.get_or_add(
+        builder,
+        SpirvType::Struct(vec![
+            SpirvScalarKey::from(scalar_type),
+            SpirvScalarKey::from(scalar_type),
+        ]),
+    );
+    let (zero, one) = if typ.width() == 8 {
+        (vec_repr(0u64), vec_repr(1u64))
+    } else {
+        (vec_repr(0u32), vec_repr(1u32))
+    };
+    let zero = map.get_or_add_constant(builder, &ast::Type::Scalar(scalar_type), &zero)?;
+    let one = map.get_or_add_constant(builder, &ast::Type::Scalar(scalar_type), &one)?;
+    let mut result = builder_fn(builder, result_type, None, arg.src1, arg.src2)?;
+    let mut carry = builder.composite_extract(int_type, None, result, [1])?;
+    if let Some(carry_in) = arg.carry_in {
+        let partial = builder.composite_extract(int_type, None, result, [0])?;
+        let carry_in = builder.select(int_type, None, carry_in, one, zero)?;
+        result = builder_fn(builder, result_type, None, partial, carry_in)?;
+        let second_carry = builder.composite_extract(int_type, None, result, [1])?;
+        carry = builder.bitwise_or(int_type, None, carry, second_carry)?;
+    }
+    builder.composite_extract(int_type, Some(arg.dst), result, [0])?;
+    if let Some(carry_out) = arg.carry_out {
+        builder.i_not_equal(pred_type, Some(carry_out), carry, zero)?;
+    }
+    Ok(())
+}
+
+// Textures were turned into image and sampler kernel parameters by
+// resolve_textures, here they only get combined and sampled at mip level 0
+fn emit_tex(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    details: &ast::TexDetails,
+    arg: &ast::Arg3<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
+    let (image, sampler) = details.texture.ok_or_else(error_unreachable)?;
+    let sampled_image_type = map.get_or_add(builder, SpirvType::SampledImage);
+    let sampled_image = builder.sampled_image(sampled_image_type, None, image, sampler)?;
+    let result_type = map.get_or_add(
+        builder,
+        SpirvType::from(ast::Type::Vector(details.data_type.into(), 4)),
+    );
+    let lod = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(ast::ScalarType::F32),
+        &vec_repr(0f32),
+    )?;
+    builder.image_sample_explicit_lod(
+        result_type,
+        Some(arg.dst),
+        sampled_image,
+        arg.src2,
+        Some(spirv::ImageOperands::LOD),
+        [dr::Operand::IdRef(lod)],
+    )?;
+    Ok(())
+}
+
+fn emit_add_int(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    typ: ast::ScalarType,
+    saturate: bool,
+    arg: &ast::Arg3<ExpandedArgParams>,
+) -> Result<(), TranslateError> {
     if saturate {
-        todo!()
+        return Err(TranslateError::Todo);
     }
     let inst_type = map.get_or_add(builder, SpirvType::from(ast::ScalarType::from(typ)));
     builder.i_add(inst_type, Some(arg.dst), arg.src1, arg.src2)?;
@@ -4030,9 +6684,9 @@ fn emit_sub_int(
     typ: ast::ScalarType,
     saturate: bool,
     arg: &ast::Arg3<ExpandedArgParams>,
-) -> Result<(), dr::Error> {
+) -> Result<(), TranslateError> {
     if saturate {
-        todo!()
+        return Err(TranslateError::Todo);
     }
     let inst_type = map.get_or_add(builder, SpirvType::from(ast::ScalarType::from(typ)));
     builder.i_sub(inst_type, Some(arg.dst), arg.src1, arg.src2)?;
@@ -4058,9 +6712,9 @@ fn emit_implicit_conversion(
         (TypeKind::Scalar, TypeKind::Scalar, ConversionKind::Default) => {
             if from_parts.width == to_parts.width {
                 let dst_type = map.get_or_add(builder, SpirvType::from(cv.to.clone()));
-                if from_parts.scalar_kind != ScalarKind::Float
-                    && to_parts.scalar_kind != ScalarKind::Float
-                {
+                let is_float =
+                    |kind: ScalarKind| kind == ScalarKind::Float || kind == ScalarKind::Float2;
+                if !is_float(from_parts.scalar_kind) && !is_float(to_parts.scalar_kind) {
                     // It is noop, but another instruction expects result of this conversion
                     builder.copy_object(dst_type, Some(cv.dst), cv.src)?;
                 } else {
@@ -4112,8 +6766,11 @@ fn emit_implicit_conversion(
                 }
             }
         }
-        (TypeKind::Scalar, TypeKind::Scalar, ConversionKind::SignExtend) => todo!(),
+        (TypeKind::Scalar, TypeKind::Scalar, ConversionKind::SignExtend) => {
+            return Err(TranslateError::Todo)
+        }
         (TypeKind::Vector, TypeKind::Scalar, ConversionKind::Default)
+        | (TypeKind::Scalar, TypeKind::Vector, ConversionKind::Default)
         | (TypeKind::Scalar, TypeKind::Array, ConversionKind::Default)
         | (TypeKind::Array, TypeKind::Scalar, ConversionKind::Default) => {
             let into_type = map.get_or_add(builder, SpirvType::from(cv.to.clone()));
@@ -4145,7 +6802,7 @@ fn emit_load_var(
 ) -> Result<(), TranslateError> {
     let result_type = map.get_or_add(builder, SpirvType::from(details.typ.clone()));
     match details.member_index {
-        Some((index, Some(width))) => {
+        Some((index, width)) => {
             let vector_type = match details.typ {
                 ast::Type::Scalar(scalar_t) => ast::Type::Vector(scalar_t, width),
                 _ => return Err(TranslateError::MismatchedType),
@@ -4159,24 +6816,6 @@ fn emit_load_var(
                 &[index as u32],
             )?;
         }
-        Some((index, None)) => {
-            let result_ptr_type = map.get_or_add(
-                builder,
-                SpirvType::new_pointer(details.typ.clone(), spirv::StorageClass::Function),
-            );
-            let index_spirv = map.get_or_add_constant(
-                builder,
-                &ast::Type::Scalar(ast::ScalarType::U32),
-                &vec_repr(index as u32),
-            )?;
-            let src = builder.in_bounds_access_chain(
-                result_ptr_type,
-                None,
-                details.arg.src,
-                &[index_spirv],
-            )?;
-            builder.load(result_type, Some(details.arg.dst), src, None, [])?;
-        }
         None => {
             builder.load(
                 result_type,
@@ -4190,6 +6829,79 @@ fn emit_load_var(
     Ok(())
 }
 
+// Clock of the SM is shared by its warps, so subgroup scope is enough
+fn emit_read_clock(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    details: &LoadVarDetails,
+) -> Result<(), TranslateError> {
+    let u64_type = map.get_or_add_scalar(builder, ast::ScalarType::U64);
+    let subgroup_scope = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(ast::ScalarType::U32),
+        &vec_repr(spirv::Scope::Subgroup as u32),
+    )?;
+    match details.typ {
+        ast::Type::Scalar(ast::ScalarType::U64) => {
+            builder.read_clock_khr(u64_type, Some(details.arg.dst), subgroup_scope)?;
+        }
+        ast::Type::Scalar(ast::ScalarType::U32) => {
+            let u32_type = map.get_or_add_scalar(builder, ast::ScalarType::U32);
+            let clock = builder.read_clock_khr(u64_type, None, subgroup_scope)?;
+            builder.u_convert(u32_type, Some(details.arg.dst), clock)?;
+        }
+        _ => return Err(TranslateError::MismatchedType),
+    }
+    Ok(())
+}
+
+// Level Zero doesn't tell which subslice a thread runs on, so every thread
+// reports the first SM. That's in range and stable across launches, which is
+// all PTX guarantees anyway
+fn emit_read_smid(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    details: &LoadVarDetails,
+) -> Result<(), TranslateError> {
+    let u32_type = map.get_or_add_scalar(builder, ast::ScalarType::U32);
+    let zero = map.get_or_add_constant(
+        builder,
+        &ast::Type::Scalar(ast::ScalarType::U32),
+        &vec_repr(0u32),
+    )?;
+    builder.copy_object(u32_type, Some(details.arg.dst), zero)?;
+    Ok(())
+}
+
+fn emit_store_var(
+    builder: &mut dr::Builder,
+    map: &mut TypeWordMap,
+    details: &StoreVarDetails,
+) -> Result<(), TranslateError> {
+    match details.member_index {
+        Some((index, width)) => {
+            let vector_type = match details.typ {
+                ast::Type::Scalar(scalar_t) => ast::Type::Vector(scalar_t, width),
+                _ => return Err(TranslateError::MismatchedType),
+            };
+            let vector_type_spirv = map.get_or_add(builder, SpirvType::from(vector_type));
+            let vector_temp = builder.load(vector_type_spirv, None, details.arg.src1, None, [])?;
+            let vector_updated = builder.composite_insert(
+                vector_type_spirv,
+                None,
+                details.arg.src2,
+                vector_temp,
+                &[index as u32],
+            )?;
+            builder.store(details.arg.src1, vector_updated, None, [])?;
+        }
+        None => {
+            builder.store(details.arg.src1, details.arg.src2, None, [])?;
+        }
+    };
+    Ok(())
+}
+
 fn normalize_identifiers<'a, 'b>(
     id_defs: &mut FnStringIdResolver<'a, 'b>,
     fn_defs: &GlobalFnDeclResolver<'a, 'b>,
@@ -4204,12 +6916,31 @@ fn normalize_identifiers<'a, 'b>(
         }
     }
     let mut result = Vec::new();
+    if func.iter().any(uses_carry_flag) {
+        let pred_type = ast::ScalarType::Pred;
+        let carry_flag = id_defs.add_def(ast::CARRY_FLAG, Some(ast::Type::Scalar(pred_type)), true);
+        result.push(Statement::Variable(ast::Variable {
+            align: None,
+            v_type: ast::VariableType::Reg(ast::VariableRegType::Scalar(pred_type)),
+            name: carry_flag,
+            array_init: Vec::new(),
+        }));
+    }
     for s in func {
         expand_map_variables(id_defs, fn_defs, &mut result, s)?;
     }
     Ok(result)
 }
 
+fn uses_carry_flag(s: &ast::Statement<ast::ParsedArgParams>) -> bool {
+    match s {
+        ast::Statement::Block(block) => block.iter().any(uses_carry_flag),
+        ast::Statement::Instruction(_, ast::Instruction::AddC { .. })
+        | ast::Statement::Instruction(_, ast::Instruction::SubC { .. }) => true,
+        _ => false,
+    }
+}
+
 fn expand_map_variables<'a, 'b>(
     id_defs: &mut FnStringIdResolver<'a, 'b>,
     fn_defs: &GlobalFnDeclResolver<'a, 'b>,
@@ -4283,6 +7014,121 @@ fn expand_map_variables<'a, 'b>(
     Ok(())
 }
 
+// SPIR-V kernels can only get images and samplers as parameters, so every
+// texture sampled by a kernel becomes a pair of extra parameters. This only
+// works if the texture is known statically: it must be either a .texref or
+// a texture object loaded straight from a kernel parameter
+fn resolve_textures(
+    func_args: &mut SpirvMethodDecl,
+    func_decl: &ast::MethodDecl<spirv::Word>,
+    func_body: Vec<TypedStatement>,
+    fn_defs: &GlobalFnDeclResolver,
+    id_defs: &mut NumericIdResolver,
+) -> Result<Vec<TypedStatement>, TranslateError> {
+    let uses_tex = func_body.iter().any(|statement| match statement {
+        Statement::Instruction(ast::Instruction::Tex(..)) => true,
+        _ => false,
+    });
+    if !uses_tex {
+        return Ok(func_body);
+    }
+    let param_indices = match func_decl {
+        ast::MethodDecl::Kernel { in_args, .. } => in_args
+            .iter()
+            .enumerate()
+            .map(|(index, arg)| (arg.name, index))
+            .collect::<HashMap<_, _>>(),
+        ast::MethodDecl::Func(..) => return Err(TranslateError::Todo),
+    };
+    let mut writes = HashMap::new();
+    let mut param_loads = HashMap::new();
+    let mut result = Vec::with_capacity(func_body.len());
+    for statement in func_body {
+        match statement {
+            Statement::Instruction(inst) => {
+                if let ast::Instruction::Ld(
+                    ast::LdDetails {
+                        state_space: ast::LdStateSpace::Param,
+                        ..
+                    },
+                    arg,
+                ) = &inst
+                {
+                    if let (TypedOperand::Reg(dst), Some(src)) =
+                        (&arg.dst, arg.src.upcast().underlying())
+                    {
+                        if let Some(index) = param_indices.get(src) {
+                            param_loads.insert(*dst, *index);
+                        }
+                    }
+                }
+                result.push(inst.visit(
+                    &mut |desc: ArgumentDescriptor<spirv::Word>, _: Option<&ast::Type>| {
+                        if desc.is_dst {
+                            *writes.entry(desc.op).or_insert(0) += 1;
+                        }
+                        Ok(desc.op)
+                    },
+                )?);
+            }
+            Statement::Call(call) => {
+                for (id, typ) in call.ret_params.iter() {
+                    if let ast::FnArgumentType::Reg(_) = typ {
+                        *writes.entry(*id).or_insert(0) += 1;
+                    }
+                }
+                result.push(Statement::Call(call));
+            }
+            Statement::RepackVector(repack) => {
+                if repack.is_extract {
+                    for id in repack.unpacked.iter() {
+                        *writes.entry(*id).or_insert(0) += 1;
+                    }
+                }
+                result.push(Statement::RepackVector(repack));
+            }
+            statement => result.push(statement),
+        }
+    }
+    for statement in result.iter_mut() {
+        if let Statement::Instruction(ast::Instruction::Tex(details, arg)) = statement {
+            let handle = match arg.src1 {
+                TypedOperand::Reg(id) => id,
+                _ => return Err(TranslateError::Todo),
+            };
+            let texture = if let Some(name) = fn_defs.texrefs.get(&handle) {
+                KernelTexture::Texref(name.to_string())
+            } else if let Some(index) = param_indices.get(&handle) {
+                KernelTexture::Object(*index)
+            } else {
+                match (param_loads.get(&handle), writes.get(&handle)) {
+                    (Some(index), Some(&1)) => KernelTexture::Object(*index),
+                    _ => return Err(TranslateError::Todo),
+                }
+            };
+            let existing = func_args
+                .textures
+                .iter()
+                .find(|(known, _, _)| *known == texture)
+                .map(|(_, image, sampler)| (*image, *sampler));
+            let (image, sampler) = match existing {
+                Some(params) => params,
+                None => {
+                    let image = id_defs.new_non_variable(None);
+                    let sampler = id_defs.new_non_variable(None);
+                    func_args.textures.push((texture, image, sampler));
+                    (image, sampler)
+                }
+            };
+            details.texture = Some((image, sampler));
+            // The handle is not needed anymore, the image and sampler
+            // parameters are read directly
+            arg.src1 = TypedOperand::Imm(ast::ImmediateValue::U64(0));
+        }
+    }
+    Ok(result)
+}
+
 // TODO: detect more patterns (mov, call via reg, call via param)
 // TODO: don't convert to ptr if the register is not ultimately used for ld/st
 // TODO: once insert_mem_ssa_statements is moved to later, move this pass after
@@ -4705,6 +7551,14 @@ enum PtxSpecialRegister {
     Ctaid64,
     Nctaid,
     Nctaid64,
+    WarpSz,
+    LaneId,
+    WarpId,
+    NWarpId,
+    SmId,
+    NSmId,
+    Clock,
+    Clock64,
 }
 
 impl PtxSpecialRegister {
@@ -4714,6 +7568,14 @@ impl PtxSpecialRegister {
             "%ntid" => Some(Self::Ntid),
             "%ctaid" => Some(Self::Ctaid),
             "%nctaid" => Some(Self::Nctaid),
+            "WARP_SZ" => Some(Self::WarpSz),
+            "%laneid" => Some(Self::LaneId),
+            "%warpid" => Some(Self::WarpId),
+            "%nwarpid" => Some(Self::NWarpId),
+            "%smid" => Some(Self::SmId),
+            "%nsmid" => Some(Self::NSmId),
+            "%clock" => Some(Self::Clock),
+            "%clock64" => Some(Self::Clock64),
             _ => None,
         }
     }
@@ -4728,11 +7590,21 @@ impl PtxSpecialRegister {
             PtxSpecialRegister::Ctaid64 => ast::Type::Vector(ast::ScalarType::U64, 3),
             PtxSpecialRegister::Nctaid => ast::Type::Vector(ast::ScalarType::U32, 4),
             PtxSpecialRegister::Nctaid64 => ast::Type::Vector(ast::ScalarType::U64, 3),
+            PtxSpecialRegister::WarpSz => ast::Type::Scalar(ast::ScalarType::U32),
+            PtxSpecialRegister::LaneId => ast::Type::Scalar(ast::ScalarType::U32),
+            PtxSpecialRegister::WarpId => ast::Type::Scalar(ast::ScalarType::U32),
+            PtxSpecialRegister::NWarpId => ast::Type::Scalar(ast::ScalarType::U32),
+            PtxSpecialRegister::SmId => ast::Type::Scalar(ast::ScalarType::U32),
+            PtxSpecialRegister::NSmId => ast::Type::Scalar(ast::ScalarType::U32),
+            PtxSpecialRegister::Clock => ast::Type::Scalar(ast::ScalarType::U32),
+            PtxSpecialRegister::Clock64 => ast::Type::Scalar(ast::ScalarType::U64),
         }
     }
 
-    fn get_builtin(self) -> spirv::BuiltIn {
-        match self {
+    // Clock registers are not backed by a builtin variable, every read is an
+    // OpReadClockKHR. %smid is a constant and %nsmid is a global set by the host
+    fn get_builtin(self) -> Option<spirv::BuiltIn> {
+        Some(match self {
             PtxSpecialRegister::Tid | PtxSpecialRegister::Tid64 => {
                 spirv::BuiltIn::LocalInvocationId
             }
@@ -4741,6 +7613,29 @@ impl PtxSpecialRegister {
             PtxSpecialRegister::Nctaid | PtxSpecialRegister::Nctaid64 => {
                 spirv::BuiltIn::NumWorkgroups
             }
+            // Warp size is the subgroup size the kernel was compiled for
+            PtxSpecialRegister::WarpSz => spirv::BuiltIn::SubgroupMaxSize,
+            // Lanes and warps are subgroups of whatever size the device picked.
+            // If it's not 32 (no SubgroupSize requested or the device doesn't
+            // support it), kernels assuming %laneid < 32 or computing warp
+            // count from %ntid / 32 will disagree with these values.
+            // %warpid is the linear local id divided by the subgroup size and
+            // %nwarpid is the number of subgroups in the block, not the warp
+            // slot on the SM, which PTX allows to change anyway
+            PtxSpecialRegister::LaneId => spirv::BuiltIn::SubgroupLocalInvocationId,
+            PtxSpecialRegister::WarpId => spirv::BuiltIn::SubgroupId,
+            PtxSpecialRegister::NWarpId => spirv::BuiltIn::NumSubgroups,
+            PtxSpecialRegister::SmId
+            | PtxSpecialRegister::NSmId
+            | PtxSpecialRegister::Clock
+            | PtxSpecialRegister::Clock64 => return None,
+        })
+    }
+
+    fn is_clock(self) -> bool {
+        match self {
+            PtxSpecialRegister::Clock | PtxSpecialRegister::Clock64 => true,
+            _ => false,
         }
     }
 
@@ -4757,7 +7652,15 @@ impl PtxSpecialRegister {
             PtxSpecialRegister::Tid64
             | PtxSpecialRegister::Ntid64
             | PtxSpecialRegister::Ctaid64
-            | PtxSpecialRegister::Nctaid64 => None,
+            | PtxSpecialRegister::Nctaid64
+            | PtxSpecialRegister::WarpSz
+            | PtxSpecialRegister::LaneId
+            | PtxSpecialRegister::WarpId
+            | PtxSpecialRegister::NWarpId
+            | PtxSpecialRegister::SmId
+            | PtxSpecialRegister::NSmId
+            | PtxSpecialRegister::Clock
+            | PtxSpecialRegister::Clock64 => None,
         }
     }
 }
@@ -4775,10 +7678,12 @@ impl SpecialRegistersMap {
         }
     }
 
-    fn builtins<'a>(&'a self) -> impl Iterator<Item = (PtxSpecialRegister, spirv::Word)> + 'a {
+    fn builtins<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (PtxSpecialRegister, spirv::BuiltIn, spirv::Word)> + 'a {
         self.reg_to_id.iter().filter_map(|(sreg, id)| {
             if sreg.normalized_sreg_and_type().is_none() {
-                Some((*sreg, *id))
+                sreg.get_builtin().map(|builtin| (*sreg, builtin, *id))
             } else {
                 None
             }
@@ -4786,22 +7691,21 @@ impl SpecialRegistersMap {
     }
 
     fn interface(&self) -> Vec<spirv::Word> {
-        self.reg_to_id
-            .iter()
-            .filter_map(|(sreg, id)| {
-                if sreg.normalized_sreg_and_type().is_none() {
-                    Some(*id)
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>()
+        self.builtins().map(|(_, _, id)| id).collect::<Vec<_>>()
+    }
+
+    fn uses_clock(&self) -> bool {
+        self.reg_to_id.keys().any(|sreg| sreg.is_clock())
     }
 
     fn get(&self, id: spirv::Word) -> Option<PtxSpecialRegister> {
         self.id_to_reg.get(&id).copied()
     }
 
+    fn get_id(&self, reg: PtxSpecialRegister) -> Option<spirv::Word> {
+        self.reg_to_id.get(&reg).copied()
+    }
+
     fn get_or_add(&mut self, current_id: &mut spirv::Word, reg: PtxSpecialRegister) -> spirv::Word {
         match self.reg_to_id.entry(reg) {
             hash_map::Entry::Occupied(e) => *e.get(),
@@ -4822,6 +7726,7 @@ struct GlobalStringIdResolver<'input> {
     variables_type_check: HashMap<u32, Option<(ast::Type, bool)>>,
     special_registers: SpecialRegistersMap,
     fns: HashMap<spirv::Word, FnDecl>,
+    texrefs: HashMap<spirv::Word, &'input str>,
 }
 
 pub struct FnDecl {
@@ -4837,6 +7742,7 @@ impl<'a> GlobalStringIdResolver<'a> {
             variables_type_check: HashMap::new(),
             special_registers: SpecialRegistersMap::new(),
             fns: HashMap::new(),
+            texrefs: HashMap::new(),
         }
     }
 
@@ -4844,6 +7750,13 @@ impl<'a> GlobalStringIdResolver<'a> {
         self.get_or_add_impl(id, None)
     }
 
+    // Texrefs are untyped, the only thing they can be used for is tex
+    fn add_texref(&mut self, id: &'a str) -> spirv::Word {
+        let numeric_id = self.get_or_add_def(id);
+        self.texrefs.insert(numeric_id, id);
+        numeric_id
+    }
+
     fn get_or_add_def_typed(
         &mut self,
         id: &'a str,
@@ -4922,6 +7835,7 @@ impl<'a> GlobalStringIdResolver<'a> {
             GlobalFnDeclResolver {
                 variables: &self.variables,
                 fns: &self.fns,
+                texrefs: &self.texrefs,
             },
             new_fn_decl,
         ))
@@ -4931,6 +7845,7 @@ impl<'a> GlobalStringIdResolver<'a> {
 pub struct GlobalFnDeclResolver<'input, 'a> {
     variables: &'a HashMap<Cow<'input, str>, spirv::Word>,
     fns: &'a HashMap<spirv::Word, FnDecl>,
+    texrefs: &'a HashMap<spirv::Word, &'input str>,
 }
 
 impl<'input, 'a> GlobalFnDeclResolver<'input, 'a> {
@@ -5096,7 +8011,7 @@ enum Statement<I, P: ast::ArgParams> {
     StoreVar(StoreVarDetails),
     Conversion(ImplicitConversion),
     Constant(ConstantDefinition),
-    RetValue(ast::RetData, spirv::Word),
+    RetValue(ast::RetData, Vec<(spirv::Word, ast::Type)>),
     PtrAccess(PtrAccess<P>),
     RepackVector(RepackVectorDetails),
 }
@@ -5154,9 +8069,12 @@ impl ExpandedStatement {
                 constant.dst = f(constant.dst, true);
                 Statement::Constant(constant)
             }
-            Statement::RetValue(data, id) => {
-                let id = f(id, false);
-                Statement::RetValue(data, id)
+            Statement::RetValue(data, values) => {
+                let values = values
+                    .into_iter()
+                    .map(|(id, typ)| (f(id, false), typ))
+                    .collect();
+                Statement::RetValue(data, values)
             }
             Statement::PtrAccess(PtrAccess {
                 underlying_type,
@@ -5200,14 +8118,15 @@ struct LoadVarDetails {
     // HACK ALERT
     // For some reason IGC explodes when you try to load from builtin vectors
     // using OpInBoundsAccessChain, the one true way to do it is to
-    // OpLoad+OpCompositeExtract
-    member_index: Option<(u8, Option<u8>)>,
+    // OpLoad+OpCompositeExtract. We do the same for vector registers
+    member_index: Option<(u8, u8)>,
 }
 
 struct StoreVarDetails {
     arg: ast::Arg2St<ExpandedArgParams>,
     typ: ast::Type,
-    member_index: Option<u8>,
+    // (index, vector_width), written back with OpLoad+OpCompositeInsert+OpStore
+    member_index: Option<(u8, u8)>,
 }
 
 struct RepackVectorDetails {
@@ -5521,6 +8440,9 @@ struct Function<'input> {
     pub globals: Vec<ast::Variable<ast::VariableType, spirv::Word>>,
     pub body: Option<Vec<ExpandedStatement>>,
     import_as: Option<String>,
+    // Name and linkage of .extern declarations and .visible/.weak
+    // definitions, so they can be resolved when linking with other modules
+    linkage: Option<(String, spirv::LinkageType)>,
 }
 
 pub trait ArgumentMapVisitor<T: ArgParamsEx, U: ArgParamsEx> {
@@ -5661,6 +8583,11 @@ impl<T: ArgParamsEx> ast::Instruction<T> {
                 let inst_type = d.typ;
                 ast::Instruction::Setp(d, a.map(visitor, &ast::Type::Scalar(inst_type))?)
             }
+            ast::Instruction::Set(d, a) => {
+                let dst_type = ast::Type::Scalar(d.dst_type);
+                let src_type = ast::Type::Scalar(d.src_type);
+                ast::Instruction::Set(d, a.map_different_types(visitor, &dst_type, &src_type)?)
+            }
             ast::Instruction::SetpBool(d, a) => {
                 let inst_type = d.typ;
                 ast::Instruction::SetpBool(d, a.map(visitor, &ast::Type::Scalar(inst_type))?)
@@ -5684,6 +8611,10 @@ impl<T: ArgParamsEx> ast::Instruction<T> {
                         ast::Type::Scalar(desc.dst.into()),
                         ast::Type::Scalar(desc.src.into()),
                     ),
+                    ast::CvtDetails::TruncatedFromFloat(desc) => (
+                        ast::Type::Scalar(desc.dst.bit_type()),
+                        ast::Type::Scalar(ast::ScalarType::F32),
+                    ),
                 };
                 ast::Instruction::Cvt(d, a.map_different_types(visitor, &dst_t, &src_t)?)
             }
@@ -5693,6 +8624,9 @@ impl<T: ArgParamsEx> ast::Instruction<T> {
             ast::Instruction::Shr(t, a) => {
                 ast::Instruction::Shr(t, a.map_shift(visitor, &ast::Type::Scalar(t.into()))?)
             }
+            ast::Instruction::Shf(details, a) => {
+                ast::Instruction::Shf(details, a.map_shf(visitor)?)
+            }
             ast::Instruction::St(d, a) => {
                 let new_args = a.map(visitor, &d)?;
                 ast::Instruction::St(d, new_args)
@@ -5703,6 +8637,14 @@ impl<T: ArgParamsEx> ast::Instruction<T> {
                 let inst_type = ast::Type::Scalar(ast::ScalarType::B64);
                 ast::Instruction::Cvta(d, a.map(visitor, &inst_type)?)
             }
+            ast::Instruction::Mul24(d, a) => {
+                let inst_type = ast::Type::Scalar(d.typ.into());
+                ast::Instruction::Mul24(d, a.map_non_shift(visitor, &inst_type, false)?)
+            }
+            ast::Instruction::Mad24(d, a) => {
+                let inst_type = ast::Type::Scalar(d.typ.into());
+                ast::Instruction::Mad24(d, a.map(visitor, &inst_type, false)?)
+            }
             ast::Instruction::Mad(d, a) => {
                 let inst_type = d.get_type();
                 let is_wide = d.is_wide();
@@ -5744,6 +8686,9 @@ impl<T: ArgParamsEx> ast::Instruction<T> {
             ast::Instruction::AtomCas(d, a) => {
                 ast::Instruction::AtomCas(d, a.map_atom(visitor, d.typ, d.space)?)
             }
+            ast::Instruction::Red(d, a) => {
+                ast::Instruction::Red(d, a.map_atom(visitor, d.inner.get_type(), d.space)?)
+            }
             ast::Instruction::Div(d, a) => {
                 ast::Instruction::Div(d, a.map_non_shift(visitor, &d.get_type(), false)?)
             }
@@ -5784,6 +8729,12 @@ impl<T: ArgParamsEx> ast::Instruction<T> {
                     arg: arg.map(visitor, &typ)?,
                 }
             }
+            ast::Instruction::Tanh { arg } => {
+                let typ = ast::Type::Scalar(ast::ScalarType::F32);
+                ast::Instruction::Tanh {
+                    arg: arg.map(visitor, &typ)?,
+                }
+            }
             ast::Instruction::Clz { typ, arg } => {
                 let dst_type = ast::Type::Scalar(ast::ScalarType::B32);
                 let src_type = ast::Type::Scalar(typ.into());
@@ -5807,6 +8758,13 @@ impl<T: ArgParamsEx> ast::Instruction<T> {
                     arg: arg.map_different_types(visitor, &dst_type, &src_type)?,
                 }
             }
+            ast::Instruction::Prmt { mode, arg } => {
+                let full_type = ast::Type::Scalar(ast::ScalarType::B32);
+                ast::Instruction::Prmt {
+                    mode,
+                    arg: arg.map(visitor, &full_type, false)?,
+                }
+            }
             ast::Instruction::Xor { typ, arg } => {
                 let full_type = ast::Type::Scalar(typ.into());
                 ast::Instruction::Xor {
@@ -5821,6 +8779,52 @@ impl<T: ArgParamsEx> ast::Instruction<T> {
                     arg: arg.map_bfe(visitor, &full_type)?,
                 }
             }
+            ast::Instruction::Bfi { typ, arg } => {
+                let full_type = ast::Type::Scalar(typ.into());
+                ast::Instruction::Bfi {
+                    typ,
+                    arg: arg.map(visitor, &full_type)?,
+                }
+            }
+            ast::Instruction::Bfind {
+                typ,
+                shift_amount,
+                arg,
+            } => {
+                let dst_type = ast::Type::Scalar(ast::ScalarType::U32);
+                let src_type = ast::Type::Scalar(typ.into());
+                ast::Instruction::Bfind {
+                    typ,
+                    shift_amount,
+                    arg: arg.map_different_types(visitor, &dst_type, &src_type)?,
+                }
+            }
+            ast::Instruction::Szext { mode, typ, arg } => {
+                let full_type = ast::Type::Scalar(typ.into());
+                ast::Instruction::Szext {
+                    mode,
+                    typ,
+                    arg: arg.map_shift(visitor, &full_type)?,
+                }
+            }
+            ast::Instruction::Bmsk { mode, arg } => {
+                let full_type = ast::Type::Scalar(ast::ScalarType::B32);
+                ast::Instruction::Bmsk {
+                    mode,
+                    arg: arg.map_non_shift(visitor, &full_type, false)?,
+                }
+            }
+            ast::Instruction::Dp4a(details, arg) => {
+                let full_type = ast::Type::Scalar(ast::ScalarType::B32);
+                ast::Instruction::Dp4a(details, arg.map(visitor, &full_type, false)?)
+            }
+            ast::Instruction::Dp2a(mode, details, arg) => {
+                let full_type = ast::Type::Scalar(ast::ScalarType::B32);
+                ast::Instruction::Dp2a(mode, details, arg.map(visitor, &full_type, false)?)
+            }
+            ast::Instruction::Video(details, arg) => {
+                ast::Instruction::Video(details, arg.map(visitor)?)
+            }
             ast::Instruction::Rem { typ, arg } => {
                 let full_type = ast::Type::Scalar(typ.into());
                 ast::Instruction::Rem {
@@ -5828,24 +8832,80 @@ impl<T: ArgParamsEx> ast::Instruction<T> {
                     arg: arg.map_non_shift(visitor, &full_type, false)?,
                 }
             }
-        })
-    }
-}
-
-impl<T: ArgParamsEx, U: ArgParamsEx> Visitable<T, U> for ast::Instruction<T> {
-    fn visit(
-        self,
-        visitor: &mut impl ArgumentMapVisitor<T, U>,
-    ) -> Result<Statement<ast::Instruction<U>, U>, TranslateError> {
-        Ok(Statement::Instruction(self.map(visitor)?))
-    }
-}
-
-impl ImplicitConversion {
-    fn map<
-        T: ArgParamsEx<Id = spirv::Word>,
-        U: ArgParamsEx<Id = spirv::Word>,
-        V: ArgumentMapVisitor<T, U>,
+            ast::Instruction::Sad { typ, arg } => {
+                let full_type = ast::Type::Scalar(typ.into());
+                ast::Instruction::Sad {
+                    typ,
+                    arg: arg.map(visitor, &full_type, false)?,
+                }
+            }
+            ast::Instruction::Copysign { typ, arg } => {
+                let full_type = ast::Type::Scalar(typ.into());
+                ast::Instruction::Copysign {
+                    typ,
+                    arg: arg.map_non_shift(visitor, &full_type, false)?,
+                }
+            }
+            ast::Instruction::Testp { op, typ, arg } => {
+                let dst_type = ast::Type::Scalar(ast::ScalarType::Pred);
+                let src_type = ast::Type::Scalar(typ.into());
+                ast::Instruction::Testp {
+                    op,
+                    typ,
+                    arg: arg.map_different_types(visitor, &dst_type, &src_type)?,
+                }
+            }
+            ast::Instruction::Shfl(mode, arg) => ast::Instruction::Shfl(mode, arg.map(visitor)?),
+            ast::Instruction::Vote(details, arg) => {
+                ast::Instruction::Vote(details, arg.map_vote(visitor, details.mode)?)
+            }
+            ast::Instruction::Membar(details) => ast::Instruction::Membar(details),
+            ast::Instruction::Nanosleep(a) => ast::Instruction::Nanosleep(a.map(visitor)?),
+            ast::Instruction::Prefetch(details, a) => {
+                let new_args = a.map_prefetch(visitor, details.state_space)?;
+                ast::Instruction::Prefetch(details, new_args)
+            }
+            ast::Instruction::Isspacep { space, arg } => {
+                let dst_type = ast::Type::Scalar(ast::ScalarType::Pred);
+                let src_type = ast::Type::Scalar(ast::ScalarType::B64);
+                ast::Instruction::Isspacep {
+                    space,
+                    arg: arg.map_different_types(visitor, &dst_type, &src_type)?,
+                }
+            }
+            ast::Instruction::AddC { typ, arg } => ast::Instruction::AddC {
+                typ,
+                arg: arg.map(visitor, typ)?,
+            },
+            ast::Instruction::SubC { typ, arg } => ast::Instruction::SubC {
+                typ,
+                arg: arg.map(visitor, typ)?,
+            },
+            ast::Instruction::WmmaLoad(d, a) => {
+                ast::Instruction::WmmaLoad(d, a.map(visitor, d.space)?)
+            }
+            ast::Instruction::WmmaStore(d, a) => {
+                ast::Instruction::WmmaStore(d, a.map(visitor, d.space)?)
+            }
+            ast::Instruction::Tex(d, a) => ast::Instruction::Tex(d, a.map_tex(visitor, d)?),
+        })
+    }
+}
+
+impl<T: ArgParamsEx, U: ArgParamsEx> Visitable<T, U> for ast::Instruction<T> {
+    fn visit(
+        self,
+        visitor: &mut impl ArgumentMapVisitor<T, U>,
+    ) -> Result<Statement<ast::Instruction<U>, U>, TranslateError> {
+        Ok(Statement::Instruction(self.map(visitor)?))
+    }
+}
+
+impl ImplicitConversion {
+    fn map<
+        T: ArgParamsEx<Id = spirv::Word>,
+        U: ArgParamsEx<Id = spirv::Word>,
+        V: ArgumentMapVisitor<T, U>,
     >(
         self,
         visitor: &mut V,
@@ -6097,6 +9157,7 @@ impl ast::Instruction<ExpandedArgParams> {
             ast::Instruction::Bra(_, _) => None,
             ast::Instruction::Shl(_, _) => None,
             ast::Instruction::Shr(_, _) => None,
+            ast::Instruction::Shf(_, _) => None,
             ast::Instruction::Ret(_) => None,
             ast::Instruction::Call(_) => None,
             ast::Instruction::Or(_, _) => None,
@@ -6106,6 +9167,7 @@ impl ast::Instruction<ExpandedArgParams> {
             ast::Instruction::Bar(_, _) => None,
             ast::Instruction::Atom(_, _) => None,
             ast::Instruction::AtomCas(_, _) => None,
+            ast::Instruction::Red(_, _) => None,
             ast::Instruction::Sub(ast::ArithDetails::Signed(_), _) => None,
             ast::Instruction::Sub(ast::ArithDetails::Unsigned(_), _) => None,
             ast::Instruction::Add(ast::ArithDetails::Signed(_), _) => None,
@@ -6114,20 +9176,49 @@ impl ast::Instruction<ExpandedArgParams> {
             ast::Instruction::Mul(ast::MulDetails::Signed(_), _) => None,
             ast::Instruction::Mad(ast::MulDetails::Unsigned(_), _) => None,
             ast::Instruction::Mad(ast::MulDetails::Signed(_), _) => None,
+            ast::Instruction::Mul24(..) => None,
+            ast::Instruction::Mad24(..) => None,
+            ast::Instruction::Sub(ast::ArithDetails::BF16, _) => None,
+            ast::Instruction::Add(ast::ArithDetails::BF16, _) => None,
+            ast::Instruction::Mul(ast::MulDetails::BF16, _) => None,
+            ast::Instruction::Mad(ast::MulDetails::BF16, _) => None,
             ast::Instruction::Min(ast::MinMaxDetails::Signed(_), _) => None,
             ast::Instruction::Min(ast::MinMaxDetails::Unsigned(_), _) => None,
             ast::Instruction::Max(ast::MinMaxDetails::Signed(_), _) => None,
             ast::Instruction::Max(ast::MinMaxDetails::Unsigned(_), _) => None,
             ast::Instruction::Cvt(ast::CvtDetails::IntFromInt(_), _) => None,
             ast::Instruction::Cvt(ast::CvtDetails::FloatFromInt(_), _) => None,
+            ast::Instruction::Cvt(ast::CvtDetails::TruncatedFromFloat(_), _) => None,
             ast::Instruction::Div(ast::DivDetails::Unsigned(_), _) => None,
             ast::Instruction::Div(ast::DivDetails::Signed(_), _) => None,
             ast::Instruction::Clz { .. } => None,
             ast::Instruction::Brev { .. } => None,
             ast::Instruction::Popc { .. } => None,
+            ast::Instruction::Prmt { .. } => None,
             ast::Instruction::Xor { .. } => None,
             ast::Instruction::Bfe { .. } => None,
+            ast::Instruction::Bfi { .. } => None,
+            ast::Instruction::Bfind { .. } => None,
+            ast::Instruction::Szext { .. } => None,
+            ast::Instruction::Bmsk { .. } => None,
+            ast::Instruction::Dp4a(..) => None,
+            ast::Instruction::Dp2a(..) => None,
+            ast::Instruction::Video(..) => None,
             ast::Instruction::Rem { .. } => None,
+            ast::Instruction::Sad { .. } => None,
+            ast::Instruction::Copysign { .. } => None,
+            ast::Instruction::Testp { .. } => None,
+            ast::Instruction::Shfl(..) => None,
+            ast::Instruction::Vote(..) => None,
+            ast::Instruction::Membar(..) => None,
+            ast::Instruction::Nanosleep(..) => None,
+            ast::Instruction::Isspacep { .. } => None,
+            ast::Instruction::Prefetch(..) => None,
+            ast::Instruction::AddC { .. } => None,
+            ast::Instruction::SubC { .. } => None,
+            ast::Instruction::WmmaLoad(..) => None,
+            ast::Instruction::WmmaStore(..) => None,
+            ast::Instruction::Tex(..) => None,
             ast::Instruction::Sub(ast::ArithDetails::Float(float_control), _)
             | ast::Instruction::Add(ast::ArithDetails::Float(float_control), _)
             | ast::Instruction::Mul(ast::MulDetails::Float(float_control), _)
@@ -6137,6 +9228,9 @@ impl ast::Instruction<ExpandedArgParams> {
             ast::Instruction::Setp(details, _) => details
                 .flush_to_zero
                 .map(|ftz| (ftz, details.typ.size_of())),
+            ast::Instruction::Set(details, _) => details
+                .flush_to_zero
+                .map(|ftz| (ftz, details.src_type.size_of())),
             ast::Instruction::SetpBool(details, _) => details
                 .flush_to_zero
                 .map(|ftz| (ftz, details.typ.size_of())),
@@ -6179,6 +9273,7 @@ impl ast::Instruction<ExpandedArgParams> {
             | ast::Instruction::Ex2 { flush_to_zero, .. } => {
                 Some((*flush_to_zero, mem::size_of::<f32>() as u8))
             }
+            ast::Instruction::Tanh { .. } => Some((false, mem::size_of::<f32>() as u8)),
         }
     }
 }
@@ -6293,6 +9388,26 @@ impl<T: ArgParamsEx> ast::Arg1Bar<T> {
         )?;
         Ok(ast::Arg1Bar { src: new_src })
     }
+
+    fn map_prefetch<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+        self,
+        visitor: &mut V,
+        state_space: ast::LdStateSpace,
+    ) -> Result<ast::Arg1Bar<U>, TranslateError> {
+        let new_src = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src,
+                is_dst: false,
+                sema: if state_space == ast::LdStateSpace::Local {
+                    ArgumentSemantics::RegisterPointer
+                } else {
+                    ArgumentSemantics::PhysicalPointer
+                },
+            },
+            &ast::Type::Pointer(ast::PointerType::Scalar(ast::ScalarType::B8), state_space),
+        )?;
+        Ok(ast::Arg1Bar { src: new_src })
+    }
 }
 
 impl<T: ArgParamsEx> ast::Arg2<T> {
@@ -6417,6 +9532,31 @@ impl<T: ArgParamsEx> ast::Arg2St<T> {
         )?;
         Ok(ast::Arg2St { src1, src2 })
     }
+
+    fn map_atom<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+        self,
+        visitor: &mut V,
+        t: ast::ScalarType,
+        state_space: ast::AtomSpace,
+    ) -> Result<ast::Arg2St<U>, TranslateError> {
+        let src1 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src1,
+                is_dst: false,
+                sema: ArgumentSemantics::PhysicalPointer,
+            },
+            &ast::Type::Pointer(ast::PointerType::Scalar(t), state_space.to_ld_ss()),
+        )?;
+        let src2 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src2,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            &ast::Type::Scalar(t),
+        )?;
+        Ok(ast::Arg2St { src1, src2 })
+    }
 }
 
 impl<T: ArgParamsEx> ast::Arg2Mov<T> {
@@ -6488,6 +9628,39 @@ impl<T: ArgParamsEx> ast::Arg3<T> {
         Ok(ast::Arg3 { dst, src1, src2 })
     }
 
+    fn map_different_types<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+        self,
+        visitor: &mut V,
+        dst_t: &ast::Type,
+        src_t: &ast::Type,
+    ) -> Result<ast::Arg3<U>, TranslateError> {
+        let dst = visitor.operand(
+            ArgumentDescriptor {
+                op: self.dst,
+                is_dst: true,
+                sema: ArgumentSemantics::Default,
+            },
+            dst_t,
+        )?;
+        let src1 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src1,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            src_t,
+        )?;
+        let src2 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src2,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            src_t,
+        )?;
+        Ok(ast::Arg3 { dst, src1, src2 })
+    }
+
     fn map_shift<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
         self,
         visitor: &mut V,
@@ -6520,31 +9693,294 @@ impl<T: ArgParamsEx> ast::Arg3<T> {
         Ok(ast::Arg3 { dst, src1, src2 })
     }
 
-    fn map_atom<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+    fn map_vote<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+        self,
+        visitor: &mut V,
+        mode: ast::VoteMode,
+    ) -> Result<ast::Arg3<U>, TranslateError> {
+        let dst_type = if mode == ast::VoteMode::Ballot {
+            ast::ScalarType::B32
+        } else {
+            ast::ScalarType::Pred
+        };
+        let dst = visitor.operand(
+            ArgumentDescriptor {
+                op: self.dst,
+                is_dst: true,
+                sema: ArgumentSemantics::Default,
+            },
+            &ast::Type::Scalar(dst_type),
+        )?;
+        let src1 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src1,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            &ast::Type::Scalar(ast::ScalarType::Pred),
+        )?;
+        let src2 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src2,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            &ast::Type::Scalar(ast::ScalarType::B32),
+        )?;
+        Ok(ast::Arg3 { dst, src1, src2 })
+    }
+
+    fn map_atom<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+        self,
+        visitor: &mut V,
+        t: ast::ScalarType,
+        state_space: ast::AtomSpace,
+    ) -> Result<ast::Arg3<U>, TranslateError> {
+        let scalar_type = ast::ScalarType::from(t);
+        let dst = visitor.operand(
+            ArgumentDescriptor {
+                op: self.dst,
+                is_dst: true,
+                sema: ArgumentSemantics::Default,
+            },
+            &ast::Type::Scalar(scalar_type),
+        )?;
+        let src1 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src1,
+                is_dst: false,
+                sema: ArgumentSemantics::PhysicalPointer,
+            },
+            &ast::Type::Pointer(
+                ast::PointerType::Scalar(scalar_type),
+                state_space.to_ld_ss(),
+            ),
+        )?;
+        let src2 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src2,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            &ast::Type::Scalar(scalar_type),
+        )?;
+        Ok(ast::Arg3 { dst, src1, src2 })
+    }
+}
+
+impl<T: ArgParamsEx> ast::Arg4<T> {
+    fn map<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+        self,
+        visitor: &mut V,
+        t: &ast::Type,
+        is_wide: bool,
+    ) -> Result<ast::Arg4<U>, TranslateError> {
+        let wide_type = if is_wide {
+            Some(t.clone().widen()?)
+        } else {
+            None
+        };
+        let dst = visitor.operand(
+            ArgumentDescriptor {
+                op: self.dst,
+                is_dst: true,
+                sema: ArgumentSemantics::Default,
+            },
+            wide_type.as_ref().unwrap_or(t),
+        )?;
+        let src1 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src1,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            t,
+        )?;
+        let src2 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src2,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            t,
+        )?;
+        let src3 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src3,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            t,
+        )?;
+        Ok(ast::Arg4 {
+            dst,
+            src1,
+            src2,
+            src3,
+        })
+    }
+
+    fn map_selp<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+        self,
+        visitor: &mut V,
+        t: ast::SelpType,
+    ) -> Result<ast::Arg4<U>, TranslateError> {
+        let dst = visitor.operand(
+            ArgumentDescriptor {
+                op: self.dst,
+                is_dst: true,
+                sema: ArgumentSemantics::Default,
+            },
+            &ast::Type::Scalar(t.into()),
+        )?;
+        let src1 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src1,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            &ast::Type::Scalar(t.into()),
+        )?;
+        let src2 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src2,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            &ast::Type::Scalar(t.into()),
+        )?;
+        let src3 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src3,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            &ast::Type::Scalar(ast::ScalarType::Pred),
+        )?;
+        Ok(ast::Arg4 {
+            dst,
+            src1,
+            src2,
+            src3,
+        })
+    }
+
+    fn map_atom<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+        self,
+        visitor: &mut V,
+        t: ast::BitType,
+        state_space: ast::AtomSpace,
+    ) -> Result<ast::Arg4<U>, TranslateError> {
+        let scalar_type = ast::ScalarType::from(t);
+        let dst = visitor.operand(
+            ArgumentDescriptor {
+                op: self.dst,
+                is_dst: true,
+                sema: ArgumentSemantics::Default,
+            },
+            &ast::Type::Scalar(scalar_type),
+        )?;
+        let src1 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src1,
+                is_dst: false,
+                sema: ArgumentSemantics::PhysicalPointer,
+            },
+            &ast::Type::Pointer(
+                ast::PointerType::Scalar(scalar_type),
+                state_space.to_ld_ss(),
+            ),
+        )?;
+        let src2 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src2,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            &ast::Type::Scalar(scalar_type),
+        )?;
+        let src3 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src3,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            &ast::Type::Scalar(scalar_type),
+        )?;
+        Ok(ast::Arg4 {
+            dst,
+            src1,
+            src2,
+            src3,
+        })
+    }
+
+    fn map_bfe<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+        self,
+        visitor: &mut V,
+        typ: &ast::Type,
+    ) -> Result<ast::Arg4<U>, TranslateError> {
+        let dst = visitor.operand(
+            ArgumentDescriptor {
+                op: self.dst,
+                is_dst: true,
+                sema: ArgumentSemantics::Default,
+            },
+            typ,
+        )?;
+        let src1 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src1,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            typ,
+        )?;
+        let u32_type = ast::Type::Scalar(ast::ScalarType::U32);
+        let src2 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src2,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            &u32_type,
+        )?;
+        let src3 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src3,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            &u32_type,
+        )?;
+        Ok(ast::Arg4 {
+            dst,
+            src1,
+            src2,
+            src3,
+        })
+    }
+
+    fn map_tex<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
         self,
         visitor: &mut V,
-        t: ast::ScalarType,
-        state_space: ast::AtomSpace,
+        details: ast::TexDetails,
     ) -> Result<ast::Arg3<U>, TranslateError> {
-        let scalar_type = ast::ScalarType::from(t);
         let dst = visitor.operand(
             ArgumentDescriptor {
                 op: self.dst,
                 is_dst: true,
                 sema: ArgumentSemantics::Default,
             },
-            &ast::Type::Scalar(scalar_type),
+            &ast::Type::Vector(details.data_type.into(), 4),
         )?;
         let src1 = visitor.operand(
             ArgumentDescriptor {
                 op: self.src1,
                 is_dst: false,
-                sema: ArgumentSemantics::PhysicalPointer,
+                sema: ArgumentSemantics::Default,
             },
-            &ast::Type::Pointer(
-                ast::PointerType::Scalar(scalar_type),
-                state_space.to_ld_ss(),
-            ),
+            &ast::Type::Scalar(ast::ScalarType::U64),
         )?;
         let src2 = visitor.operand(
             ArgumentDescriptor {
@@ -6552,31 +9988,23 @@ impl<T: ArgParamsEx> ast::Arg3<T> {
                 is_dst: false,
                 sema: ArgumentSemantics::Default,
             },
-            &ast::Type::Scalar(scalar_type),
+            &ast::Type::Scalar(details.coordinate_type.into()),
         )?;
         Ok(ast::Arg3 { dst, src1, src2 })
     }
-}
 
-impl<T: ArgParamsEx> ast::Arg4<T> {
-    fn map<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+    fn map_shf<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
         self,
         visitor: &mut V,
-        t: &ast::Type,
-        is_wide: bool,
     ) -> Result<ast::Arg4<U>, TranslateError> {
-        let wide_type = if is_wide {
-            Some(t.clone().widen()?)
-        } else {
-            None
-        };
+        let b32_type = ast::Type::Scalar(ast::ScalarType::B32);
         let dst = visitor.operand(
             ArgumentDescriptor {
                 op: self.dst,
                 is_dst: true,
                 sema: ArgumentSemantics::Default,
             },
-            wide_type.as_ref().unwrap_or(t),
+            &b32_type,
         )?;
         let src1 = visitor.operand(
             ArgumentDescriptor {
@@ -6584,7 +10012,7 @@ impl<T: ArgParamsEx> ast::Arg4<T> {
                 is_dst: false,
                 sema: ArgumentSemantics::Default,
             },
-            t,
+            &b32_type,
         )?;
         let src2 = visitor.operand(
             ArgumentDescriptor {
@@ -6592,7 +10020,7 @@ impl<T: ArgParamsEx> ast::Arg4<T> {
                 is_dst: false,
                 sema: ArgumentSemantics::Default,
             },
-            t,
+            &b32_type,
         )?;
         let src3 = visitor.operand(
             ArgumentDescriptor {
@@ -6600,7 +10028,7 @@ impl<T: ArgParamsEx> ast::Arg4<T> {
                 is_dst: false,
                 sema: ArgumentSemantics::Default,
             },
-            t,
+            &ast::Type::Scalar(ast::ScalarType::U32),
         )?;
         Ok(ast::Arg4 {
             dst,
@@ -6609,19 +10037,21 @@ impl<T: ArgParamsEx> ast::Arg4<T> {
             src3,
         })
     }
+}
 
-    fn map_selp<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+impl<T: ArgParamsEx> ast::Arg5<T> {
+    fn map<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
         self,
         visitor: &mut V,
-        t: ast::SelpType,
-    ) -> Result<ast::Arg4<U>, TranslateError> {
+        typ: &ast::Type,
+    ) -> Result<ast::Arg5<U>, TranslateError> {
         let dst = visitor.operand(
             ArgumentDescriptor {
                 op: self.dst,
                 is_dst: true,
                 sema: ArgumentSemantics::Default,
             },
-            &ast::Type::Scalar(t.into()),
+            typ,
         )?;
         let src1 = visitor.operand(
             ArgumentDescriptor {
@@ -6629,7 +10059,7 @@ impl<T: ArgParamsEx> ast::Arg4<T> {
                 is_dst: false,
                 sema: ArgumentSemantics::Default,
             },
-            &ast::Type::Scalar(t.into()),
+            typ,
         )?;
         let src2 = visitor.operand(
             ArgumentDescriptor {
@@ -6637,49 +10067,69 @@ impl<T: ArgParamsEx> ast::Arg4<T> {
                 is_dst: false,
                 sema: ArgumentSemantics::Default,
             },
-            &ast::Type::Scalar(t.into()),
+            typ,
         )?;
+        let u32_type = ast::Type::Scalar(ast::ScalarType::U32);
         let src3 = visitor.operand(
             ArgumentDescriptor {
                 op: self.src3,
                 is_dst: false,
                 sema: ArgumentSemantics::Default,
             },
-            &ast::Type::Scalar(ast::ScalarType::Pred),
+            &u32_type,
         )?;
-        Ok(ast::Arg4 {
+        let src4 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src4,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            &u32_type,
+        )?;
+        Ok(ast::Arg5 {
             dst,
             src1,
             src2,
             src3,
+            src4,
         })
     }
+}
 
-    fn map_atom<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+impl<T: ArgParamsEx> ast::Arg4Setp<T> {
+    fn map<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
         self,
         visitor: &mut V,
-        t: ast::BitType,
-        state_space: ast::AtomSpace,
-    ) -> Result<ast::Arg4<U>, TranslateError> {
-        let scalar_type = ast::ScalarType::from(t);
-        let dst = visitor.operand(
+        t: &ast::Type,
+    ) -> Result<ast::Arg4Setp<U>, TranslateError> {
+        let dst1 = visitor.id(
             ArgumentDescriptor {
-                op: self.dst,
+                op: self.dst1,
                 is_dst: true,
                 sema: ArgumentSemantics::Default,
             },
-            &ast::Type::Scalar(scalar_type),
+            Some(&ast::Type::Scalar(ast::ScalarType::Pred)),
         )?;
+        let dst2 = self
+            .dst2
+            .map(|dst2| {
+                visitor.id(
+                    ArgumentDescriptor {
+                        op: dst2,
+                        is_dst: true,
+                        sema: ArgumentSemantics::Default,
+                    },
+                    Some(&ast::Type::Scalar(ast::ScalarType::Pred)),
+                )
+            })
+            .transpose()?;
         let src1 = visitor.operand(
             ArgumentDescriptor {
                 op: self.src1,
                 is_dst: false,
-                sema: ArgumentSemantics::PhysicalPointer,
+                sema: ArgumentSemantics::Default,
             },
-            &ast::Type::Pointer(
-                ast::PointerType::Scalar(scalar_type),
-                state_space.to_ld_ss(),
-            ),
+            t,
         )?;
         let src2 = visitor.operand(
             ArgumentDescriptor {
@@ -6687,36 +10137,88 @@ impl<T: ArgParamsEx> ast::Arg4<T> {
                 is_dst: false,
                 sema: ArgumentSemantics::Default,
             },
-            &ast::Type::Scalar(scalar_type),
+            t,
         )?;
-        let src3 = visitor.operand(
+        Ok(ast::Arg4Setp {
+            dst1,
+            dst2,
+            src1,
+            src2,
+        })
+    }
+}
+
+impl<T: ArgParamsEx> ast::ArgShfl<T> {
+    fn map<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+        self,
+        visitor: &mut V,
+    ) -> Result<ast::ArgShfl<U>, TranslateError> {
+        let b32_type = ast::Type::Scalar(ast::ScalarType::B32);
+        let u32_type = ast::Type::Scalar(ast::ScalarType::U32);
+        let dst1 = visitor.id(
             ArgumentDescriptor {
-                op: self.src3,
-                is_dst: false,
+                op: self.dst1,
+                is_dst: true,
                 sema: ArgumentSemantics::Default,
             },
-            &ast::Type::Scalar(scalar_type),
+            Some(&b32_type),
         )?;
-        Ok(ast::Arg4 {
-            dst,
+        let dst2 = self
+            .dst2
+            .map(|dst2| {
+                visitor.id(
+                    ArgumentDescriptor {
+                        op: dst2,
+                        is_dst: true,
+                        sema: ArgumentSemantics::Default,
+                    },
+                    Some(&ast::Type::Scalar(ast::ScalarType::Pred)),
+                )
+            })
+            .transpose()?;
+        let mut map_src = |op: T::Operand, typ: &ast::Type| {
+            visitor.operand(
+                ArgumentDescriptor {
+                    op,
+                    is_dst: false,
+                    sema: ArgumentSemantics::Default,
+                },
+                typ,
+            )
+        };
+        let src1 = map_src(self.src1, &b32_type)?;
+        let src2 = map_src(self.src2, &b32_type)?;
+        let src3 = map_src(self.src3, &b32_type)?;
+        let src4 = map_src(self.src4, &b32_type)?;
+        let lane_id = map_src(self.lane_id, &u32_type)?;
+        let warp_size = map_src(self.warp_size, &u32_type)?;
+        Ok(ast::ArgShfl {
+            dst1,
+            dst2,
             src1,
             src2,
             src3,
+            src4,
+            lane_id,
+            warp_size,
         })
     }
+}
 
-    fn map_bfe<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+impl<T: ArgParamsEx> ast::ArgVideo<T> {
+    fn map<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
         self,
         visitor: &mut V,
-        typ: &ast::Type,
-    ) -> Result<ast::Arg4<U>, TranslateError> {
+    ) -> Result<ast::ArgVideo<U>, TranslateError> {
+        // Signedness is part of the instruction details, operands are plain bits
+        let typ = ast::Type::Scalar(ast::ScalarType::B32);
         let dst = visitor.operand(
             ArgumentDescriptor {
                 op: self.dst,
                 is_dst: true,
                 sema: ArgumentSemantics::Default,
             },
-            typ,
+            &typ,
         )?;
         let src1 = visitor.operand(
             ArgumentDescriptor {
@@ -6724,26 +10226,30 @@ impl<T: ArgParamsEx> ast::Arg4<T> {
                 is_dst: false,
                 sema: ArgumentSemantics::Default,
             },
-            typ,
+            &typ,
         )?;
-        let u32_type = ast::Type::Scalar(ast::ScalarType::U32);
         let src2 = visitor.operand(
             ArgumentDescriptor {
                 op: self.src2,
                 is_dst: false,
                 sema: ArgumentSemantics::Default,
             },
-            &u32_type,
-        )?;
-        let src3 = visitor.operand(
-            ArgumentDescriptor {
-                op: self.src3,
-                is_dst: false,
-                sema: ArgumentSemantics::Default,
-            },
-            &u32_type,
+            &typ,
         )?;
-        Ok(ast::Arg4 {
+        let src3 = self
+            .src3
+            .map(|src3| {
+                visitor.operand(
+                    ArgumentDescriptor {
+                        op: src3,
+                        is_dst: false,
+                        sema: ArgumentSemantics::Default,
+                    },
+                    &typ,
+                )
+            })
+            .transpose()?;
+        Ok(ast::ArgVideo {
             dst,
             src1,
             src2,
@@ -6752,30 +10258,32 @@ impl<T: ArgParamsEx> ast::Arg4<T> {
     }
 }
 
-impl<T: ArgParamsEx> ast::Arg4Setp<T> {
+impl<T: ArgParamsEx> ast::ArgCarry<T> {
     fn map<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
         self,
         visitor: &mut V,
-        t: &ast::Type,
-    ) -> Result<ast::Arg4Setp<U>, TranslateError> {
-        let dst1 = visitor.id(
+        typ: ast::IntType,
+    ) -> Result<ast::ArgCarry<U>, TranslateError> {
+        let typ = ast::Type::Scalar(typ.into());
+        let pred_type = ast::Type::Scalar(ast::ScalarType::Pred);
+        let dst = visitor.operand(
             ArgumentDescriptor {
-                op: self.dst1,
+                op: self.dst,
                 is_dst: true,
                 sema: ArgumentSemantics::Default,
             },
-            Some(&ast::Type::Scalar(ast::ScalarType::Pred)),
+            &typ,
         )?;
-        let dst2 = self
-            .dst2
-            .map(|dst2| {
+        let carry_out = self
+            .carry_out
+            .map(|carry_out| {
                 visitor.id(
                     ArgumentDescriptor {
-                        op: dst2,
+                        op: carry_out,
                         is_dst: true,
                         sema: ArgumentSemantics::Default,
                     },
-                    Some(&ast::Type::Scalar(ast::ScalarType::Pred)),
+                    Some(&pred_type),
                 )
             })
             .transpose()?;
@@ -6785,7 +10293,7 @@ impl<T: ArgParamsEx> ast::Arg4Setp<T> {
                 is_dst: false,
                 sema: ArgumentSemantics::Default,
             },
-            t,
+            &typ,
         )?;
         let src2 = visitor.operand(
             ArgumentDescriptor {
@@ -6793,13 +10301,27 @@ impl<T: ArgParamsEx> ast::Arg4Setp<T> {
                 is_dst: false,
                 sema: ArgumentSemantics::Default,
             },
-            t,
+            &typ,
         )?;
-        Ok(ast::Arg4Setp {
-            dst1,
-            dst2,
+        let carry_in = self
+            .carry_in
+            .map(|carry_in| {
+                visitor.id(
+                    ArgumentDescriptor {
+                        op: carry_in,
+                        is_dst: false,
+                        sema: ArgumentSemantics::Default,
+                    },
+                    Some(&pred_type),
+                )
+            })
+            .transpose()?;
+        Ok(ast::ArgCarry {
+            dst,
+            carry_out,
             src1,
             src2,
+            carry_in,
         })
     }
 }
@@ -6865,6 +10387,86 @@ impl<T: ArgParamsEx> ast::Arg5Setp<T> {
     }
 }
 
+impl<T: ArgParamsEx> ast::ArgWmmaLoad<T> {
+    fn map<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+        self,
+        visitor: &mut V,
+        state_space: ast::LdStateSpace,
+    ) -> Result<ast::ArgWmmaLoad<U>, TranslateError> {
+        let dst = self
+            .dst
+            .into_iter()
+            .map(|dst| {
+                visitor.id(
+                    ArgumentDescriptor {
+                        op: dst,
+                        is_dst: true,
+                        sema: ArgumentSemantics::Default,
+                    },
+                    Some(&ast::Type::Scalar(ast::ScalarType::B32)),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let src1 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src1,
+                is_dst: false,
+                sema: ArgumentSemantics::PhysicalPointer,
+            },
+            &ast::Type::Pointer(ast::PointerType::Scalar(ast::ScalarType::U16), state_space),
+        )?;
+        let src2 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src2,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            &ast::Type::Scalar(ast::ScalarType::U32),
+        )?;
+        Ok(ast::ArgWmmaLoad { dst, src1, src2 })
+    }
+}
+
+impl<T: ArgParamsEx> ast::ArgWmmaStore<T> {
+    fn map<U: ArgParamsEx, V: ArgumentMapVisitor<T, U>>(
+        self,
+        visitor: &mut V,
+        state_space: ast::LdStateSpace,
+    ) -> Result<ast::ArgWmmaStore<U>, TranslateError> {
+        let src1 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src1,
+                is_dst: false,
+                sema: ArgumentSemantics::PhysicalPointer,
+            },
+            &ast::Type::Pointer(ast::PointerType::Scalar(ast::ScalarType::U16), state_space),
+        )?;
+        let src2 = self
+            .src2
+            .into_iter()
+            .map(|src| {
+                visitor.id(
+                    ArgumentDescriptor {
+                        op: src,
+                        is_dst: false,
+                        sema: ArgumentSemantics::Default,
+                    },
+                    Some(&ast::Type::Scalar(ast::ScalarType::B32)),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let src3 = visitor.operand(
+            ArgumentDescriptor {
+                op: self.src3,
+                is_dst: false,
+                sema: ArgumentSemantics::Default,
+            },
+            &ast::Type::Scalar(ast::ScalarType::U32),
+        )?;
+        Ok(ast::ArgWmmaStore { src1, src2, src3 })
+    }
+}
+
 impl<T> ast::Operand<T> {
     fn map_variable<U, F: FnMut(T) -> Result<U, TranslateError>>(
         self,
@@ -7010,6 +10612,7 @@ impl ast::ArithDetails {
             ast::ArithDetails::Unsigned(t) => (*t).into(),
             ast::ArithDetails::Signed(d) => d.typ.into(),
             ast::ArithDetails::Float(d) => d.typ.into(),
+            ast::ArithDetails::BF16 => ast::ScalarType::B16,
         })
     }
 }
@@ -7020,6 +10623,7 @@ impl ast::MulDetails {
             ast::MulDetails::Unsigned(d) => d.typ.into(),
             ast::MulDetails::Signed(d) => d.typ.into(),
             ast::MulDetails::Float(d) => d.typ.into(),
+            ast::MulDetails::BF16 => ast::ScalarType::B16,
         })
     }
 }
@@ -7053,6 +10657,32 @@ impl ast::AtomInnerDetails {
             ast::AtomInnerDetails::Float { typ, .. } => (*typ).into(),
         }
     }
+
+    // Operations which are implemented by calls to ptx_impl functions
+    fn ptx_impl_op(
+        &self,
+        options: &TranslateOptions,
+    ) -> Option<(&'static str, ast::SizedScalarType)> {
+        match self {
+            ast::AtomInnerDetails::Unsigned {
+                op: ast::AtomUIntOp::Inc,
+                ..
+            } => Some(("inc", ast::SizedScalarType::U32)),
+            ast::AtomInnerDetails::Unsigned {
+                op: ast::AtomUIntOp::Dec,
+                ..
+            } => Some(("dec", ast::SizedScalarType::U32)),
+            ast::AtomInnerDetails::Float {
+                op: ast::AtomFloatOp::Add,
+                typ: ast::FloatType::F32,
+            } if !options.atomic_float32_add => Some(("add_f32", ast::SizedScalarType::F32)),
+            ast::AtomInnerDetails::Float {
+                op: ast::AtomFloatOp::Add,
+                typ: ast::FloatType::F64,
+            } if !options.atomic_float64_add => Some(("add_f64", ast::SizedScalarType::F64)),
+            _ => None,
+        }
+    }
 }
 
 impl ast::SIntType {
@@ -7119,6 +10749,7 @@ impl ast::MulDetails {
             ast::MulDetails::Unsigned(d) => d.control == ast::MulIntControl::Wide,
             ast::MulDetails::Signed(d) => d.control == ast::MulIntControl::Wide,
             ast::MulDetails::Float(_) => false,
+            ast::MulDetails::BF16 => false,
         }
     }
 }
@@ -7154,6 +10785,19 @@ impl ast::AtomSemantics {
     }
 }
 
+impl ast::FenceSemantics {
+    fn to_spirv(self) -> spirv::MemorySemantics {
+        match self {
+            ast::FenceSemantics::SequentiallyConsistent => {
+                spirv::MemorySemantics::SEQUENTIALLY_CONSISTENT
+            }
+            ast::FenceSemantics::AcquireRelease => spirv::MemorySemantics::ACQUIRE_RELEASE,
+            ast::FenceSemantics::Acquire => spirv::MemorySemantics::ACQUIRE,
+            ast::FenceSemantics::Release => spirv::MemorySemantics::RELEASE,
+        }
+    }
+}
+
 impl ast::FnArgumentType {
     fn semantics(&self) -> ArgumentSemantics {
         match self {
@@ -7469,6 +11113,8 @@ struct SpirvMethodDecl<'input> {
     output: Vec<ast::Variable<ast::Type, spirv::Word>>,
     name: MethodName<'input>,
     uses_shared_mem: bool,
+    // (texture, image parameter, sampler parameter), filled by resolve_textures
+    textures: Vec<(KernelTexture, spirv::Word, spirv::Word)>,
 }
 
 impl<'input> SpirvMethodDecl<'input> {
@@ -7526,6 +11172,7 @@ impl<'input> SpirvMethodDecl<'input> {
             output,
             name: MethodName::new(ast_decl),
             uses_shared_mem: false,
+            textures: Vec::new(),
         }
     }
 }
@@ -2232,6 +2232,11 @@ pub extern "C" fn cuDeviceGetUuid(uuid: *mut CUuuid, dev: CUdevice) -> CUresult
     r#impl::device::get_uuid(uuid, dev.decuda()).encuda()
 }
 
+#[cfg_attr(not(test), no_mangle)]
+pub extern "C" fn cuDeviceGetUuid_v2(uuid: *mut CUuuid, dev: CUdevice) -> CUresult {
+    r#impl::device::get_uuid(uuid, dev.decuda()).encuda()
+}
+
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn cuDeviceGetLuid(
     luid: *mut ::std::os::raw::c_char,
@@ -2374,17 +2379,17 @@ pub extern "C" fn cuCtxGetFlags(flags: *mut ::std::os::raw::c_uint) -> CUresult
 
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn cuCtxSynchronize() -> CUresult {
-    r#impl::context::synchronize()
+    r#impl::context::synchronize().encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn cuCtxSetLimit(limit: CUlimit, value: usize) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::context::set_limit(limit, value).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn cuCtxGetLimit(pvalue: *mut usize, limit: CUlimit) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::context::get_limit(pvalue, limit).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -2454,7 +2459,6 @@ pub extern "C" fn cuModuleLoadData(
     r#impl::module::load_data(module.decuda(), image).encuda()
 }
 
-// TODO: parse jit options
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn cuModuleLoadDataEx(
     module: *mut CUmodule,
@@ -2463,7 +2467,7 @@ pub extern "C" fn cuModuleLoadDataEx(
     options: *mut CUjit_option,
     optionValues: *mut *mut ::std::os::raw::c_void,
 ) -> CUresult {
-    r#impl::module::load_data(module.decuda(), image).encuda()
+    r#impl::module::load_data_ex(module.decuda(), image, numOptions, options, optionValues).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -2504,7 +2508,7 @@ pub extern "C" fn cuModuleGetTexRef(
     hmod: CUmodule,
     name: *const ::std::os::raw::c_char,
 ) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::module::get_texref(pTexRef.decuda(), hmod.decuda(), name).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -2523,7 +2527,7 @@ pub extern "C" fn cuLinkCreate_v2(
     optionValues: *mut *mut ::std::os::raw::c_void,
     stateOut: *mut CUlinkState,
 ) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::link::create(numOptions, options, optionValues, stateOut.decuda()).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -2537,7 +2541,7 @@ pub extern "C" fn cuLinkAddData_v2(
     options: *mut CUjit_option,
     optionValues: *mut *mut ::std::os::raw::c_void,
 ) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::link::add_data(state.decuda(), type_, data, size).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -2549,7 +2553,7 @@ pub extern "C" fn cuLinkAddFile_v2(
     options: *mut CUjit_option,
     optionValues: *mut *mut ::std::os::raw::c_void,
 ) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::link::add_file(state.decuda(), type_, path).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -2558,12 +2562,12 @@ pub extern "C" fn cuLinkComplete(
     cubinOut: *mut *mut ::std::os::raw::c_void,
     sizeOut: *mut usize,
 ) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::link::complete(state.decuda(), cubinOut, sizeOut).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn cuLinkDestroy(state: CUlinkState) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::link::destroy(state.decuda()).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -2734,17 +2738,28 @@ pub extern "C" fn cuMemcpyHtoD_v2(
     srcHost: *const ::std::os::raw::c_void,
     ByteCount: usize,
 ) -> CUresult {
-    r#impl::memory::copy_v2(dstDevice.decuda(), srcHost, ByteCount).encuda()
+    r#impl::memory::copy_v2(
+        dstDevice.decuda(),
+        srcHost,
+        ByteCount,
+        r#impl::stream::CU_STREAM_LEGACY,
+    )
+    .encuda()
 }
 
-// TODO: implement default stream semantics
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn cuMemcpyHtoD_v2_ptds(
     dstDevice: CUdeviceptr,
     srcHost: *const ::std::os::raw::c_void,
     ByteCount: usize,
 ) -> CUresult {
-    r#impl::memory::copy_v2(dstDevice.decuda(), srcHost, ByteCount).encuda()
+    r#impl::memory::copy_v2(
+        dstDevice.decuda(),
+        srcHost,
+        ByteCount,
+        r#impl::stream::CU_STREAM_PER_THREAD,
+    )
+    .encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -2753,17 +2768,28 @@ pub extern "C" fn cuMemcpyDtoH_v2(
     srcDevice: CUdeviceptr,
     ByteCount: usize,
 ) -> CUresult {
-    r#impl::memory::copy_v2(dstHost, srcDevice.decuda(), ByteCount).encuda()
+    r#impl::memory::copy_v2(
+        dstHost,
+        srcDevice.decuda(),
+        ByteCount,
+        r#impl::stream::CU_STREAM_LEGACY,
+    )
+    .encuda()
 }
 
-// TODO: implement default stream semantics
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn cuMemcpyDtoH_v2_ptds(
     dstHost: *mut ::std::os::raw::c_void,
     srcDevice: CUdeviceptr,
     ByteCount: usize,
 ) -> CUresult {
-    r#impl::memory::copy_v2(dstHost, srcDevice.decuda(), ByteCount).encuda()
+    r#impl::memory::copy_v2(
+        dstHost,
+        srcDevice.decuda(),
+        ByteCount,
+        r#impl::stream::CU_STREAM_PER_THREAD,
+    )
+    .encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -2944,17 +2970,22 @@ pub extern "C" fn cuMemsetD8_v2(
     uc: ::std::os::raw::c_uchar,
     N: usize,
 ) -> CUresult {
-    r#impl::memory::set_d8_v2(dstDevice.decuda(), uc, N).encuda()
+    r#impl::memory::set_d8_v2(dstDevice.decuda(), uc, N, r#impl::stream::CU_STREAM_LEGACY).encuda()
 }
 
-// TODO: implement default stream semantics
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn cuMemsetD8_v2_ptds(
     dstDevice: CUdeviceptr,
     uc: ::std::os::raw::c_uchar,
     N: usize,
 ) -> CUresult {
-    r#impl::memory::set_d8_v2(dstDevice.decuda(), uc, N).encuda()
+    r#impl::memory::set_d8_v2(
+        dstDevice.decuda(),
+        uc,
+        N,
+        r#impl::stream::CU_STREAM_PER_THREAD,
+    )
+    .encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -2972,17 +3003,22 @@ pub extern "C" fn cuMemsetD32_v2(
     ui: ::std::os::raw::c_uint,
     N: usize,
 ) -> CUresult {
-    r#impl::memory::set_d32_v2(dstDevice.decuda(), ui, N).encuda()
+    r#impl::memory::set_d32_v2(dstDevice.decuda(), ui, N, r#impl::stream::CU_STREAM_LEGACY).encuda()
 }
 
-// TODO: implement default stream semantics
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn cuMemsetD32_v2_ptds(
     dstDevice: CUdeviceptr,
     ui: ::std::os::raw::c_uint,
     N: usize,
 ) -> CUresult {
-    r#impl::memory::set_d32_v2(dstDevice.decuda(), ui, N).encuda()
+    r#impl::memory::set_d32_v2(
+        dstDevice.decuda(),
+        ui,
+        N,
+        r#impl::stream::CU_STREAM_PER_THREAD,
+    )
+    .encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -3339,7 +3375,7 @@ pub extern "C" fn cuStreamCreateWithPriority(
     flags: ::std::os::raw::c_uint,
     priority: ::std::os::raw::c_int,
 ) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::stream::create_with_priority(phStream.decuda(), flags, priority).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -3347,7 +3383,7 @@ pub extern "C" fn cuStreamGetPriority(
     hStream: CUstream,
     priority: *mut ::std::os::raw::c_int,
 ) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::stream::get_priority(hStream.decuda(), priority).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -3355,7 +3391,7 @@ pub extern "C" fn cuStreamGetFlags(
     hStream: CUstream,
     flags: *mut ::std::os::raw::c_uint,
 ) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::stream::get_flags(hStream.decuda(), flags).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -3363,10 +3399,10 @@ pub extern "C" fn cuStreamGetCtx(hStream: CUstream, pctx: *mut CUcontext) -> CUr
     r#impl::stream::get_ctx(hStream.decuda(), pctx.decuda()).encuda()
 }
 
-// TODO: implement default stream semantics
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn cuStreamGetCtx_ptsz(hStream: CUstream, pctx: *mut CUcontext) -> CUresult {
-    r#impl::stream::get_ctx(hStream.decuda(), pctx.decuda()).encuda()
+    let hstream = r#impl::stream::per_thread_default(hStream.decuda());
+    r#impl::stream::get_ctx(hstream, pctx.decuda()).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -3634,7 +3670,7 @@ pub extern "C" fn cuFuncSetAttribute(
     attrib: CUfunction_attribute,
     value: ::std::os::raw::c_int,
 ) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::function::set_attribute(hfunc.decuda(), attrib, value).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -3677,7 +3713,6 @@ pub extern "C" fn cuLaunchKernel(
     .encuda()
 }
 
-// TODO: implement default stream semantics
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn cuLaunchKernel_ptsz(
     f: CUfunction,
@@ -3701,7 +3736,7 @@ pub extern "C" fn cuLaunchKernel_ptsz(
         blockDimY,
         blockDimZ,
         sharedMemBytes,
-        hStream.decuda(),
+        r#impl::stream::per_thread_default(hStream.decuda()),
         kernelParams,
         extra,
     )
@@ -4238,7 +4273,7 @@ pub extern "C" fn cuTexRefSetAddress_v2(
     dptr: CUdeviceptr,
     bytes: usize,
 ) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::texref::set_address(ByteOffset, hTexRef.decuda(), dptr.decuda(), bytes).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -4257,7 +4292,7 @@ pub extern "C" fn cuTexRefSetFormat(
     fmt: CUarray_format,
     NumPackedComponents: ::std::os::raw::c_int,
 ) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::texref::set_format(hTexRef.decuda(), fmt, NumPackedComponents).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -4313,7 +4348,7 @@ pub extern "C" fn cuTexRefSetFlags(hTexRef: CUtexref, Flags: ::std::os::raw::c_u
 
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn cuTexRefGetAddress_v2(pdptr: *mut CUdeviceptr, hTexRef: CUtexref) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::texref::get_address(pdptr.decuda(), hTexRef.decuda()).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -4349,7 +4384,7 @@ pub extern "C" fn cuTexRefGetFormat(
     pNumChannels: *mut ::std::os::raw::c_int,
     hTexRef: CUtexref,
 ) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::texref::get_format(pFormat, pNumChannels, hTexRef.decuda()).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -4426,12 +4461,12 @@ pub extern "C" fn cuTexObjectCreate(
     pTexDesc: *const CUDA_TEXTURE_DESC,
     pResViewDesc: *const CUDA_RESOURCE_VIEW_DESC,
 ) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::texobj::create(pTexObject, pResDesc, pTexDesc, pResViewDesc).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn cuTexObjectDestroy(texObject: CUtexObject) -> CUresult {
-    r#impl::unimplemented()
+    r#impl::texobj::destroy(texObject).encuda()
 }
 
 #[cfg_attr(not(test), no_mangle)]
@@ -4604,10 +4639,10 @@ pub extern "C" fn cuProfilerInitialize(
 
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn cuProfilerStart() -> CUresult {
-    r#impl::unimplemented()
+    CUresult::CUDA_SUCCESS
 }
 
 #[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn cuProfilerStop() -> CUresult {
-    r#impl::unimplemented()
+    CUresult::CUDA_SUCCESS
 }
@@ -1,15 +1,50 @@
-use super::{device, stream::Stream, stream::StreamData, HasLivenessCookie, LiveCheck};
+use super::{device, printf, stream::Stream, stream::StreamData, HasLivenessCookie, LiveCheck};
 use super::{CUresult, GlobalState};
-use crate::{cuda::CUcontext, cuda_impl};
-use l0::sys::ze_result_t;
+use crate::{
+    cuda::{CUcontext, CUlimit},
+    cuda_impl,
+};
 use std::{cell::RefCell, num::NonZeroU32, os::raw::c_uint, ptr, sync::atomic::AtomicU32};
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     mem::{self},
+    thread::{self, ThreadId},
 };
 
 thread_local! {
     pub static CONTEXT_STACK: RefCell<Vec<*mut Context>> = RefCell::new(Vec::new());
+    static PER_THREAD_STREAMS: PerThreadStreams = PerThreadStreams {
+        thread: thread::current().id(),
+        contexts: RefCell::new(Vec::new()),
+    };
+}
+
+// Contexts in which this thread has a per-thread default stream. Streams are
+// released when the thread exits, otherwise every short-lived thread would
+// leak a command queue
+struct PerThreadStreams {
+    thread: ThreadId,
+    contexts: RefCell<Vec<*mut Context>>,
+}
+
+impl Drop for PerThreadStreams {
+    fn drop(&mut self) {
+        let contexts = mem::take(self.contexts.get_mut());
+        // There's no one to report an error to, the stream is leaked then
+        let _ = GlobalState::lock(|_| {
+            for ctx in contexts {
+                // Memory of destroyed contexts is never freed, so this is safe
+                let ctx = match unsafe { &mut *ctx }.as_option_mut() {
+                    Some(ctx) => ctx,
+                    None => continue,
+                };
+                if let Some(stream) = ctx.per_thread_streams.remove(&self.thread) {
+                    // Work submitted by the thread still has to finish
+                    let _ = stream.queue.synchronize();
+                }
+            }
+        });
+    }
 }
 
 pub type Context = LiveCheck<ContextData>;
@@ -29,6 +64,12 @@ impl HasLivenessCookie for ContextData {
             stream.context = ptr::null_mut();
             Stream::destroy_impl(unsafe { Stream::ptr_from_inner(stream) })?;
         }
+        for stream in self.per_thread_streams.values_mut() {
+            stream.context = ptr::null_mut();
+        }
+        if let Some(buffer) = self.printf_buffer.take() {
+            buffer.free(self)?;
+        }
         Ok(())
     }
 }
@@ -77,13 +118,48 @@ impl ContextRefCount {
     }
 }
 
+// Level Zero has no control over the stack: private memory is sized by the
+// compiler, the value is only stored so applications that tune it keep
+// working. The printf FIFO size is used when the printf buffer is created
+pub struct ContextLimits {
+    pub stack_size: usize,
+    pub printf_fifo_size: usize,
+}
+
+impl ContextLimits {
+    // Defaults reported by CUDA for a fresh context
+    const DEFAULT_STACK_SIZE: usize = 1024;
+    const DEFAULT_PRINTF_FIFO_SIZE: usize = 1024 * 1024;
+
+    fn new() -> Self {
+        ContextLimits {
+            stack_size: Self::DEFAULT_STACK_SIZE,
+            printf_fifo_size: Self::DEFAULT_PRINTF_FIFO_SIZE,
+        }
+    }
+
+    fn get_mut(&mut self, limit: CUlimit) -> Result<&mut usize, CUresult> {
+        match limit {
+            CUlimit::CU_LIMIT_STACK_SIZE => Ok(&mut self.stack_size),
+            CUlimit::CU_LIMIT_PRINTF_FIFO_SIZE => Ok(&mut self.printf_fifo_size),
+            _ => Err(CUresult::CUDA_ERROR_UNSUPPORTED_LIMIT),
+        }
+    }
+}
+
 pub struct ContextData {
     pub flags: AtomicU32,
     // This pointer is null only for a moment when constructing primary context
     pub device: *mut device::Device,
     ref_count: ContextRefCount,
     pub default_stream: StreamData,
+    // Default streams of host threads using per-thread default stream
+    // semantics (CU_STREAM_PER_THREAD or *_ptsz/*_ptds entry points)
+    pub per_thread_streams: HashMap<ThreadId, StreamData>,
     pub streams: HashSet<*mut StreamData>,
+    pub limits: ContextLimits,
+    // Created when the first module using vprintf is loaded
+    pub printf_buffer: Option<printf::PrintfBuffer>,
     // All the fields below are here to support internal CUDA driver API
     pub cuda_manager: *mut cuda_impl::rt::ContextStateManager,
     pub cuda_state: *mut cuda_impl::rt::ContextState,
@@ -110,12 +186,28 @@ impl ContextData {
             device: dev,
             ref_count: ContextRefCount::new(is_primary),
             default_stream,
+            per_thread_streams: HashMap::new(),
             streams: HashSet::new(),
+            limits: ContextLimits::new(),
+            printf_buffer: None,
             cuda_manager: ptr::null_mut(),
             cuda_state: ptr::null_mut(),
             cuda_dtor_cb: None,
         })
     }
+
+    // Created on the first use from the calling thread, released when the
+    // thread exits
+    pub fn per_thread_stream(&mut self) -> Result<&mut StreamData, CUresult> {
+        let thread = thread::current().id();
+        if !self.per_thread_streams.contains_key(&thread) {
+            let stream = StreamData::new(self, 0, 0)?;
+            self.per_thread_streams.insert(thread, stream);
+            let ctx = unsafe { Context::ptr_from_inner(self) };
+            PER_THREAD_STREAMS.with(|streams| streams.contexts.borrow_mut().push(ctx));
+        }
+        Ok(self.per_thread_streams.get_mut(&thread).unwrap())
+    }
 }
 
 impl Context {
@@ -190,9 +282,9 @@ pub fn pop_current_v2(pctx: *mut *mut Context) -> CUresult {
     CUresult::CUDA_SUCCESS
 }
 
-pub fn get_current(pctx: *mut *mut Context) -> l0::Result<()> {
+pub fn get_current(pctx: *mut *mut Context) -> Result<(), CUresult> {
     if pctx == ptr::null_mut() {
-        return Err(ze_result_t::ZE_RESULT_ERROR_INVALID_ARGUMENT);
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
     }
     let ctx = CONTEXT_STACK.with(|stack| match stack.borrow().last() {
         Some(ctx) => *ctx as *mut _,
@@ -220,8 +312,23 @@ pub fn get_api_version(ctx: *mut Context, version: *mut u32) -> Result<(), CUres
         unsafe { &*ctx }.as_result()?;
         Ok::<_, CUresult>(())
     })??;
-    //TODO: query device for properties roughly matching CUDA API version
-    unsafe { *version = 1100 };
+    unsafe { *version = super::driver_get_version() as u32 };
+    Ok(())
+}
+
+pub fn set_limit(limit: CUlimit, value: usize) -> Result<(), CUresult> {
+    GlobalState::lock_current_context(|ctx| {
+        *ctx.limits.get_mut(limit)? = value;
+        Ok::<_, CUresult>(())
+    })?
+}
+
+pub fn get_limit(pvalue: *mut usize, limit: CUlimit) -> Result<(), CUresult> {
+    if pvalue == ptr::null_mut() {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    let value = GlobalState::lock_current_context(|ctx| ctx.limits.get_mut(limit).map(|v| *v))??;
+    unsafe { *pvalue = value };
     Ok(())
 }
 
@@ -257,16 +364,26 @@ pub fn detach(pctx: *mut Context) -> Result<(), CUresult> {
     })?
 }
 
-pub(crate) fn synchronize() -> CUresult {
-    // TODO: change the implementation once we do async stream operations
-    CUresult::CUDA_SUCCESS
+pub(crate) fn synchronize() -> Result<(), CUresult> {
+    GlobalState::lock_current_context(|ctx| {
+        ctx.default_stream.queue.synchronize()?;
+        for stream in ctx.per_thread_streams.values() {
+            stream.queue.synchronize()?;
+        }
+        for stream in ctx.streams.iter() {
+            let stream = unsafe { &mut **stream };
+            stream.queue.synchronize()?;
+        }
+        printf::flush(ctx)
+    })?
 }
 
 #[cfg(test)]
 mod test {
     use super::super::test::CudaDriverFns;
     use super::super::CUresult;
-    use std::{ffi::c_void, ptr};
+    use crate::cuda::CUlimit;
+    use std::{ffi::c_void, mem, ptr};
 
     cuda_driver_test!(destroy_leaves_zombie_context);
 
@@ -364,4 +481,92 @@ mod test {
         assert_eq!(T::cuCtxGetCurrent(&mut ctx), CUresult::CUDA_SUCCESS);
         assert_eq!(ctx, ptr::null_mut());
     }
+
+    // Work submitted through the CUDA API is waited for before the call
+    // returns, so this submits straight to the stream's queue and keeps the
+    // fence alive to have the fill still in flight during cuCtxSynchronize
+    #[test]
+    fn synchronize_waits_for_all_streams() {
+        type T = crate::r#impl::test::Zluda;
+        const LEN: usize = 64 * 1024 * 1024;
+        assert_eq!(T::cuInit(0), CUresult::CUDA_SUCCESS);
+        let mut ctx = ptr::null_mut();
+        assert_eq!(T::cuCtxCreate_v2(&mut ctx, 0, 0), CUresult::CUDA_SUCCESS);
+        let mut stream = ptr::null_mut();
+        assert_eq!(T::cuStreamCreate(&mut stream, 0), CUresult::CUDA_SUCCESS);
+        let mut dev_mem = ptr::null_mut();
+        assert_eq!(
+            T::cuMemAlloc_v2(&mut dev_mem, LEN * mem::size_of::<u32>()),
+            CUresult::CUDA_SUCCESS
+        );
+        let stream_data = unsafe { &mut *(stream as *mut super::super::stream::Stream) }
+            .as_result_mut()
+            .unwrap();
+        let mut cmd_list = stream_data.command_list().unwrap();
+        unsafe {
+            cmd_list.append_memory_fill_unsafe(
+                dev_mem,
+                &0x8f3a6c21u32,
+                LEN * mem::size_of::<u32>(),
+                None,
+                &mut [],
+            )
+        }
+        .unwrap();
+        let fence = stream_data.queue.execute(cmd_list).unwrap();
+        assert_eq!(T::cuCtxSynchronize(), CUresult::CUDA_SUCCESS);
+        assert!(fence.is_signaled().unwrap());
+        drop(fence);
+        // Cleanup
+        assert_eq!(T::cuMemFree_v2(dev_mem), CUresult::CUDA_SUCCESS);
+        assert_eq!(T::cuStreamDestroy_v2(stream), CUresult::CUDA_SUCCESS);
+        assert_eq!(T::cuCtxDestroy_v2(ctx), CUresult::CUDA_SUCCESS);
+    }
+
+    cuda_driver_test!(profiler_start_stop_succeed);
+
+    fn profiler_start_stop_succeed<T: CudaDriverFns>() {
+        assert_eq!(T::cuInit(0), CUresult::CUDA_SUCCESS);
+        let mut ctx = ptr::null_mut();
+        assert_eq!(T::cuCtxCreate_v2(&mut ctx, 0, 0), CUresult::CUDA_SUCCESS);
+        assert_eq!(T::cuProfilerStart(), CUresult::CUDA_SUCCESS);
+        assert_eq!(T::cuProfilerStop(), CUresult::CUDA_SUCCESS);
+        assert_eq!(T::cuCtxDestroy_v2(ctx), CUresult::CUDA_SUCCESS);
+    }
+
+    cuda_driver_test!(limits_round_trip);
+
+    fn limits_round_trip<T: CudaDriverFns>() {
+        assert_eq!(T::cuInit(0), CUresult::CUDA_SUCCESS);
+        let mut ctx = ptr::null_mut();
+        assert_eq!(T::cuCtxCreate_v2(&mut ctx, 0, 0), CUresult::CUDA_SUCCESS);
+        for &(limit, new_value) in &[
+            (CUlimit::CU_LIMIT_STACK_SIZE, 4096),
+            (CUlimit::CU_LIMIT_PRINTF_FIFO_SIZE, 2 * 1024 * 1024),
+        ] {
+            let mut value = 0;
+            assert_eq!(T::cuCtxGetLimit(&mut value, limit), CUresult::CUDA_SUCCESS);
+            assert_ne!(value, 0);
+            assert_eq!(T::cuCtxSetLimit(limit, new_value), CUresult::CUDA_SUCCESS);
+            assert_eq!(T::cuCtxGetLimit(&mut value, limit), CUresult::CUDA_SUCCESS);
+            assert_eq!(value, new_value);
+        }
+        assert_eq!(T::cuCtxDestroy_v2(ctx), CUresult::CUDA_SUCCESS);
+    }
+
+    // NVIDIA reports an unrelated context API version, so this only runs on ZLUDA
+    #[test]
+    fn api_version_matches_driver_version() {
+        type T = crate::r#impl::test::Zluda;
+        assert_eq!(T::cuInit(0), CUresult::CUDA_SUCCESS);
+        let mut ctx = ptr::null_mut();
+        assert_eq!(T::cuCtxCreate_v2(&mut ctx, 0, 0), CUresult::CUDA_SUCCESS);
+        let mut version = 0;
+        assert_eq!(
+            T::cuCtxGetApiVersion(ctx, &mut version),
+            CUresult::CUDA_SUCCESS
+        );
+        assert_eq!(version, super::super::driver_get_version() as u32);
+        assert_eq!(T::cuCtxDestroy_v2(ctx), CUresult::CUDA_SUCCESS);
+    }
 }
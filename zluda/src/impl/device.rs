@@ -1,15 +1,72 @@
-use super::{context, CUresult, GlobalState};
+use super::{context, module, CUresult, GlobalState};
 use crate::cuda;
 use cuda::{CUdevice_attribute, CUuuid_st};
 use std::{
-    cmp, mem,
+    borrow::Cow,
+    cmp, env,
+    ffi::{OsStr, OsString},
+    mem,
     os::raw::{c_char, c_int, c_uint},
-    ptr,
+    ptr, slice,
     sync::atomic::{AtomicU32, Ordering},
 };
 
 const PROJECT_URL_SUFFIX_SHORT: &'static str = " [ZLUDA]";
 const PROJECT_URL_SUFFIX_LONG: &'static str = " [github.com/vosen/ZLUDA]";
+const DEVICE_NAME_SUFFIX_ENV: &str = "ZLUDA_DEVICE_NAME_SUFFIX";
+const DEVICE_NAME_ENV: &str = "ZLUDA_DEVICE_NAME";
+const DEVICE_FILTER_ENV: &str = "ZLUDA_DEVICE";
+const SHADER_CLOCK_PROBE_PTX: &str = "
+    .version 6.5
+    .target sm_30
+    .address_size 64
+
+    .visible .entry shader_clock_probe(
+        .param .u64 output
+    )
+    {
+        .reg .u64 	    out_addr;
+        .reg .u64 	    clock;
+
+        ld.param.u64    out_addr, [output];
+        mov.u64         clock, %clock64;
+        st.u64          [out_addr], clock;
+        ret;
+    }";
+const STACK_CALLS_PROBE_PTX: &str = "
+    .version 6.5
+    .target sm_30
+    .address_size 64
+
+    .func (.reg .u64 result) countdown(.reg .u64 n);
+
+    .visible .entry stack_calls_probe(
+        .param .u64 output
+    )
+    {
+        .reg .u64 	    out_addr;
+        .reg .u64 	    value;
+
+        ld.param.u64    out_addr, [output];
+        ld.u64          value, [out_addr];
+        call (value), countdown, (value);
+        st.u64          [out_addr], value;
+        ret;
+    }
+
+    .func (.reg .u64 result) countdown(
+        .reg .u64 n
+    )
+    {
+        .reg .pred 	    is_zero;
+
+        mov.u64         result, n;
+        setp.eq.u64     is_zero, n, 0;
+        @is_zero ret;
+        sub.u64         n, n, 1;
+        call (result), countdown, (n);
+        ret;
+    }";
 
 #[repr(transparent)]
 #[derive(Clone, Copy, Eq, PartialEq, Hash)]
@@ -25,6 +82,9 @@ pub struct Device {
     image_properties: Option<Box<l0::sys::ze_device_image_properties_t>>,
     memory_properties: Option<Vec<l0::sys::ze_device_memory_properties_t>>,
     compute_properties: Option<Box<l0::sys::ze_device_compute_properties_t>>,
+    float_atomic_properties: Option<Box<l0::sys::ze_float_atomic_ext_properties_t>>,
+    shader_clock: Option<bool>,
+    stack_calls: Option<bool>,
 }
 
 unsafe impl Send for Device {}
@@ -51,6 +111,9 @@ impl Device {
             image_properties: None,
             memory_properties: None,
             compute_properties: None,
+            float_atomic_properties: None,
+            shader_clock: None,
+            stack_calls: None,
         })
     }
 
@@ -94,10 +157,78 @@ impl Device {
         }
     }
 
+    fn get_float_atomic_properties(
+        &mut self,
+    ) -> l0::Result<&l0::sys::ze_float_atomic_ext_properties_t> {
+        if let Some(ref prop) = self.float_atomic_properties {
+            return Ok(prop);
+        }
+        match self.base.get_float_atomic_properties() {
+            Ok(prop) => Ok(self.float_atomic_properties.get_or_insert(prop)),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Level Zero does not report which SPIR-V extensions a device accepts, so
+    // we build a kernel that reads the clock and see if the driver takes it
+    pub fn supports_shader_clock(&mut self) -> bool {
+        if let Some(supported) = self.shader_clock {
+            return supported;
+        }
+        let supported = self.builds_probe(SHADER_CLOCK_PROBE_PTX);
+        *self.shader_clock.get_or_insert(supported)
+    }
+
+    // Same for stack calls, recursive functions can't be inlined and only
+    // build on devices that support them
+    pub fn supports_stack_calls(&mut self) -> bool {
+        if let Some(supported) = self.stack_calls {
+            return supported;
+        }
+        let supported = self.builds_probe(STACK_CALLS_PROBE_PTX);
+        *self.stack_calls.get_or_insert(supported)
+    }
+
+    fn builds_probe(&mut self, ptx_text: &str) -> bool {
+        module::SpirvModule::new_with_options(ptx_text, ptx::TranslateOptions::default())
+            .and_then(|spirv_module| spirv_module.compile(&mut self.l0_context, &self.base))
+            .is_ok()
+    }
+
     pub fn late_init(&mut self) {
         self.primary_context.as_option_mut().unwrap().device = self as *mut _;
     }
 
+    pub fn get_max_shared_local_memory(&mut self) -> l0::Result<u32> {
+        Ok(self.get_compute_properties()?.maxSharedLocalMemory)
+    }
+
+    // Streaming Multiprocessor corresponds roughly to a sub-slice (thread group can't cross either)
+    pub fn get_multiprocessor_count(&mut self) -> l0::Result<u32> {
+        let props = self.get_properties()?;
+        Ok(props.numSlices * props.numSubslicesPerSlice)
+    }
+
+    // PTX atomics on generic pointers can hit either global or shared memory,
+    // so float atomic add is native only if the device has it for both. If the
+    // driver can't report fp atomic support we use the emulated variants
+    pub fn get_translate_options(&mut self) -> ptx::TranslateOptions {
+        let props = match self.get_float_atomic_properties() {
+            Ok(props) => props,
+            Err(_) => return ptx::TranslateOptions::default(),
+        };
+        let supports_add = |flags: l0::sys::ze_device_fp_atomic_ext_flags_t| {
+            let add = l0::sys::ze_device_fp_atomic_ext_flags_t::ZE_DEVICE_FP_ATOMIC_EXT_FLAG_GLOBAL_ADD
+                | l0::sys::ze_device_fp_atomic_ext_flags_t::ZE_DEVICE_FP_ATOMIC_EXT_FLAG_LOCAL_ADD;
+            flags & add == add
+        };
+        ptx::TranslateOptions {
+            atomic_float32_add: supports_add(props.fp32Flags),
+            atomic_float64_add: supports_add(props.fp64Flags),
+            ..ptx::TranslateOptions::default()
+        }
+    }
+
     fn get_max_simd(&mut self) -> l0::Result<u32> {
         let props = self.get_compute_properties()?;
         Ok(*props.subGroupSizes[0..props.numSubGroupSizes as usize]
@@ -108,7 +239,7 @@ impl Device {
 }
 
 pub fn init(driver: &l0::Driver) -> Result<Vec<Device>, CUresult> {
-    let ze_devices = driver.devices()?;
+    let ze_devices = select_devices(driver.devices()?, env::var_os(DEVICE_FILTER_ENV));
     let mut devices = ze_devices
         .into_iter()
         .enumerate()
@@ -121,6 +252,41 @@ pub fn init(driver: &l0::Driver) -> Result<Vec<Device>, CUresult> {
     Ok(devices)
 }
 
+// ZLUDA_DEVICE is a comma-separated list of Level Zero device indices, similar
+// to CUDA_VISIBLE_DEVICES. Devices are filtered before they get their CUDA
+// ordinals, so cuDeviceGetCount and cuDeviceGet only see the selected ones.
+// A setting we can't parse is reported and ignored, indices past the last
+// device are skipped
+fn select_devices<T>(devices: Vec<T>, setting: Option<OsString>) -> Vec<T> {
+    let setting = match setting {
+        Some(setting) => setting,
+        None => return devices,
+    };
+    let indices = match parse_device_filter(&setting) {
+        Some(indices) => indices,
+        None => {
+            eprintln!(
+                "[ZLUDA] Ignoring {}={:?}, expected a comma-separated list of device indices",
+                DEVICE_FILTER_ENV, setting
+            );
+            return devices;
+        }
+    };
+    let mut devices = devices.into_iter().map(Some).collect::<Vec<_>>();
+    indices
+        .into_iter()
+        .filter_map(|idx| devices.get_mut(idx).and_then(Option::take))
+        .collect()
+}
+
+fn parse_device_filter(setting: &OsStr) -> Option<Vec<usize>> {
+    setting
+        .to_str()?
+        .split(',')
+        .map(|idx| idx.trim().parse::<usize>().ok())
+        .collect()
+}
+
 pub fn get_count(count: *mut c_int) -> Result<(), CUresult> {
     let len = GlobalState::lock(|state| state.devices.len())?;
     unsafe { *count = len as c_int };
@@ -151,31 +317,63 @@ pub fn get_name(name: *mut c_char, len: i32, dev_idx: Index) -> Result<(), CUres
     let name_len = (0..256)
         .position(|i| unsafe { *name_ptr.add(i) } == 0)
         .unwrap_or(256);
-    let mut dst_null_pos = cmp::min((len - 1) as usize, name_len);
-    unsafe { std::ptr::copy_nonoverlapping(name_ptr, name, dst_null_pos) };
-    if name_len + PROJECT_URL_SUFFIX_LONG.len() < (len as usize) {
-        unsafe {
-            std::ptr::copy_nonoverlapping(
-                PROJECT_URL_SUFFIX_LONG.as_ptr(),
-                name.add(name_len) as *mut _,
-                PROJECT_URL_SUFFIX_LONG.len(),
-            )
-        };
-        dst_null_pos += PROJECT_URL_SUFFIX_LONG.len();
-    } else if name_len + PROJECT_URL_SUFFIX_SHORT.len() < (len as usize) {
-        unsafe {
-            std::ptr::copy_nonoverlapping(
-                PROJECT_URL_SUFFIX_SHORT.as_ptr(),
-                name.add(name_len) as *mut _,
-                PROJECT_URL_SUFFIX_SHORT.len(),
-            )
-        };
-        dst_null_pos += PROJECT_URL_SUFFIX_SHORT.len();
-    }
-    unsafe { *(name.add(dst_null_pos)) = 0 };
+    let device_name = unsafe { slice::from_raw_parts(name_ptr as *const u8, name_len) };
+    let (device_name, suffixes) = reported_name(
+        device_name,
+        env::var_os(DEVICE_NAME_ENV),
+        env::var_os(DEVICE_NAME_SUFFIX_ENV),
+    );
+    unsafe { write_name(name, len as usize, &device_name, &suffixes) };
     Ok(())
 }
 
+// ZLUDA_DEVICE_NAME replaces the whole name (e.g. "GeForce RTX 3080") for
+// applications that only accept known GPUs, so no suffix is appended to it
+fn reported_name(
+    device_name: &[u8],
+    name_override: Option<OsString>,
+    suffix_setting: Option<OsString>,
+) -> (Cow<[u8]>, Vec<Cow<'static, str>>) {
+    match name_override {
+        Some(spoofed_name) => (
+            Cow::Owned(spoofed_name.to_string_lossy().into_owned().into_bytes()),
+            Vec::new(),
+        ),
+        None => (Cow::Borrowed(device_name), name_suffixes(suffix_setting)),
+    }
+}
+
+// ZLUDA_DEVICE_NAME_SUFFIX=0 (or empty) removes the suffix for applications
+// that match on the exact device name, any other value except 1 replaces it
+fn name_suffixes(setting: Option<OsString>) -> Vec<Cow<'static, str>> {
+    match setting.as_ref().and_then(|s| s.to_str()) {
+        None | Some("1") => vec![
+            Cow::Borrowed(PROJECT_URL_SUFFIX_LONG),
+            Cow::Borrowed(PROJECT_URL_SUFFIX_SHORT),
+        ],
+        Some("0") | Some("") => Vec::new(),
+        Some(custom) => vec![Cow::Owned(custom.to_string())],
+    }
+}
+
+// Writes the device name followed by the first suffix that fits
+unsafe fn write_name(name: *mut c_char, len: usize, device_name: &[u8], suffixes: &[Cow<str>]) {
+    let mut dst_null_pos = cmp::min(len - 1, device_name.len());
+    ptr::copy_nonoverlapping(device_name.as_ptr(), name as *mut u8, dst_null_pos);
+    if let Some(suffix) = suffixes
+        .iter()
+        .find(|suffix| device_name.len() + suffix.len() < len)
+    {
+        ptr::copy_nonoverlapping(
+            suffix.as_ptr(),
+            name.add(device_name.len()) as *mut _,
+            suffix.len(),
+        );
+        dst_null_pos += suffix.len();
+    }
+    *(name.add(dst_null_pos)) = 0;
+}
+
 pub fn total_mem_v2(bytes: *mut usize, dev_idx: Index) -> Result<(), CUresult> {
     if bytes == ptr::null_mut() {
         return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
@@ -204,6 +402,13 @@ impl CUdevice_attribute {
             CUdevice_attribute::CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR => Some(8),
             CUdevice_attribute::CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR => Some(0),
             CUdevice_attribute::CU_DEVICE_ATTRIBUTE_CAN_MAP_HOST_MEMORY => Some(1),
+            // Not a Windows TCC driver and not part of a multi-GPU board
+            CUdevice_attribute::CU_DEVICE_ATTRIBUTE_TCC_DRIVER => Some(0),
+            CUdevice_attribute::CU_DEVICE_ATTRIBUTE_MULTI_GPU_BOARD => Some(0),
+            // TODO: Level 0 core API does not report PCI location
+            CUdevice_attribute::CU_DEVICE_ATTRIBUTE_PCI_BUS_ID => Some(0),
+            CUdevice_attribute::CU_DEVICE_ATTRIBUTE_PCI_DEVICE_ID => Some(0),
+            CUdevice_attribute::CU_DEVICE_ATTRIBUTE_PCI_DOMAIN_ID => Some(0),
             _ => None,
         }
     }
@@ -228,11 +433,9 @@ pub fn get_attribute(
                 Ok::<_, l0::sys::ze_result_t>(props.maxHardwareContexts as i32)
             })??
         }
-        // Streaming Multiprocessor corresponds roughly to a sub-slice (thread group can't cross either)
         CUdevice_attribute::CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT => {
             GlobalState::lock_device(dev_idx, |dev| {
-                let props = dev.get_properties()?;
-                Ok::<_, l0::sys::ze_result_t>((props.numSlices * props.numSubslicesPerSlice) as i32)
+                Ok::<_, l0::sys::ze_result_t>(dev.get_multiprocessor_count()? as i32)
             })??
         }
         // I honestly don't know how to answer this query
@@ -320,6 +523,14 @@ pub fn get_attribute(
                 Ok::<_, l0::sys::ze_result_t>(props.maxSharedLocalMemory as i32)
             })??
         }
+        CUdevice_attribute::CU_DEVICE_ATTRIBUTE_ECC_ENABLED => {
+            GlobalState::lock_device(dev_idx, |dev| {
+                let props = dev.get_properties()?;
+                let ecc =
+                    props.flags & l0::sys::ze_device_property_flags_t::ZE_DEVICE_PROPERTY_FLAG_ECC;
+                Ok::<_, l0::sys::ze_result_t>(if ecc.0 != 0 { 1 } else { 0 })
+            })??
+        }
         CUdevice_attribute::CU_DEVICE_ATTRIBUTE_WARP_SIZE => {
             GlobalState::lock_device(dev_idx, |dev| Ok::<_, CUresult>(dev.get_max_simd()? as i32))??
         }
@@ -397,6 +608,83 @@ pub(crate) fn primary_ctx_release_v2(_dev_idx: Index) -> CUresult {
 mod test {
     use super::super::test::CudaDriverFns;
     use super::super::CUresult;
+    use super::{reported_name, select_devices, write_name};
+    use crate::cuda::{CUdevice_attribute, CUuuid};
+    use std::ffi::CStr;
+    use std::os::raw::c_char;
+
+    fn written_name(name_override: Option<&str>, suffix_setting: Option<&str>) -> String {
+        let mut buffer = [0x7f as c_char; 64];
+        let (device_name, suffixes) = reported_name(
+            b"Intel(R) Graphics",
+            name_override.map(Into::into),
+            suffix_setting.map(Into::into),
+        );
+        unsafe {
+            write_name(buffer.as_mut_ptr(), buffer.len(), &device_name, &suffixes);
+            CStr::from_ptr(buffer.as_ptr())
+                .to_str()
+                .unwrap()
+                .to_string()
+        }
+    }
+
+    #[test]
+    fn device_filter_selects_ordinals() {
+        let devices = || vec!["gpu0", "gpu1", "gpu2"];
+        assert_eq!(select_devices(devices(), None), devices());
+        let filtered = select_devices(devices(), Some("1".into()));
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0], "gpu1");
+        assert_eq!(
+            select_devices(devices(), Some("2, 0, 7".into())),
+            vec!["gpu2", "gpu0"]
+        );
+    }
+
+    #[test]
+    fn unparsable_device_filter_is_ignored() {
+        let devices = || vec!["gpu0", "gpu1", "gpu2"];
+        assert_eq!(select_devices(devices(), Some("1,gpu2".into())), devices());
+        assert_eq!(select_devices(devices(), Some("-1".into())), devices());
+        assert_eq!(select_devices(devices(), Some("".into())), devices());
+    }
+
+    #[test]
+    fn device_name_suffix_can_be_disabled() {
+        assert_eq!(
+            written_name(None, None),
+            "Intel(R) Graphics [github.com/vosen/ZLUDA]"
+        );
+        assert_eq!(written_name(None, Some("0")), "Intel(R) Graphics");
+        assert_eq!(
+            written_name(None, Some(" (custom)")),
+            "Intel(R) Graphics (custom)"
+        );
+    }
+
+    #[test]
+    fn device_name_can_be_overridden() {
+        assert_eq!(
+            written_name(Some("GeForce RTX 3080"), None),
+            "GeForce RTX 3080"
+        );
+        assert_eq!(
+            written_name(Some("GeForce RTX 3080"), Some(" (custom)")),
+            "GeForce RTX 3080"
+        );
+    }
+
+    #[test]
+    fn overridden_device_name_is_truncated() {
+        let mut buffer = [0x7f as c_char; 8];
+        let (device_name, suffixes) =
+            reported_name(b"Intel(R) Graphics", Some("GeForce RTX 3080".into()), None);
+        unsafe {
+            write_name(buffer.as_mut_ptr(), buffer.len(), &device_name, &suffixes);
+            assert_eq!(CStr::from_ptr(buffer.as_ptr()).to_str().unwrap(), "GeForce");
+        }
+    }
 
     cuda_driver_test!(primary_ctx_default_inactive);
 
@@ -411,4 +699,44 @@ mod test {
         assert_eq!(flags, 0);
         assert_eq!(active, 0);
     }
+
+    cuda_driver_test!(uuid_v2_matches_uuid);
+
+    fn uuid_v2_matches_uuid<T: CudaDriverFns>() {
+        assert_eq!(T::cuInit(0), CUresult::CUDA_SUCCESS);
+        let mut uuid = CUuuid { bytes: [0; 16] };
+        let mut uuid_v2 = CUuuid { bytes: [0xff; 16] };
+        assert_eq!(T::cuDeviceGetUuid(&mut uuid, 0), CUresult::CUDA_SUCCESS);
+        assert_eq!(
+            T::cuDeviceGetUuid_v2(&mut uuid_v2, 0),
+            CUresult::CUDA_SUCCESS
+        );
+        assert_eq!(uuid.bytes, uuid_v2.bytes);
+    }
+
+    cuda_driver_test!(boolean_attributes_are_defined);
+
+    fn boolean_attributes_are_defined<T: CudaDriverFns>() {
+        assert_eq!(T::cuInit(0), CUresult::CUDA_SUCCESS);
+        let mut tcc = -1;
+        assert_eq!(
+            T::cuDeviceGetAttribute(
+                &mut tcc,
+                CUdevice_attribute::CU_DEVICE_ATTRIBUTE_TCC_DRIVER,
+                0
+            ),
+            CUresult::CUDA_SUCCESS
+        );
+        assert_eq!(tcc, 0);
+        let mut ecc = -1;
+        assert_eq!(
+            T::cuDeviceGetAttribute(
+                &mut ecc,
+                CUdevice_attribute::CU_DEVICE_ATTRIBUTE_ECC_ENABLED,
+                0
+            ),
+            CUresult::CUDA_SUCCESS
+        );
+        assert!(ecc == 0 || ecc == 1);
+    }
 }
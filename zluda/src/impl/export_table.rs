@@ -213,14 +213,45 @@ unsafe extern "C" fn get_module_from_cubin(
         return CUresult::CUDA_ERROR_INVALID_VALUE;
     }
     let result = result.decuda();
-    let fatbin_header = (*fatbinc_wrapper).data;
+    load_fatbin_impl(result, (*fatbinc_wrapper).data, None)
+}
+
+// Returns None if the image is neither a fatbin nor a fatbin wrapper
+pub(crate) unsafe fn load_fatbin(
+    result: *mut *mut module::Module,
+    image: *const c_void,
+    jit_target: Option<c_uint>,
+) -> Option<CUresult> {
+    let fatbin_header = match *(image as *const c_uint) {
+        FATBINC_MAGIC => {
+            let fatbinc_wrapper = image as *const FatbincWrapper;
+            if (*fatbinc_wrapper).version != FATBINC_VERSION {
+                return Some(CUresult::CUDA_ERROR_INVALID_VALUE);
+            }
+            (*fatbinc_wrapper).data
+        }
+        FATBIN_MAGIC => image as *const FatbinHeader,
+        _ => return None,
+    };
+    Some(load_fatbin_impl(result, fatbin_header, jit_target))
+}
+
+unsafe fn load_fatbin_impl(
+    result: *mut *mut module::Module,
+    fatbin_header: *const FatbinHeader,
+    jit_target: Option<c_uint>,
+) -> CUresult {
     if (*fatbin_header).magic != FATBIN_MAGIC || (*fatbin_header).version != FATBIN_VERSION {
         return CUresult::CUDA_ERROR_INVALID_VALUE;
     }
     let file = (fatbin_header as *const u8).add((*fatbin_header).header_size as usize);
     let end = file.add((*fatbin_header).files_size as usize);
     let mut ptx_files = get_ptx_files(file, end);
-    ptx_files.sort_unstable_by_key(|f| c_uint::max_value() - (**f).sm_version);
+    sort_ptx_files(&mut ptx_files, jit_target);
+    let options = match module::current_translate_options() {
+        Ok(options) => options,
+        Err(err) => return err,
+    };
     for file in ptx_files {
         let kernel_text = match decompress_kernel_module(file) {
             None => continue,
@@ -233,7 +264,7 @@ unsafe extern "C" fn get_module_from_cubin(
             },
             Err(_) => continue,
         };
-        let module = module::SpirvModule::new(kernel_text_string);
+        let module = module::SpirvModule::new_with_options(kernel_text_string, options);
         match module {
             Ok(module) => {
                 match module::load_data_impl(result, module) {
@@ -248,6 +279,17 @@ unsafe extern "C" fn get_module_from_cubin(
     CUresult::CUDA_ERROR_COMPAT_NOT_SUPPORTED_ON_DEVICE
 }
 
+// PTX for the requested target goes first, the rest is ordered from the highest SM version
+unsafe fn sort_ptx_files(ptx_files: &mut [*const FatbinFileHeader], jit_target: Option<c_uint>) {
+    ptx_files.sort_unstable_by_key(|f| {
+        let sm_version = (**f).sm_version;
+        (
+            Some(sm_version) != jit_target,
+            c_uint::max_value() - sm_version,
+        )
+    });
+}
+
 unsafe fn get_ptx_files(file: *const u8, end: *const u8) -> Vec<*const FatbinFileHeader> {
     let mut index = file;
     let mut result = Vec::new();
@@ -396,3 +438,61 @@ fn lock_context<T>(
         })?
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        get_ptx_files, sort_ptx_files, FatbinFileHeader, FATBIN_FILE_HEADER_KIND_PTX,
+        FATBIN_FILE_HEADER_VERSION_CURRENT,
+    };
+    use std::{mem, os::raw::c_uint};
+
+    fn ptx_file_header(sm_version: c_uint) -> FatbinFileHeader {
+        FatbinFileHeader {
+            kind: FATBIN_FILE_HEADER_KIND_PTX,
+            version: FATBIN_FILE_HEADER_VERSION_CURRENT,
+            header_size: mem::size_of::<FatbinFileHeader>() as c_uint,
+            padded_payload_size: 0,
+            unknown0: 0,
+            payload_size: 0,
+            unknown1: 0,
+            unknown2: 0,
+            sm_version,
+            bit_width: 64,
+            unknown3: 0,
+            unknown4: 0,
+            unknown5: 0,
+            uncompressed_payload: 0,
+        }
+    }
+
+    fn selection_order(sm_versions: &[c_uint], jit_target: Option<c_uint>) -> Vec<c_uint> {
+        let files = sm_versions
+            .iter()
+            .copied()
+            .map(ptx_file_header)
+            .collect::<Vec<_>>();
+        unsafe {
+            let start = files.as_ptr() as *const u8;
+            let end = start.add(files.len() * mem::size_of::<FatbinFileHeader>());
+            let mut ptx_files = get_ptx_files(start, end);
+            sort_ptx_files(&mut ptx_files, jit_target);
+            ptx_files.iter().map(|f| (**f).sm_version).collect()
+        }
+    }
+
+    #[test]
+    fn highest_sm_ptx_is_selected_by_default() {
+        assert_eq!(selection_order(&[52, 70, 61], None), vec![70, 61, 52]);
+    }
+
+    #[test]
+    fn jit_target_selects_matching_sm_ptx() {
+        assert_eq!(selection_order(&[52, 70, 61], Some(61)), vec![61, 70, 52]);
+    }
+
+    #[test]
+    fn unmatched_jit_target_falls_back_to_highest_sm_ptx() {
+        assert_eq!(selection_order(&[52, 70, 61], Some(75)), vec![70, 61, 52]);
+    }
+}
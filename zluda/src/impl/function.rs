@@ -1,11 +1,19 @@
-use super::{stream::Stream, CUresult, GlobalState, HasLivenessCookie, LiveCheck};
-use crate::cuda::CUfunction_attribute;
+use super::{
+    printf,
+    stream::Stream,
+    texobj::{TexObject, Texture},
+    texref::TexRef,
+    CUresult, GlobalState, HasLivenessCookie, LiveCheck,
+};
+use crate::cuda::{CUfunction_attribute, CUtexObject};
 use ::std::os::raw::{c_uint, c_void};
 use std::{hint, ptr};
 
 const CU_LAUNCH_PARAM_END: *mut c_void = 0 as *mut _;
 const CU_LAUNCH_PARAM_BUFFER_POINTER: *mut c_void = 1 as *mut _;
 const CU_LAUNCH_PARAM_BUFFER_SIZE: *mut c_void = 2 as *mut _;
+// Kernels have to explicitly opt in to use more dynamic shared memory than this
+pub const DEFAULT_MAX_DYNAMIC_SHARED_SIZE_BYTES: c_uint = 48 * 1024;
 
 pub type Function = LiveCheck<FunctionData>;
 
@@ -27,8 +35,18 @@ pub struct FunctionData {
     pub base: l0::Kernel<'static>,
     pub arg_size: Vec<usize>,
     pub use_shared_mem: bool,
+    pub textures: Vec<TextureArgument>,
     pub properties: Option<Box<l0::sys::ze_kernel_properties_t>>,
     pub legacy_args: LegacyArguments,
+    pub max_dynamic_shared_size_bytes: c_uint,
+}
+
+// Where the texture behind each image and sampler argument pair comes from,
+// see ptx::KernelTexture
+pub enum TextureArgument {
+    // Index of the kernel argument holding the texture object
+    Object(usize),
+    Texref(*mut TexRef),
 }
 
 pub struct LegacyArguments {
@@ -81,14 +99,20 @@ pub fn launch_kernel(
     {
         return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
     }
-    GlobalState::lock_stream(hstream, |stream| {
+    let ctx = GlobalState::lock_stream(hstream, |stream| {
         let func: &mut FunctionData = unsafe { &mut *f }.as_result_mut()?;
+        let dev = unsafe { &mut *(*stream.context).device };
+        if shared_mem_bytes > func.max_dynamic_shared_size_bytes
+            || shared_mem_bytes > dev.get_max_shared_local_memory()?
+        {
+            return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+        }
+        let mut arg_values = Vec::with_capacity(func.arg_size.len());
         if kernel_params != ptr::null_mut() {
             for (i, arg_size) in func.arg_size.iter().enumerate() {
-                unsafe {
-                    func.base
-                        .set_arg_raw(i as u32, *arg_size, *kernel_params.add(i))?
-                };
+                let arg_value = unsafe { *kernel_params.add(i) };
+                unsafe { func.base.set_arg_raw(i as u32, *arg_size, arg_value)? };
+                arg_values.push(arg_value as *const c_void);
             }
         } else {
             let mut offset = 0;
@@ -119,13 +143,9 @@ pub fn launch_kernel(
                     let mut offset = 0;
                     for (i, arg_size) in func.arg_size.iter().enumerate() {
                         let buffer_offset = round_up_to_multiple(offset, *arg_size);
-                        unsafe {
-                            func.base.set_arg_raw(
-                                i as u32,
-                                *arg_size,
-                                buffer_ptr.add(buffer_offset) as *const _,
-                            )?
-                        };
+                        let arg_value = unsafe { buffer_ptr.add(buffer_offset) as *const c_void };
+                        unsafe { func.base.set_arg_raw(i as u32, *arg_size, arg_value)? };
+                        arg_values.push(arg_value);
                         offset = buffer_offset + *arg_size;
                     }
                 }
@@ -141,10 +161,36 @@ pub fn launch_kernel(
                 )?
             };
         }
+        let textures = func
+            .textures
+            .iter()
+            .map(|texture| get_texture(texture, &arg_values))
+            .collect::<Result<Vec<_>, _>>()?;
+        let first_texture_arg = func.arg_size.len() + func.use_shared_mem as usize;
+        for (i, texture) in textures.iter().enumerate() {
+            let image_arg = (first_texture_arg + 2 * i) as u32;
+            func.base.set_arg_image(image_arg, &texture.image)?;
+            func.base.set_arg_sampler(image_arg + 1, &texture.sampler)?;
+        }
         func.base
             .set_group_size(block_dim_x, block_dim_y, block_dim_z)?;
         func.legacy_args.reset();
         let mut cmd_list = stream.command_list()?;
+        // Images are snapshots of the memory the textures were created over,
+        // they are refreshed right before the kernel runs
+        for texture in textures.iter() {
+            unsafe {
+                cmd_list.append_image_copy_from_memory(
+                    &texture.image,
+                    texture.address,
+                    None,
+                    &mut [],
+                )?
+            };
+        }
+        if !textures.is_empty() {
+            cmd_list.append_barrier(None, &mut [])?;
+        }
         cmd_list.append_launch_kernel(
             &mut func.base,
             &[grid_dim_x, grid_dim_y, grid_dim_z],
@@ -152,8 +198,30 @@ pub fn launch_kernel(
             &mut [],
         )?;
         stream.queue.execute(cmd_list)?;
-        Ok(())
-    })?
+        Ok(stream.context)
+    })??;
+    // Launches are synchronous, so whatever the kernel printed is already there
+    GlobalState::lock(|_| printf::flush(unsafe { &mut *ctx }))?
+}
+
+fn get_texture<'a>(
+    texture: &TextureArgument,
+    arg_values: &[*const c_void],
+) -> Result<&'a Texture, CUresult> {
+    match texture {
+        TextureArgument::Object(index) => {
+            let handle = unsafe { *(arg_values[*index] as *const CUtexObject) };
+            if handle == 0 {
+                return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+            }
+            unsafe { &*(handle as *mut TexObject) }.as_result()
+        }
+        TextureArgument::Texref(texref) => unsafe { &**texref }
+            .as_result()?
+            .texture
+            .as_ref()
+            .ok_or(CUresult::CUDA_ERROR_INVALID_VALUE),
+    }
 }
 
 fn round_up_to_multiple(x: usize, multiple: usize) -> usize {
@@ -177,6 +245,36 @@ pub(crate) fn get_attribute(
             unsafe { *pi = max_threads as i32 };
             Ok(())
         }
+        CUfunction_attribute::CU_FUNC_ATTRIBUTE_MAX_DYNAMIC_SHARED_SIZE_BYTES => {
+            let max_shared =
+                GlobalState::lock_function(func, |func| func.max_dynamic_shared_size_bytes)?;
+            unsafe { *pi = max_shared as i32 };
+            Ok(())
+        }
+        _ => Err(CUresult::CUDA_ERROR_NOT_SUPPORTED),
+    }
+}
+
+pub(crate) fn set_attribute(
+    func: *mut Function,
+    attrib: CUfunction_attribute,
+    value: i32,
+) -> Result<(), CUresult> {
+    if func == ptr::null_mut() {
+        return Err(CUresult::CUDA_ERROR_INVALID_HANDLE);
+    }
+    match attrib {
+        CUfunction_attribute::CU_FUNC_ATTRIBUTE_MAX_DYNAMIC_SHARED_SIZE_BYTES => {
+            GlobalState::lock_current_context(|ctx| {
+                let func = unsafe { &mut *func }.as_result_mut()?;
+                let dev = unsafe { &mut *ctx.device };
+                if value < 0 || value as u32 > dev.get_max_shared_local_memory()? {
+                    return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+                }
+                func.max_dynamic_shared_size_bytes = value as c_uint;
+                Ok(())
+            })?
+        }
         _ => Err(CUresult::CUDA_ERROR_NOT_SUPPORTED),
     }
 }
@@ -189,3 +287,114 @@ pub(crate) fn set_block_shape(func: *mut Function, x: i32, y: i32, z: i32) -> Re
         func.legacy_args.block_shape = Some((x, y, z));
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::super::test::CudaDriverFns;
+    use super::super::CUresult;
+    use crate::cuda::CUfunction_attribute;
+    use std::{ffi::c_void, ptr};
+
+    const NOOP_PTX: &'static str = "
+        .version 6.5
+        .target sm_30
+        .address_size 64
+
+        .visible .entry noop()
+        {
+            ret;
+        }\0";
+
+    cuda_driver_test!(dynamic_shared_memory_requires_opt_in);
+
+    fn dynamic_shared_memory_requires_opt_in<T: CudaDriverFns>() {
+        const MAX_DYNAMIC_SHARED: CUfunction_attribute =
+            CUfunction_attribute::CU_FUNC_ATTRIBUTE_MAX_DYNAMIC_SHARED_SIZE_BYTES;
+        assert_eq!(T::cuInit(0), CUresult::CUDA_SUCCESS);
+        let mut ctx = ptr::null_mut();
+        assert_eq!(T::cuCtxCreate_v2(&mut ctx, 0, 0), CUresult::CUDA_SUCCESS);
+        let mut module = ptr::null_mut();
+        assert_eq!(
+            T::cuModuleLoadData(&mut module, NOOP_PTX.as_ptr() as *const _),
+            CUresult::CUDA_SUCCESS
+        );
+        let mut func = ptr::null_mut();
+        assert_eq!(
+            T::cuModuleGetFunction(&mut func, module, b"noop\0".as_ptr() as *const _),
+            CUresult::CUDA_SUCCESS
+        );
+        let mut limit = 0;
+        assert_eq!(
+            T::cuFuncGetAttribute(&mut limit, MAX_DYNAMIC_SHARED, func),
+            CUresult::CUDA_SUCCESS
+        );
+        assert_eq!(limit, 48 * 1024);
+        let mut params: [*mut c_void; 0] = [];
+        assert_eq!(
+            T::cuLaunchKernel(
+                func,
+                1,
+                1,
+                1,
+                1,
+                1,
+                1,
+                limit as u32 + 1,
+                ptr::null_mut(),
+                params.as_mut_ptr(),
+                ptr::null_mut()
+            ),
+            CUresult::CUDA_ERROR_INVALID_VALUE
+        );
+        // Opt-in is still bounded by the device
+        assert_eq!(
+            T::cuFuncSetAttribute(func, MAX_DYNAMIC_SHARED, i32::max_value()),
+            CUresult::CUDA_ERROR_INVALID_VALUE
+        );
+        let new_limit = 1024;
+        assert_eq!(
+            T::cuFuncSetAttribute(func, MAX_DYNAMIC_SHARED, new_limit),
+            CUresult::CUDA_SUCCESS
+        );
+        assert_eq!(
+            T::cuFuncGetAttribute(&mut limit, MAX_DYNAMIC_SHARED, func),
+            CUresult::CUDA_SUCCESS
+        );
+        assert_eq!(limit, new_limit);
+        assert_eq!(
+            T::cuLaunchKernel(
+                func,
+                1,
+                1,
+                1,
+                1,
+                1,
+                1,
+                new_limit as u32 + 1,
+                ptr::null_mut(),
+                params.as_mut_ptr(),
+                ptr::null_mut()
+            ),
+            CUresult::CUDA_ERROR_INVALID_VALUE
+        );
+        assert_eq!(
+            T::cuLaunchKernel(
+                func,
+                1,
+                1,
+                1,
+                1,
+                1,
+                1,
+                new_limit as u32,
+                ptr::null_mut(),
+                params.as_mut_ptr(),
+                ptr::null_mut()
+            ),
+            CUresult::CUDA_SUCCESS
+        );
+        // Cleanup
+        assert_eq!(T::cuModuleUnload(module), CUresult::CUDA_SUCCESS);
+        assert_eq!(T::cuCtxDestroy_v2(ctx), CUresult::CUDA_SUCCESS);
+    }
+}
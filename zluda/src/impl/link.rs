@@ -0,0 +1,246 @@
+use super::{module::SpirvModule, CUresult, HasLivenessCookie, LiveCheck};
+use crate::cuda::{CUjitInputType, CUjit_option};
+use std::{
+    ffi::{c_void, CStr},
+    fs,
+    os::raw::{c_char, c_uint},
+    ptr, slice, str,
+};
+
+pub type LinkState = LiveCheck<LinkStateData>;
+
+impl HasLivenessCookie for LinkStateData {
+    #[cfg(target_pointer_width = "64")]
+    const COOKIE: usize = 0x9a5c0e2f61d8b347;
+
+    #[cfg(target_pointer_width = "32")]
+    const COOKIE: usize = 0x3e8f51a6;
+
+    const LIVENESS_FAIL: CUresult = CUresult::CUDA_ERROR_INVALID_HANDLE;
+
+    fn try_drop(&mut self) -> Result<(), CUresult> {
+        Ok(())
+    }
+}
+
+pub struct LinkStateData {
+    ptx_inputs: Vec<String>,
+    // Owned by the link state, the pointer returned from cuLinkComplete is
+    // valid until cuLinkDestroy
+    cubin: Vec<u8>,
+}
+
+// Real cubins are ELF files with native code. Ours is the linked PTX text
+// (NUL-terminated) behind this magic, module::load_data recognizes it and
+// translates the text like any other PTX module
+const ZLUDA_CUBIN_MAGIC: &[u8] = b"ZLUDA_CUBIN\0";
+
+// Returns the PTX text of a cubin created by cuLinkComplete. Compares byte by
+// byte, so we never read past the end of a short NUL-terminated PTX image
+pub(crate) unsafe fn get_cubin_ptx(image: *const c_void) -> Option<*const c_char> {
+    let image = image as *const u8;
+    let is_cubin = ZLUDA_CUBIN_MAGIC
+        .iter()
+        .enumerate()
+        .all(|(i, c)| *image.add(i) == *c);
+    if is_cubin {
+        Some(image.add(ZLUDA_CUBIN_MAGIC.len()) as *const c_char)
+    } else {
+        None
+    }
+}
+
+// Linker options (log buffers, optimization level, etc.) are ignored
+pub(crate) fn create(
+    num_options: c_uint,
+    options: *mut CUjit_option,
+    option_values: *mut *mut c_void,
+    state_out: *mut *mut LinkState,
+) -> Result<(), CUresult> {
+    if state_out == ptr::null_mut()
+        || (num_options > 0 && (options == ptr::null_mut() || option_values == ptr::null_mut()))
+    {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    let state = LinkState::new(LinkStateData {
+        ptx_inputs: Vec::new(),
+        cubin: Vec::new(),
+    });
+    unsafe { *state_out = Box::into_raw(Box::new(state)) };
+    Ok(())
+}
+
+pub(crate) fn add_data(
+    state: *mut LinkState,
+    input_type: CUjitInputType,
+    data: *mut c_void,
+    size: usize,
+) -> Result<(), CUresult> {
+    if state == ptr::null_mut() || data == ptr::null_mut() {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    let state = unsafe { &mut *state }.as_result_mut()?;
+    let bytes = unsafe { slice::from_raw_parts(data as *const u8, size) };
+    state.add_input(input_type, bytes)
+}
+
+pub(crate) fn add_file(
+    state: *mut LinkState,
+    input_type: CUjitInputType,
+    path: *const c_char,
+) -> Result<(), CUresult> {
+    if state == ptr::null_mut() || path == ptr::null() {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    let state = unsafe { &mut *state }.as_result_mut()?;
+    let path = unsafe { CStr::from_ptr(path) }
+        .to_str()
+        .map_err(|_| CUresult::CUDA_ERROR_INVALID_VALUE)?;
+    let bytes = fs::read(path).map_err(|_| CUresult::CUDA_ERROR_FILE_NOT_FOUND)?;
+    state.add_input(input_type, &bytes)
+}
+
+pub(crate) fn complete(
+    state: *mut LinkState,
+    cubin_out: *mut *mut c_void,
+    size_out: *mut usize,
+) -> Result<(), CUresult> {
+    if state == ptr::null_mut() || cubin_out == ptr::null_mut() || size_out == ptr::null_mut() {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    let state = unsafe { &mut *state }.as_result_mut()?;
+    let ptx = state.link()?;
+    // Report translation errors now, like a linker would, and not when the
+    // cubin is loaded
+    SpirvModule::new(&ptx)?;
+    let mut cubin = ZLUDA_CUBIN_MAGIC.to_vec();
+    cubin.extend_from_slice(ptx.as_bytes());
+    cubin.push(0);
+    state.cubin = cubin;
+    unsafe {
+        *cubin_out = state.cubin.as_mut_ptr() as *mut _;
+        *size_out = state.cubin.len();
+    }
+    Ok(())
+}
+
+pub(crate) fn destroy(state: *mut LinkState) -> Result<(), CUresult> {
+    if state == ptr::null_mut() {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    // Link states don't belong to a context, no need to lock the global state
+    LinkState::destroy_impl(state)
+}
+
+impl LinkStateData {
+    fn add_input(&mut self, input_type: CUjitInputType, bytes: &[u8]) -> Result<(), CUresult> {
+        if input_type != CUjitInputType::CU_JIT_INPUT_PTX {
+            return Err(CUresult::CUDA_ERROR_NOT_SUPPORTED);
+        }
+        // Size of PTX input may or may not include the terminating NUL
+        let text = match bytes.iter().position(|c| *c == 0) {
+            Some(end) => &bytes[..end],
+            None => bytes,
+        };
+        let text = str::from_utf8(text).map_err(|_| CUresult::CUDA_ERROR_INVALID_PTX)?;
+        self.ptx_inputs.push(text.to_string());
+        Ok(())
+    }
+
+    // There is no real linking: inputs are concatenated into a single module,
+    // with the module header (.version, .target, .address_size) kept only from
+    // the first input
+    fn link(&self) -> Result<String, CUresult> {
+        let (first, rest) = match self.ptx_inputs.split_first() {
+            Some(inputs) => inputs,
+            None => return Err(CUresult::CUDA_ERROR_INVALID_VALUE),
+        };
+        let mut ptx = first.clone();
+        for input in rest {
+            ptx.push('\n');
+            for line in input.lines() {
+                if !is_module_header(line) {
+                    ptx.push_str(line);
+                    ptx.push('\n');
+                }
+            }
+        }
+        Ok(ptx)
+    }
+}
+
+fn is_module_header(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with(".version") || line.starts_with(".target") || line.starts_with(".address_size")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{get_cubin_ptx, LinkState};
+    use crate::cuda::{CUjitInputType, CUresult};
+    use std::{ffi::CStr, ptr, slice};
+
+    const PTX: &str = ".version 6.5
+.target sm_30
+.address_size 64
+
+.visible .entry add_one(
+    .param .u64 input
+)
+{
+    .reg .u64 in_addr;
+    .reg .u64 temp;
+
+    ld.param.u64 in_addr, [input];
+    ld.global.u64 temp, [in_addr];
+    add.u64 temp, temp, 1;
+    st.global.u64 [in_addr], temp;
+    ret;
+}
+";
+
+    #[test]
+    fn links_single_ptx_input() {
+        let mut state: *mut LinkState = ptr::null_mut();
+        super::create(0, ptr::null_mut(), ptr::null_mut(), &mut state).unwrap();
+        super::add_data(
+            state,
+            CUjitInputType::CU_JIT_INPUT_PTX,
+            PTX.as_ptr() as *mut _,
+            PTX.len(),
+        )
+        .unwrap();
+        let mut cubin = ptr::null_mut();
+        let mut size = 0;
+        super::complete(state, &mut cubin, &mut size).unwrap();
+        let ptx = unsafe { CStr::from_ptr(get_cubin_ptx(cubin).unwrap()) };
+        assert_eq!(ptx.to_str(), Ok(PTX));
+        assert_eq!(
+            unsafe { slice::from_raw_parts(cubin as *const u8, size) }.last(),
+            Some(&0)
+        );
+        super::destroy(state).unwrap();
+    }
+
+    #[test]
+    fn rejects_non_ptx_input() {
+        let mut state: *mut LinkState = ptr::null_mut();
+        super::create(0, ptr::null_mut(), ptr::null_mut(), &mut state).unwrap();
+        assert_eq!(
+            super::add_data(
+                state,
+                CUjitInputType::CU_JIT_INPUT_OBJECT,
+                PTX.as_ptr() as *mut _,
+                PTX.len(),
+            ),
+            Err(CUresult::CUDA_ERROR_NOT_SUPPORTED)
+        );
+        super::destroy(state).unwrap();
+    }
+
+    #[test]
+    fn plain_ptx_is_not_a_cubin() {
+        let ptx = format!("{}\0", PTX);
+        assert!(unsafe { get_cubin_ptx(ptx.as_ptr() as *const _) }.is_none());
+    }
+}
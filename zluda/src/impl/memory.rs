@@ -1,4 +1,4 @@
-use super::{stream, CUresult, GlobalState};
+use super::{context::ContextData, stream, CUresult, GlobalState};
 use std::{ffi::c_void, mem};
 
 pub fn alloc_v2(dptr: *mut *mut c_void, bytesize: usize) -> Result<(), CUresult> {
@@ -10,8 +10,15 @@ pub fn alloc_v2(dptr: *mut *mut c_void, bytesize: usize) -> Result<(), CUresult>
     Ok(())
 }
 
-pub fn copy_v2(dst: *mut c_void, src: *const c_void, bytesize: usize) -> Result<(), CUresult> {
-    GlobalState::lock_stream(stream::CU_STREAM_LEGACY, |stream| {
+// Synchronous copies and fills run on a default stream, CU_STREAM_LEGACY or,
+// for *_ptds entry points, CU_STREAM_PER_THREAD
+pub fn copy_v2(
+    dst: *mut c_void,
+    src: *const c_void,
+    bytesize: usize,
+    default_stream: *mut stream::Stream,
+) -> Result<(), CUresult> {
+    GlobalState::lock_stream(default_stream, |stream| {
         let mut cmd_list = stream.command_list()?;
         unsafe { cmd_list.append_memory_copy_unsafe(dst, src, bytesize, None, &mut []) }?;
         stream.queue.execute(cmd_list)?;
@@ -27,8 +34,13 @@ pub fn free_v2(ptr: *mut c_void) -> Result<(), CUresult> {
     .map_err(|_| CUresult::CUDA_ERROR_INVALID_VALUE)?
 }
 
-pub(crate) fn set_d32_v2(dst: *mut c_void, ui: u32, n: usize) -> Result<(), CUresult> {
-    GlobalState::lock_stream(stream::CU_STREAM_LEGACY, |stream| {
+pub(crate) fn set_d32_v2(
+    dst: *mut c_void,
+    ui: u32,
+    n: usize,
+    default_stream: *mut stream::Stream,
+) -> Result<(), CUresult> {
+    GlobalState::lock_stream(default_stream, |stream| {
         let mut cmd_list = stream.command_list()?;
         unsafe {
             cmd_list.append_memory_fill_unsafe(dst, &ui, mem::size_of::<u32>() * n, None, &mut [])
@@ -38,8 +50,13 @@ pub(crate) fn set_d32_v2(dst: *mut c_void, ui: u32, n: usize) -> Result<(), CUre
     })?
 }
 
-pub(crate) fn set_d8_v2(dst: *mut c_void, uc: u8, n: usize) -> Result<(), CUresult> {
-    GlobalState::lock_stream(stream::CU_STREAM_LEGACY, |stream| {
+pub(crate) fn set_d8_v2(
+    dst: *mut c_void,
+    uc: u8,
+    n: usize,
+    default_stream: *mut stream::Stream,
+) -> Result<(), CUresult> {
+    GlobalState::lock_stream(default_stream, |stream| {
         let mut cmd_list = stream.command_list()?;
         unsafe {
             cmd_list.append_memory_fill_unsafe(dst, &uc, mem::size_of::<u8>() * n, None, &mut [])
@@ -49,6 +66,41 @@ pub(crate) fn set_d8_v2(dst: *mut c_void, uc: u8, n: usize) -> Result<(), CUresu
     })?
 }
 
+// Blocking copies between host memory and device memory on the context's
+// default stream, for globals and buffers owned by ZLUDA itself
+pub(crate) fn copy_to_device<T>(
+    ctx: &mut ContextData,
+    dst: *mut c_void,
+    src: &T,
+) -> Result<(), CUresult> {
+    let stream = &ctx.default_stream;
+    let mut cmd_list = stream.command_list()?;
+    unsafe {
+        cmd_list.append_memory_copy_unsafe(
+            dst,
+            src as *const T as *const c_void,
+            mem::size_of::<T>(),
+            None,
+            &mut [],
+        )
+    }?;
+    stream.queue.execute(cmd_list)?;
+    Ok(())
+}
+
+pub(crate) fn copy_from_device<T>(
+    ctx: &mut ContextData,
+    dst: *mut T,
+    src: *const c_void,
+    len: usize,
+) -> Result<(), CUresult> {
+    let stream = &ctx.default_stream;
+    let mut cmd_list = stream.command_list()?;
+    unsafe { cmd_list.append_memory_copy_unsafe(dst as *mut c_void, src, len, None, &mut []) }?;
+    stream.queue.execute(cmd_list)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use super::super::test::CudaDriverFns;
@@ -1,9 +1,13 @@
 use crate::{
-    cuda::{CUctx_st, CUdevice, CUdeviceptr, CUfunc_st, CUmod_st, CUresult, CUstream_st},
+    cuda::{
+        CUctx_st, CUdevice, CUdeviceptr, CUfunc_st, CUlinkState_st, CUmod_st, CUresult,
+        CUstream_st, CUtexref_st,
+    },
     r#impl::device::Device,
 };
 use std::{
-    ffi::c_void,
+    env,
+    ffi::{c_void, OsString},
     mem::{self, ManuallyDrop},
     os::raw::c_int,
     ptr,
@@ -18,9 +22,13 @@ pub mod context;
 pub mod device;
 pub mod export_table;
 pub mod function;
+pub mod link;
 pub mod memory;
 pub mod module;
+pub mod printf;
 pub mod stream;
+pub mod texobj;
+pub mod texref;
 
 #[cfg(debug_assertions)]
 pub fn unimplemented() -> CUresult {
@@ -135,21 +143,53 @@ impl From<l0::sys::ze_result_t> for CUresult {
     fn from(result: l0::sys::ze_result_t) -> Self {
         match result {
             l0::sys::ze_result_t::ZE_RESULT_SUCCESS => CUresult::CUDA_SUCCESS,
-            l0_sys::ze_result_t::ZE_RESULT_ERROR_UNINITIALIZED => {
+            l0::sys::ze_result_t::ZE_RESULT_NOT_READY => CUresult::CUDA_ERROR_NOT_READY,
+            l0::sys::ze_result_t::ZE_RESULT_ERROR_UNINITIALIZED => {
                 CUresult::CUDA_ERROR_NOT_INITIALIZED
             }
-            l0_sys::ze_result_t::ZE_RESULT_ERROR_INVALID_ENUMERATION
-            | l0_sys::ze_result_t::ZE_RESULT_ERROR_INVALID_ARGUMENT
-            | l0_sys::ze_result_t::ZE_RESULT_ERROR_INVALID_GROUP_SIZE_DIMENSION
-            | l0_sys::ze_result_t::ZE_RESULT_ERROR_INVALID_GLOBAL_WIDTH_DIMENSION => {
-                CUresult::CUDA_ERROR_INVALID_VALUE
+            l0::sys::ze_result_t::ZE_RESULT_ERROR_DEVICE_LOST => {
+                CUresult::CUDA_ERROR_HARDWARE_STACK_ERROR
             }
-            l0_sys::ze_result_t::ZE_RESULT_ERROR_OUT_OF_HOST_MEMORY => {
+            l0::sys::ze_result_t::ZE_RESULT_ERROR_OUT_OF_HOST_MEMORY
+            | l0::sys::ze_result_t::ZE_RESULT_ERROR_OUT_OF_DEVICE_MEMORY => {
                 CUresult::CUDA_ERROR_OUT_OF_MEMORY
             }
-            l0_sys::ze_result_t::ZE_RESULT_ERROR_UNSUPPORTED_FEATURE => {
+            l0::sys::ze_result_t::ZE_RESULT_ERROR_INSUFFICIENT_PERMISSIONS => {
+                CUresult::CUDA_ERROR_NOT_PERMITTED
+            }
+            l0::sys::ze_result_t::ZE_RESULT_ERROR_MODULE_BUILD_FAILURE => {
+                CUresult::CUDA_ERROR_INVALID_PTX
+            }
+            l0::sys::ze_result_t::ZE_RESULT_ERROR_MODULE_LINK_FAILURE
+            | l0::sys::ze_result_t::ZE_RESULT_ERROR_INVALID_NATIVE_BINARY => {
+                CUresult::CUDA_ERROR_INVALID_IMAGE
+            }
+            l0::sys::ze_result_t::ZE_RESULT_ERROR_UNSUPPORTED_VERSION
+            | l0::sys::ze_result_t::ZE_RESULT_ERROR_UNSUPPORTED_FEATURE
+            | l0::sys::ze_result_t::ZE_RESULT_ERROR_UNSUPPORTED_SIZE
+            | l0::sys::ze_result_t::ZE_RESULT_ERROR_UNSUPPORTED_ALIGNMENT
+            | l0::sys::ze_result_t::ZE_RESULT_ERROR_UNSUPPORTED_ENUMERATION
+            | l0::sys::ze_result_t::ZE_RESULT_ERROR_UNSUPPORTED_IMAGE_FORMAT => {
                 CUresult::CUDA_ERROR_NOT_SUPPORTED
             }
+            l0::sys::ze_result_t::ZE_RESULT_ERROR_INVALID_NULL_HANDLE => {
+                CUresult::CUDA_ERROR_INVALID_HANDLE
+            }
+            l0::sys::ze_result_t::ZE_RESULT_ERROR_INVALID_GLOBAL_NAME
+            | l0::sys::ze_result_t::ZE_RESULT_ERROR_INVALID_KERNEL_NAME
+            | l0::sys::ze_result_t::ZE_RESULT_ERROR_INVALID_FUNCTION_NAME => {
+                CUresult::CUDA_ERROR_NOT_FOUND
+            }
+            l0::sys::ze_result_t::ZE_RESULT_ERROR_INVALID_ARGUMENT
+            | l0::sys::ze_result_t::ZE_RESULT_ERROR_INVALID_ENUMERATION
+            | l0::sys::ze_result_t::ZE_RESULT_ERROR_INVALID_NULL_POINTER
+            | l0::sys::ze_result_t::ZE_RESULT_ERROR_INVALID_SIZE
+            | l0::sys::ze_result_t::ZE_RESULT_ERROR_INVALID_GROUP_SIZE_DIMENSION
+            | l0::sys::ze_result_t::ZE_RESULT_ERROR_INVALID_GLOBAL_WIDTH_DIMENSION
+            | l0::sys::ze_result_t::ZE_RESULT_ERROR_INVALID_KERNEL_ARGUMENT_INDEX
+            | l0::sys::ze_result_t::ZE_RESULT_ERROR_INVALID_KERNEL_ARGUMENT_SIZE => {
+                CUresult::CUDA_ERROR_INVALID_VALUE
+            }
             _ => CUresult::CUDA_ERROR_UNKNOWN,
         }
     }
@@ -256,11 +296,10 @@ impl GlobalState {
         stream: *mut stream::Stream,
         f: impl FnOnce(&mut stream::StreamData) -> T,
     ) -> Result<T, CUresult> {
-        if stream == ptr::null_mut()
-            || stream == stream::CU_STREAM_LEGACY
-            || stream == stream::CU_STREAM_PER_THREAD
-        {
+        if stream == ptr::null_mut() || stream == stream::CU_STREAM_LEGACY {
             Self::lock_current_context(|ctx| Ok(f(&mut ctx.default_stream)))?
+        } else if stream == stream::CU_STREAM_PER_THREAD {
+            Self::lock_current_context(|ctx| Ok(f(ctx.per_thread_stream()?)))?
         } else {
             Self::lock(|_| {
                 let stream = unsafe { &mut *stream }.as_result_mut()?;
@@ -414,8 +453,33 @@ unsafe fn transmute_lifetime_mut<'a, 'b, T: ?Sized>(t: &'a mut T) -> &'b mut T {
     mem::transmute(t)
 }
 
+const CUDA_VERSION_ENV: &str = "ZLUDA_CUDA_VERSION";
+// CUDA 11.8
+const DEFAULT_CUDA_VERSION: c_int = 11080;
+
 pub fn driver_get_version() -> c_int {
-    i32::max_value()
+    cuda_version(env::var_os(CUDA_VERSION_ENV))
+}
+
+// ZLUDA_CUDA_VERSION accepts either the raw number returned by
+// cuDriverGetVersion (e.g. 11020) or major.minor (e.g. 11.2)
+fn cuda_version(setting: Option<OsString>) -> c_int {
+    let setting = match setting.as_ref().and_then(|s| s.to_str()) {
+        Some(setting) => setting.trim(),
+        None => return DEFAULT_CUDA_VERSION,
+    };
+    let version = match setting.split_once('.') {
+        Some((major, minor)) => major
+            .parse::<c_int>()
+            .ok()
+            .zip(minor.parse::<c_int>().ok())
+            .map(|(major, minor)| major * 1000 + minor * 10),
+        None => setting.parse::<c_int>().ok(),
+    };
+    match version {
+        Some(version) if version > 0 => version,
+        _ => DEFAULT_CUDA_VERSION,
+    }
 }
 
 impl<'a> CudaRepr for CUctx_st {
@@ -453,3 +517,144 @@ impl<'a> CudaRepr for CUfunc_st {
 impl<'a> CudaRepr for CUstream_st {
     type Impl = stream::Stream;
 }
+
+impl<'a> CudaRepr for CUtexref_st {
+    type Impl = texref::TexRef;
+}
+
+impl<'a> CudaRepr for CUlinkState_st {
+    type Impl = link::LinkState;
+}
+
+#[cfg(test)]
+mod driver_version_test {
+    use super::{cuda_version, DEFAULT_CUDA_VERSION};
+
+    #[test]
+    fn default_version_is_11_8() {
+        assert_eq!(cuda_version(None), DEFAULT_CUDA_VERSION);
+        assert_eq!(DEFAULT_CUDA_VERSION, 11080);
+    }
+
+    #[test]
+    fn version_can_be_overridden() {
+        assert_eq!(cuda_version(Some("10020".into())), 10020);
+        assert_eq!(cuda_version(Some("12.1".into())), 12010);
+        assert_eq!(cuda_version(Some("latest".into())), DEFAULT_CUDA_VERSION);
+    }
+}
+
+#[cfg(test)]
+mod ze_result_test {
+    use crate::cuda::CUresult;
+    use l0::sys::ze_result_t;
+
+    fn assert_maps_to(results: &[ze_result_t], expected: CUresult) {
+        for result in results {
+            assert_eq!(CUresult::from(*result), expected, "{:?}", result);
+        }
+    }
+
+    #[test]
+    fn success_and_not_ready() {
+        assert_maps_to(&[ze_result_t::ZE_RESULT_SUCCESS], CUresult::CUDA_SUCCESS);
+        assert_maps_to(
+            &[ze_result_t::ZE_RESULT_NOT_READY],
+            CUresult::CUDA_ERROR_NOT_READY,
+        );
+    }
+
+    #[test]
+    fn resource_errors() {
+        assert_maps_to(
+            &[
+                ze_result_t::ZE_RESULT_ERROR_OUT_OF_HOST_MEMORY,
+                ze_result_t::ZE_RESULT_ERROR_OUT_OF_DEVICE_MEMORY,
+            ],
+            CUresult::CUDA_ERROR_OUT_OF_MEMORY,
+        );
+        assert_maps_to(
+            &[ze_result_t::ZE_RESULT_ERROR_DEVICE_LOST],
+            CUresult::CUDA_ERROR_HARDWARE_STACK_ERROR,
+        );
+        assert_maps_to(
+            &[ze_result_t::ZE_RESULT_ERROR_UNINITIALIZED],
+            CUresult::CUDA_ERROR_NOT_INITIALIZED,
+        );
+        assert_maps_to(
+            &[ze_result_t::ZE_RESULT_ERROR_INSUFFICIENT_PERMISSIONS],
+            CUresult::CUDA_ERROR_NOT_PERMITTED,
+        );
+    }
+
+    #[test]
+    fn unsupported_errors() {
+        assert_maps_to(
+            &[
+                ze_result_t::ZE_RESULT_ERROR_UNSUPPORTED_VERSION,
+                ze_result_t::ZE_RESULT_ERROR_UNSUPPORTED_FEATURE,
+                ze_result_t::ZE_RESULT_ERROR_UNSUPPORTED_SIZE,
+                ze_result_t::ZE_RESULT_ERROR_UNSUPPORTED_ALIGNMENT,
+                ze_result_t::ZE_RESULT_ERROR_UNSUPPORTED_ENUMERATION,
+                ze_result_t::ZE_RESULT_ERROR_UNSUPPORTED_IMAGE_FORMAT,
+            ],
+            CUresult::CUDA_ERROR_NOT_SUPPORTED,
+        );
+    }
+
+    #[test]
+    fn module_errors() {
+        assert_maps_to(
+            &[ze_result_t::ZE_RESULT_ERROR_MODULE_BUILD_FAILURE],
+            CUresult::CUDA_ERROR_INVALID_PTX,
+        );
+        assert_maps_to(
+            &[
+                ze_result_t::ZE_RESULT_ERROR_MODULE_LINK_FAILURE,
+                ze_result_t::ZE_RESULT_ERROR_INVALID_NATIVE_BINARY,
+            ],
+            CUresult::CUDA_ERROR_INVALID_IMAGE,
+        );
+        assert_maps_to(
+            &[
+                ze_result_t::ZE_RESULT_ERROR_INVALID_GLOBAL_NAME,
+                ze_result_t::ZE_RESULT_ERROR_INVALID_KERNEL_NAME,
+                ze_result_t::ZE_RESULT_ERROR_INVALID_FUNCTION_NAME,
+            ],
+            CUresult::CUDA_ERROR_NOT_FOUND,
+        );
+    }
+
+    #[test]
+    fn argument_errors() {
+        assert_maps_to(
+            &[ze_result_t::ZE_RESULT_ERROR_INVALID_NULL_HANDLE],
+            CUresult::CUDA_ERROR_INVALID_HANDLE,
+        );
+        assert_maps_to(
+            &[
+                ze_result_t::ZE_RESULT_ERROR_INVALID_ARGUMENT,
+                ze_result_t::ZE_RESULT_ERROR_INVALID_ENUMERATION,
+                ze_result_t::ZE_RESULT_ERROR_INVALID_NULL_POINTER,
+                ze_result_t::ZE_RESULT_ERROR_INVALID_SIZE,
+                ze_result_t::ZE_RESULT_ERROR_INVALID_GROUP_SIZE_DIMENSION,
+                ze_result_t::ZE_RESULT_ERROR_INVALID_GLOBAL_WIDTH_DIMENSION,
+                ze_result_t::ZE_RESULT_ERROR_INVALID_KERNEL_ARGUMENT_INDEX,
+                ze_result_t::ZE_RESULT_ERROR_INVALID_KERNEL_ARGUMENT_SIZE,
+            ],
+            CUresult::CUDA_ERROR_INVALID_VALUE,
+        );
+    }
+
+    #[test]
+    fn unmapped_errors_are_unknown() {
+        assert_maps_to(
+            &[
+                ze_result_t::ZE_RESULT_ERROR_UNKNOWN,
+                ze_result_t::ZE_RESULT_ERROR_NOT_AVAILABLE,
+                ze_result_t::ZE_RESULT_ERROR_HANDLE_OBJECT_IN_USE,
+            ],
+            CUresult::CUDA_ERROR_UNKNOWN,
+        );
+    }
+}
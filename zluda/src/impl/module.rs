@@ -1,14 +1,29 @@
 use std::{
-    collections::hash_map, collections::HashMap, ffi::c_void, ffi::CStr, ffi::CString, mem,
-    os::raw::c_char, ptr, slice,
+    collections::hash_map,
+    collections::{HashMap, HashSet},
+    env,
+    ffi::c_void,
+    ffi::CStr,
+    ffi::CString,
+    fs, mem,
+    os::raw::c_char,
+    os::raw::c_uint,
+    path::{Path, PathBuf},
+    ptr, slice,
 };
 
 use super::{
-    device,
+    context::ContextData,
+    device, export_table,
     function::Function,
-    function::{FunctionData, LegacyArguments},
+    function::{
+        FunctionData, LegacyArguments, TextureArgument, DEFAULT_MAX_DYNAMIC_SHARED_SIZE_BYTES,
+    },
+    link, memory, printf,
+    texref::{TexRef, TexRefData},
     CUresult, GlobalState, HasLivenessCookie, LiveCheck,
 };
+use crate::cuda::CUjit_option;
 use ptx;
 
 pub type Module = LiveCheck<ModuleData>;
@@ -38,47 +53,108 @@ pub struct SpirvModule {
     pub kernel_info: HashMap<String, ptx::KernelInfo>,
     pub should_link_ptx_impl: Option<&'static [u8]>,
     pub build_options: CString,
+    pub subgroup_size: Option<u32>,
+    pub uses_printf: bool,
+    pub texrefs: HashSet<String>,
+    pub uses_nsmid: bool,
+    pub uses_recursion: bool,
+    pub uses_shader_clock: bool,
 }
 
 pub struct CompiledModule {
     pub base: l0::Module,
     pub kernels: HashMap<CString, Box<Function>>,
+    pub texrefs: HashMap<CString, Box<TexRef>>,
 }
 
-impl<L, T, E> From<ptx::ParseError<L, T, E>> for CUresult {
-    fn from(_: ptx::ParseError<L, T, E>) -> Self {
-        CUresult::CUDA_ERROR_INVALID_PTX
+impl From<&ptx::ast::PtxError> for CUresult {
+    fn from(err: &ptx::ast::PtxError) -> Self {
+        match err {
+            // We can only synchronize the whole block
+            ptx::ast::PtxError::PartialBarrier => CUresult::CUDA_ERROR_NOT_SUPPORTED,
+            _ => CUresult::CUDA_ERROR_INVALID_PTX,
+        }
+    }
+}
+
+impl From<ptx::ParseTranslateError> for CUresult {
+    fn from(err: ptx::ParseTranslateError) -> Self {
+        match err {
+            ptx::ParseTranslateError::Parse(_) => CUresult::CUDA_ERROR_INVALID_PTX,
+            ptx::ParseTranslateError::Ptx(err) => (&err).into(),
+            ptx::ParseTranslateError::Translate(err) => err.into(),
+            // Parts of the translator that are not implemented yet panic
+            ptx::ParseTranslateError::Panic(_) => CUresult::CUDA_ERROR_NOT_SUPPORTED,
+        }
     }
 }
 
 impl From<ptx::TranslateError> for CUresult {
-    fn from(_: ptx::TranslateError) -> Self {
-        CUresult::CUDA_ERROR_INVALID_PTX
+    fn from(err: ptx::TranslateError) -> Self {
+        match err {
+            ptx::TranslateError::UnknownSymbol
+            | ptx::TranslateError::UntypedSymbol
+            | ptx::TranslateError::MismatchedType => CUresult::CUDA_ERROR_INVALID_PTX,
+            // Valid PTX that we can't translate (yet)
+            ptx::TranslateError::Todo
+            | ptx::TranslateError::IrreducibleControlFlow
+            | ptx::TranslateError::MissingShaderClock
+            | ptx::TranslateError::MissingStackCalls => CUresult::CUDA_ERROR_NOT_SUPPORTED,
+            // Bugs in the translator, not in the module
+            ptx::TranslateError::Spirv(_) | ptx::TranslateError::Unreachable => {
+                CUresult::CUDA_ERROR_UNKNOWN
+            }
+        }
     }
 }
 
 impl SpirvModule {
-    pub fn new_raw<'a>(text: *const c_char) -> Result<Self, CUresult> {
+    pub fn new_raw<'a>(
+        text: *const c_char,
+        options: ptx::TranslateOptions,
+    ) -> Result<Self, CUresult> {
         let u8_text = unsafe { CStr::from_ptr(text) };
         let ptx_text = u8_text
             .to_str()
             .map_err(|_| CUresult::CUDA_ERROR_INVALID_PTX)?;
-        Self::new(ptx_text)
+        match get_ptx_override(ptx_text) {
+            Some(override_text) => Self::new_with_options(&override_text, options),
+            None => Self::new_with_options(ptx_text, options),
+        }
     }
 
     pub fn new<'a>(ptx_text: &str) -> Result<Self, CUresult> {
-        let mut errors = Vec::new();
-        let ast = ptx::ModuleParser::new().parse(&mut errors, ptx_text)?;
-        let spirv_module = ptx::to_spirv_module(ast)?;
+        Self::new_with_options(ptx_text, ptx::TranslateOptions::default())
+    }
+
+    pub fn new_with_options<'a>(
+        ptx_text: &str,
+        options: ptx::TranslateOptions,
+    ) -> Result<Self, CUresult> {
+        let spirv_module = ptx::parse_and_translate_with_options(ptx_text, options)?;
         Ok(SpirvModule {
             binaries: spirv_module.assemble(),
             kernel_info: spirv_module.kernel_info,
             should_link_ptx_impl: spirv_module.should_link_ptx_impl,
             build_options: spirv_module.build_options,
+            subgroup_size: spirv_module.subgroup_size,
+            uses_printf: spirv_module.uses_printf,
+            texrefs: spirv_module.texrefs,
+            uses_nsmid: spirv_module.uses_nsmid,
+            uses_recursion: spirv_module.uses_recursion,
+            uses_shader_clock: spirv_module.uses_shader_clock,
         })
     }
 
     pub fn compile(&self, ctx: &mut l0::Context, dev: &l0::Device) -> Result<l0::Module, CUresult> {
+        // Driver would otherwise silently pick a different subgroup size
+        if let Some(subgroup_size) = self.subgroup_size {
+            let props = dev.get_compute_properties()?;
+            let supported_sizes = &props.subGroupSizes[..props.numSubGroupSizes as usize];
+            if !supported_sizes.contains(&subgroup_size) {
+                return Err(CUresult::CUDA_ERROR_NOT_SUPPORTED);
+            }
+        }
         let byte_il = unsafe {
             slice::from_raw_parts(
                 self.binaries.as_ptr() as *const u8,
@@ -101,6 +177,66 @@ impl SpirvModule {
         };
         Ok(l0_module?)
     }
+
+    // Checking a feature builds a probe module on the device, so it is only
+    // done for modules that need the feature
+    fn check_device_support(&self, device: &mut device::Device) -> Result<(), CUresult> {
+        if (self.uses_shader_clock && !device.supports_shader_clock())
+            || (self.uses_recursion && !device.supports_stack_calls())
+        {
+            return Err(CUresult::CUDA_ERROR_NOT_SUPPORTED);
+        }
+        Ok(())
+    }
+
+    // Sets up globals the translator left for the host to fill
+    fn bind(&self, ctx: &mut ContextData, module: &l0::Module) -> Result<(), CUresult> {
+        if self.uses_printf {
+            printf::bind_module(ctx, module)?;
+        }
+        if self.uses_nsmid {
+            let device = unsafe { &mut *ctx.device };
+            let sm_count = device.get_multiprocessor_count()?;
+            let global_name = CString::new(ptx::NSMID_GLOBAL).unwrap();
+            let (_, global_ptr) = module.get_global_pointer(&global_name)?;
+            memory::copy_to_device(ctx, global_ptr, &sm_count)?;
+        }
+        Ok(())
+    }
+}
+
+const PTX_OVERRIDE_DIR_ENV: &str = "ZLUDA_PTX_OVERRIDE_DIR";
+
+// Debugging aid: if ZLUDA_PTX_OVERRIDE_DIR contains a file named after the
+// hash of the module text (e.g. 0123456789abcdef.ptx), it is used instead.
+// Hashes of loaded modules are printed to stderr while the variable is set
+fn get_ptx_override(ptx_text: &str) -> Option<String> {
+    let dir = env::var_os(PTX_OVERRIDE_DIR_ENV)?;
+    let override_text = get_ptx_override_from(Path::new(&dir), ptx_text);
+    eprintln!(
+        "[ZLUDA] Loading module {:016x}{}",
+        ptx_hash(ptx_text),
+        if override_text.is_some() {
+            " (overridden)"
+        } else {
+            ""
+        }
+    );
+    override_text
+}
+
+fn get_ptx_override_from(dir: &Path, ptx_text: &str) -> Option<String> {
+    let mut path = PathBuf::from(dir);
+    path.push(format!("{:016x}.ptx", ptx_hash(ptx_text)));
+    fs::read_to_string(path).ok()
+}
+
+// 64-bit FNV-1a. Override file names have to stay the same across runs and
+// Rust versions, which std hashers don't promise
+pub fn ptx_hash(ptx_text: &str) -> u64 {
+    ptx_text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
 }
 
 pub fn get_function(
@@ -114,17 +250,7 @@ pub fn get_function(
     let name = unsafe { CStr::from_ptr(name) }.to_owned();
     let function: *mut Function = GlobalState::lock_current_context(|ctx| {
         let module = unsafe { &mut *hmod }.as_result_mut()?;
-        let device = unsafe { &mut *ctx.device };
-        let compiled_module = match module.device_binaries.entry(device.index) {
-            hash_map::Entry::Occupied(entry) => entry.into_mut(),
-            hash_map::Entry::Vacant(entry) => {
-                let new_module = CompiledModule {
-                    base: module.spirv.compile(&mut device.l0_context, &device.base)?,
-                    kernels: HashMap::new(),
-                };
-                entry.insert(new_module)
-            }
-        };
+        let compiled_module = get_or_compile(&module.spirv, &mut module.device_binaries, ctx)?;
         let kernel = match compiled_module.kernels.entry(name) {
             hash_map::Entry::Occupied(entry) => entry.into_mut().as_mut(),
             hash_map::Entry::Vacant(entry) => {
@@ -135,6 +261,20 @@ pub fn get_function(
                         std::str::from_utf8_unchecked(entry.key().as_c_str().to_bytes())
                     })
                     .ok_or(CUresult::CUDA_ERROR_NOT_FOUND)?;
+                let texrefs = &mut compiled_module.texrefs;
+                let textures = kernel_info
+                    .textures
+                    .iter()
+                    .map(|texture| match texture {
+                        ptx::KernelTexture::Object(index) => Ok(TextureArgument::Object(*index)),
+                        ptx::KernelTexture::Texref(name) => {
+                            let name = CString::new(name.as_str())
+                                .map_err(|_| CUresult::CUDA_ERROR_UNKNOWN)?;
+                            let texref = get_or_add_texref(texrefs, name);
+                            Ok(TextureArgument::Texref(texref as *mut _))
+                        }
+                    })
+                    .collect::<Result<Vec<_>, CUresult>>()?;
                 let mut kernel =
                     l0::Kernel::new_resident(&compiled_module.base, entry.key().as_c_str())?;
                 kernel.set_indirect_access(
@@ -146,8 +286,10 @@ pub fn get_function(
                     base: kernel,
                     arg_size: kernel_info.arguments_sizes.clone(),
                     use_shared_mem: kernel_info.uses_shared_mem,
+                    textures,
                     properties: None,
                     legacy_args: LegacyArguments::new(),
+                    max_dynamic_shared_size_bytes: DEFAULT_MAX_DYNAMIC_SHARED_SIZE_BYTES,
                 })))
             }
         };
@@ -157,19 +299,123 @@ pub fn get_function(
     Ok(())
 }
 
+pub fn get_texref(
+    ptexref: *mut *mut TexRef,
+    hmod: *mut Module,
+    name: *const c_char,
+) -> Result<(), CUresult> {
+    if ptexref == ptr::null_mut() || hmod == ptr::null_mut() || name == ptr::null() {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    let name = unsafe { CStr::from_ptr(name) }.to_owned();
+    let texref: *mut TexRef = GlobalState::lock_current_context(|ctx| {
+        let module = unsafe { &mut *hmod }.as_result_mut()?;
+        let is_declared = name
+            .to_str()
+            .map_or(false, |name| module.spirv.texrefs.contains(name));
+        if !is_declared {
+            return Err(CUresult::CUDA_ERROR_NOT_FOUND);
+        }
+        let compiled_module = get_or_compile(&module.spirv, &mut module.device_binaries, ctx)?;
+        Ok::<_, CUresult>(get_or_add_texref(&mut compiled_module.texrefs, name) as *mut _)
+    })??;
+    unsafe { *ptexref = texref };
+    Ok(())
+}
+
+// Texrefs are created on first use, either by cuModuleGetTexRef or by
+// getting a kernel that samples them
+fn get_or_add_texref(texrefs: &mut HashMap<CString, Box<TexRef>>, name: CString) -> &mut TexRef {
+    texrefs
+        .entry(name)
+        .or_insert_with(|| Box::new(TexRef::new(TexRefData::new())))
+}
+
+fn get_or_compile<'a>(
+    spirv: &SpirvModule,
+    device_binaries: &'a mut HashMap<device::Index, CompiledModule>,
+    ctx: &mut ContextData,
+) -> Result<&'a mut CompiledModule, CUresult> {
+    let device = unsafe { &mut *ctx.device };
+    Ok(match device_binaries.entry(device.index) {
+        hash_map::Entry::Occupied(entry) => entry.into_mut(),
+        hash_map::Entry::Vacant(entry) => {
+            spirv.check_device_support(device)?;
+            let l0_module = spirv.compile(&mut device.l0_context, &device.base)?;
+            spirv.bind(ctx, &l0_module)?;
+            let new_module = CompiledModule {
+                base: l0_module,
+                kernels: HashMap::new(),
+                texrefs: HashMap::new(),
+            };
+            entry.insert(new_module)
+        }
+    })
+}
+
 pub(crate) fn load_data(pmod: *mut *mut Module, image: *const c_void) -> Result<(), CUresult> {
-    let spirv_data = SpirvModule::new_raw(image as *const _)?;
+    load_data_with_target(pmod, image, None)
+}
+
+pub(crate) fn load_data_ex(
+    pmod: *mut *mut Module,
+    image: *const c_void,
+    num_options: c_uint,
+    options: *mut CUjit_option,
+    option_values: *mut *mut c_void,
+) -> Result<(), CUresult> {
+    if num_options > 0 && (options == ptr::null_mut() || option_values == ptr::null_mut()) {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    let mut jit_target = None;
+    for i in 0..num_options as usize {
+        // Scalar option values are passed by value, cast to a pointer
+        if unsafe { *options.add(i) } == CUjit_option::CU_JIT_TARGET {
+            jit_target = Some(unsafe { *option_values.add(i) } as usize as c_uint);
+        }
+    }
+    load_data_with_target(pmod, image, jit_target)
+}
+
+fn load_data_with_target(
+    pmod: *mut *mut Module,
+    image: *const c_void,
+    jit_target: Option<c_uint>,
+) -> Result<(), CUresult> {
+    if pmod == ptr::null_mut() || image == ptr::null() {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    if let Some(result) = unsafe { export_table::load_fatbin(pmod, image, jit_target) } {
+        return match result {
+            CUresult::CUDA_SUCCESS => Ok(()),
+            err => Err(err),
+        };
+    }
+    let ptx_text = unsafe { link::get_cubin_ptx(image) }.unwrap_or(image as *const _);
+    let spirv_data = SpirvModule::new_raw(ptx_text, current_translate_options()?)?;
     load_data_impl(pmod, spirv_data)
 }
 
+// Modules are translated for the device of the current context, some
+// instructions have a native and an emulated variant
+pub(crate) fn current_translate_options() -> Result<ptx::TranslateOptions, CUresult> {
+    GlobalState::lock_current_context(|ctx| {
+        let device = unsafe { &mut *ctx.device };
+        device.get_translate_options()
+    })
+}
+
 pub fn load_data_impl(pmod: *mut *mut Module, spirv_data: SpirvModule) -> Result<(), CUresult> {
     let module = GlobalState::lock_current_context(|ctx| {
         let device = unsafe { &mut *ctx.device };
+        spirv_data.check_device_support(device)?;
         let l0_module = spirv_data.compile(&mut device.l0_context, &device.base)?;
+        spirv_data.bind(ctx, &l0_module)?;
         let mut device_binaries = HashMap::new();
         let compiled_module = CompiledModule {
             base: l0_module,
             kernels: HashMap::new(),
+            texrefs: HashMap::new(),
         };
         device_binaries.insert(device.index, compiled_module);
         let module_data = ModuleData {
@@ -200,6 +446,92 @@ pub(crate) fn load(pmod: *mut *mut Module, fname: *const i8) -> Result<(), CUres
         .map_err(|_| CUresult::CUDA_ERROR_INVALID_VALUE)?;
     let file = std::fs::read(path_utf8).map_err(|_| CUresult::CUDA_ERROR_FILE_NOT_FOUND)?;
     let module_text = std::str::from_utf8(&file).map_err(|_| CUresult::CUDA_ERROR_INVALID_PTX)?;
-    let spirv_data = SpirvModule::new(module_text)?;
+    let spirv_data = SpirvModule::new_with_options(module_text, current_translate_options()?)?;
     load_data_impl(pmod, spirv_data)
 }
+
+#[cfg(test)]
+mod test {
+    use super::{get_ptx_override_from, ptx_hash, CUresult, SpirvModule};
+    use std::{env, fs, process};
+
+    const KERNEL_HEADER: &str = ".version 6.5
+.target sm_30
+.address_size 64
+.visible .entry kernel(.param .u64 input)
+{
+    .reg .f32 temp;
+";
+
+    #[test]
+    fn malformed_module_is_invalid_ptx() {
+        let ptx = format!(
+            "{}    mov.f32 temp, undefined_reg;\n    ret;\n}}\n",
+            KERNEL_HEADER
+        );
+        assert_eq!(
+            SpirvModule::new(&ptx).err(),
+            Some(CUresult::CUDA_ERROR_INVALID_PTX)
+        );
+        let ptx = format!("{}    not_an_instruction temp;\n", KERNEL_HEADER);
+        assert_eq!(
+            SpirvModule::new(&ptx).err(),
+            Some(CUresult::CUDA_ERROR_INVALID_PTX)
+        );
+    }
+
+    #[test]
+    fn partial_barrier_is_not_supported() {
+        let ptx = format!("{}    bar.sync 0, 32;\n    ret;\n}}\n", KERNEL_HEADER);
+        assert_eq!(
+            SpirvModule::new(&ptx).err(),
+            Some(CUresult::CUDA_ERROR_NOT_SUPPORTED)
+        );
+    }
+
+    // Loop formed by FIRST and SECOND can be entered through either block,
+    // the translator deliberately rejects such control flow
+    #[test]
+    fn untranslatable_module_is_not_supported() {
+        let ptx = format!(
+            "{}    .reg .pred p;
+    setp.eq.f32 p, temp, 0f00000000;
+    @p bra SECOND;
+FIRST:
+    add.f32 temp, temp, 0f3F800000;
+SECOND:
+    setp.lt.f32 p, temp, 0f41200000;
+    @p bra FIRST;
+    ret;
+}}
+",
+            KERNEL_HEADER
+        );
+        assert_eq!(
+            SpirvModule::new(&ptx).err(),
+            Some(CUresult::CUDA_ERROR_NOT_SUPPORTED)
+        );
+    }
+
+    #[test]
+    fn ptx_override_is_picked_up() {
+        let original = ".version 6.5\n.target sm_30\n.address_size 64\n";
+        let patched = ".version 6.5\n.target sm_30\n.address_size 64\n// patched\n";
+        let mut dir = env::temp_dir();
+        dir.push(format!("zluda_ptx_override_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(get_ptx_override_from(&dir, original), None);
+        let mut override_path = dir.clone();
+        override_path.push(format!("{:016x}.ptx", ptx_hash(original)));
+        fs::write(&override_path, patched).unwrap();
+        let result = get_ptx_override_from(&dir, original);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(result.as_deref(), Some(patched));
+    }
+
+    #[test]
+    fn ptx_hash_is_stable() {
+        assert_eq!(ptx_hash(""), 0xcbf29ce484222325);
+        assert_eq!(ptx_hash("a"), 0xaf63dc4c8601ec8c);
+    }
+}
@@ -0,0 +1,680 @@
+use super::{
+    context::ContextData,
+    memory::{copy_from_device, copy_to_device},
+    CUresult,
+};
+use std::{
+    convert::TryInto,
+    ffi::{c_void, CString},
+    io::{self, Write},
+    mem,
+};
+
+// Device-side vprintf (see zluda_ptx_impl.cl) appends records to a buffer
+// starting with this header. The host prints the records at synchronization
+// points and then resets `used`
+#[repr(C)]
+struct PrintfBufferHeader {
+    used: u32,
+    capacity: u32,
+}
+
+pub struct PrintfBuffer {
+    base: *mut c_void,
+    capacity: usize,
+}
+
+impl PrintfBuffer {
+    pub fn free(self, ctx: &mut ContextData) -> Result<(), CUresult> {
+        let dev = unsafe { &mut *ctx.device };
+        unsafe { dev.l0_context.mem_free(self.base) }?;
+        Ok(())
+    }
+}
+
+// Points the printf buffer global of a freshly built module at the buffer of
+// the context, creating the buffer on first use
+pub fn bind_module(ctx: &mut ContextData, module: &l0::Module) -> Result<(), CUresult> {
+    let buffer_base = match &ctx.printf_buffer {
+        Some(buffer) => buffer.base,
+        None => {
+            let buffer = create_buffer(ctx)?;
+            let base = buffer.base;
+            ctx.printf_buffer = Some(buffer);
+            base
+        }
+    };
+    let global_name = CString::new(ptx::PRINTF_BUFFER_GLOBAL).unwrap();
+    let (global_size, global_ptr) = module.get_global_pointer(&global_name)?;
+    if global_size != mem::size_of::<*mut c_void>() {
+        return Err(CUresult::CUDA_ERROR_UNKNOWN);
+    }
+    copy_to_device(ctx, global_ptr, &buffer_base)
+}
+
+fn create_buffer(ctx: &mut ContextData) -> Result<PrintfBuffer, CUresult> {
+    let capacity = ctx.limits.printf_fifo_size;
+    let header = PrintfBufferHeader {
+        used: 0,
+        capacity: capacity
+            .try_into()
+            .map_err(|_| CUresult::CUDA_ERROR_INVALID_VALUE)?,
+    };
+    let dev = unsafe { &mut *ctx.device };
+    let base = unsafe {
+        dev.base.mem_alloc_device(
+            &mut dev.l0_context,
+            mem::size_of::<PrintfBufferHeader>() + capacity,
+            mem::align_of::<u64>(),
+        )
+    }?;
+    let buffer = PrintfBuffer { base, capacity };
+    if let Err(err) = copy_to_device(ctx, base, &header) {
+        buffer.free(ctx)?;
+        return Err(err);
+    }
+    Ok(buffer)
+}
+
+// Prints and discards everything kernels of this context have printed so far
+pub fn flush(ctx: &mut ContextData) -> Result<(), CUresult> {
+    let (base, capacity) = match &ctx.printf_buffer {
+        Some(buffer) => (buffer.base, buffer.capacity),
+        None => return Ok(()),
+    };
+    let mut header = PrintfBufferHeader {
+        used: 0,
+        capacity: 0,
+    };
+    copy_from_device(ctx, &mut header, base, mem::size_of::<PrintfBufferHeader>())?;
+    // Reservations of records that did not fit still count towards `used`
+    let used = (header.used as usize).min(capacity);
+    if used == 0 {
+        return Ok(());
+    }
+    let mut records = vec![0u8; used];
+    let data = unsafe { (base as *mut u8).add(mem::size_of::<PrintfBufferHeader>()) };
+    copy_from_device(ctx, records.as_mut_ptr(), data as *const c_void, used)?;
+    copy_to_device(ctx, base, &0u32)?;
+    let mut read_string = |address| read_device_string(ctx, address);
+    write_output(|mut out| print_records(&records, &mut read_string, &mut out))
+        .map_err(|_| CUresult::CUDA_ERROR_UNKNOWN)
+}
+
+#[cfg(not(test))]
+fn write_output(print: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    print(&mut stdout)?;
+    stdout.flush()
+}
+
+// Tests check what kernels launched from their thread printed
+#[cfg(test)]
+fn write_output(print: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> io::Result<()> {
+    test::OUTPUT.with(|output| print(&mut *output.borrow_mut()))
+}
+
+// Records only hold the addresses of %s arguments. Strings are read in chunks
+// ending at a multiple of STRING_CHUNK, so we never read past the page holding
+// the terminating nul. Unreadable strings are printed as their address
+const STRING_CHUNK: usize = 64;
+const MAX_STRING_LENGTH: usize = 4096;
+
+fn read_device_string(ctx: &mut ContextData, address: u64) -> Option<Vec<u8>> {
+    let mut result = Vec::new();
+    let mut address = address as usize;
+    while result.len() < MAX_STRING_LENGTH {
+        let mut chunk = [0u8; STRING_CHUNK];
+        let chunk_size = align_up(address + 1, STRING_CHUNK) - address;
+        copy_from_device(
+            ctx,
+            chunk.as_mut_ptr(),
+            address as *const c_void,
+            chunk_size,
+        )
+        .ok()?;
+        let chunk = &chunk[..chunk_size];
+        match chunk.iter().position(|c| *c == 0) {
+            Some(end) => {
+                result.extend_from_slice(&chunk[..end]);
+                return Some(result);
+            }
+            None => result.extend_from_slice(chunk),
+        }
+        address += chunk_size;
+    }
+    Some(result)
+}
+
+fn align_up(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) & !(alignment - 1)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let bytes = bytes.get(offset..offset + 8)?;
+    Some(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+// Every record is (record size, format size, format, arguments), see
+// zluda_ptx_impl.cl. A malformed record stops the printing
+fn print_records(
+    records: &[u8],
+    read_string: &mut impl FnMut(u64) -> Option<Vec<u8>>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let mut offset = 0;
+    while offset < records.len() {
+        let record_size = match read_u32(records, offset) {
+            Some(size) if size >= 8 => size as usize,
+            _ => break,
+        };
+        let format_size = match read_u32(records, offset + 4) {
+            Some(size) => size as usize,
+            None => break,
+        };
+        let record = match records.get(offset..offset + record_size) {
+            Some(record) => record,
+            None => break,
+        };
+        let arguments_offset = align_up(8 + format_size, 8);
+        match (
+            record.get(8..8 + format_size),
+            record.get(arguments_offset.min(record.len())..),
+        ) {
+            (Some(format), Some(arguments)) => {
+                let format = format.split(|c| *c == 0).next().unwrap_or(&[]);
+                print_record(format, arguments, read_string, out)?;
+            }
+            _ => break,
+        }
+        offset += record_size;
+    }
+    Ok(())
+}
+
+// Width and precision come from the device (format string or `*` arguments),
+// a corrupted record must not make us format gigabytes of padding
+const MAX_FIELD_SIZE: usize = 4096;
+
+struct Spec {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    width: Option<usize>,
+    precision: Option<usize>,
+    wide: bool,
+    conversion: u8,
+}
+
+struct Arguments<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Arguments<'a> {
+    // Same layout as printf_arguments_size(...) in zluda_ptx_impl.cl
+    fn next(&mut self, size: usize) -> u64 {
+        let offset = align_up(self.offset, size);
+        self.offset = offset + size;
+        let value = if size == 8 {
+            read_u64(self.bytes, offset)
+        } else {
+            read_u32(self.bytes, offset).map(u64::from)
+        };
+        value.unwrap_or(0)
+    }
+}
+
+fn print_record(
+    format: &[u8],
+    arguments: &[u8],
+    read_string: &mut impl FnMut(u64) -> Option<Vec<u8>>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let mut arguments = Arguments {
+        bytes: arguments,
+        offset: 0,
+    };
+    let mut i = 0;
+    while i < format.len() {
+        if format[i] != b'%' {
+            let end = format[i..]
+                .iter()
+                .position(|c| *c == b'%')
+                .map_or(format.len(), |pos| i + pos);
+            out.write_all(&format[i..end])?;
+            i = end;
+            continue;
+        }
+        i += 1;
+        if format.get(i) == Some(&b'%') {
+            out.write_all(b"%")?;
+            i += 1;
+            continue;
+        }
+        let mut spec = Spec {
+            left: false,
+            zero: false,
+            plus: false,
+            space: false,
+            alternate: false,
+            width: None,
+            precision: None,
+            wide: false,
+            conversion: 0,
+        };
+        while let Some(flag) = format.get(i) {
+            match flag {
+                b'-' => spec.left = true,
+                b'0' => spec.zero = true,
+                b'+' => spec.plus = true,
+                b' ' => spec.space = true,
+                b'#' => spec.alternate = true,
+                _ => break,
+            }
+            i += 1;
+        }
+        if format.get(i) == Some(&b'*') {
+            let width = arguments.next(4) as i32;
+            spec.left |= width < 0;
+            spec.width = Some((width as i64).abs() as usize);
+            i += 1;
+        } else {
+            spec.width = parse_number(format, &mut i);
+        }
+        spec.width = spec.width.map(|width| width.min(MAX_FIELD_SIZE));
+        if format.get(i) == Some(&b'.') {
+            i += 1;
+            if format.get(i) == Some(&b'*') {
+                let precision = arguments.next(4) as i32;
+                spec.precision = if precision < 0 {
+                    None
+                } else {
+                    Some(precision as usize)
+                };
+                i += 1;
+            } else {
+                spec.precision = Some(parse_number(format, &mut i).unwrap_or(0));
+            }
+            spec.precision = spec.precision.map(|precision| precision.min(MAX_FIELD_SIZE));
+        }
+        while let Some(length) = format.get(i) {
+            match length {
+                b'h' => {}
+                b'l' | b'L' | b'z' | b'j' | b't' => spec.wide = true,
+                _ => break,
+            }
+            i += 1;
+        }
+        spec.conversion = match format.get(i) {
+            Some(conversion) => *conversion,
+            None => break,
+        };
+        i += 1;
+        print_argument(&spec, &mut arguments, read_string, out)?;
+    }
+    Ok(())
+}
+
+fn parse_number(format: &[u8], i: &mut usize) -> Option<usize> {
+    let start = *i;
+    while format.get(*i).map_or(false, |c| c.is_ascii_digit()) {
+        *i += 1;
+    }
+    std::str::from_utf8(&format[start..*i]).ok()?.parse().ok()
+}
+
+fn print_argument(
+    spec: &Spec,
+    arguments: &mut Arguments,
+    read_string: &mut impl FnMut(u64) -> Option<Vec<u8>>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let int_size = if spec.wide { 8 } else { 4 };
+    let (prefix, body) = match spec.conversion {
+        b'd' | b'i' => {
+            let value = arguments.next(int_size);
+            let value = if spec.wide {
+                value as i64
+            } else {
+                value as u32 as i32 as i64
+            };
+            let sign = if value < 0 {
+                "-"
+            } else if spec.plus {
+                "+"
+            } else if spec.space {
+                " "
+            } else {
+                ""
+            };
+            let digits = (value.wrapping_abs() as u64).to_string();
+            (sign, int_digits(digits, spec))
+        }
+        b'u' | b'o' | b'x' | b'X' => {
+            let value = arguments.next(int_size);
+            let digits = match spec.conversion {
+                b'u' => value.to_string(),
+                b'o' => format!("{:o}", value),
+                b'x' => format!("{:x}", value),
+                _ => format!("{:X}", value),
+            };
+            let prefix = match spec.conversion {
+                b'o' if spec.alternate => "0",
+                b'x' if spec.alternate && value != 0 => "0x",
+                b'X' if spec.alternate && value != 0 => "0X",
+                _ => "",
+            };
+            (prefix, int_digits(digits, spec))
+        }
+        b'c' => ("", ((arguments.next(int_size) as u8) as char).to_string()),
+        b'p' => ("0x", format!("{:x}", arguments.next(8))),
+        b's' => match arguments.next(8) {
+            0 => ("", "(null)".to_string()),
+            address => match read_string(address) {
+                Some(mut string) => {
+                    string.truncate(spec.precision.unwrap_or(string.len()));
+                    ("", String::from_utf8_lossy(&string).into_owned())
+                }
+                None => ("0x", format!("{:x}", address)),
+            },
+        },
+        conversion if is_float_conversion(conversion) => {
+            let value = f64::from_bits(arguments.next(8));
+            let sign = if value.is_sign_negative() && !value.is_nan() {
+                "-"
+            } else if spec.plus {
+                "+"
+            } else if spec.space {
+                " "
+            } else {
+                ""
+            };
+            (sign, float_digits(value.abs(), spec))
+        }
+        _ => return Ok(()),
+    };
+    let len = prefix.len() + body.len();
+    let padding = spec.width.unwrap_or(0).saturating_sub(len);
+    let numeric = spec.conversion != b'c' && spec.conversion != b's';
+    let zero_pad = spec.zero
+        && !spec.left
+        && numeric
+        && (spec.precision.is_none() || is_float_conversion(spec.conversion));
+    if spec.left {
+        write!(out, "{}{}{}", prefix, body, " ".repeat(padding))
+    } else if zero_pad && body.starts_with(|c: char| c.is_ascii_digit()) {
+        write!(out, "{}{}{}", prefix, "0".repeat(padding), body)
+    } else {
+        write!(out, "{}{}{}", " ".repeat(padding), prefix, body)
+    }
+}
+
+fn is_float_conversion(conversion: u8) -> bool {
+    matches!(
+        conversion,
+        b'f' | b'F' | b'e' | b'E' | b'g' | b'G' | b'a' | b'A'
+    )
+}
+
+fn int_digits(digits: String, spec: &Spec) -> String {
+    match spec.precision {
+        Some(0) if digits == "0" => String::new(),
+        Some(precision) if precision > digits.len() => {
+            format!("{}{}", "0".repeat(precision - digits.len()), digits)
+        }
+        _ => digits,
+    }
+}
+
+// Formats a non-negative value, hexadecimal floats (%a) are printed as %e
+fn float_digits(value: f64, spec: &Spec) -> String {
+    let upper = spec.conversion.is_ascii_uppercase();
+    let digits = if value.is_nan() {
+        "nan".to_string()
+    } else if value.is_infinite() {
+        "inf".to_string()
+    } else {
+        let precision = spec.precision.unwrap_or(6);
+        match spec.conversion.to_ascii_lowercase() {
+            b'f' => format!("{:.*}", precision, value),
+            b'g' => {
+                let precision = precision.max(1);
+                let exponent = exponent_of(value, precision - 1);
+                let digits = if exponent < -4 || exponent >= precision as i32 {
+                    exponential(value, precision - 1)
+                } else {
+                    format!("{:.*}", (precision as i32 - 1 - exponent) as usize, value)
+                };
+                if spec.alternate {
+                    digits
+                } else {
+                    strip_trailing_zeros(digits)
+                }
+            }
+            _ => exponential(value, precision),
+        }
+    };
+    if upper {
+        digits.to_ascii_uppercase()
+    } else {
+        digits
+    }
+}
+
+// Decimal exponent of the value after rounding to `precision` fractional digits
+// in the exponential notation
+fn exponent_of(value: f64, precision: usize) -> i32 {
+    let formatted = format!("{:.*e}", precision, value);
+    formatted
+        .split('e')
+        .nth(1)
+        .and_then(|exp| exp.parse().ok())
+        .unwrap_or(0)
+}
+
+// C prints at least two exponent digits with an explicit sign
+fn exponential(value: f64, precision: usize) -> String {
+    let formatted = format!("{:.*e}", precision, value);
+    let mut parts = formatted.splitn(2, 'e');
+    let mantissa = parts.next().unwrap_or("");
+    let exponent: i32 = parts.next().and_then(|exp| exp.parse().ok()).unwrap_or(0);
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+}
+
+fn strip_trailing_zeros(digits: String) -> String {
+    let (mantissa, exponent) = match digits.find('e') {
+        Some(pos) => digits.split_at(pos),
+        None => (digits.as_str(), ""),
+    };
+    if !mantissa.contains('.') {
+        return digits;
+    }
+    let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+    format!("{}{}", mantissa, exponent)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{CudaDriverFns, Zluda};
+    use super::super::CUresult;
+    use super::print_records;
+    use std::{cell::RefCell, ffi::c_void, mem, ptr};
+
+    thread_local! {
+        pub(super) static OUTPUT: RefCell<Vec<u8>> = RefCell::new(Vec::new());
+    }
+
+    // Builds a record the way device-side vprintf does
+    fn record(format: &str, arguments: &[u8]) -> Vec<u8> {
+        let format_size = format.len() + 1;
+        let arguments_offset = (8 + format_size + 7) & !7;
+        let record_size = arguments_offset + ((arguments.len() + 7) & !7);
+        let mut result = Vec::with_capacity(record_size);
+        result.extend_from_slice(&(record_size as u32).to_le_bytes());
+        result.extend_from_slice(&(format_size as u32).to_le_bytes());
+        result.extend_from_slice(format.as_bytes());
+        result.resize(arguments_offset, 0);
+        result.extend_from_slice(arguments);
+        result.resize(record_size, 0);
+        result
+    }
+
+    fn print(records: &[u8]) -> String {
+        let mut out = Vec::new();
+        let mut read_string = |address: u64| match address {
+            0x1000 => Some(b"device".to_vec()),
+            _ => None,
+        };
+        print_records(records, &mut read_string, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn prints_single_int() {
+        let records = record("%d\n", &(-42i32).to_le_bytes());
+        assert_eq!(print(&records), "-42\n");
+    }
+
+    #[test]
+    fn wide_arguments_are_aligned() {
+        let mut arguments = Vec::new();
+        arguments.extend_from_slice(&0xabu32.to_le_bytes());
+        arguments.extend_from_slice(&[0; 4]);
+        arguments.extend_from_slice(&(-3i64).to_le_bytes());
+        arguments.extend_from_slice(&2.5f64.to_le_bytes());
+        let mut records = record("%#x %lld %.2f|", &arguments);
+        let mut arguments = Vec::new();
+        arguments.extend_from_slice(&7i32.to_le_bytes());
+        arguments.extend_from_slice(&1u32.to_le_bytes());
+        arguments.extend_from_slice(&(-1.5f64).to_le_bytes());
+        arguments.extend_from_slice(&1234.5f64.to_le_bytes());
+        arguments.extend_from_slice(&0.0001f64.to_le_bytes());
+        records.extend(record("%5d|%-3u|%05.1f|%e|%g%%", &arguments));
+        assert_eq!(
+            print(&records),
+            "0xab -3 2.50|    7|1  |-01.5|1.234500e+03|0.0001%"
+        );
+    }
+
+    #[test]
+    fn width_and_precision_are_clamped() {
+        let mut arguments = Vec::new();
+        arguments.extend_from_slice(&i32::max_value().to_le_bytes());
+        arguments.extend_from_slice(&1u32.to_le_bytes());
+        arguments.extend_from_slice(&i32::max_value().to_le_bytes());
+        arguments.extend_from_slice(&0u32.to_le_bytes());
+        arguments.extend_from_slice(&1.0f64.to_le_bytes());
+        let records = record("%*d|%.*f|%99999d", &arguments);
+        let output = print(&records);
+        let fields = output.split('|').collect::<Vec<_>>();
+        assert_eq!(fields[0].len(), super::MAX_FIELD_SIZE);
+        assert_eq!(fields[1].len(), super::MAX_FIELD_SIZE + 2);
+        assert_eq!(fields[2].len(), super::MAX_FIELD_SIZE);
+    }
+
+    #[test]
+    fn strings_are_read_from_device() {
+        let mut arguments = Vec::new();
+        arguments.extend_from_slice(&0x1000u64.to_le_bytes());
+        arguments.extend_from_slice(&0x1000u64.to_le_bytes());
+        arguments.extend_from_slice(&0u64.to_le_bytes());
+        arguments.extend_from_slice(&0x2000u64.to_le_bytes());
+        let records = record("%s|%-8.3s|%s|%s", &arguments);
+        assert_eq!(print(&records), "device|dev     |(null)|0x2000");
+    }
+
+    const PRINTF_PTX: &'static str = "
+        .version 6.5
+        .target sm_30
+        .address_size 64
+
+        .extern .func (.param .b32 func_retval0) vprintf
+        (
+            .param .b64 vprintf_param_0,
+            .param .b64 vprintf_param_1
+        );
+
+        .global .align 1 .b8 format[6] = {118, 61, 37, 100, 10, 0};
+
+        .visible .entry print(
+            .param .u32 value
+        )
+        {
+            .local .align 8 .b8 __local_depot0[8];
+            .reg .b64       arguments;
+            .reg .b64       format_addr;
+            .reg .b32       temp;
+
+            mov.u64         arguments, __local_depot0;
+            ld.param.u32    temp, [value];
+            st.local.u32    [arguments], temp;
+            cvta.local.u64  arguments, arguments;
+            mov.u64         format_addr, format;
+            cvta.global.u64 format_addr, format_addr;
+            {
+            .param .b64 param0;
+            st.param.b64    [param0+0], format_addr;
+            .param .b64 param1;
+            st.param.b64    [param1+0], arguments;
+            .param .b32 retval0;
+            call.uni (retval0), vprintf, (param0, param1);
+            ld.param.b32    temp, [retval0+0];
+            }
+            ret;
+        }\0";
+
+    // CUDA prints to the process stdout, so this only runs on ZLUDA
+    #[test]
+    fn kernel_printf_is_printed_after_launch() {
+        assert_eq!(Zluda::cuInit(0), CUresult::CUDA_SUCCESS);
+        let mut ctx = ptr::null_mut();
+        assert_eq!(
+            Zluda::cuCtxCreate_v2(&mut ctx, 0, 0),
+            CUresult::CUDA_SUCCESS
+        );
+        let mut module = ptr::null_mut();
+        assert_eq!(
+            Zluda::cuModuleLoadData(&mut module, PRINTF_PTX.as_ptr() as *const _),
+            CUresult::CUDA_SUCCESS
+        );
+        let mut func = ptr::null_mut();
+        assert_eq!(
+            Zluda::cuModuleGetFunction(&mut func, module, b"print\0".as_ptr() as *const _),
+            CUresult::CUDA_SUCCESS
+        );
+        OUTPUT.with(|output| output.borrow_mut().clear());
+        let mut value = -12i32;
+        let mut params = [&mut value as *mut i32 as *mut c_void];
+        assert_eq!(
+            Zluda::cuLaunchKernel(
+                func,
+                1,
+                1,
+                1,
+                1,
+                1,
+                1,
+                0,
+                ptr::null_mut(),
+                params.as_mut_ptr(),
+                ptr::null_mut()
+            ),
+            CUresult::CUDA_SUCCESS
+        );
+        let output = OUTPUT.with(|output| mem::take(&mut *output.borrow_mut()));
+        assert_eq!(String::from_utf8(output).unwrap(), "v=-12\n");
+        // Cleanup
+        assert_eq!(Zluda::cuModuleUnload(module), CUresult::CUDA_SUCCESS);
+        assert_eq!(Zluda::cuCtxDestroy_v2(ctx), CUresult::CUDA_SUCCESS);
+    }
+}
@@ -11,6 +11,16 @@ pub type Stream = LiveCheck<StreamData>;
 pub const CU_STREAM_LEGACY: *mut Stream = 1 as *mut _;
 pub const CU_STREAM_PER_THREAD: *mut Stream = 2 as *mut _;
 
+// Code built with per-thread default stream calls *_ptsz and *_ptds entry
+// points, in those the NULL stream is the calling thread's default stream
+pub(crate) fn per_thread_default(hstream: *mut Stream) -> *mut Stream {
+    if hstream == ptr::null_mut() {
+        CU_STREAM_PER_THREAD
+    } else {
+        hstream
+    }
+}
+
 impl HasLivenessCookie for StreamData {
     #[cfg(target_pointer_width = "64")]
     const COOKIE: usize = 0x512097354de18d35;
@@ -34,6 +44,10 @@ impl HasLivenessCookie for StreamData {
 pub struct StreamData {
     pub context: *mut ContextData,
     pub queue: l0::CommandQueue,
+    // Only recorded for cuStreamGetFlags and cuStreamGetPriority, every
+    // stream is executed the same way
+    pub flags: u32,
+    pub priority: i32,
 }
 
 impl StreamData {
@@ -41,14 +55,18 @@ impl StreamData {
         Ok(StreamData {
             context: ptr::null_mut(),
             queue: l0::CommandQueue::new(ctx, dev)?,
+            flags: 0,
+            priority: 0,
         })
     }
-    pub fn new(ctx: &mut ContextData) -> Result<Self, CUresult> {
+    pub fn new(ctx: &mut ContextData, flags: u32, priority: i32) -> Result<Self, CUresult> {
         let l0_ctx = &mut unsafe { &mut *ctx.device }.l0_context;
         let l0_dev = &unsafe { &*ctx.device }.base;
         Ok(StreamData {
             context: ctx as *mut _,
             queue: l0::CommandQueue::new(l0_ctx, l0_dev)?,
+            flags,
+            priority,
         })
     }
 
@@ -80,9 +98,17 @@ pub(crate) fn get_ctx(hstream: *mut Stream, pctx: *mut *mut Context) -> Result<(
     Ok(())
 }
 
-pub(crate) fn create(phstream: *mut *mut Stream, _flags: u32) -> Result<(), CUresult> {
+pub(crate) fn create(phstream: *mut *mut Stream, flags: u32) -> Result<(), CUresult> {
+    create_with_priority(phstream, flags, 0)
+}
+
+pub(crate) fn create_with_priority(
+    phstream: *mut *mut Stream,
+    flags: u32,
+    priority: i32,
+) -> Result<(), CUresult> {
     let stream_ptr = GlobalState::lock_current_context(|ctx| {
-        let mut stream_box = Box::new(Stream::new(StreamData::new(ctx)?));
+        let mut stream_box = Box::new(Stream::new(StreamData::new(ctx, flags, priority)?));
         let stream_ptr = stream_box.as_mut().as_option_mut().unwrap() as *mut _;
         if !ctx.streams.insert(stream_ptr) {
             return Err(CUresult::CUDA_ERROR_UNKNOWN);
@@ -94,6 +120,22 @@ pub(crate) fn create(phstream: *mut *mut Stream, _flags: u32) -> Result<(), CUre
     Ok(())
 }
 
+pub(crate) fn get_flags(hstream: *mut Stream, flags: *mut u32) -> Result<(), CUresult> {
+    if flags == ptr::null_mut() {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    unsafe { *flags = GlobalState::lock_stream(hstream, |stream| stream.flags)? };
+    Ok(())
+}
+
+pub(crate) fn get_priority(hstream: *mut Stream, priority: *mut i32) -> Result<(), CUresult> {
+    if priority == ptr::null_mut() {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    unsafe { *priority = GlobalState::lock_stream(hstream, |stream| stream.priority)? };
+    Ok(())
+}
+
 pub(crate) fn destroy_v2(pstream: *mut Stream) -> Result<(), CUresult> {
     if pstream == ptr::null_mut() || pstream == CU_STREAM_LEGACY || pstream == CU_STREAM_PER_THREAD
     {
@@ -104,11 +146,16 @@ pub(crate) fn destroy_v2(pstream: *mut Stream) -> Result<(), CUresult> {
 
 #[cfg(test)]
 mod test {
-    use crate::cuda::CUstream;
+    use crate::cuda::{self, CUdeviceptr, CUstream};
 
-    use super::super::test::CudaDriverFns;
-    use super::super::CUresult;
-    use std::{ptr, thread};
+    use super::super::test::{CudaDriverFns, Zluda};
+    use super::super::{context, CUresult, GlobalState};
+    use std::{
+        ffi::c_void,
+        mem, ptr,
+        sync::{Arc, Barrier},
+        thread,
+    };
 
     const CU_STREAM_LEGACY: CUstream = 1 as *mut _;
     const CU_STREAM_PER_THREAD: CUstream = 2 as *mut _;
@@ -239,4 +286,131 @@ mod test {
         // Cleanup
         assert_eq!(T::cuCtxDestroy_v2(ctx), CUresult::CUDA_SUCCESS);
     }
+
+    cuda_driver_test!(stream_flags_are_recorded);
+
+    fn stream_flags_are_recorded<T: CudaDriverFns>() {
+        assert_eq!(T::cuInit(0), CUresult::CUDA_SUCCESS);
+        let mut ctx = ptr::null_mut();
+        assert_eq!(T::cuCtxCreate_v2(&mut ctx, 0, 0), CUresult::CUDA_SUCCESS);
+        let mut stream = ptr::null_mut();
+        // CU_STREAM_NON_BLOCKING
+        assert_eq!(T::cuStreamCreate(&mut stream, 1), CUresult::CUDA_SUCCESS);
+        let mut flags = u32::MAX;
+        assert_eq!(
+            T::cuStreamGetFlags(stream, &mut flags),
+            CUresult::CUDA_SUCCESS
+        );
+        assert_eq!(flags, 1);
+        let mut priority = i32::MAX;
+        assert_eq!(
+            T::cuStreamGetPriority(stream, &mut priority),
+            CUresult::CUDA_SUCCESS
+        );
+        assert_eq!(priority, 0);
+        let mut default_flags = u32::MAX;
+        assert_eq!(
+            T::cuStreamGetFlags(CU_STREAM_LEGACY, &mut default_flags),
+            CUresult::CUDA_SUCCESS
+        );
+        assert_eq!(default_flags, 0);
+        // Cleanup
+        assert_eq!(T::cuStreamDestroy_v2(stream), CUresult::CUDA_SUCCESS);
+        assert_eq!(T::cuCtxDestroy_v2(ctx), CUresult::CUDA_SUCCESS);
+    }
+
+    fn queue_of(stream: *mut super::Stream) -> usize {
+        GlobalState::lock_stream(stream, |stream| unsafe { stream.queue.as_ffi() } as usize)
+            .unwrap()
+    }
+
+    // Work submitted by two threads to their default streams goes to separate
+    // queues, so neither thread waits for the other's work
+    #[test]
+    fn per_thread_default_streams_dont_serialize() {
+        assert_eq!(Zluda::cuInit(0), CUresult::CUDA_SUCCESS);
+        let mut ctx = ptr::null_mut();
+        assert_eq!(
+            Zluda::cuCtxCreate_v2(&mut ctx, 0, 0),
+            CUresult::CUDA_SUCCESS
+        );
+        let main_queue = queue_of(super::per_thread_default(ptr::null_mut()));
+        assert_eq!(main_queue, queue_of(super::CU_STREAM_PER_THREAD));
+        assert_ne!(main_queue, queue_of(super::CU_STREAM_LEGACY));
+        let ctx_ptr = ctx as usize;
+        let other_queue = thread::spawn(move || {
+            assert_eq!(
+                cuda::cuCtxSetCurrent(ctx_ptr as *mut _),
+                CUresult::CUDA_SUCCESS
+            );
+            queue_of(super::per_thread_default(ptr::null_mut()))
+        })
+        .join()
+        .unwrap();
+        assert_ne!(main_queue, other_queue);
+        // Cleanup
+        assert_eq!(Zluda::cuCtxDestroy_v2(ctx), CUresult::CUDA_SUCCESS);
+    }
+
+    fn per_thread_stream_count(ctx: *mut c_void) -> usize {
+        GlobalState::lock(|_| {
+            let ctx = unsafe { &mut *(ctx as *mut context::Context) };
+            ctx.as_result_mut().unwrap().per_thread_streams.len()
+        })
+        .unwrap()
+    }
+
+    // Both threads have their default streams before either of them submits
+    // work, and each stream is released when its thread exits
+    #[test]
+    fn per_thread_streams_are_released_on_thread_exit() {
+        const LEN: usize = 1024;
+        assert_eq!(Zluda::cuInit(0), CUresult::CUDA_SUCCESS);
+        let mut ctx = ptr::null_mut();
+        assert_eq!(
+            Zluda::cuCtxCreate_v2(&mut ctx, 0, 0),
+            CUresult::CUDA_SUCCESS
+        );
+        let ctx_ptr = ctx as usize;
+        let barrier = Arc::new(Barrier::new(2));
+        let workers = (1..=2u32)
+            .map(|value| {
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    assert_eq!(
+                        cuda::cuCtxSetCurrent(ctx_ptr as *mut _),
+                        CUresult::CUDA_SUCCESS
+                    );
+                    let mut buffer = ptr::null_mut();
+                    assert_eq!(
+                        Zluda::cuMemAlloc_v2(&mut buffer, LEN * mem::size_of::<u32>()),
+                        CUresult::CUDA_SUCCESS
+                    );
+                    queue_of(super::CU_STREAM_PER_THREAD);
+                    barrier.wait();
+                    assert_eq!(
+                        cuda::cuMemsetD32_v2_ptds(CUdeviceptr(buffer as _), value, LEN),
+                        CUresult::CUDA_SUCCESS
+                    );
+                    let mut result = vec![0u32; LEN];
+                    assert_eq!(
+                        cuda::cuMemcpyDtoH_v2_ptds(
+                            result.as_mut_ptr() as *mut _,
+                            CUdeviceptr(buffer as _),
+                            LEN * mem::size_of::<u32>()
+                        ),
+                        CUresult::CUDA_SUCCESS
+                    );
+                    assert!(result.iter().all(|x| *x == value));
+                    assert_eq!(Zluda::cuMemFree_v2(buffer), CUresult::CUDA_SUCCESS);
+                })
+            })
+            .collect::<Vec<_>>();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(per_thread_stream_count(ctx), 0);
+        // Cleanup
+        assert_eq!(Zluda::cuCtxDestroy_v2(ctx), CUresult::CUDA_SUCCESS);
+    }
 }
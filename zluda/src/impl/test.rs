@@ -1,18 +1,33 @@
 #![allow(non_snake_case)]
 
 use crate::cuda as zluda;
+use crate::cuda::CUarray_format;
+use crate::cuda::CUdevice_attribute;
+use crate::cuda::CUfunction_attribute;
+use crate::cuda::CUlimit;
 use crate::cuda::CUstream;
+use crate::cuda::CUtexObject;
 use crate::cuda::CUuuid;
+use crate::cuda::CUDA_RESOURCE_DESC;
+use crate::cuda::CUDA_RESOURCE_VIEW_DESC;
+use crate::cuda::CUDA_TEXTURE_DESC;
 use crate::{
     cuda::{CUdevice, CUdeviceptr},
     r#impl::CUresult,
 };
 use ::std::{
     ffi::c_void,
-    os::raw::{c_int, c_uint},
+    mem,
+    os::raw::{c_char, c_int, c_uint},
 };
 use cuda_driver_sys as cuda;
 
+// Added in CUDA 11.4, cuda_driver_sys does not declare it yet
+extern "C" {
+    #[link_name = "cuDeviceGetUuid_v2"]
+    fn cuda_cuDeviceGetUuid_v2(uuid: *mut cuda::CUuuid, dev: cuda::CUdevice) -> cuda::CUresult;
+}
+
 #[macro_export]
 macro_rules! cuda_driver_test {
     ($func:ident) => {
@@ -37,13 +52,75 @@ pub trait CudaDriverFns {
     fn cuCtxPopCurrent_v2(pctx: *mut *mut c_void) -> CUresult;
     fn cuCtxGetApiVersion(ctx: *mut c_void, version: *mut c_uint) -> CUresult;
     fn cuCtxGetCurrent(pctx: *mut *mut c_void) -> CUresult;
+    fn cuCtxSetLimit(limit: CUlimit, value: usize) -> CUresult;
+    fn cuCtxGetLimit(pvalue: *mut usize, limit: CUlimit) -> CUresult;
     fn cuMemAlloc_v2(dptr: *mut *mut c_void, bytesize: usize) -> CUresult;
     fn cuDeviceGetUuid(uuid: *mut CUuuid, dev: c_int) -> CUresult;
+    fn cuDeviceGetUuid_v2(uuid: *mut CUuuid, dev: c_int) -> CUresult;
     fn cuDevicePrimaryCtxGetState(dev: c_int, flags: *mut c_uint, active: *mut c_int) -> CUresult;
+    fn cuDeviceGetAttribute(pi: *mut c_int, attrib: CUdevice_attribute, dev: c_int) -> CUresult;
     fn cuStreamGetCtx(hStream: CUstream, pctx: *mut *mut c_void) -> CUresult;
     fn cuStreamCreate(stream: *mut CUstream, flags: c_uint) -> CUresult;
+    fn cuStreamGetFlags(stream: CUstream, flags: *mut c_uint) -> CUresult;
+    fn cuStreamGetPriority(stream: CUstream, priority: *mut c_int) -> CUresult;
     fn cuMemFree_v2(mem: *mut c_void) -> CUresult;
     fn cuStreamDestroy_v2(stream: CUstream) -> CUresult;
+    fn cuCtxSynchronize() -> CUresult;
+    fn cuProfilerStart() -> CUresult;
+    fn cuProfilerStop() -> CUresult;
+    fn cuMemcpyDtoH_v2(dst: *mut c_void, src: *mut c_void, bytesize: usize) -> CUresult;
+    fn cuModuleLoadData(module: *mut *mut c_void, image: *const c_void) -> CUresult;
+    fn cuModuleUnload(hmod: *mut c_void) -> CUresult;
+    fn cuModuleGetFunction(
+        hfunc: *mut *mut c_void,
+        hmod: *mut c_void,
+        name: *const c_char,
+    ) -> CUresult;
+    fn cuFuncGetAttribute(
+        pi: *mut c_int,
+        attrib: CUfunction_attribute,
+        hfunc: *mut c_void,
+    ) -> CUresult;
+    fn cuFuncSetAttribute(
+        hfunc: *mut c_void,
+        attrib: CUfunction_attribute,
+        value: c_int,
+    ) -> CUresult;
+    fn cuLaunchKernel(
+        f: *mut c_void,
+        grid_dim_x: c_uint,
+        grid_dim_y: c_uint,
+        grid_dim_z: c_uint,
+        block_dim_x: c_uint,
+        block_dim_y: c_uint,
+        block_dim_z: c_uint,
+        shared_mem_bytes: c_uint,
+        stream: CUstream,
+        kernel_params: *mut *mut c_void,
+        extra: *mut *mut c_void,
+    ) -> CUresult;
+    fn cuModuleGetTexRef(
+        ptexref: *mut *mut c_void,
+        hmod: *mut c_void,
+        name: *const c_char,
+    ) -> CUresult;
+    fn cuTexRefSetAddress_v2(
+        byte_offset: *mut usize,
+        texref: *mut c_void,
+        dptr: *mut c_void,
+        bytes: usize,
+    ) -> CUresult;
+    fn cuTexRefGetAddress_v2(pdptr: *mut *mut c_void, texref: *mut c_void) -> CUresult;
+    fn cuTexRefSetFormat(texref: *mut c_void, fmt: CUarray_format, num_channels: c_int)
+        -> CUresult;
+    fn cuMemcpyHtoD_v2(dst: *mut c_void, src: *const c_void, bytesize: usize) -> CUresult;
+    fn cuTexObjectCreate(
+        tex_object: *mut CUtexObject,
+        res_desc: *const CUDA_RESOURCE_DESC,
+        tex_desc: *const CUDA_TEXTURE_DESC,
+        res_view_desc: *const CUDA_RESOURCE_VIEW_DESC,
+    ) -> CUresult;
+    fn cuTexObjectDestroy(tex_object: CUtexObject) -> CUresult;
 }
 
 pub struct Zluda();
@@ -72,6 +149,14 @@ impl CudaDriverFns for Zluda {
     fn cuCtxGetCurrent(pctx: *mut *mut c_void) -> CUresult {
         zluda::cuCtxGetCurrent(pctx as *mut _)
     }
+
+    fn cuCtxSetLimit(limit: CUlimit, value: usize) -> CUresult {
+        zluda::cuCtxSetLimit(limit, value)
+    }
+
+    fn cuCtxGetLimit(pvalue: *mut usize, limit: CUlimit) -> CUresult {
+        zluda::cuCtxGetLimit(pvalue, limit)
+    }
     fn cuMemAlloc_v2(dptr: *mut *mut c_void, bytesize: usize) -> CUresult {
         zluda::cuMemAlloc_v2(dptr as *mut _, bytesize)
     }
@@ -80,10 +165,18 @@ impl CudaDriverFns for Zluda {
         zluda::cuDeviceGetUuid(uuid, CUdevice(dev))
     }
 
+    fn cuDeviceGetUuid_v2(uuid: *mut CUuuid, dev: c_int) -> CUresult {
+        zluda::cuDeviceGetUuid_v2(uuid, CUdevice(dev))
+    }
+
     fn cuDevicePrimaryCtxGetState(dev: c_int, flags: *mut c_uint, active: *mut c_int) -> CUresult {
         zluda::cuDevicePrimaryCtxGetState(CUdevice(dev), flags, active)
     }
 
+    fn cuDeviceGetAttribute(pi: *mut c_int, attrib: CUdevice_attribute, dev: c_int) -> CUresult {
+        zluda::cuDeviceGetAttribute(pi, attrib, CUdevice(dev))
+    }
+
     fn cuStreamGetCtx(hStream: CUstream, pctx: *mut *mut c_void) -> CUresult {
         zluda::cuStreamGetCtx(hStream, pctx as _)
     }
@@ -92,6 +185,14 @@ impl CudaDriverFns for Zluda {
         zluda::cuStreamCreate(stream, flags)
     }
 
+    fn cuStreamGetFlags(stream: CUstream, flags: *mut c_uint) -> CUresult {
+        zluda::cuStreamGetFlags(stream, flags)
+    }
+
+    fn cuStreamGetPriority(stream: CUstream, priority: *mut c_int) -> CUresult {
+        zluda::cuStreamGetPriority(stream, priority)
+    }
+
     fn cuMemFree_v2(dptr: *mut c_void) -> CUresult {
         zluda::cuMemFree_v2(CUdeviceptr(dptr as _))
     }
@@ -99,6 +200,128 @@ impl CudaDriverFns for Zluda {
     fn cuStreamDestroy_v2(stream: CUstream) -> CUresult {
         zluda::cuStreamDestroy_v2(stream)
     }
+
+    fn cuCtxSynchronize() -> CUresult {
+        zluda::cuCtxSynchronize()
+    }
+
+    fn cuProfilerStart() -> CUresult {
+        zluda::cuProfilerStart()
+    }
+
+    fn cuProfilerStop() -> CUresult {
+        zluda::cuProfilerStop()
+    }
+
+    fn cuMemcpyDtoH_v2(dst: *mut c_void, src: *mut c_void, bytesize: usize) -> CUresult {
+        zluda::cuMemcpyDtoH_v2(dst, CUdeviceptr(src as _), bytesize)
+    }
+
+    fn cuModuleLoadData(module: *mut *mut c_void, image: *const c_void) -> CUresult {
+        zluda::cuModuleLoadData(module as *mut _, image)
+    }
+
+    fn cuModuleUnload(hmod: *mut c_void) -> CUresult {
+        zluda::cuModuleUnload(hmod as *mut _)
+    }
+
+    fn cuModuleGetFunction(
+        hfunc: *mut *mut c_void,
+        hmod: *mut c_void,
+        name: *const c_char,
+    ) -> CUresult {
+        zluda::cuModuleGetFunction(hfunc as *mut _, hmod as *mut _, name)
+    }
+
+    fn cuFuncGetAttribute(
+        pi: *mut c_int,
+        attrib: CUfunction_attribute,
+        hfunc: *mut c_void,
+    ) -> CUresult {
+        zluda::cuFuncGetAttribute(pi, attrib, hfunc as *mut _)
+    }
+
+    fn cuFuncSetAttribute(
+        hfunc: *mut c_void,
+        attrib: CUfunction_attribute,
+        value: c_int,
+    ) -> CUresult {
+        zluda::cuFuncSetAttribute(hfunc as *mut _, attrib, value)
+    }
+
+    fn cuLaunchKernel(
+        f: *mut c_void,
+        grid_dim_x: c_uint,
+        grid_dim_y: c_uint,
+        grid_dim_z: c_uint,
+        block_dim_x: c_uint,
+        block_dim_y: c_uint,
+        block_dim_z: c_uint,
+        shared_mem_bytes: c_uint,
+        stream: CUstream,
+        kernel_params: *mut *mut c_void,
+        extra: *mut *mut c_void,
+    ) -> CUresult {
+        zluda::cuLaunchKernel(
+            f as *mut _,
+            grid_dim_x,
+            grid_dim_y,
+            grid_dim_z,
+            block_dim_x,
+            block_dim_y,
+            block_dim_z,
+            shared_mem_bytes,
+            stream,
+            kernel_params,
+            extra,
+        )
+    }
+
+    fn cuModuleGetTexRef(
+        ptexref: *mut *mut c_void,
+        hmod: *mut c_void,
+        name: *const c_char,
+    ) -> CUresult {
+        zluda::cuModuleGetTexRef(ptexref as *mut _, hmod as *mut _, name)
+    }
+
+    fn cuTexRefSetAddress_v2(
+        byte_offset: *mut usize,
+        texref: *mut c_void,
+        dptr: *mut c_void,
+        bytes: usize,
+    ) -> CUresult {
+        zluda::cuTexRefSetAddress_v2(byte_offset, texref as *mut _, CUdeviceptr(dptr as _), bytes)
+    }
+
+    fn cuTexRefGetAddress_v2(pdptr: *mut *mut c_void, texref: *mut c_void) -> CUresult {
+        zluda::cuTexRefGetAddress_v2(pdptr as *mut _, texref as *mut _)
+    }
+
+    fn cuTexRefSetFormat(
+        texref: *mut c_void,
+        fmt: CUarray_format,
+        num_channels: c_int,
+    ) -> CUresult {
+        zluda::cuTexRefSetFormat(texref as *mut _, fmt, num_channels)
+    }
+
+    fn cuMemcpyHtoD_v2(dst: *mut c_void, src: *const c_void, bytesize: usize) -> CUresult {
+        zluda::cuMemcpyHtoD_v2(CUdeviceptr(dst as _), src, bytesize)
+    }
+
+    fn cuTexObjectCreate(
+        tex_object: *mut CUtexObject,
+        res_desc: *const CUDA_RESOURCE_DESC,
+        tex_desc: *const CUDA_TEXTURE_DESC,
+        res_view_desc: *const CUDA_RESOURCE_VIEW_DESC,
+    ) -> CUresult {
+        zluda::cuTexObjectCreate(tex_object, res_desc, tex_desc, res_view_desc)
+    }
+
+    fn cuTexObjectDestroy(tex_object: CUtexObject) -> CUresult {
+        zluda::cuTexObjectDestroy(tex_object)
+    }
 }
 
 pub struct Cuda();
@@ -127,6 +350,14 @@ impl CudaDriverFns for Cuda {
     fn cuCtxGetCurrent(pctx: *mut *mut c_void) -> CUresult {
         unsafe { CUresult(cuda::cuCtxGetCurrent(pctx as *mut _) as c_uint) }
     }
+
+    fn cuCtxSetLimit(limit: CUlimit, value: usize) -> CUresult {
+        unsafe { CUresult(cuda::cuCtxSetLimit(mem::transmute(limit), value) as c_uint) }
+    }
+
+    fn cuCtxGetLimit(pvalue: *mut usize, limit: CUlimit) -> CUresult {
+        unsafe { CUresult(cuda::cuCtxGetLimit(pvalue, mem::transmute(limit)) as c_uint) }
+    }
     fn cuMemAlloc_v2(dptr: *mut *mut c_void, bytesize: usize) -> CUresult {
         unsafe { CUresult(cuda::cuMemAlloc_v2(dptr as *mut _, bytesize) as c_uint) }
     }
@@ -135,10 +366,18 @@ impl CudaDriverFns for Cuda {
         unsafe { CUresult(cuda::cuDeviceGetUuid(uuid as *mut _, dev) as c_uint) }
     }
 
+    fn cuDeviceGetUuid_v2(uuid: *mut CUuuid, dev: c_int) -> CUresult {
+        unsafe { CUresult(cuda_cuDeviceGetUuid_v2(uuid as *mut _, dev) as c_uint) }
+    }
+
     fn cuDevicePrimaryCtxGetState(dev: c_int, flags: *mut c_uint, active: *mut c_int) -> CUresult {
         unsafe { CUresult(cuda::cuDevicePrimaryCtxGetState(dev, flags, active) as c_uint) }
     }
 
+    fn cuDeviceGetAttribute(pi: *mut c_int, attrib: CUdevice_attribute, dev: c_int) -> CUresult {
+        unsafe { CUresult(cuda::cuDeviceGetAttribute(pi, mem::transmute(attrib), dev) as c_uint) }
+    }
+
     fn cuStreamGetCtx(hStream: CUstream, pctx: *mut *mut c_void) -> CUresult {
         unsafe { CUresult(cuda::cuStreamGetCtx(hStream as _, pctx as _) as c_uint) }
     }
@@ -147,6 +386,14 @@ impl CudaDriverFns for Cuda {
         unsafe { CUresult(cuda::cuStreamCreate(stream as _, flags as _) as c_uint) }
     }
 
+    fn cuStreamGetFlags(stream: CUstream, flags: *mut c_uint) -> CUresult {
+        unsafe { CUresult(cuda::cuStreamGetFlags(stream as _, flags) as c_uint) }
+    }
+
+    fn cuStreamGetPriority(stream: CUstream, priority: *mut c_int) -> CUresult {
+        unsafe { CUresult(cuda::cuStreamGetPriority(stream as _, priority) as c_uint) }
+    }
+
     fn cuMemFree_v2(mem: *mut c_void) -> CUresult {
         unsafe { CUresult(cuda::cuMemFree_v2(mem as _) as c_uint) }
     }
@@ -154,4 +401,159 @@ impl CudaDriverFns for Cuda {
     fn cuStreamDestroy_v2(stream: CUstream) -> CUresult {
         unsafe { CUresult(cuda::cuStreamDestroy_v2(stream as _) as c_uint) }
     }
+
+    fn cuCtxSynchronize() -> CUresult {
+        unsafe { CUresult(cuda::cuCtxSynchronize() as c_uint) }
+    }
+
+    fn cuProfilerStart() -> CUresult {
+        unsafe { CUresult(cuda::cuProfilerStart() as c_uint) }
+    }
+
+    fn cuProfilerStop() -> CUresult {
+        unsafe { CUresult(cuda::cuProfilerStop() as c_uint) }
+    }
+
+    fn cuMemcpyDtoH_v2(dst: *mut c_void, src: *mut c_void, bytesize: usize) -> CUresult {
+        unsafe { CUresult(cuda::cuMemcpyDtoH_v2(dst, src as _, bytesize) as c_uint) }
+    }
+
+    fn cuModuleLoadData(module: *mut *mut c_void, image: *const c_void) -> CUresult {
+        unsafe { CUresult(cuda::cuModuleLoadData(module as *mut _, image) as c_uint) }
+    }
+
+    fn cuModuleUnload(hmod: *mut c_void) -> CUresult {
+        unsafe { CUresult(cuda::cuModuleUnload(hmod as *mut _) as c_uint) }
+    }
+
+    fn cuModuleGetFunction(
+        hfunc: *mut *mut c_void,
+        hmod: *mut c_void,
+        name: *const c_char,
+    ) -> CUresult {
+        unsafe {
+            CUresult(cuda::cuModuleGetFunction(hfunc as *mut _, hmod as *mut _, name) as c_uint)
+        }
+    }
+
+    fn cuFuncGetAttribute(
+        pi: *mut c_int,
+        attrib: CUfunction_attribute,
+        hfunc: *mut c_void,
+    ) -> CUresult {
+        unsafe {
+            CUresult(
+                cuda::cuFuncGetAttribute(pi, mem::transmute(attrib), hfunc as *mut _) as c_uint,
+            )
+        }
+    }
+
+    fn cuFuncSetAttribute(
+        hfunc: *mut c_void,
+        attrib: CUfunction_attribute,
+        value: c_int,
+    ) -> CUresult {
+        unsafe {
+            CUresult(
+                cuda::cuFuncSetAttribute(hfunc as *mut _, mem::transmute(attrib), value) as c_uint,
+            )
+        }
+    }
+
+    fn cuLaunchKernel(
+        f: *mut c_void,
+        grid_dim_x: c_uint,
+        grid_dim_y: c_uint,
+        grid_dim_z: c_uint,
+        block_dim_x: c_uint,
+        block_dim_y: c_uint,
+        block_dim_z: c_uint,
+        shared_mem_bytes: c_uint,
+        stream: CUstream,
+        kernel_params: *mut *mut c_void,
+        extra: *mut *mut c_void,
+    ) -> CUresult {
+        unsafe {
+            CUresult(cuda::cuLaunchKernel(
+                f as *mut _,
+                grid_dim_x,
+                grid_dim_y,
+                grid_dim_z,
+                block_dim_x,
+                block_dim_y,
+                block_dim_z,
+                shared_mem_bytes,
+                stream as *mut _,
+                kernel_params,
+                extra,
+            ) as c_uint)
+        }
+    }
+
+    fn cuModuleGetTexRef(
+        ptexref: *mut *mut c_void,
+        hmod: *mut c_void,
+        name: *const c_char,
+    ) -> CUresult {
+        unsafe {
+            CUresult(cuda::cuModuleGetTexRef(ptexref as *mut _, hmod as *mut _, name) as c_uint)
+        }
+    }
+
+    fn cuTexRefSetAddress_v2(
+        byte_offset: *mut usize,
+        texref: *mut c_void,
+        dptr: *mut c_void,
+        bytes: usize,
+    ) -> CUresult {
+        unsafe {
+            CUresult(
+                cuda::cuTexRefSetAddress_v2(byte_offset, texref as *mut _, dptr as _, bytes)
+                    as c_uint,
+            )
+        }
+    }
+
+    fn cuTexRefGetAddress_v2(pdptr: *mut *mut c_void, texref: *mut c_void) -> CUresult {
+        unsafe {
+            CUresult(cuda::cuTexRefGetAddress_v2(pdptr as *mut _, texref as *mut _) as c_uint)
+        }
+    }
+
+    fn cuTexRefSetFormat(
+        texref: *mut c_void,
+        fmt: CUarray_format,
+        num_channels: c_int,
+    ) -> CUresult {
+        unsafe {
+            CUresult(
+                cuda::cuTexRefSetFormat(texref as *mut _, mem::transmute(fmt), num_channels)
+                    as c_uint,
+            )
+        }
+    }
+
+    fn cuMemcpyHtoD_v2(dst: *mut c_void, src: *const c_void, bytesize: usize) -> CUresult {
+        unsafe { CUresult(cuda::cuMemcpyHtoD_v2(dst as _, src, bytesize) as c_uint) }
+    }
+
+    fn cuTexObjectCreate(
+        tex_object: *mut CUtexObject,
+        res_desc: *const CUDA_RESOURCE_DESC,
+        tex_desc: *const CUDA_TEXTURE_DESC,
+        res_view_desc: *const CUDA_RESOURCE_VIEW_DESC,
+    ) -> CUresult {
+        unsafe {
+            CUresult(cuda::cuTexObjectCreate(
+                tex_object,
+                res_desc as *const _,
+                tex_desc as *const _,
+                res_view_desc as *const _,
+            ) as c_uint)
+        }
+    }
+
+    fn cuTexObjectDestroy(tex_object: CUtexObject) -> CUresult {
+        unsafe { CUresult(cuda::cuTexObjectDestroy(tex_object) as c_uint) }
+    }
 }
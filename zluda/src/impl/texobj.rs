@@ -0,0 +1,379 @@
+use super::{context::ContextData, CUresult, GlobalState, HasLivenessCookie, LiveCheck};
+use crate::cuda::{
+    CUaddress_mode, CUarray_format, CUfilter_mode, CUresourcetype, CUtexObject, CUDA_RESOURCE_DESC,
+    CUDA_RESOURCE_VIEW_DESC, CUDA_TEXTURE_DESC,
+};
+use l0::sys::{
+    ze_image_format_layout_t, ze_image_format_swizzle_t, ze_image_format_t, ze_image_format_type_t,
+    ze_sampler_address_mode_t, ze_sampler_filter_mode_t,
+};
+use std::{ffi::c_void, ptr};
+
+const CU_TRSF_READ_AS_INTEGER: u32 = 1;
+const CU_TRSF_NORMALIZED_COORDINATES: u32 = 2;
+
+// Texture object handles are pointers to this, checked for liveness on launch
+pub type TexObject = LiveCheck<Texture>;
+
+impl HasLivenessCookie for Texture {
+    #[cfg(target_pointer_width = "64")]
+    const COOKIE: usize = 0x47c1e5a90d3b6f28;
+
+    #[cfg(target_pointer_width = "32")]
+    const COOKIE: usize = 0x0d3b6f28;
+
+    const LIVENESS_FAIL: CUresult = CUresult::CUDA_ERROR_INVALID_VALUE;
+
+    fn try_drop(&mut self) -> Result<(), CUresult> {
+        Ok(())
+    }
+}
+
+// Kernels sample textures from a 1D image (see resolve_textures in
+// ptx/src/translate.rs). CUDA textures read linear memory directly, so the
+// image is refreshed from that memory before every launch using it
+pub struct Texture {
+    pub(crate) image: l0::Image,
+    pub(crate) sampler: l0::Sampler,
+    pub(crate) address: *mut c_void,
+}
+
+// Only 1D textures over linear memory are supported, resource views are not
+pub(crate) fn create(
+    tex_object: *mut CUtexObject,
+    res_desc: *const CUDA_RESOURCE_DESC,
+    tex_desc: *const CUDA_TEXTURE_DESC,
+    res_view_desc: *const CUDA_RESOURCE_VIEW_DESC,
+) -> Result<(), CUresult> {
+    if tex_object == ptr::null_mut() || res_desc == ptr::null() || tex_desc == ptr::null() {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    if res_view_desc != ptr::null() {
+        return Err(CUresult::CUDA_ERROR_NOT_SUPPORTED);
+    }
+    let (res_desc, tex_desc) = unsafe { (&*res_desc, &*tex_desc) };
+    if res_desc.resType != CUresourcetype::CU_RESOURCE_TYPE_LINEAR {
+        return Err(CUresult::CUDA_ERROR_NOT_SUPPORTED);
+    }
+    let linear = unsafe { res_desc.res.linear };
+    let texture = GlobalState::lock_current_context(|ctx| {
+        Texture::new(
+            ctx,
+            linear.devPtr.0 as *mut c_void,
+            linear.sizeInBytes,
+            linear.format,
+            linear.numChannels,
+            tex_desc.filterMode,
+            tex_desc.addressMode[0],
+            tex_desc.flags,
+        )
+    })??;
+    let texture = Box::into_raw(Box::new(TexObject::new(texture)));
+    unsafe { *tex_object = texture as CUtexObject };
+    Ok(())
+}
+
+pub(crate) fn destroy(tex_object: CUtexObject) -> Result<(), CUresult> {
+    if tex_object == 0 {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    GlobalState::lock(|_| TexObject::destroy_impl(tex_object as *mut TexObject))?
+}
+
+impl Texture {
+    pub(crate) fn new(
+        ctx: &mut ContextData,
+        address: *mut c_void,
+        size_in_bytes: usize,
+        format: CUarray_format,
+        channels: u32,
+        filter_mode: CUfilter_mode,
+        address_mode: CUaddress_mode,
+        flags: u32,
+    ) -> Result<Self, CUresult> {
+        let element_size = match channels {
+            1 | 2 | 4 => format_size(format)? * channels as usize,
+            _ => return Err(CUresult::CUDA_ERROR_INVALID_VALUE),
+        };
+        let width = size_in_bytes / element_size;
+        if address == ptr::null_mut() || width == 0 {
+            return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+        }
+        let normalized = flags & CU_TRSF_NORMALIZED_COORDINATES != 0;
+        let dev = unsafe { &mut *ctx.device };
+        let image = l0::Image::new_1d(
+            &mut dev.l0_context,
+            &dev.base,
+            image_format(format, channels, flags)?,
+            width as u64,
+        )?;
+        let sampler = l0::Sampler::new(
+            &mut dev.l0_context,
+            &dev.base,
+            sampler_address_mode(address_mode, normalized),
+            sampler_filter_mode(filter_mode),
+            normalized,
+        )?;
+        Ok(Texture {
+            image,
+            sampler,
+            address,
+        })
+    }
+}
+
+fn format_size(format: CUarray_format) -> Result<usize, CUresult> {
+    Ok(match format {
+        CUarray_format::CU_AD_FORMAT_UNSIGNED_INT8 | CUarray_format::CU_AD_FORMAT_SIGNED_INT8 => 1,
+        CUarray_format::CU_AD_FORMAT_UNSIGNED_INT16
+        | CUarray_format::CU_AD_FORMAT_SIGNED_INT16
+        | CUarray_format::CU_AD_FORMAT_HALF => 2,
+        CUarray_format::CU_AD_FORMAT_UNSIGNED_INT32
+        | CUarray_format::CU_AD_FORMAT_SIGNED_INT32
+        | CUarray_format::CU_AD_FORMAT_FLOAT => 4,
+        _ => return Err(CUresult::CUDA_ERROR_INVALID_VALUE),
+    })
+}
+
+// 8 and 16 bit integers are read as normalized floats, unless the texture
+// asks for integers
+fn image_format(
+    format: CUarray_format,
+    channels: u32,
+    flags: u32,
+) -> Result<ze_image_format_t, CUresult> {
+    let read_as_integer = flags & CU_TRSF_READ_AS_INTEGER != 0;
+    let layout = match (format_size(format)?, channels) {
+        (1, 1) => ze_image_format_layout_t::ZE_IMAGE_FORMAT_LAYOUT_8,
+        (1, 2) => ze_image_format_layout_t::ZE_IMAGE_FORMAT_LAYOUT_8_8,
+        (1, 4) => ze_image_format_layout_t::ZE_IMAGE_FORMAT_LAYOUT_8_8_8_8,
+        (2, 1) => ze_image_format_layout_t::ZE_IMAGE_FORMAT_LAYOUT_16,
+        (2, 2) => ze_image_format_layout_t::ZE_IMAGE_FORMAT_LAYOUT_16_16,
+        (2, 4) => ze_image_format_layout_t::ZE_IMAGE_FORMAT_LAYOUT_16_16_16_16,
+        (4, 1) => ze_image_format_layout_t::ZE_IMAGE_FORMAT_LAYOUT_32,
+        (4, 2) => ze_image_format_layout_t::ZE_IMAGE_FORMAT_LAYOUT_32_32,
+        (4, 4) => ze_image_format_layout_t::ZE_IMAGE_FORMAT_LAYOUT_32_32_32_32,
+        _ => return Err(CUresult::CUDA_ERROR_INVALID_VALUE),
+    };
+    let type_ = match format {
+        CUarray_format::CU_AD_FORMAT_UNSIGNED_INT8
+        | CUarray_format::CU_AD_FORMAT_UNSIGNED_INT16
+            if !read_as_integer =>
+        {
+            ze_image_format_type_t::ZE_IMAGE_FORMAT_TYPE_UNORM
+        }
+        CUarray_format::CU_AD_FORMAT_SIGNED_INT8 | CUarray_format::CU_AD_FORMAT_SIGNED_INT16
+            if !read_as_integer =>
+        {
+            ze_image_format_type_t::ZE_IMAGE_FORMAT_TYPE_SNORM
+        }
+        CUarray_format::CU_AD_FORMAT_UNSIGNED_INT8
+        | CUarray_format::CU_AD_FORMAT_UNSIGNED_INT16
+        | CUarray_format::CU_AD_FORMAT_UNSIGNED_INT32 => {
+            ze_image_format_type_t::ZE_IMAGE_FORMAT_TYPE_UINT
+        }
+        CUarray_format::CU_AD_FORMAT_SIGNED_INT8
+        | CUarray_format::CU_AD_FORMAT_SIGNED_INT16
+        | CUarray_format::CU_AD_FORMAT_SIGNED_INT32 => {
+            ze_image_format_type_t::ZE_IMAGE_FORMAT_TYPE_SINT
+        }
+        _ => ze_image_format_type_t::ZE_IMAGE_FORMAT_TYPE_FLOAT,
+    };
+    let swizzle = |channel: u32, component: ze_image_format_swizzle_t| {
+        if channel < channels {
+            component
+        } else {
+            ze_image_format_swizzle_t::ZE_IMAGE_FORMAT_SWIZZLE_0
+        }
+    };
+    Ok(ze_image_format_t {
+        layout,
+        type_,
+        x: swizzle(0, ze_image_format_swizzle_t::ZE_IMAGE_FORMAT_SWIZZLE_R),
+        y: swizzle(1, ze_image_format_swizzle_t::ZE_IMAGE_FORMAT_SWIZZLE_G),
+        z: swizzle(2, ze_image_format_swizzle_t::ZE_IMAGE_FORMAT_SWIZZLE_B),
+        w: swizzle(3, ze_image_format_swizzle_t::ZE_IMAGE_FORMAT_SWIZZLE_A),
+    })
+}
+
+// Wrapping and mirroring only exist for normalized coordinates, CUDA clamps
+// unnormalized ones whatever the address mode is
+fn sampler_address_mode(mode: CUaddress_mode, normalized: bool) -> ze_sampler_address_mode_t {
+    match mode {
+        CUaddress_mode::CU_TR_ADDRESS_MODE_WRAP if normalized => {
+            ze_sampler_address_mode_t::ZE_SAMPLER_ADDRESS_MODE_REPEAT
+        }
+        CUaddress_mode::CU_TR_ADDRESS_MODE_MIRROR if normalized => {
+            ze_sampler_address_mode_t::ZE_SAMPLER_ADDRESS_MODE_MIRROR
+        }
+        CUaddress_mode::CU_TR_ADDRESS_MODE_BORDER => {
+            ze_sampler_address_mode_t::ZE_SAMPLER_ADDRESS_MODE_CLAMP_TO_BORDER
+        }
+        _ => ze_sampler_address_mode_t::ZE_SAMPLER_ADDRESS_MODE_CLAMP,
+    }
+}
+
+fn sampler_filter_mode(mode: CUfilter_mode) -> ze_sampler_filter_mode_t {
+    match mode {
+        CUfilter_mode::CU_TR_FILTER_MODE_LINEAR => {
+            ze_sampler_filter_mode_t::ZE_SAMPLER_FILTER_MODE_LINEAR
+        }
+        _ => ze_sampler_filter_mode_t::ZE_SAMPLER_FILTER_MODE_NEAREST,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::CudaDriverFns;
+    use super::super::CUresult;
+    use crate::cuda::{
+        CUaddress_mode, CUarray_format, CUdeviceptr, CUfilter_mode, CUresourcetype,
+        CUDA_RESOURCE_DESC, CUDA_TEXTURE_DESC,
+    };
+    use std::{ffi::c_void, mem, ptr};
+
+    const TEXOBJ_PTX: &'static str = "
+        .version 6.5
+        .target sm_30
+        .address_size 64
+
+        .visible .entry fetch(
+            .param .u64 texture,
+            .param .u64 output
+        )
+        {
+            .reg .u64 	    tex;
+            .reg .u64 	    out_addr;
+            .reg .s32 	    index;
+            .reg .f32 	    r<4>;
+
+            ld.param.u64    tex, [texture];
+            ld.param.u64    out_addr, [output];
+            mov.s32         index, 2;
+            tex.1d.v4.f32.s32 {r0, r1, r2, r3}, [tex, {index}];
+            st.global.f32   [out_addr], r0;
+            ret;
+        }
+
+        .visible .entry fetch_filtered(
+            .param .u64 texture,
+            .param .u64 output
+        )
+        {
+            .reg .u64 	    tex;
+            .reg .u64 	    out_addr;
+            .reg .f32 	    x;
+            .reg .f32 	    r<4>;
+
+            ld.param.u64    tex, [texture];
+            ld.param.u64    out_addr, [output];
+            // 1.75
+            mov.f32         x, 0f3FE00000;
+            tex.1d.v4.f32.f32 {r0, r1, r2, r3}, [tex, {x}];
+            st.global.f32   [out_addr], r0;
+            ret;
+        }\0";
+
+    cuda_driver_test!(texobj_linear_fetch);
+
+    fn texobj_linear_fetch<T: CudaDriverFns>() {
+        let result = fetch_texel::<T>(b"fetch\0", CUfilter_mode::CU_TR_FILTER_MODE_POINT);
+        assert_eq!(result, 30f32);
+    }
+
+    cuda_driver_test!(texobj_linear_filter);
+
+    // Texel centers are at 0.5, 1.5, ... so 1.75 is a quarter of the way from
+    // texel 1 to texel 2. The weight is exact in the fixed point format both
+    // Intel and NVIDIA samplers use, so the result is the same on both
+    fn texobj_linear_filter<T: CudaDriverFns>() {
+        let result = fetch_texel::<T>(b"fetch_filtered\0", CUfilter_mode::CU_TR_FILTER_MODE_LINEAR);
+        assert_eq!(result, 22.5f32);
+    }
+
+    fn fetch_texel<T: CudaDriverFns>(kernel: &[u8], filter_mode: CUfilter_mode) -> f32 {
+        assert_eq!(T::cuInit(0), CUresult::CUDA_SUCCESS);
+        let mut ctx = ptr::null_mut();
+        assert_eq!(T::cuCtxCreate_v2(&mut ctx, 0, 0), CUresult::CUDA_SUCCESS);
+        let mut module = ptr::null_mut();
+        assert_eq!(
+            T::cuModuleLoadData(&mut module, TEXOBJ_PTX.as_ptr() as *const _),
+            CUresult::CUDA_SUCCESS
+        );
+        let texels = [10f32, 20f32, 30f32, 40f32];
+        let mut texture = ptr::null_mut();
+        assert_eq!(
+            T::cuMemAlloc_v2(&mut texture, mem::size_of_val(&texels)),
+            CUresult::CUDA_SUCCESS
+        );
+        assert_eq!(
+            T::cuMemcpyHtoD_v2(
+                texture,
+                texels.as_ptr() as *const _,
+                mem::size_of_val(&texels)
+            ),
+            CUresult::CUDA_SUCCESS
+        );
+        let mut res_desc = unsafe { mem::zeroed::<CUDA_RESOURCE_DESC>() };
+        res_desc.resType = CUresourcetype::CU_RESOURCE_TYPE_LINEAR;
+        res_desc.res.linear.devPtr = CUdeviceptr(texture as _);
+        res_desc.res.linear.format = CUarray_format::CU_AD_FORMAT_FLOAT;
+        res_desc.res.linear.numChannels = 1;
+        res_desc.res.linear.sizeInBytes = mem::size_of_val(&texels);
+        let mut tex_desc = unsafe { mem::zeroed::<CUDA_TEXTURE_DESC>() };
+        tex_desc.addressMode = [CUaddress_mode::CU_TR_ADDRESS_MODE_CLAMP; 3];
+        tex_desc.filterMode = filter_mode;
+        let mut tex_object = 0;
+        assert_eq!(
+            T::cuTexObjectCreate(&mut tex_object, &res_desc, &tex_desc, ptr::null()),
+            CUresult::CUDA_SUCCESS
+        );
+        assert_ne!(tex_object, 0);
+        let mut func = ptr::null_mut();
+        assert_eq!(
+            T::cuModuleGetFunction(&mut func, module, kernel.as_ptr() as *const _),
+            CUresult::CUDA_SUCCESS
+        );
+        let mut output = ptr::null_mut();
+        assert_eq!(
+            T::cuMemAlloc_v2(&mut output, mem::size_of::<f32>()),
+            CUresult::CUDA_SUCCESS
+        );
+        let mut params = [
+            &mut tex_object as *mut u64 as *mut c_void,
+            &mut output as *mut *mut c_void as *mut c_void,
+        ];
+        assert_eq!(
+            T::cuLaunchKernel(
+                func,
+                1,
+                1,
+                1,
+                1,
+                1,
+                1,
+                0,
+                ptr::null_mut(),
+                params.as_mut_ptr(),
+                ptr::null_mut()
+            ),
+            CUresult::CUDA_SUCCESS
+        );
+        let mut result = 0f32;
+        assert_eq!(
+            T::cuMemcpyDtoH_v2(
+                &mut result as *mut f32 as *mut _,
+                output,
+                mem::size_of::<f32>()
+            ),
+            CUresult::CUDA_SUCCESS
+        );
+        // Cleanup
+        assert_eq!(T::cuTexObjectDestroy(tex_object), CUresult::CUDA_SUCCESS);
+        assert_eq!(T::cuMemFree_v2(output), CUresult::CUDA_SUCCESS);
+        assert_eq!(T::cuMemFree_v2(texture), CUresult::CUDA_SUCCESS);
+        assert_eq!(T::cuModuleUnload(module), CUresult::CUDA_SUCCESS);
+        assert_eq!(T::cuCtxDestroy_v2(ctx), CUresult::CUDA_SUCCESS);
+        result
+    }
+}
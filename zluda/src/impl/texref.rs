@@ -0,0 +1,269 @@
+use super::{
+    context::ContextData, texobj::Texture, CUresult, GlobalState, HasLivenessCookie, LiveCheck,
+};
+use crate::cuda::{CUaddress_mode, CUarray_format, CUfilter_mode};
+use std::{ffi::c_void, os::raw::c_int, ptr};
+
+pub type TexRef = LiveCheck<TexRefData>;
+
+impl HasLivenessCookie for TexRefData {
+    #[cfg(target_pointer_width = "64")]
+    const COOKIE: usize = 0x9a7f0d3c2e41b865;
+
+    #[cfg(target_pointer_width = "32")]
+    const COOKIE: usize = 0x2e41b865;
+
+    const LIVENESS_FAIL: CUresult = CUresult::CUDA_ERROR_INVALID_HANDLE;
+
+    fn try_drop(&mut self) -> Result<(), CUresult> {
+        Ok(())
+    }
+}
+
+// Only linear memory can be bound. Kernels sampling the texref get the
+// texture's image and sampler as arguments (see ptx::KernelTexture), the
+// texture is recreated whenever the binding or the format changes
+pub struct TexRefData {
+    address: *mut c_void,
+    bytes: usize,
+    format: CUarray_format,
+    num_channels: c_int,
+    pub(crate) texture: Option<Texture>,
+}
+
+impl TexRefData {
+    pub fn new() -> Self {
+        TexRefData {
+            address: ptr::null_mut(),
+            bytes: 0,
+            format: CUarray_format::CU_AD_FORMAT_FLOAT,
+            num_channels: 1,
+            texture: None,
+        }
+    }
+}
+
+// Linear memory is fetched with integer coordinates, without filtering
+fn linear_texture(
+    ctx: &mut ContextData,
+    address: *mut c_void,
+    bytes: usize,
+    format: CUarray_format,
+    num_channels: c_int,
+) -> Result<Texture, CUresult> {
+    Texture::new(
+        ctx,
+        address,
+        bytes,
+        format,
+        num_channels as u32,
+        CUfilter_mode::CU_TR_FILTER_MODE_POINT,
+        CUaddress_mode::CU_TR_ADDRESS_MODE_CLAMP,
+        0,
+    )
+}
+
+pub(crate) fn set_address(
+    byte_offset: *mut usize,
+    texref: *mut TexRef,
+    dptr: *mut c_void,
+    bytes: usize,
+) -> Result<(), CUresult> {
+    if texref == ptr::null_mut() {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    GlobalState::lock_current_context(|ctx| {
+        let texref = unsafe { &mut *texref }.as_result_mut()?;
+        let texture = linear_texture(ctx, dptr, bytes, texref.format, texref.num_channels)?;
+        texref.address = dptr;
+        texref.bytes = bytes;
+        texref.texture = Some(texture);
+        Ok::<_, CUresult>(())
+    })??;
+    // Any device address is good enough, there is no alignment requirement to
+    // compensate for
+    if byte_offset != ptr::null_mut() {
+        unsafe { *byte_offset = 0 };
+    }
+    Ok(())
+}
+
+pub(crate) fn get_address(pdptr: *mut *mut c_void, texref: *mut TexRef) -> Result<(), CUresult> {
+    if pdptr == ptr::null_mut() || texref == ptr::null_mut() {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    let address = GlobalState::lock(|_| {
+        let texref = unsafe { &mut *texref }.as_result_mut()?;
+        Ok::<_, CUresult>(texref.address)
+    })??;
+    unsafe { *pdptr = address };
+    Ok(())
+}
+
+pub(crate) fn set_format(
+    texref: *mut TexRef,
+    format: CUarray_format,
+    num_channels: c_int,
+) -> Result<(), CUresult> {
+    if texref == ptr::null_mut() || !matches!(num_channels, 1 | 2 | 4) {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    GlobalState::lock_current_context(|ctx| {
+        let texref = unsafe { &mut *texref }.as_result_mut()?;
+        if texref.address != ptr::null_mut() {
+            let texture = linear_texture(ctx, texref.address, texref.bytes, format, num_channels)?;
+            texref.texture = Some(texture);
+        }
+        texref.format = format;
+        texref.num_channels = num_channels;
+        Ok::<_, CUresult>(())
+    })?
+}
+
+pub(crate) fn get_format(
+    pformat: *mut CUarray_format,
+    pnum_channels: *mut c_int,
+    texref: *mut TexRef,
+) -> Result<(), CUresult> {
+    if pformat == ptr::null_mut() || pnum_channels == ptr::null_mut() || texref == ptr::null_mut() {
+        return Err(CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+    let (format, num_channels) = GlobalState::lock(|_| {
+        let texref = unsafe { &mut *texref }.as_result_mut()?;
+        Ok::<_, CUresult>((texref.format, texref.num_channels))
+    })??;
+    unsafe {
+        *pformat = format;
+        *pnum_channels = num_channels;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::CudaDriverFns;
+    use super::super::CUresult;
+    use crate::cuda::CUarray_format;
+    use std::{ffi::c_void, mem, ptr};
+
+    const TEXREF_PTX: &'static str = "
+        .version 6.5
+        .target sm_30
+        .address_size 64
+
+        .global .texref tex;
+
+        .visible .entry fetch(
+            .param .u64 output
+        )
+        {
+            .reg .u64 	    out_addr;
+            .reg .s32 	    index;
+            .reg .f32 	    r<4>;
+
+            ld.param.u64    out_addr, [output];
+            mov.s32         index, 2;
+            tex.1d.v4.f32.s32 {r0, r1, r2, r3}, [tex, {index}];
+            st.global.f32   [out_addr], r0;
+            ret;
+        }\0";
+
+    cuda_driver_test!(texref_address_binding);
+
+    fn texref_address_binding<T: CudaDriverFns>() {
+        assert_eq!(T::cuInit(0), CUresult::CUDA_SUCCESS);
+        let mut ctx = ptr::null_mut();
+        assert_eq!(T::cuCtxCreate_v2(&mut ctx, 0, 0), CUresult::CUDA_SUCCESS);
+        let mut module = ptr::null_mut();
+        assert_eq!(
+            T::cuModuleLoadData(&mut module, TEXREF_PTX.as_ptr() as *const _),
+            CUresult::CUDA_SUCCESS
+        );
+        let mut texref = ptr::null_mut();
+        assert_eq!(
+            T::cuModuleGetTexRef(&mut texref, module, b"not_a_texref\0".as_ptr() as *const _),
+            CUresult::CUDA_ERROR_NOT_FOUND
+        );
+        assert_eq!(
+            T::cuModuleGetTexRef(&mut texref, module, b"tex\0".as_ptr() as *const _),
+            CUresult::CUDA_SUCCESS
+        );
+        let texels = (0..16).map(|i| (i * 10) as f32).collect::<Vec<_>>();
+        let mut texture = ptr::null_mut();
+        assert_eq!(
+            T::cuMemAlloc_v2(&mut texture, 16 * mem::size_of::<f32>()),
+            CUresult::CUDA_SUCCESS
+        );
+        assert_eq!(
+            T::cuMemcpyHtoD_v2(
+                texture,
+                texels.as_ptr() as *const _,
+                16 * mem::size_of::<f32>()
+            ),
+            CUresult::CUDA_SUCCESS
+        );
+        assert_eq!(
+            T::cuTexRefSetFormat(texref, CUarray_format::CU_AD_FORMAT_FLOAT, 1),
+            CUresult::CUDA_SUCCESS
+        );
+        let mut byte_offset = usize::max_value();
+        assert_eq!(
+            T::cuTexRefSetAddress_v2(
+                &mut byte_offset,
+                texref,
+                texture,
+                16 * mem::size_of::<f32>()
+            ),
+            CUresult::CUDA_SUCCESS
+        );
+        assert_eq!(byte_offset, 0);
+        let mut bound_address = ptr::null_mut();
+        assert_eq!(
+            T::cuTexRefGetAddress_v2(&mut bound_address, texref),
+            CUresult::CUDA_SUCCESS
+        );
+        assert_eq!(bound_address, texture);
+        let mut func = ptr::null_mut();
+        assert_eq!(
+            T::cuModuleGetFunction(&mut func, module, b"fetch\0".as_ptr() as *const _),
+            CUresult::CUDA_SUCCESS
+        );
+        let mut output = ptr::null_mut();
+        assert_eq!(
+            T::cuMemAlloc_v2(&mut output, mem::size_of::<f32>()),
+            CUresult::CUDA_SUCCESS
+        );
+        let mut params = [&mut output as *mut *mut c_void as *mut c_void];
+        assert_eq!(
+            T::cuLaunchKernel(
+                func,
+                1,
+                1,
+                1,
+                1,
+                1,
+                1,
+                0,
+                ptr::null_mut(),
+                params.as_mut_ptr(),
+                ptr::null_mut()
+            ),
+            CUresult::CUDA_SUCCESS
+        );
+        let mut result = 0f32;
+        assert_eq!(
+            T::cuMemcpyDtoH_v2(
+                &mut result as *mut f32 as *mut _,
+                output,
+                mem::size_of::<f32>()
+            ),
+            CUresult::CUDA_SUCCESS
+        );
+        assert_eq!(result, 20f32);
+        // Cleanup
+        assert_eq!(T::cuMemFree_v2(output), CUresult::CUDA_SUCCESS);
+        assert_eq!(T::cuMemFree_v2(texture), CUresult::CUDA_SUCCESS);
+        assert_eq!(T::cuModuleUnload(module), CUresult::CUDA_SUCCESS);
+        assert_eq!(T::cuCtxDestroy_v2(ctx), CUresult::CUDA_SUCCESS);
+    }
+}
@@ -2334,6 +2334,9 @@ extern_redirect! {
 extern_redirect! {
     pub fn cuDeviceGetUuid(uuid: *mut CUuuid, dev: CUdevice) -> CUresult;
 }
+extern_redirect! {
+    pub fn cuDeviceGetUuid_v2(uuid: *mut CUuuid, dev: CUdevice) -> CUresult;
+}
 extern_redirect! {
     pub fn cuDeviceGetLuid(
         luid: *mut ::std::os::raw::c_char,
@@ -3401,55 +3404,63 @@ extern_redirect! {
         userData: *mut ::std::os::raw::c_void,
     ) -> CUresult;
 }
-extern_redirect! {
+extern_redirect_with! {
     pub fn cuFuncSetBlockShape(
         hfunc: CUfunction,
         x: ::std::os::raw::c_int,
         y: ::std::os::raw::c_int,
         z: ::std::os::raw::c_int,
     ) -> CUresult;
+    super::cuFuncSetBlockShape;
 }
-extern_redirect! {
+extern_redirect_with! {
     pub fn cuFuncSetSharedSize(hfunc: CUfunction, bytes: ::std::os::raw::c_uint) -> CUresult;
+    super::cuFuncSetSharedSize;
 }
 extern_redirect! {
     pub fn cuParamSetSize(hfunc: CUfunction, numbytes: ::std::os::raw::c_uint) -> CUresult;
 }
-extern_redirect! {
+extern_redirect_with! {
     pub fn cuParamSeti(
         hfunc: CUfunction,
         offset: ::std::os::raw::c_int,
         value: ::std::os::raw::c_uint,
     ) -> CUresult;
+    super::cuParamSeti;
 }
-extern_redirect! {
+extern_redirect_with! {
     pub fn cuParamSetf(hfunc: CUfunction, offset: ::std::os::raw::c_int, value: f32) -> CUresult;
+    super::cuParamSetf;
 }
-extern_redirect! {
+extern_redirect_with! {
     pub fn cuParamSetv(
         hfunc: CUfunction,
         offset: ::std::os::raw::c_int,
         ptr: *mut ::std::os::raw::c_void,
         numbytes: ::std::os::raw::c_uint,
     ) -> CUresult;
+    super::cuParamSetv;
 }
-extern_redirect! {
+extern_redirect_with! {
     pub fn cuLaunch(f: CUfunction) -> CUresult;
+    super::cuLaunch;
 }
-extern_redirect! {
+extern_redirect_with! {
     pub fn cuLaunchGrid(
         f: CUfunction,
         grid_width: ::std::os::raw::c_int,
         grid_height: ::std::os::raw::c_int,
     ) -> CUresult;
+    super::cuLaunchGrid;
 }
-extern_redirect! {
+extern_redirect_with! {
     pub fn cuLaunchGridAsync(
         f: CUfunction,
         grid_width: ::std::os::raw::c_int,
         grid_height: ::std::os::raw::c_int,
         hStream: CUstream,
     ) -> CUresult;
+    super::cuLaunchGridAsync;
 }
 extern_redirect! {
     pub fn cuParamSetTexRef(
@@ -4070,3 +4081,11 @@ extern_redirect_with! {
 extern_redirect! {
     pub fn cuFuncGetModule(hmod: *mut CUmodule, hfunc: CUfunction) -> CUresult;
 }
+extern_redirect_with! {
+    pub fn cuProfilerStart() -> CUresult;
+    super::cuProfilerStart;
+}
+extern_redirect_with! {
+    pub fn cuProfilerStop() -> CUresult;
+    super::cuProfilerStop;
+}
@@ -9,9 +9,9 @@ use std::{
     os::raw::{c_int, c_uint, c_ulong, c_ushort},
     path::PathBuf,
     rc::Rc,
-    slice,
+    slice, str,
 };
-use std::{fs::File, ptr};
+use std::{convert::TryInto, fs::File, ptr};
 
 use cuda::{CUdeviceptr, CUfunction, CUjit_option, CUmodule, CUresult, CUstream, CUuuid};
 use ptx::ast;
@@ -54,7 +54,7 @@ macro_rules! extern_redirect_with {
                 let typed_fn = unsafe { std::mem::transmute::<_, fn( $( $arg_id : $arg_type),* ) -> $ret_type>(fn_ptr) };
                 typed_fn($( $arg_id ),*)
             };
-            unsafe { $receiver($( $arg_id ),* , continuation) }
+            unsafe { $receiver($( $arg_id, )* continuation) }
         }
     };
 }
@@ -68,18 +68,35 @@ pub static mut KERNELS: Option<HashMap<CUfunction, KernelDump>> = None;
 pub static mut BUFFERS: Vec<(usize, usize)> = Vec::new();
 pub static mut LAUNCH_COUNTER: usize = 0;
 pub static mut KERNEL_PATTERN: Option<Regex> = None;
+// With ZLUDA_DUMP_PROFILER_RANGE set, kernels are dumped only between
+// cuProfilerStart and cuProfilerStop
+pub static mut PROFILER_RANGE_ONLY: bool = false;
+pub static mut PROFILER_ACTIVE: bool = false;
+pub static mut LEGACY_LAUNCHES: Option<HashMap<CUfunction, LegacyLaunch>> = None;
 
 pub struct ModuleDump {
     content: Rc<String>,
+    jit_options: Option<Rc<String>>,
     kernels_args: HashMap<String, Vec<usize>>,
 }
 
 pub struct KernelDump {
     module_content: Rc<String>,
+    jit_options: Option<Rc<String>>,
     name: String,
     arguments: Vec<usize>,
 }
 
+// Launch configuration set with cuFuncSetBlockShape, cuFuncSetSharedSize and
+// cuParamSet*, used by cuLaunch, cuLaunchGrid and cuLaunchGridAsync
+pub struct LegacyLaunch {
+    block_dim: (c_uint, c_uint, c_uint),
+    shared_mem_bytes: c_uint,
+    params: Vec<u8>,
+    // Sorted offsets of arguments in params
+    arg_offsets: Vec<usize>,
+}
+
 // We are doing dlopen here instead of just using LD_PRELOAD,
 // it's because CUDA Runtime API does dlopen to open libcuda.so, which ignores LD_PRELOAD
 pub unsafe fn init_libcuda_handle() {
@@ -99,6 +116,7 @@ pub unsafe fn init_libcuda_handle() {
             },
             Err(_) => (),
         }
+        PROFILER_RANGE_ONLY = env::var("ZLUDA_DUMP_PROFILER_RANGE").is_ok();
         eprintln!("[ZLUDA_DUMP] Initialized");
     }
 }
@@ -111,27 +129,33 @@ pub unsafe fn cuModuleLoadData(
 ) -> CUresult {
     let result = cont(module, raw_image);
     if result == CUresult::CUDA_SUCCESS {
-        record_module_image_raw(*module, raw_image);
+        record_module_image_raw(*module, raw_image, None);
     }
     result
 }
 
-unsafe fn record_module_image_raw(module: CUmodule, raw_image: *const ::std::os::raw::c_void) {
+unsafe fn record_module_image_raw(
+    module: CUmodule,
+    raw_image: *const ::std::os::raw::c_void,
+    jit_options: Option<String>,
+) {
+    if slice::from_raw_parts(raw_image as *const u8, ELF_MAGIC.len()) == ELF_MAGIC {
+        record_module_elf(module, raw_image as *const u8, jit_options);
+        return;
+    }
     let image = to_str(raw_image);
     match image {
         None => eprintln!("[ZLUDA_DUMP] Malformed module image: {:?}", raw_image),
-        Some(image) => record_module_image(module, image),
+        Some(image) => record_module_image(module, image, jit_options),
     };
 }
 
-unsafe fn record_module_image(module: CUmodule, image: &str) {
+unsafe fn record_module_image(module: CUmodule, image: &str, jit_options: Option<String>) {
     if !image.contains(&".address_size") {
         eprintln!("[ZLUDA_DUMP] Malformed module image: {:?}", module)
     } else {
-        let mut errors = Vec::new();
-        let ast = ptx::ModuleParser::new().parse(&mut errors, image);
-        match (&*errors, ast) {
-            (&[], Ok(ast)) => {
+        match ptx::parse_module_checked(image) {
+            Ok(ast) => {
                 let kernels_args = ast
                     .directives
                     .iter()
@@ -142,26 +166,151 @@ unsafe fn record_module_image(module: CUmodule, image: &str) {
                     module,
                     ModuleDump {
                         content: Rc::new(image.to_string()),
+                        jit_options: jit_options.map(Rc::new),
                         kernels_args,
                     },
                 );
             }
-            (errs, ast) => {
-                let err_string = errs
-                    .iter()
-                    .map(|e| format!("{:?}", e))
-                    .chain(ast.err().iter().map(|e| format!("{:?}", e)))
-                    .collect::<Vec<_>>()
-                    .join("\n");
+            Err(err) => {
                 eprintln!(
                     "[ZLUDA_DUMP] Errors when parsing module:\n---ERRORS---\n{}\n---MODULE---\n{}",
-                    err_string, image
+                    err, image
                 );
             }
         }
     }
 }
 
+unsafe fn record_module_elf(module: CUmodule, elf: *const u8, jit_options: Option<String>) {
+    let ptx = elf_image_size(elf).and_then(|size| get_elf_ptx(slice::from_raw_parts(elf, size)));
+    match ptx {
+        Some(ptx) => record_module_image(module, ptx, jit_options),
+        None => eprintln!(
+            "[ZLUDA_DUMP] Module {:?} is an ELF cubin without embedded PTX, skipping",
+            module
+        ),
+    }
+}
+
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+const ELF_CLASS_64: u8 = 2;
+const ELF_DATA_LSB: u8 = 1;
+const ELF64_HEADER_SIZE: usize = 64;
+const ELF64_SECTION_HEADER_SIZE: usize = 64;
+const ELF_SECTION_NOBITS: u32 = 8;
+// Cubins carry PTX only when compiled with device debug info (-G)
+const ELF_PTX_SECTION: &[u8] = b".nv_debug_ptx_txt";
+
+struct ElfSectionTable {
+    offset: usize,
+    entry_size: usize,
+    count: usize,
+    names_index: usize,
+}
+
+struct ElfSection {
+    name: usize,
+    kind: u32,
+    offset: usize,
+    size: usize,
+}
+
+impl ElfSectionTable {
+    // Only 64-bit little-endian cubins are supported
+    fn new(elf: &[u8]) -> Option<Self> {
+        if elf.get(..ELF_MAGIC.len())? != ELF_MAGIC
+            || *elf.get(4)? != ELF_CLASS_64
+            || *elf.get(5)? != ELF_DATA_LSB
+        {
+            return None;
+        }
+        let entry_size = read_u16(elf, 0x3A)? as usize;
+        if entry_size < ELF64_SECTION_HEADER_SIZE {
+            return None;
+        }
+        Some(ElfSectionTable {
+            offset: read_u64(elf, 0x28)? as usize,
+            entry_size,
+            count: read_u16(elf, 0x3C)? as usize,
+            names_index: read_u16(elf, 0x3E)? as usize,
+        })
+    }
+
+    // Offsets come straight from the image, a corrupted one must not overflow
+    fn end(&self) -> Option<usize> {
+        self.offset
+            .checked_add(self.entry_size.checked_mul(self.count)?)
+    }
+
+    fn section(&self, elf: &[u8], index: usize) -> Option<ElfSection> {
+        if index >= self.count {
+            return None;
+        }
+        let header = self
+            .offset
+            .checked_add(index.checked_mul(self.entry_size)?)?;
+        let header = elf.get(header..header.checked_add(ELF64_SECTION_HEADER_SIZE)?)?;
+        Some(ElfSection {
+            name: read_u32(header, 0)? as usize,
+            kind: read_u32(header, 4)?,
+            offset: read_u64(header, 0x18)? as usize,
+            size: read_u64(header, 0x20)? as usize,
+        })
+    }
+
+    fn sections<'a>(&'a self, elf: &'a [u8]) -> impl Iterator<Item = ElfSection> + 'a {
+        (0..self.count).filter_map(move |index| self.section(elf, index))
+    }
+}
+
+impl ElfSection {
+    fn content<'a>(&self, elf: &'a [u8]) -> Option<&'a [u8]> {
+        elf.get(self.offset..self.offset.checked_add(self.size)?)
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + 8)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+// cuModuleLoadData does not tell us the size of the image, so we compute it
+// from the headers: the image ends with the last section or the section table
+unsafe fn elf_image_size(elf: *const u8) -> Option<usize> {
+    let table = ElfSectionTable::new(slice::from_raw_parts(elf, ELF64_HEADER_SIZE))?;
+    let table_end = table.end()?;
+    let headers = slice::from_raw_parts(elf, table_end);
+    table
+        .sections(headers)
+        .filter(|section| section.kind != ELF_SECTION_NOBITS)
+        .try_fold(table_end, |end, section| {
+            Some(end.max(section.offset.checked_add(section.size)?))
+        })
+}
+
+fn get_elf_ptx(elf: &[u8]) -> Option<&str> {
+    let table = ElfSectionTable::new(elf)?;
+    let names = table.section(elf, table.names_index)?.content(elf)?;
+    let ptx_section = table.sections(elf).find(|section| {
+        names
+            .get(section.name..)
+            .and_then(|name| name.split(|c| *c == 0).next())
+            == Some(ELF_PTX_SECTION)
+    })?;
+    let text = ptx_section.content(elf)?.split(|c| *c == 0).next()?;
+    str::from_utf8(text).ok()
+}
+
 unsafe fn to_str<T>(image: *const T) -> Option<&'static str> {
     let ptr = image as *const u8;
     let mut offset = 0;
@@ -212,7 +361,82 @@ pub unsafe fn cuModuleLoadDataEx(
 ) -> CUresult {
     let result = cont(module, image, numOptions, options, optionValues);
     if result == CUresult::CUDA_SUCCESS {
-        record_module_image_raw(*module, image);
+        let jit_options = if numOptions == 0 || options == ptr::null_mut() {
+            None
+        } else {
+            let values = if optionValues == ptr::null_mut() {
+                vec![ptr::null_mut(); numOptions as usize]
+            } else {
+                slice::from_raw_parts(optionValues, numOptions as usize).to_vec()
+            };
+            Some(format_jit_options(
+                slice::from_raw_parts(options, numOptions as usize),
+                &values,
+            ))
+        };
+        record_module_image_raw(*module, image, jit_options);
+    }
+    result
+}
+
+// Scalar option values are passed by value, cast to a pointer
+fn format_jit_options(options: &[CUjit_option], values: &[*mut c_void]) -> String {
+    let mut result = String::new();
+    for (option, value) in options.iter().zip(values.iter()) {
+        let raw_value = *value as usize;
+        let (name, value) = match *option {
+            CUjit_option::CU_JIT_MAX_REGISTERS => ("CU_JIT_MAX_REGISTERS", raw_value.to_string()),
+            CUjit_option::CU_JIT_THREADS_PER_BLOCK => {
+                ("CU_JIT_THREADS_PER_BLOCK", raw_value.to_string())
+            }
+            CUjit_option::CU_JIT_WALL_TIME => ("CU_JIT_WALL_TIME", format!("{:?}", value)),
+            CUjit_option::CU_JIT_INFO_LOG_BUFFER => {
+                ("CU_JIT_INFO_LOG_BUFFER", format!("{:?}", value))
+            }
+            CUjit_option::CU_JIT_INFO_LOG_BUFFER_SIZE_BYTES => {
+                ("CU_JIT_INFO_LOG_BUFFER_SIZE_BYTES", raw_value.to_string())
+            }
+            CUjit_option::CU_JIT_ERROR_LOG_BUFFER => {
+                ("CU_JIT_ERROR_LOG_BUFFER", format!("{:?}", value))
+            }
+            CUjit_option::CU_JIT_ERROR_LOG_BUFFER_SIZE_BYTES => {
+                ("CU_JIT_ERROR_LOG_BUFFER_SIZE_BYTES", raw_value.to_string())
+            }
+            CUjit_option::CU_JIT_OPTIMIZATION_LEVEL => {
+                ("CU_JIT_OPTIMIZATION_LEVEL", raw_value.to_string())
+            }
+            CUjit_option::CU_JIT_TARGET_FROM_CUCONTEXT => {
+                ("CU_JIT_TARGET_FROM_CUCONTEXT", raw_value.to_string())
+            }
+            CUjit_option::CU_JIT_TARGET => ("CU_JIT_TARGET", format!("sm_{}", raw_value)),
+            CUjit_option::CU_JIT_FALLBACK_STRATEGY => {
+                ("CU_JIT_FALLBACK_STRATEGY", raw_value.to_string())
+            }
+            CUjit_option::CU_JIT_GENERATE_DEBUG_INFO => {
+                ("CU_JIT_GENERATE_DEBUG_INFO", raw_value.to_string())
+            }
+            CUjit_option::CU_JIT_LOG_VERBOSE => ("CU_JIT_LOG_VERBOSE", raw_value.to_string()),
+            CUjit_option::CU_JIT_GENERATE_LINE_INFO => {
+                ("CU_JIT_GENERATE_LINE_INFO", raw_value.to_string())
+            }
+            CUjit_option::CU_JIT_CACHE_MODE => ("CU_JIT_CACHE_MODE", raw_value.to_string()),
+            CUjit_option::CU_JIT_NEW_SM3X_OPT => ("CU_JIT_NEW_SM3X_OPT", raw_value.to_string()),
+            CUjit_option::CU_JIT_FAST_COMPILE => ("CU_JIT_FAST_COMPILE", raw_value.to_string()),
+            CUjit_option::CU_JIT_GLOBAL_SYMBOL_NAMES => {
+                ("CU_JIT_GLOBAL_SYMBOL_NAMES", format!("{:?}", value))
+            }
+            CUjit_option::CU_JIT_GLOBAL_SYMBOL_ADDRESSES => {
+                ("CU_JIT_GLOBAL_SYMBOL_ADDRESSES", format!("{:?}", value))
+            }
+            CUjit_option::CU_JIT_GLOBAL_SYMBOL_COUNT => {
+                ("CU_JIT_GLOBAL_SYMBOL_COUNT", raw_value.to_string())
+            }
+            CUjit_option(unknown) => {
+                result.push_str(&format!("UNKNOWN_{}={:?}\n", unknown, value));
+                continue;
+            }
+        };
+        result.push_str(&format!("{}={}\n", name, value));
     }
     result
 }
@@ -237,6 +461,7 @@ unsafe fn cuModuleGetFunction(
                         *hfunc,
                         KernelDump {
                             module_content: module_dump.content.clone(),
+                            jit_options: module_dump.jit_options.clone(),
                             name: kernel.to_string(),
                             arguments: args.clone(),
                         },
@@ -263,10 +488,16 @@ pub unsafe fn cuMemAlloc_v2(
     cont: impl FnOnce(*mut CUdeviceptr, usize) -> CUresult,
 ) -> CUresult {
     let result = cont(dptr, bytesize);
-    assert_eq!(result, CUresult::CUDA_SUCCESS);
+    if result != CUresult::CUDA_SUCCESS {
+        eprintln!(
+            "[ZLUDA_DUMP] cuMemAlloc_v2 of {} bytes failed with error {}",
+            bytesize, result.0
+        );
+        return result;
+    }
     let start = (*dptr).0 as usize;
     BUFFERS.push((start, bytesize));
-    CUresult::CUDA_SUCCESS
+    result
 }
 
 #[allow(non_snake_case)]
@@ -296,7 +527,51 @@ pub unsafe fn cuLaunchKernel(
         *mut *mut ::std::os::raw::c_void,
     ) -> CUresult,
 ) -> CUresult {
-    let mut error;
+    dump_launch(
+        f,
+        gridDimX,
+        gridDimY,
+        gridDimZ,
+        blockDimX,
+        blockDimY,
+        blockDimZ,
+        sharedMemBytes,
+        hStream,
+        kernelParams,
+        || {
+            cont(
+                f,
+                gridDimX,
+                gridDimY,
+                gridDimZ,
+                blockDimX,
+                blockDimY,
+                blockDimZ,
+                sharedMemBytes,
+                hStream,
+                kernelParams,
+                extra,
+            )
+        },
+    )
+}
+
+// Shared by cuLaunchKernel and the legacy launch functions, launch does the
+// actual launch with the arguments given here
+#[allow(non_snake_case)]
+unsafe fn dump_launch(
+    f: CUfunction,
+    gridDimX: ::std::os::raw::c_uint,
+    gridDimY: ::std::os::raw::c_uint,
+    gridDimZ: ::std::os::raw::c_uint,
+    blockDimX: ::std::os::raw::c_uint,
+    blockDimY: ::std::os::raw::c_uint,
+    blockDimZ: ::std::os::raw::c_uint,
+    sharedMemBytes: ::std::os::raw::c_uint,
+    hStream: CUstream,
+    kernelParams: *mut *mut ::std::os::raw::c_void,
+    launch: impl FnOnce() -> CUresult,
+) -> CUresult {
     let dump_env = match create_dump_dir(f, LAUNCH_COUNTER) {
         Ok(dump_env) => dump_env,
         Err(err) => {
@@ -318,23 +593,9 @@ pub unsafe fn cuLaunchKernel(
         )
         .unwrap_or_else(|err| eprintln!("[ZLUDA_DUMP] {:#?}", err));
     };
-    error = cont(
-        f,
-        gridDimX,
-        gridDimY,
-        gridDimZ,
-        blockDimX,
-        blockDimY,
-        blockDimZ,
-        sharedMemBytes,
-        hStream,
-        kernelParams,
-        extra,
-    );
-    assert_eq!(error, CUresult::CUDA_SUCCESS);
-    error = cuda::cuStreamSynchronize(hStream);
-    assert_eq!(error, CUresult::CUDA_SUCCESS);
-    if let Some((_, kernel_dump)) = &dump_env {
+    let launch_result = launch();
+    let result = wait_for_launch(launch_result, || cuda::cuStreamSynchronize(hStream));
+    if let (CUresult::CUDA_SUCCESS, Some((_, kernel_dump))) = (result, &dump_env) {
         dump_arguments(
             kernelParams,
             "post",
@@ -345,7 +606,225 @@ pub unsafe fn cuLaunchKernel(
         .unwrap_or_else(|err| eprintln!("[ZLUDA_DUMP] {:#?}", err));
     }
     LAUNCH_COUNTER += 1;
-    CUresult::CUDA_SUCCESS
+    result
+}
+
+#[allow(non_snake_case)]
+pub unsafe fn cuFuncSetBlockShape(
+    hfunc: CUfunction,
+    x: c_int,
+    y: c_int,
+    z: c_int,
+    cont: impl FnOnce(CUfunction, c_int, c_int, c_int) -> CUresult,
+) -> CUresult {
+    let result = cont(hfunc, x, y, z);
+    if result == CUresult::CUDA_SUCCESS {
+        legacy_launch(hfunc).block_dim = (x as c_uint, y as c_uint, z as c_uint);
+    }
+    result
+}
+
+#[allow(non_snake_case)]
+pub unsafe fn cuFuncSetSharedSize(
+    hfunc: CUfunction,
+    bytes: c_uint,
+    cont: impl FnOnce(CUfunction, c_uint) -> CUresult,
+) -> CUresult {
+    let result = cont(hfunc, bytes);
+    if result == CUresult::CUDA_SUCCESS {
+        legacy_launch(hfunc).shared_mem_bytes = bytes;
+    }
+    result
+}
+
+#[allow(non_snake_case)]
+pub unsafe fn cuParamSeti(
+    hfunc: CUfunction,
+    offset: c_int,
+    value: c_uint,
+    cont: impl FnOnce(CUfunction, c_int, c_uint) -> CUresult,
+) -> CUresult {
+    let result = cont(hfunc, offset, value);
+    if result == CUresult::CUDA_SUCCESS {
+        legacy_launch(hfunc).set_param(offset as usize, &value.to_ne_bytes());
+    }
+    result
+}
+
+#[allow(non_snake_case)]
+pub unsafe fn cuParamSetf(
+    hfunc: CUfunction,
+    offset: c_int,
+    value: f32,
+    cont: impl FnOnce(CUfunction, c_int, f32) -> CUresult,
+) -> CUresult {
+    let result = cont(hfunc, offset, value);
+    if result == CUresult::CUDA_SUCCESS {
+        legacy_launch(hfunc).set_param(offset as usize, &value.to_ne_bytes());
+    }
+    result
+}
+
+#[allow(non_snake_case)]
+pub unsafe fn cuParamSetv(
+    hfunc: CUfunction,
+    offset: c_int,
+    ptr: *mut c_void,
+    numbytes: c_uint,
+    cont: impl FnOnce(CUfunction, c_int, *mut c_void, c_uint) -> CUresult,
+) -> CUresult {
+    let result = cont(hfunc, offset, ptr, numbytes);
+    if result == CUresult::CUDA_SUCCESS {
+        let value = slice::from_raw_parts(ptr as *const u8, numbytes as usize);
+        legacy_launch(hfunc).set_param(offset as usize, value);
+    }
+    result
+}
+
+#[allow(non_snake_case)]
+pub unsafe fn cuLaunch(f: CUfunction, cont: impl FnOnce(CUfunction) -> CUresult) -> CUresult {
+    dump_legacy_launch(f, 1, 1, ptr::null_mut(), || cont(f))
+}
+
+#[allow(non_snake_case)]
+pub unsafe fn cuLaunchGrid(
+    f: CUfunction,
+    grid_width: c_int,
+    grid_height: c_int,
+    cont: impl FnOnce(CUfunction, c_int, c_int) -> CUresult,
+) -> CUresult {
+    dump_legacy_launch(f, grid_width, grid_height, ptr::null_mut(), || {
+        cont(f, grid_width, grid_height)
+    })
+}
+
+#[allow(non_snake_case)]
+pub unsafe fn cuLaunchGridAsync(
+    f: CUfunction,
+    grid_width: c_int,
+    grid_height: c_int,
+    hStream: CUstream,
+    cont: impl FnOnce(CUfunction, c_int, c_int, CUstream) -> CUresult,
+) -> CUresult {
+    dump_legacy_launch(f, grid_width, grid_height, hStream, || {
+        cont(f, grid_width, grid_height, hStream)
+    })
+}
+
+unsafe fn legacy_launch(f: CUfunction) -> &'static mut LegacyLaunch {
+    LEGACY_LAUNCHES
+        .get_or_insert_with(|| HashMap::new())
+        .entry(f)
+        .or_insert_with(LegacyLaunch::new)
+}
+
+// Legacy launches get the block shape, shared memory size and arguments from
+// the state set on the function, we rebuild cuLaunchKernel arguments from it
+#[allow(non_snake_case)]
+unsafe fn dump_legacy_launch(
+    f: CUfunction,
+    grid_width: c_int,
+    grid_height: c_int,
+    hStream: CUstream,
+    launch: impl FnOnce() -> CUresult,
+) -> CUresult {
+    let state = legacy_launch(f);
+    let kernel_params = KERNELS
+        .as_ref()
+        .and_then(|kernels| kernels.get(&f))
+        .and_then(|kernel_dump| state.kernel_params(&kernel_dump.arguments));
+    let mut kernel_params = match kernel_params {
+        Some(kernel_params) => kernel_params,
+        None => {
+            eprintln!(
+                "[ZLUDA_DUMP] Can't reconstruct arguments of legacy launch of {:?}",
+                f
+            );
+            return launch();
+        }
+    };
+    let (block_x, block_y, block_z) = state.block_dim;
+    dump_launch(
+        f,
+        grid_width as c_uint,
+        grid_height as c_uint,
+        1,
+        block_x,
+        block_y,
+        block_z,
+        state.shared_mem_bytes,
+        hStream,
+        kernel_params.as_mut_ptr(),
+        launch,
+    )
+}
+
+impl LegacyLaunch {
+    fn new() -> Self {
+        LegacyLaunch {
+            block_dim: (1, 1, 1),
+            shared_mem_bytes: 0,
+            params: Vec::new(),
+            arg_offsets: Vec::new(),
+        }
+    }
+
+    fn set_param(&mut self, offset: usize, value: &[u8]) {
+        let end = offset + value.len();
+        if self.params.len() < end {
+            self.params.resize(end, 0);
+        }
+        self.params[offset..end].copy_from_slice(value);
+        if let Err(index) = self.arg_offsets.binary_search(&offset) {
+            self.arg_offsets.insert(index, offset);
+        }
+    }
+
+    // Arguments are assumed to be set in the parameter buffer in the order of
+    // kernel parameters. Returned pointers point into the parameter buffer and
+    // are valid until the next set_param
+    fn kernel_params(&mut self, arg_sizes: &[usize]) -> Option<Vec<*mut c_void>> {
+        if self.arg_offsets.len() != arg_sizes.len() {
+            return None;
+        }
+        let params_len = self.params.len();
+        if self
+            .arg_offsets
+            .iter()
+            .zip(arg_sizes)
+            .any(|(offset, size)| offset + size > params_len)
+        {
+            return None;
+        }
+        let params = self.params.as_mut_ptr();
+        Some(
+            self.arg_offsets
+                .iter()
+                .map(|offset| unsafe { params.add(*offset) } as *mut c_void)
+                .collect(),
+        )
+    }
+}
+
+// Launch and synchronization errors are logged and returned to the application
+// unchanged, it must behave the same with and without the dumper. Arguments
+// after the launch are only worth dumping if the kernel ran to completion
+fn wait_for_launch(launch_result: CUresult, sync: impl FnOnce() -> CUresult) -> CUresult {
+    if launch_result != CUresult::CUDA_SUCCESS {
+        eprintln!(
+            "[ZLUDA_DUMP] Kernel launch failed with error {}",
+            launch_result.0
+        );
+        return launch_result;
+    }
+    let sync_result = sync();
+    if sync_result != CUresult::CUDA_SUCCESS {
+        eprintln!(
+            "[ZLUDA_DUMP] Stream synchronization after kernel launch failed with error {}",
+            sync_result.0
+        );
+    }
+    sync_result
 }
 
 #[allow(non_snake_case)]
@@ -372,7 +851,22 @@ fn dump_launch_arguments(
     Ok(())
 }
 
+#[allow(non_snake_case)]
+pub unsafe fn cuProfilerStart(cont: impl FnOnce() -> CUresult) -> CUresult {
+    PROFILER_ACTIVE = true;
+    cont()
+}
+
+#[allow(non_snake_case)]
+pub unsafe fn cuProfilerStop(cont: impl FnOnce() -> CUresult) -> CUresult {
+    PROFILER_ACTIVE = false;
+    cont()
+}
+
 unsafe fn should_dump_kernel(name: &str) -> bool {
+    if PROFILER_RANGE_ONLY && !PROFILER_ACTIVE {
+        return false;
+    }
     match &KERNEL_PATTERN {
         Some(pattern) => pattern.is_match(name),
         None => true,
@@ -423,6 +917,12 @@ unsafe fn dump_pre_data(
     module_file_path.push("module.ptx");
     let mut module_file = File::create(module_file_path)?;
     module_file.write_all(kernel_dump.module_content.as_bytes())?;
+    if let Some(jit_options) = &kernel_dump.jit_options {
+        let mut options_file_path = dump_dir.clone();
+        options_file_path.push("jit_options.txt");
+        let mut options_file = File::create(options_file_path)?;
+        options_file.write_all(jit_options.as_bytes())?;
+    }
     dump_arguments(
         kernelParams,
         "pre",
@@ -451,10 +951,18 @@ unsafe fn dump_arguments(
         let dev_ptr = *(*kernel_params.add(i) as *mut usize);
         match BUFFERS.iter().find(|(start, _)| *start == dev_ptr as usize) {
             Some((start, len)) => {
-                let mut output = vec![0u8; *len];
-                let error =
-                    cuda::cuMemcpyDtoH_v2(output.as_mut_ptr() as *mut _, CUdeviceptr(*start), *len);
-                assert_eq!(error, CUresult::CUDA_SUCCESS);
+                let mut output = match copy_buffer(*start, *len, |dst, src, len| {
+                    cuda::cuMemcpyDtoH_v2(dst, src, len)
+                }) {
+                    Ok(output) => output,
+                    Err(error) => {
+                        eprintln!(
+                            "[ZLUDA_DUMP] Copying argument {} of {} failed with error {}",
+                            i, kernel_name, error.0
+                        );
+                        continue;
+                    }
+                };
                 let mut path = dump_dir.clone();
                 path.push(format!("arg_{:03}.buffer", i));
                 let mut file = File::create(path)?;
@@ -474,6 +982,20 @@ unsafe fn dump_arguments(
     Ok(())
 }
 
+// The buffer might have been freed by the application since it was allocated,
+// the copy failing is not a reason to stop dumping other arguments
+fn copy_buffer(
+    start: usize,
+    len: usize,
+    memcpy: impl FnOnce(*mut c_void, CUdeviceptr, usize) -> CUresult,
+) -> Result<Vec<u8>, CUresult> {
+    let mut output = vec![0u8; len];
+    match memcpy(output.as_mut_ptr() as *mut _, CUdeviceptr(start), len) {
+        CUresult::CUDA_SUCCESS => Ok(output),
+        error => Err(error),
+    }
+}
+
 fn get_dump_dir() -> Result<PathBuf, Box<dyn Error>> {
     let dir = env::var("ZLUDA_DUMP_DIR")?;
     let mut main_dir = PathBuf::from(dir);
@@ -491,6 +1013,13 @@ const CUDART_INTERFACE_GUID: CUuuid = CUuuid {
     ],
 };
 
+// Entry 0 of an export table holds the size of the whole table in bytes.
+// Known entries of the CUDART interface table:
+//   2 - returns the primary context of a device
+//   6 - loads a module from a fatbin wrapper, used by __cudaRegisterFatBinary
+//   7 - takes a single 64-bit argument, returns nothing
+// Entries we don't hook are copied as-is, so the runtime keeps calling into
+// the driver for them
 const GET_MODULE_OFFSET: usize = 6;
 static mut CUDART_INTERFACE_VTABLE: Vec<*const c_void> = Vec::new();
 static mut ORIGINAL_GET_MODULE_FROM_CUBIN: Option<
@@ -515,19 +1044,7 @@ pub unsafe fn cuGetExportTable(
             if base_result != CUresult::CUDA_SUCCESS {
                 return base_result;
             }
-            let len = *(base_table as *const usize);
-            CUDART_INTERFACE_VTABLE = vec![ptr::null(); len];
-            ptr::copy_nonoverlapping(
-                base_table as *const _,
-                CUDART_INTERFACE_VTABLE.as_mut_ptr(),
-                len,
-            );
-            if GET_MODULE_OFFSET >= len {
-                return CUresult::CUDA_ERROR_UNKNOWN;
-            }
-            ORIGINAL_GET_MODULE_FROM_CUBIN =
-                mem::transmute(CUDART_INTERFACE_VTABLE[GET_MODULE_OFFSET]);
-            CUDART_INTERFACE_VTABLE[GET_MODULE_OFFSET] = get_module_from_cubin as *const _;
+            CUDART_INTERFACE_VTABLE = hook_cudart_interface(base_table as *const _);
         }
         *ppExportTable = CUDART_INTERFACE_VTABLE.as_ptr() as *const _;
         return CUresult::CUDA_SUCCESS;
@@ -536,6 +1053,25 @@ pub unsafe fn cuGetExportTable(
     }
 }
 
+unsafe fn hook_cudart_interface(base_table: *const *const c_void) -> Vec<*const c_void> {
+    let mut vtable = copy_export_table(base_table);
+    if GET_MODULE_OFFSET < vtable.len() {
+        ORIGINAL_GET_MODULE_FROM_CUBIN = mem::transmute(vtable[GET_MODULE_OFFSET]);
+        vtable[GET_MODULE_OFFSET] = get_module_from_cubin as *const _;
+    } else {
+        eprintln!(
+            "[ZLUDA_DUMP] CUDART interface table has only {} entries, module loading is not hooked",
+            vtable.len()
+        );
+    }
+    vtable
+}
+
+unsafe fn copy_export_table(table: *const *const c_void) -> Vec<*const c_void> {
+    let len = *(table as *const usize) / mem::size_of::<*const c_void>();
+    slice::from_raw_parts(table, len).to_vec()
+}
+
 const FATBINC_MAGIC: c_uint = 0x466243B1;
 const FATBINC_VERSION: c_uint = 0x1;
 
@@ -618,7 +1154,7 @@ unsafe extern "C" fn get_module_from_cubin(
     if let Some(text) = maybe_kernel_text {
         match CStr::from_bytes_with_nul(&text) {
             Ok(cstr) => match cstr.to_str() {
-                Ok(utf8_str) => record_module_image(*module, utf8_str),
+                Ok(utf8_str) => record_module_image(*module, utf8_str, None),
                 Err(_) => {}
             },
             Err(_) => {}
@@ -668,3 +1204,221 @@ unsafe fn decompress_kernel_module(file: *const FatbinFileHeader) -> Option<Vec<
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        copy_buffer, elf_image_size, format_jit_options, get_elf_ptx, wait_for_launch, CUdeviceptr,
+        CUfunction, CUjit_option, CUresult, LegacyLaunch,
+    };
+    use std::{ffi::c_void, mem, ptr};
+
+    #[test]
+    fn profiler_range_gates_dumping() {
+        unsafe {
+            super::PROFILER_RANGE_ONLY = true;
+            assert!(!super::should_dump_kernel("kernel"));
+            let result = super::cuProfilerStart(|| CUresult::CUDA_SUCCESS);
+            assert_eq!(result, CUresult::CUDA_SUCCESS);
+            assert!(super::should_dump_kernel("kernel"));
+            let result = super::cuProfilerStop(|| CUresult::CUDA_SUCCESS);
+            assert_eq!(result, CUresult::CUDA_SUCCESS);
+            assert!(!super::should_dump_kernel("kernel"));
+            super::PROFILER_RANGE_ONLY = false;
+            assert!(super::should_dump_kernel("kernel"));
+        }
+    }
+
+    #[test]
+    fn launch_sync_error_is_returned() {
+        let result = wait_for_launch(CUresult::CUDA_SUCCESS, || {
+            CUresult::CUDA_ERROR_LAUNCH_FAILED
+        });
+        assert_eq!(result, CUresult::CUDA_ERROR_LAUNCH_FAILED);
+    }
+
+    #[test]
+    fn failed_launch_is_not_synchronized() {
+        let result = wait_for_launch(CUresult::CUDA_ERROR_INVALID_VALUE, || {
+            panic!("synchronized a stream after a failed launch")
+        });
+        assert_eq!(result, CUresult::CUDA_ERROR_INVALID_VALUE);
+    }
+
+    #[test]
+    fn failed_allocation_is_returned() {
+        let mut dptr = CUdeviceptr(0);
+        let result = unsafe {
+            super::cuMemAlloc_v2(&mut dptr, 1024, |_, _| CUresult::CUDA_ERROR_OUT_OF_MEMORY)
+        };
+        assert_eq!(result, CUresult::CUDA_ERROR_OUT_OF_MEMORY);
+        assert!(unsafe { super::BUFFERS.iter() }.all(|(_, len)| *len != 1024));
+    }
+
+    #[test]
+    fn failed_buffer_copy_is_returned() {
+        let result = copy_buffer(0x1000, 16, |_, src, len| {
+            assert_eq!((src.0, len), (0x1000, 16));
+            CUresult::CUDA_ERROR_INVALID_VALUE
+        });
+        assert_eq!(result.err(), Some(CUresult::CUDA_ERROR_INVALID_VALUE));
+        let result = copy_buffer(0x1000, 4, |dst, _, len| {
+            unsafe { ptr::write_bytes(dst as *mut u8, 7, len) };
+            CUresult::CUDA_SUCCESS
+        });
+        assert_eq!(result, Ok(vec![7u8; 4]));
+    }
+
+    #[test]
+    fn records_legacy_launch_configuration() {
+        let f = 0x1000 as CUfunction;
+        unsafe {
+            let result =
+                super::cuFuncSetBlockShape(f, 32, 4, 2, |_, _, _, _| CUresult::CUDA_SUCCESS);
+            assert_eq!(result, CUresult::CUDA_SUCCESS);
+            let result = super::cuFuncSetSharedSize(f, 256, |_, _| CUresult::CUDA_SUCCESS);
+            assert_eq!(result, CUresult::CUDA_SUCCESS);
+            let result = super::cuFuncSetBlockShape(f, 0, 0, 0, |_, _, _, _| {
+                CUresult::CUDA_ERROR_INVALID_VALUE
+            });
+            assert_eq!(result, CUresult::CUDA_ERROR_INVALID_VALUE);
+            let state = super::legacy_launch(f);
+            assert_eq!(state.block_dim, (32, 4, 2));
+            assert_eq!(state.shared_mem_bytes, 256);
+        }
+    }
+
+    #[test]
+    fn reconstructs_legacy_launch_arguments() {
+        let mut state = LegacyLaunch::new();
+        let pointer = 0x1122334455667788u64;
+        state.set_param(8, &pointer.to_ne_bytes());
+        state.set_param(0, &7u32.to_ne_bytes());
+        state.set_param(4, &1.5f32.to_ne_bytes());
+        assert!(state.kernel_params(&[4, 4]).is_none());
+        assert!(state.kernel_params(&[4, 4, 16]).is_none());
+        let params = state.kernel_params(&[4, 4, 8]).unwrap();
+        unsafe {
+            assert_eq!(*(params[0] as *const u32), 7);
+            assert_eq!(*(params[1] as *const f32), 1.5);
+            assert_eq!(ptr::read_unaligned(params[2] as *const u64), pointer);
+        }
+    }
+
+    #[test]
+    fn formats_common_jit_options() {
+        let options = [
+            CUjit_option::CU_JIT_TARGET,
+            CUjit_option::CU_JIT_MAX_REGISTERS,
+            CUjit_option::CU_JIT_OPTIMIZATION_LEVEL,
+            CUjit_option::CU_JIT_ERROR_LOG_BUFFER,
+        ];
+        let values = [
+            75usize as *mut _,
+            32usize as *mut _,
+            4usize as *mut _,
+            ptr::null_mut(),
+        ];
+        assert_eq!(
+            format_jit_options(&options, &values),
+            "CU_JIT_TARGET=sm_75\n\
+             CU_JIT_MAX_REGISTERS=32\n\
+             CU_JIT_OPTIMIZATION_LEVEL=4\n\
+             CU_JIT_ERROR_LOG_BUFFER=0x0\n"
+        );
+    }
+
+    #[test]
+    fn formats_unknown_jit_option() {
+        let options = [CUjit_option(1000)];
+        let values = [ptr::null_mut()];
+        assert_eq!(format_jit_options(&options, &values), "UNKNOWN_1000=0x0\n");
+    }
+
+    #[test]
+    fn hooks_cudart_interface_copy() {
+        let entry_size = mem::size_of::<*const c_void>();
+        let mut table = (0..16usize).map(|i| i as *const c_void).collect::<Vec<_>>();
+        table[0] = (table.len() * entry_size) as *const _;
+        let hooked = unsafe { super::hook_cudart_interface(table.as_ptr()) };
+        assert_eq!(hooked.len(), table.len());
+        for (index, (original, copy)) in table.iter().zip(hooked.iter()).enumerate() {
+            if index == super::GET_MODULE_OFFSET {
+                assert_eq!(*copy, super::get_module_from_cubin as *const c_void);
+            } else {
+                assert_eq!(original, copy);
+            }
+        }
+        assert_eq!(
+            unsafe { super::ORIGINAL_GET_MODULE_FROM_CUBIN }.map(|f| f as *const c_void),
+            Some(table[super::GET_MODULE_OFFSET])
+        );
+        // Too short to hook, copied unchanged
+        table[0] = (4 * entry_size) as *const _;
+        let hooked = unsafe { super::hook_cudart_interface(table.as_ptr()) };
+        assert_eq!(hooked, &table[..4]);
+    }
+
+    const PTX: &str = ".version 6.5\n.target sm_30\n.address_size 64\n";
+
+    // Sections: null, .shstrtab, .nv_debug_ptx_txt, followed by the section table
+    fn synthetic_cubin() -> Vec<u8> {
+        let names = b"\0.shstrtab\0.nv_debug_ptx_txt\0";
+        let names_offset = 64;
+        let ptx_offset = names_offset + names.len();
+        let ptx_size = PTX.len() + 1;
+        let table_offset = ptx_offset + ptx_size;
+        let mut elf = vec![0u8; table_offset];
+        elf[..6].copy_from_slice(&[0x7F, b'E', b'L', b'F', 2, 1]);
+        elf[0x28..0x30].copy_from_slice(&(table_offset as u64).to_le_bytes());
+        elf[0x3A..0x3C].copy_from_slice(&64u16.to_le_bytes());
+        elf[0x3C..0x3E].copy_from_slice(&3u16.to_le_bytes());
+        elf[0x3E..0x40].copy_from_slice(&1u16.to_le_bytes());
+        elf[names_offset..ptx_offset].copy_from_slice(names);
+        elf[ptx_offset..ptx_offset + PTX.len()].copy_from_slice(PTX.as_bytes());
+        let sections = [
+            (0u32, 0u32, 0, 0),
+            (1, 3, names_offset, names.len()),
+            (11, 1, ptx_offset, ptx_size),
+        ];
+        for (name, kind, offset, size) in sections.iter() {
+            let mut header = [0u8; 64];
+            header[0..4].copy_from_slice(&name.to_le_bytes());
+            header[4..8].copy_from_slice(&kind.to_le_bytes());
+            header[0x18..0x20].copy_from_slice(&(*offset as u64).to_le_bytes());
+            header[0x20..0x28].copy_from_slice(&(*size as u64).to_le_bytes());
+            elf.extend_from_slice(&header);
+        }
+        elf
+    }
+
+    #[test]
+    fn extracts_ptx_from_elf_cubin() {
+        let elf = synthetic_cubin();
+        assert_eq!(unsafe { elf_image_size(elf.as_ptr()) }, Some(elf.len()));
+        assert_eq!(get_elf_ptx(&elf), Some(PTX));
+    }
+
+    #[test]
+    fn corrupted_elf_offsets_are_rejected() {
+        let mut elf = synthetic_cubin();
+        let table_offset = elf.len() - 3 * 64;
+        // Size of .nv_debug_ptx_txt overflows when added to its offset
+        let ptx_size = table_offset + 2 * 64 + 0x20;
+        elf[ptx_size..ptx_size + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(unsafe { elf_image_size(elf.as_ptr()) }, None);
+        assert_eq!(get_elf_ptx(&elf), None);
+        // Section table overflows the address space
+        elf[0x28..0x30].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(unsafe { elf_image_size(elf.as_ptr()) }, None);
+        assert_eq!(get_elf_ptx(&elf), None);
+    }
+
+    #[test]
+    fn elf_cubin_without_ptx_has_no_ptx() {
+        let mut elf = synthetic_cubin();
+        // Rename .nv_debug_ptx_txt to .nv_debug_ptx_tx_
+        elf[64 + 27] = b'_';
+        assert_eq!(get_elf_ptx(&elf), None);
+    }
+}